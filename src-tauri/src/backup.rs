@@ -0,0 +1,1016 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
+use zip::write::{FileOptions, ZipWriter};
+use zip::ZipArchive;
+
+use crate::crypto::{sha256_hex, to_hex, Crypto, KeyDerivationStrength};
+use crate::error::Error;
+use crate::merge::{extract_archive, MergeSource};
+use crate::storage::{Storage, VaultItem};
+use crate::Result;
+
+// what export_encrypted_vault writes and a restore can't do without
+const REQUIRED_FILES: [&str; 3] = ["vault.db", "salt", "verify"];
+
+/// A vault put together next to the vault it is about to replace, from an archive, a snapshot
+/// or a remote copy. Dropping it without [`StagedRestore::commit`] leaves the vault untouched.
+pub struct StagedRestore {
+    staging: PathBuf,
+    vault_path: PathBuf,
+    key: Vec<u8>,
+    // the staged vault has the salt and verification token of the one it replaces
+    keeps_key: bool,
+    committed: bool,
+}
+
+impl StagedRestore {
+    /// An empty staging folder for a vault that is to replace the one at `vault_path`.
+    pub fn begin(vault_path: &Path) -> Result<Self> {
+        let (parent, name) = match (vault_path.parent(), vault_path.file_name()) {
+            (Some(parent), Some(name)) => (parent, name.to_string_lossy()),
+            _ => return Err(Error::InvalidInput(format!("Cannot restore into {}", vault_path.display()))),
+        };
+        // on the same file system as the vault, so moving it into place is a rename
+        let staging = parent.join(format!(".{}-restore-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir(&staging)?;
+        Ok(Self { staging, vault_path: vault_path.to_path_buf(), key: Vec::new(), keeps_key: false, committed: false })
+    }
+
+    /// Extracts `archive` and verifies `master_key` against its `verify` token. Nothing about
+    /// the vault at `vault_path` changes yet.
+    pub fn prepare(archive: &Path, vault_path: &Path, master_key: &str) -> Result<Self> {
+        let mut restore = Self::begin(vault_path)?;
+        extract_archive(File::open(archive)?, &restore.staging)?;
+        let storage = restore.open()?;
+        let mut crypto = Crypto::new();
+        restore.key = crypto.derive_key(master_key, &storage.get_salt()?, storage.get_key_derivation_strength()?)?;
+        crypto.unlock(&restore.key)?;
+        let verified = crypto.decrypt(&storage.get_verification_token()?).is_ok();
+        crypto.lock();
+        if !verified {
+            return Err(Error::InvalidMasterKey);
+        }
+        Ok(restore)
+    }
+
+    /// Where the vault is put together.
+    pub fn path(&self) -> &Path {
+        &self.staging
+    }
+
+    /// Checks the staged vault is complete and opens it, which also brings a vault from an
+    /// older version up to the current schema. Close it again before committing.
+    pub fn open(&mut self) -> Result<Storage> {
+        for name in REQUIRED_FILES {
+            if !self.staging.join(name).is_file() {
+                return Err(Error::InvalidInput(format!("Not a vault archive: {} is missing", name)));
+            }
+        }
+        let data_dir = self.staging.join("data");
+        if data_dir.exists() && !data_dir.is_dir() {
+            return Err(Error::InvalidInput("Not a vault archive: data is not a folder".into()));
+        }
+        self.keeps_key = KEY_FILES.iter().all(|name| fs::read(self.staging.join(name)).ok() == fs::read(self.vault_path.join(name)).ok());
+        Storage::new(self.staging.clone())
+    }
+
+    /// The key the archive was verified with, to unlock the restored vault right away.
+    pub fn key(&self) -> Option<&[u8]> {
+        (!self.key.is_empty()).then_some(self.key.as_slice())
+    }
+
+    /// Whether the restored vault opens with the key of the vault it replaces.
+    pub fn keeps_key(&self) -> bool {
+        self.keeps_key
+    }
+
+    /// Moves the current vault aside and the archive into its place. The vault must be closed.
+    /// Returns where the previous vault was moved, if there was one.
+    pub fn commit(mut self) -> Result<Option<PathBuf>> {
+        let previous = if self.vault_path.exists() {
+            let name = self.vault_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let aside = self.vault_path.with_file_name(format!("{}.before-restore-{}", name, Utc::now().format("%Y%m%d-%H%M%S")));
+            fs::rename(&self.vault_path, &aside)?;
+            Some(aside)
+        } else {
+            None
+        };
+
+        if let Err(e) = fs::rename(&self.staging, &self.vault_path) {
+            if let Some(aside) = &previous {
+                if let Err(e) = fs::rename(aside, &self.vault_path) {
+                    warn!("Failed to move the previous vault back from {}: {}", aside.display(), e);
+                }
+            }
+            return Err(e.into());
+        }
+        self.committed = true;
+        info!("Restored vault archive into {}", self.vault_path.display());
+        Ok(previous)
+    }
+}
+
+impl Drop for StagedRestore {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        if !self.committed && self.staging.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.staging) {
+                warn!("Failed to remove restore staging folder {}: {}", self.staging.display(), e);
+            }
+        }
+    }
+}
+
+// Snapshots: the whole vault as it is on disk, encrypted with the vault key. Restoring one
+// needs the master key it was made with.
+const SNAPSHOT_PREFIX: &str = "fetch-";
+const SNAPSHOT_SUFFIX: &str = ".fsnap";
+const SNAPSHOT_MAGIC: &[u8; 8] = b"FETCHSNP";
+const SNAPSHOT_VERSION: u8 = 2;
+// version 1 encrypted the archive in one piece, which had to fit in memory twice
+const SNAPSHOT_VERSION_WHOLE: u8 = 1;
+// version 2 encrypts it in chunks of this size, each prefixed with its index and whether it's the last
+const SNAPSHOT_CHUNK_SIZE: usize = 1 << 20;
+const CHUNK_PREFIX_LEN: usize = 9;
+// what encryption adds to a chunk is far less, anything bigger is a damaged snapshot
+const MAX_CHUNK_OVERHEAD: usize = 1024;
+const MAX_HEADER_LEN: usize = 64 * 1024;
+const SNAPSHOT_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+// the vault files a snapshot is made of besides the database and data/
+const KEY_FILES: [&str; 2] = ["salt", "verify"];
+
+// stored in the clear in front of the encrypted archive, it's what a restore needs to derive
+// the key the snapshot was made with
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotHeader {
+    created_at: DateTime<Utc>,
+    salt: String,
+    strength: KeyDerivationStrength,
+}
+
+pub(crate) fn snapshot_file_name(created_at: DateTime<Utc>) -> String {
+    format!("{}{}-{}{}", SNAPSHOT_PREFIX, created_at.format(SNAPSHOT_TIMESTAMP_FORMAT), &uuid::Uuid::new_v4().simple().to_string()[..8], SNAPSHOT_SUFFIX)
+}
+
+pub(crate) fn is_snapshot_file_name(name: &str) -> bool {
+    name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(SNAPSHOT_SUFFIX) && !name.contains('/') && !name.contains('\\')
+}
+
+pub(crate) fn snapshot_time(name: &str) -> Option<DateTime<Utc>> {
+    let stamp = name.strip_prefix(SNAPSHOT_PREFIX)?.get(..16)?;
+    NaiveDateTime::parse_from_str(stamp, SNAPSHOT_TIMESTAMP_FORMAT).ok().map(|time| DateTime::from_naive_utc_and_offset(time, Utc))
+}
+
+/// Writes a snapshot of the vault to `destination`: an archive encrypted with the vault key,
+/// chunk by chunk so the vault never has to fit in memory. Only the salt and key strength go in
+/// the clear, so it can be stored anywhere. Returns its size.
+pub fn create_snapshot(storage: &Storage, crypto: &Crypto, destination: &Path) -> Result<u64> {
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    let id = uuid::Uuid::new_v4();
+    let database_copy = std::env::temp_dir().join(format!("fetch-snapshot-{}.db", id));
+    let archive = std::env::temp_dir().join(format!("fetch-snapshot-{}.zip", id));
+    let written = archive_vault(storage, &database_copy, &archive).and_then(|()| encrypt_snapshot(storage, crypto, &archive, destination));
+    for temp in [database_copy, archive] {
+        if let Err(e) = fs::remove_file(&temp) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove temporary snapshot file {}: {}", temp.display(), e);
+            }
+        }
+    }
+    written
+}
+
+fn archive_vault(storage: &Storage, database_copy: &Path, destination: &Path) -> Result<()> {
+    let vault_path = storage.get_vault_path();
+    // a consistent copy taken through SQLite rather than the live file
+    storage.snapshot_database(database_copy)?;
+
+    let mut zip = ZipWriter::new(File::create(destination)?);
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("vault.db", options)?;
+    io::copy(&mut File::open(database_copy)?, &mut zip)?;
+    for name in KEY_FILES {
+        zip.start_file(name, options)?;
+        io::copy(&mut File::open(vault_path.join(name))?, &mut zip)?;
+    }
+    zip.add_directory("data", options)?;
+    for entry in fs::read_dir(vault_path.join("data"))? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            // data files are encrypted already, compressing them gains nothing
+            zip.start_file(format!("data/{}", name), options.compression_method(zip::CompressionMethod::Stored))?;
+            io::copy(&mut File::open(entry.path())?, &mut zip)?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn encrypt_snapshot(storage: &Storage, crypto: &Crypto, archive: &Path, destination: &Path) -> Result<u64> {
+    let header = serde_json::to_vec(&SnapshotHeader {
+        created_at: Utc::now(),
+        salt: STANDARD.encode(storage.get_salt()?),
+        strength: storage.get_key_derivation_strength()?,
+    })?;
+    let mut out = BufWriter::new(File::create(destination)?);
+    out.write_all(SNAPSHOT_MAGIC)?;
+    out.write_all(&[SNAPSHOT_VERSION])?;
+    out.write_all(&(header.len() as u32).to_be_bytes())?;
+    out.write_all(&header)?;
+
+    let mut archive = File::open(archive)?;
+    let total = archive.metadata()?.len();
+    let mut chunk = Zeroizing::new(vec![0u8; CHUNK_PREFIX_LEN + SNAPSHOT_CHUNK_SIZE]);
+    let (mut index, mut read) = (0u64, 0u64);
+    loop {
+        let len = read_chunk(&mut archive, &mut chunk[CHUNK_PREFIX_LEN..])?;
+        read += len as u64;
+        let last = read >= total;
+        chunk[..8].copy_from_slice(&index.to_be_bytes());
+        chunk[8] = u8::from(last);
+        let encrypted = crypto.encrypt(&chunk[..CHUNK_PREFIX_LEN + len])?;
+        out.write_all(&(encrypted.len() as u32).to_be_bytes())?;
+        out.write_all(&encrypted)?;
+        if last {
+            break;
+        }
+        index += 1;
+    }
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(file.metadata()?.len())
+}
+
+// fills `buf` unless the reader ends first, returns how much it got
+pub(crate) fn read_chunk(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn read_snapshot_header(reader: &mut impl Read) -> Result<(u8, SnapshotHeader)> {
+    let invalid = |_| Error::InvalidInput("Not a vault snapshot".into());
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).map_err(invalid)?;
+    if &magic != SNAPSHOT_MAGIC {
+        return Err(Error::InvalidInput("Not a vault snapshot".into()));
+    }
+    let mut prefix = [0u8; 5];
+    reader.read_exact(&mut prefix).map_err(invalid)?;
+    let version = prefix[0];
+    if version != SNAPSHOT_VERSION && version != SNAPSHOT_VERSION_WHOLE {
+        return Err(Error::InvalidInput(format!("Unsupported snapshot version {}", version)));
+    }
+    let header_len = u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize;
+    if header_len > MAX_HEADER_LEN {
+        return Err(Error::InvalidInput("Not a vault snapshot".into()));
+    }
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header).map_err(invalid)?;
+    Ok((version, serde_json::from_slice(&header)?))
+}
+
+// Writes the archive inside a snapshot to `out`. A key that doesn't open the first chunk is the
+// wrong key, one that fails later means the snapshot is damaged.
+fn decrypt_snapshot(reader: &mut impl Read, version: u8, crypto: &Crypto, out: &mut impl Write) -> Result<()> {
+    if version == SNAPSHOT_VERSION_WHOLE {
+        let mut encrypted = Vec::new();
+        reader.read_to_end(&mut encrypted)?;
+        let archive = Zeroizing::new(crypto.decrypt(&encrypted).map_err(|_| Error::InvalidMasterKey)?);
+        out.write_all(&archive)?;
+        return Ok(());
+    }
+
+    let damaged = || Error::InvalidInput("The snapshot is damaged or incomplete".into());
+    let mut index = 0u64;
+    loop {
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len).map_err(|_| damaged())?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > CHUNK_PREFIX_LEN + SNAPSHOT_CHUNK_SIZE + MAX_CHUNK_OVERHEAD {
+            return Err(damaged());
+        }
+        let mut encrypted = vec![0u8; len];
+        reader.read_exact(&mut encrypted).map_err(|_| damaged())?;
+        let chunk = Zeroizing::new(crypto.decrypt(&encrypted).map_err(|_| if index == 0 { Error::InvalidMasterKey } else { damaged() })?);
+        // chunks are numbered inside the encryption, so they can't be dropped, repeated or reordered
+        if chunk.len() < CHUNK_PREFIX_LEN || chunk[..8] != index.to_be_bytes() {
+            return Err(damaged());
+        }
+        out.write_all(&chunk[CHUNK_PREFIX_LEN..])?;
+        if chunk[8] != 0 {
+            return Ok(());
+        }
+        index += 1;
+    }
+}
+
+// the key a snapshot made with another master key is encrypted with, `None` to use the vault's
+fn snapshot_crypto(header: &SnapshotHeader, crypto: &Crypto, master_key: Option<&str>) -> Result<Option<Crypto>> {
+    let Some(master_key) = master_key else {
+        return Ok(None);
+    };
+    let salt = STANDARD.decode(&header.salt).map_err(|e| Error::InvalidInput(format!("Invalid snapshot salt: {}", e)))?;
+    let mut snapshot_crypto = Crypto::new();
+    let mut key = crypto.derive_key(master_key, &salt, header.strength)?;
+    let unlocked = snapshot_crypto.unlock(&key);
+    key.zeroize();
+    unlocked?;
+    Ok(Some(snapshot_crypto))
+}
+
+impl StagedRestore {
+    /// Decrypts a snapshot from [`create_snapshot`] and extracts it next to the vault `storage`
+    /// belongs to, carrying this device's state over. Without `master_key` the snapshot must have
+    /// been made with the key `crypto` is unlocked with; otherwise the key is derived from the
+    /// snapshot's own salt. Nothing about the vault changes yet.
+    pub fn from_snapshot(snapshot: &Path, storage: &Storage, crypto: &Crypto, master_key: Option<&str>) -> Result<Self> {
+        if !crypto.is_unlocked() {
+            return Err(Error::VaultLocked);
+        }
+        let mut reader = BufReader::new(File::open(snapshot)?);
+        let (version, header) = read_snapshot_header(&mut reader)?;
+        let snapshot_crypto = snapshot_crypto(&header, crypto, master_key)?;
+
+        let mut restore = Self::begin(storage.get_vault_path())?;
+        // next to the staging folder rather than in it, so it doesn't end up in the vault
+        let archive = restore.staging.with_extension("zip");
+        let decrypted = File::create(&archive).map_err(Error::from).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            decrypt_snapshot(&mut reader, version, snapshot_crypto.as_ref().unwrap_or(crypto), &mut out)?;
+            out.flush()?;
+            Ok(())
+        });
+        let extracted = decrypted.and_then(|()| extract_archive(File::open(&archive)?, &restore.staging));
+        if let Err(e) = fs::remove_file(&archive) {
+            warn!("Failed to remove temporary archive {}: {}", archive.display(), e);
+        }
+        match extracted {
+            Err(Error::InvalidMasterKey) if master_key.is_none() => {
+                return Err(Error::InvalidInput("This snapshot was made with a different master key, enter it to restore".into()))
+            }
+            result => result?,
+        }
+
+        let staged = restore.open()?;
+        storage.copy_device_state(&staged)?;
+        drop(staged);
+        info!("Staged vault snapshot from {}", header.created_at);
+        Ok(restore)
+    }
+}
+
+// Automatic local backups: snapshots written to a directory on this device and thinned out
+// grandfather-father-son style, so the directory doesn't grow without bound.
+const DEFAULT_KEEP_DAILY: u32 = 7;
+const DEFAULT_KEEP_WEEKLY: u32 = 4;
+const DEFAULT_KEEP_MONTHLY: u32 = 12;
+
+/// When a vault is backed up on its own and where to. Kept in the vault registry like the sync
+/// settings, together with how the last backup went.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupSchedule {
+    pub directory: PathBuf,
+    // at most one backup a day, taken by the background timer
+    #[serde(default)]
+    pub daily: bool,
+    // a backup once this many writes have gone to the vault since the last one
+    #[serde(default)]
+    pub every_writes: Option<u32>,
+    // a backup before deleting the vault, emptying the trash, changing the master key or importing
+    #[serde(default)]
+    pub before_destructive: bool,
+    #[serde(default = "default_keep_daily")]
+    pub keep_daily: u32,
+    #[serde(default = "default_keep_weekly")]
+    pub keep_weekly: u32,
+    #[serde(default = "default_keep_monthly")]
+    pub keep_monthly: u32,
+    #[serde(default)]
+    pub last_backup_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+fn default_keep_daily() -> u32 {
+    DEFAULT_KEEP_DAILY
+}
+
+fn default_keep_weekly() -> u32 {
+    DEFAULT_KEEP_WEEKLY
+}
+
+fn default_keep_monthly() -> u32 {
+    DEFAULT_KEEP_MONTHLY
+}
+
+impl BackupSchedule {
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            daily: true,
+            every_writes: None,
+            before_destructive: true,
+            keep_daily: DEFAULT_KEEP_DAILY,
+            keep_weekly: DEFAULT_KEEP_WEEKLY,
+            keep_monthly: DEFAULT_KEEP_MONTHLY,
+            last_backup_at: None,
+            last_error: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.directory.is_absolute() {
+            return Err(Error::InvalidInput("Backup directory must be an absolute path".into()));
+        }
+        if self.every_writes == Some(0) {
+            return Err(Error::InvalidInput("Backups every 0 writes make no sense, turn the option off instead".into()));
+        }
+        if self.keep_daily == 0 && self.keep_weekly == 0 && self.keep_monthly == 0 {
+            return Err(Error::InvalidInput("At least one backup must be kept".into()));
+        }
+        Ok(())
+    }
+
+    /// Whether the background timer should take a backup now, given how many writes the vault
+    /// has seen since the last one.
+    pub fn is_due(&self, now: DateTime<Utc>, writes_since_last: u64) -> bool {
+        let daily_due = self.daily && self.last_backup_at.map_or(true, |last| now - last >= chrono::Duration::days(1));
+        let writes_due = self.every_writes.is_some_and(|every| writes_since_last >= u64::from(every));
+        daily_due || writes_due
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LocalBackup {
+    pub path: PathBuf,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Writes a snapshot of the vault into the schedule's directory and rotates out the backups
+/// the schedule no longer keeps.
+pub fn write_local_backup(storage: &Storage, crypto: &Crypto, schedule: &BackupSchedule) -> Result<LocalBackup> {
+    fs::create_dir_all(&schedule.directory)?;
+    let created_at = Utc::now();
+    let path = schedule.directory.join(snapshot_file_name(created_at));
+    let temp = path.with_extension("fsnap.tmp");
+    let written = create_snapshot(storage, crypto, &temp).and_then(|size| {
+        fs::rename(&temp, &path)?;
+        Ok(size)
+    });
+    let size = match written {
+        Ok(size) => size,
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+    };
+    info!("Wrote local backup {}", path.display());
+
+    // a failed rotation leaves extra backups behind, which is no reason to report the backup as failed
+    if let Err(e) = rotate_local_backups(schedule) {
+        warn!("Failed to rotate backups in {}: {}", schedule.directory.display(), e);
+    }
+    Ok(LocalBackup { path, size, created_at })
+}
+
+/// The snapshots in `directory`, newest first. Anything else in there is left alone.
+pub fn list_local_backups(directory: &Path) -> Result<Vec<LocalBackup>> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if !is_snapshot_file_name(&name) || !entry.file_type()?.is_file() {
+            continue;
+        }
+        if let Some(created_at) = snapshot_time(&name) {
+            backups.push(LocalBackup { path: entry.path(), size: entry.metadata()?.len(), created_at });
+        }
+    }
+    backups.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.path.cmp(&a.path)));
+    Ok(backups)
+}
+
+/// Deletes the backups the schedule doesn't keep and returns their paths.
+pub fn rotate_local_backups(schedule: &BackupSchedule) -> Result<Vec<PathBuf>> {
+    let backups = list_local_backups(&schedule.directory)?;
+    let keep = backups_to_keep(&backups, schedule);
+    let mut removed = Vec::new();
+    for (index, backup) in backups.into_iter().enumerate() {
+        if keep.contains(&index) {
+            continue;
+        }
+        fs::remove_file(&backup.path)?;
+        info!("Rotated out backup {}", backup.path.display());
+        removed.push(backup.path);
+    }
+    Ok(removed)
+}
+
+// Grandfather-father-son: the newest backup of each of the last `keep_daily` days, `keep_weekly`
+// ISO weeks and `keep_monthly` months that have one. The newest backup is always kept.
+// `backups` must be sorted newest first.
+fn backups_to_keep(backups: &[LocalBackup], schedule: &BackupSchedule) -> HashSet<usize> {
+    let mut keep = HashSet::new();
+    if !backups.is_empty() {
+        keep.insert(0);
+    }
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    let mut months = HashSet::new();
+    for (index, backup) in backups.iter().enumerate() {
+        let date = backup.created_at.date_naive();
+        if days.len() < schedule.keep_daily as usize && days.insert(date) {
+            keep.insert(index);
+        }
+        let week = date.iso_week();
+        if weeks.len() < schedule.keep_weekly as usize && weeks.insert((week.year(), week.week())) {
+            keep.insert(index);
+        }
+        if months.len() < schedule.keep_monthly as usize && months.insert((date.year(), date.month())) {
+            keep.insert(index);
+        }
+    }
+    keep
+}
+
+const BACKUP_FORMAT: &str = "fetch-backup";
+const BACKUP_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+const INDEX_FILE: &str = "items.json.enc";
+const BLOB_DIR: &str = "blobs";
+// encrypted into the manifest so a wrong passphrase is caught before anything else is read
+const CHECK_PLAINTEXT: &[u8] = b"fetch-backup";
+const MIN_PASSPHRASE_LENGTH: usize = 8;
+// largest file read out of a backup; item content and attachments stay well below it
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupKdf {
+    salt: String,
+    strength: KeyDerivationStrength,
+}
+
+/// Stored in the clear at the root of a portable backup. Everything it describes is encrypted
+/// with a key derived from the backup's own passphrase, so it restores into any vault.
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupManifest {
+    format: String,
+    version: u32,
+    created_at: DateTime<Utc>,
+    item_count: usize,
+    attachment_count: usize,
+    link_count: usize,
+    kdf: BackupKdf,
+    check: String,
+    // every other file in the archive -> sha256 of its bytes as stored
+    files: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupIndex {
+    items: Vec<BackupItem>,
+    links: Vec<BackupLink>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupItem {
+    // data_path is left empty, the content is in `content`
+    item: VaultItem,
+    content: Option<String>,
+    attachments: Vec<BackupAttachment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupAttachment {
+    name: String,
+    mime_type: String,
+    blob: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupLink {
+    source_id: String,
+    target_id: String,
+    label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupSummary {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub item_count: usize,
+    pub attachment_count: usize,
+    pub link_count: usize,
+    // files whose checksum was verified
+    pub files_checked: usize,
+    // whether the content was also decrypted, which needs the passphrase
+    pub decrypted: bool,
+}
+
+impl BackupManifest {
+    fn summary(&self, files_checked: usize, decrypted: bool) -> BackupSummary {
+        BackupSummary {
+            version: self.version,
+            created_at: self.created_at,
+            item_count: self.item_count,
+            attachment_count: self.attachment_count,
+            link_count: self.link_count,
+            files_checked,
+            decrypted,
+        }
+    }
+
+    fn unlock(&self, passphrase: &str) -> Result<Crypto> {
+        let salt = STANDARD.decode(&self.kdf.salt).map_err(|e| Error::InvalidInput(format!("Backup is damaged: invalid salt: {}", e)))?;
+        let check = STANDARD.decode(&self.check).map_err(|e| Error::InvalidInput(format!("Backup is damaged: invalid check value: {}", e)))?;
+        let mut crypto = Crypto::new();
+        let mut key = crypto.derive_key(passphrase, &salt, self.kdf.strength)?;
+        let unlocked = crypto.unlock(&key);
+        key.zeroize();
+        unlocked?;
+        if crypto.decrypt(&check).ok().as_deref() != Some(CHECK_PLAINTEXT) {
+            return Err(Error::InvalidInput("Wrong backup passphrase".into()));
+        }
+        Ok(crypto)
+    }
+}
+
+struct BackupWriter {
+    zip: ZipWriter<File>,
+    files: BTreeMap<String, String>,
+}
+
+impl BackupWriter {
+    fn add(&mut self, name: String, data: &[u8]) -> Result<String> {
+        // everything but the manifest is encrypted, compressing it gains nothing
+        self.zip.start_file(name.as_str(), FileOptions::default().compression_method(zip::CompressionMethod::Stored))?;
+        self.zip.write_all(data)?;
+        self.files.insert(name.clone(), sha256_hex(data));
+        Ok(name)
+    }
+
+    // encrypts `plaintext` under the backup key into a new blob and wipes it
+    fn add_blob(&mut self, mut plaintext: Vec<u8>, crypto: &Crypto) -> Result<String> {
+        let encrypted = crypto.encrypt(&plaintext);
+        plaintext.zeroize();
+        self.add(format!("{}/{}", BLOB_DIR, uuid::Uuid::new_v4()), &encrypted?)
+    }
+}
+
+/// Writes every live item of the vault, with its content, attachments and links, to a portable
+/// backup at `destination` encrypted under `passphrase`.
+pub fn write_backup(storage: &Storage, crypto: &Crypto, destination: &Path, passphrase: &str, strength: KeyDerivationStrength) -> Result<BackupSummary> {
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+        return Err(Error::InvalidInput(format!("Backup passphrase must be at least {} characters", MIN_PASSPHRASE_LENGTH)));
+    }
+
+    let salt = Crypto::generate_salt();
+    let mut backup_crypto = Crypto::new();
+    let mut key = backup_crypto.derive_key(passphrase, &salt, strength)?;
+    let unlocked = backup_crypto.unlock(&key);
+    key.zeroize();
+    unlocked?;
+
+    let temp = destination.with_file_name(format!(
+        ".{}.tmp",
+        destination.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+    ));
+    let written = write_backup_file(storage, crypto, &backup_crypto, &temp, salt, strength).and_then(|summary| {
+        fs::rename(&temp, destination)?;
+        Ok(summary)
+    });
+    if written.is_err() {
+        if let Err(e) = fs::remove_file(&temp) {
+            warn!("Failed to remove incomplete backup {}: {}", temp.display(), e);
+        }
+    }
+    let summary = written?;
+    info!("Wrote backup of {} items to {}", summary.item_count, destination.display());
+    Ok(summary)
+}
+
+fn write_backup_file(storage: &Storage, crypto: &Crypto, backup_crypto: &Crypto, path: &Path, salt: Vec<u8>, strength: KeyDerivationStrength) -> Result<BackupSummary> {
+    let mut writer = BackupWriter { zip: ZipWriter::new(File::create(path)?), files: BTreeMap::new() };
+    let live: Vec<VaultItem> = storage.get_all_items_recursive(crypto)?.into_iter().filter(|item| item.deleted_at.is_none()).collect();
+    let ids: HashSet<String> = live.iter().map(|item| item.id.clone()).collect();
+
+    let mut index = BackupIndex { items: Vec::with_capacity(live.len()), links: Vec::new() };
+    let mut attachment_count = 0;
+    for mut item in live {
+        let content = if item.data_path.is_empty() {
+            None
+        } else {
+            Some(writer.add_blob(storage.read_encrypted_file(&item.data_path, crypto)?, backup_crypto)?)
+        };
+        let mut attachments = Vec::new();
+        for attachment in storage.list_attachments(&item.id, crypto)? {
+            let blob = writer.add_blob(storage.read_attachment(&attachment, crypto)?, backup_crypto)?;
+            attachments.push(BackupAttachment { name: attachment.name, mime_type: attachment.mime_type, blob });
+        }
+        attachment_count += attachments.len();
+        item.data_path = String::new();
+        index.items.push(BackupItem { item, content, attachments });
+    }
+    index.links = storage
+        .list_all_links(crypto)?
+        .into_iter()
+        .filter(|link| ids.contains(&link.source_id) && ids.contains(&link.target_id))
+        .map(|link| BackupLink { source_id: link.source_id, target_id: link.target_id, label: link.label })
+        .collect();
+
+    let item_count = index.items.len();
+    let link_count = index.links.len();
+    let mut json = serde_json::to_vec(&index)?;
+    let encrypted = backup_crypto.encrypt(&json);
+    json.zeroize();
+    writer.add(INDEX_FILE.to_string(), &encrypted?)?;
+
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        item_count,
+        attachment_count,
+        link_count,
+        kdf: BackupKdf { salt: STANDARD.encode(salt), strength },
+        check: STANDARD.encode(backup_crypto.encrypt(CHECK_PLAINTEXT)?),
+        files: writer.files,
+    };
+    writer.zip.start_file(MANIFEST_FILE, FileOptions::default())?;
+    writer.zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    writer.zip.finish()?.sync_all()?;
+    Ok(manifest.summary(manifest.files.len(), true))
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>> {
+    let entry = archive.by_name(name).map_err(|_| Error::InvalidInput(format!("Backup is damaged: {} is missing", name)))?;
+    let too_large = || Error::InvalidInput(format!("Backup is damaged: {} is too large", name));
+    if entry.size() > MAX_ENTRY_SIZE {
+        return Err(too_large());
+    }
+    // the size comes from the archive itself, so it's only trusted as far as the cap
+    let mut data = Vec::new();
+    entry.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(too_large());
+    }
+    Ok(data)
+}
+
+fn open_archive(path: &Path) -> Result<(ZipArchive<File>, BackupManifest)> {
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(|_| Error::InvalidInput("Not a backup file".into()))?;
+    let manifest = read_entry(&mut archive, MANIFEST_FILE).map_err(|_| Error::InvalidInput("Not a backup file: the manifest is missing".into()))?;
+    let manifest: BackupManifest = serde_json::from_slice(&manifest)?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err(Error::InvalidInput("Not a backup file".into()));
+    }
+    if manifest.version > BACKUP_VERSION {
+        return Err(Error::InvalidInput(format!("This backup uses format version {}, update the app to read it", manifest.version)));
+    }
+    Ok((archive, manifest))
+}
+
+// every file must be listed in the manifest with a matching checksum, and vice versa
+fn check_files(archive: &mut ZipArchive<File>, manifest: &BackupManifest) -> Result<usize> {
+    let mut seen = HashSet::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = entry.name().to_string();
+        if entry.is_dir() || name == MANIFEST_FILE {
+            continue;
+        }
+        let expected = manifest.files.get(&name).ok_or_else(|| Error::InvalidInput(format!("Backup is damaged: unexpected file {}", name)))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut entry, &mut hasher).map_err(|e| Error::InvalidInput(format!("Backup is damaged: {} cannot be read: {}", name, e)))?;
+        if to_hex(&hasher.finalize()) != *expected {
+            return Err(Error::InvalidInput(format!("Backup is damaged: {} does not match its checksum", name)));
+        }
+        seen.insert(name);
+    }
+    if let Some(missing) = manifest.files.keys().find(|name| !seen.contains(*name)) {
+        return Err(Error::InvalidInput(format!("Backup is damaged: {} is missing", missing)));
+    }
+    Ok(seen.len())
+}
+
+fn read_blob(archive: &mut ZipArchive<File>, manifest: &BackupManifest, crypto: &Crypto, name: &str) -> Result<Vec<u8>> {
+    // Security: only files the manifest vouches for
+    if !manifest.files.contains_key(name) {
+        return Err(Error::InvalidInput(format!("Backup is damaged: {} is missing", name)));
+    }
+    crypto
+        .decrypt(&read_entry(archive, name)?)
+        .map_err(|_| Error::InvalidInput(format!("Backup is damaged: {} cannot be decrypted", name)))
+}
+
+fn read_index(archive: &mut ZipArchive<File>, manifest: &BackupManifest, crypto: &Crypto) -> Result<BackupIndex> {
+    let mut json = read_blob(archive, manifest, crypto, INDEX_FILE)?;
+    let index = serde_json::from_slice::<BackupIndex>(&json);
+    json.zeroize();
+    let index = index?;
+    let attachment_count: usize = index.items.iter().map(|item| item.attachments.len()).sum();
+    if index.items.len() != manifest.item_count || attachment_count != manifest.attachment_count || index.links.len() != manifest.link_count {
+        return Err(Error::InvalidInput("Backup is damaged: its contents don't match the manifest".into()));
+    }
+    Ok(index)
+}
+
+/// Checks a backup without restoring it: the manifest, and every file against its checksum.
+/// With the passphrase every item, attachment and link is decrypted as well.
+pub fn verify_backup(path: &Path, passphrase: Option<&str>) -> Result<BackupSummary> {
+    let (mut archive, manifest) = open_archive(path)?;
+    let files_checked = check_files(&mut archive, &manifest)?;
+    let Some(passphrase) = passphrase else {
+        return Ok(manifest.summary(files_checked, false));
+    };
+
+    let crypto = manifest.unlock(passphrase)?;
+    let index = read_index(&mut archive, &manifest, &crypto)?;
+    for item in &index.items {
+        let blobs = item.content.iter().chain(item.attachments.iter().map(|attachment| &attachment.blob));
+        for blob in blobs {
+            read_blob(&mut archive, &manifest, &crypto, blob)?.zeroize();
+        }
+    }
+    let ids: HashSet<&str> = index.items.iter().map(|item| item.item.id.as_str()).collect();
+    if index.links.iter().any(|link| !ids.contains(link.source_id.as_str()) || !ids.contains(link.target_id.as_str())) {
+        return Err(Error::InvalidInput("Backup is damaged: a link points at a missing item".into()));
+    }
+    info!("Verified backup {} with {} items", path.display(), manifest.item_count);
+    Ok(manifest.summary(files_checked, true))
+}
+
+/// Unpacks a backup into a temporary vault under a throwaway key, ready to be merged into the
+/// open vault with [`crate::merge::merge_vault`].
+pub fn open_backup(path: &Path, passphrase: &str) -> Result<MergeSource> {
+    let (mut archive, manifest) = open_archive(path)?;
+    check_files(&mut archive, &manifest)?;
+    let backup_crypto = manifest.unlock(passphrase)?;
+    let index = read_index(&mut archive, &manifest, &backup_crypto)?;
+
+    let temp_dir = std::env::temp_dir().join(format!("fetch-backup-{}", uuid::Uuid::new_v4()));
+    let mut crypto = Crypto::new();
+    let mut key: [u8; 32] = rand::random();
+    let unlocked = crypto.unlock(&key);
+    key.zeroize();
+    unlocked?;
+
+    let storage = Storage::new(temp_dir.clone())?;
+    let filled = fill_temporary_vault(&storage, &crypto, &mut archive, &manifest, &backup_crypto, index);
+    // from here on the merge source owns the temporary vault and removes it when dropped
+    let source = MergeSource::from_temporary(storage, crypto, temp_dir);
+    filled.map(|()| source)
+}
+
+fn fill_temporary_vault(
+    storage: &Storage,
+    crypto: &Crypto,
+    archive: &mut ZipArchive<File>,
+    manifest: &BackupManifest,
+    backup_crypto: &Crypto,
+    index: BackupIndex,
+) -> Result<()> {
+    for backup_item in index.items {
+        let mut item = backup_item.item;
+        if let Some(blob) = &backup_item.content {
+            let mut content = read_blob(archive, manifest, backup_crypto, blob)?;
+            let encrypted = crypto.encrypt(&content);
+            content.zeroize();
+            item.data_path = uuid::Uuid::new_v4().to_string();
+            storage.write_encrypted_file(&encrypted?, &item.data_path)?;
+        }
+        storage.add_item(&item, crypto)?;
+        for attachment in backup_item.attachments {
+            let mut content = read_blob(archive, manifest, backup_crypto, &attachment.blob)?;
+            let added = storage.add_attachment(&item.id, &attachment.name, &attachment.mime_type, &content, crypto);
+            content.zeroize();
+            added?;
+        }
+    }
+    for link in index.links {
+        storage.add_link(&link.source_id, &link.target_id, link.label.as_deref(), crypto)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    // a Wednesday, so the week before starts two days back
+    fn newest() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap()
+    }
+
+    // one backup a day going back `days` days, newest first
+    fn daily_backups(days: i64) -> Vec<LocalBackup> {
+        (0..days).map(|day| backup(newest() - Duration::days(day))).collect()
+    }
+
+    fn backup(created_at: DateTime<Utc>) -> LocalBackup {
+        LocalBackup { path: PathBuf::from(snapshot_file_name(created_at)), size: 0, created_at }
+    }
+
+    fn schedule(keep_daily: u32, keep_weekly: u32, keep_monthly: u32) -> BackupSchedule {
+        BackupSchedule { keep_daily, keep_weekly, keep_monthly, ..BackupSchedule::new(std::env::temp_dir()) }
+    }
+
+    fn kept(backups: &[LocalBackup], schedule: &BackupSchedule) -> Vec<usize> {
+        let mut kept: Vec<usize> = backups_to_keep(backups, schedule).into_iter().collect();
+        kept.sort_unstable();
+        kept
+    }
+
+    #[test]
+    fn keeps_the_newest_backup_of_each_period() {
+        assert_eq!(kept(&daily_backups(10), &schedule(3, 0, 0)), [0, 1, 2]);
+        // 2024-05-13 is the Monday of this week, the 12th is the newest of the week before
+        assert_eq!(kept(&daily_backups(30), &schedule(0, 2, 0)), [0, 3]);
+        // the 30th of April and the 31st of March end the months before
+        assert_eq!(kept(&daily_backups(100), &schedule(0, 0, 3)), [0, 15, 45]);
+        // the periods overlap rather than add up
+        assert_eq!(kept(&daily_backups(100), &schedule(7, 4, 3)), [0, 1, 2, 3, 4, 5, 6, 10, 15, 17, 45]);
+        assert!(kept(&[], &schedule(7, 4, 12)).is_empty());
+    }
+
+    #[test]
+    fn periods_without_a_backup_are_not_counted() {
+        let newest = newest();
+        let backups = [backup(newest), backup(newest - Duration::hours(2)), backup(newest - Duration::days(5)), backup(newest - Duration::days(9))];
+        // the two from the same day count once, the gaps don't count at all
+        assert_eq!(kept(&backups, &schedule(2, 0, 0)), [0, 2]);
+        assert_eq!(kept(&backups, &schedule(3, 0, 0)), [0, 2, 3]);
+        // the newest backup survives whatever the schedule says
+        assert_eq!(kept(&backups, &schedule(0, 0, 0)), [0]);
+    }
+
+    #[test]
+    fn rotation_only_touches_snapshots() {
+        let directory = std::env::temp_dir().join(format!("fetch-rotation-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        for day in 0..5 {
+            fs::write(directory.join(snapshot_file_name(newest() - Duration::days(day))), b"snapshot").unwrap();
+        }
+        fs::write(directory.join("notes.txt"), b"not a backup").unwrap();
+
+        let schedule = BackupSchedule { directory: directory.clone(), ..schedule(2, 0, 0) };
+        let removed = rotate_local_backups(&schedule).unwrap();
+        assert_eq!(removed.len(), 3);
+        let left = list_local_backups(&directory).unwrap();
+        assert_eq!(left.iter().map(|backup| backup.created_at).collect::<Vec<_>>(), [newest(), newest() - Duration::days(1)]);
+        assert!(directory.join("notes.txt").exists());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn schedules_are_due_by_time_or_writes() {
+        let now = newest();
+        let mut schedule = schedule(7, 4, 12);
+        assert!(schedule.is_due(now, 0));
+        schedule.last_backup_at = Some(now - Duration::hours(23));
+        assert!(!schedule.is_due(now, 0));
+        schedule.last_backup_at = Some(now - Duration::hours(25));
+        assert!(schedule.is_due(now, 0));
+
+        schedule.daily = false;
+        schedule.every_writes = Some(10);
+        assert!(!schedule.is_due(now, 9));
+        assert!(schedule.is_due(now, 10));
+
+        assert!(schedule.validate().is_ok());
+        assert!(BackupSchedule { every_writes: Some(0), ..schedule.clone() }.validate().is_err());
+        assert!(BackupSchedule { directory: PathBuf::from("relative"), ..schedule.clone() }.validate().is_err());
+        assert!(BackupSchedule { keep_daily: 0, keep_weekly: 0, keep_monthly: 0, ..schedule }.validate().is_err());
+    }
+}
@@ -0,0 +1,581 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::ser::{Error as _, SerializeMap, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use uuid::Uuid;
+
+use crate::crypto::Crypto;
+use crate::error::Error;
+use crate::export::{ExportControl, FolderPaths};
+use crate::import::{clean_tags, store_item, totp_secret_from_text, totp_secret_to_base32, FolderTree, Payload, MAX_NAME_LENGTH};
+use crate::fields::{normalize_custom_fields, validate_custom_fields, CustomField, CustomFieldKind};
+use crate::login::{LoginData, LOGIN_ITEM_TYPE};
+use crate::records::{is_record_type, CardData, IdentityData, ItemRecord};
+use crate::search::is_searchable_content_type;
+use crate::storage::{Storage, VaultItem};
+use crate::Result;
+
+// Bitwarden's cipher types
+const LOGIN: u8 = 1;
+const SECURE_NOTE: u8 = 2;
+const CARD: u8 = 3;
+const IDENTITY: u8 = 4;
+
+// Bitwarden's custom field types; linked fields point at another field and carry no value
+const FIELD_TEXT: u8 = 0;
+const FIELD_HIDDEN: u8 = 1;
+const FIELD_BOOLEAN: u8 = 2;
+
+// Bitwarden has no tags, they travel as a comma separated text field of this name
+const TAGS_FIELD: &str = "Tags";
+// and its favorite flag becomes this tag
+const FAVORITE_TAG: &str = "favorite";
+
+/// What an import did; items that couldn't be brought over are listed with the reason.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct BitwardenImportReport {
+    pub folders: usize,
+    pub items: usize,
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenFile {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<BitwardenFolder>,
+    // organization exports have collections instead of folders
+    #[serde(default)]
+    collections: Vec<BitwardenFolder>,
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    organization_id: Option<String>,
+    #[serde(default)]
+    folder_id: Option<String>,
+    #[serde(rename = "type")]
+    kind: u8,
+    #[serde(default)]
+    reprompt: u8,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    favorite: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", deserialize_with = "null_as_empty")]
+    fields: Vec<BitwardenField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    login: Option<BitwardenLogin>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secure_note: Option<BitwardenSecureNote>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    card: Option<BitwardenCard>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity: Option<BitwardenIdentity>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    collection_ids: Vec<String>,
+    #[serde(default)]
+    revision_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    creation_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    deleted_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenField {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(rename = "type", default)]
+    kind: u8,
+    #[serde(default)]
+    linked_id: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenLogin {
+    #[serde(default, deserialize_with = "null_as_empty")]
+    uris: Vec<BitwardenUri>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    totp: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BitwardenUri {
+    #[serde(rename = "match", default)]
+    match_type: Option<u8>,
+    #[serde(default)]
+    uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct BitwardenSecureNote {
+    #[serde(rename = "type", default)]
+    kind: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenCard {
+    #[serde(default)]
+    cardholder_name: Option<String>,
+    #[serde(default)]
+    brand: Option<String>,
+    #[serde(default)]
+    number: Option<String>,
+    #[serde(default)]
+    exp_month: Option<String>,
+    #[serde(default)]
+    exp_year: Option<String>,
+    #[serde(default)]
+    code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenIdentity {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    first_name: Option<String>,
+    #[serde(default)]
+    middle_name: Option<String>,
+    #[serde(default)]
+    last_name: Option<String>,
+    #[serde(default)]
+    address1: Option<String>,
+    #[serde(default)]
+    address2: Option<String>,
+    #[serde(default)]
+    address3: Option<String>,
+    #[serde(default)]
+    city: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    postal_code: Option<String>,
+    #[serde(default)]
+    country: Option<String>,
+    #[serde(default)]
+    company: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    phone: Option<String>,
+    #[serde(default)]
+    ssn: Option<String>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    passport_number: Option<String>,
+    #[serde(default)]
+    license_number: Option<String>,
+}
+
+// Bitwarden writes `null` rather than leaving out empty lists
+fn null_as_empty<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Option::<Vec<T>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Imports an unencrypted Bitwarden JSON export under `parent_id`. Folders are matched by name
+/// against the ones already there, so importing the same export twice doesn't duplicate them.
+pub fn import_bitwarden(storage: &Storage, crypto: &Crypto, json: &str, parent_id: Option<&str>) -> Result<BitwardenImportReport> {
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    let file: BitwardenFile = serde_json::from_str(json)
+        .map_err(|e| Error::InvalidInput(format!("Not a Bitwarden JSON export: {}", e)))?;
+    if file.encrypted {
+        return Err(Error::InvalidInput("Encrypted Bitwarden exports can't be imported, export as unencrypted JSON".into()));
+    }
+
+    let mut report = BitwardenImportReport::default();
+    let mut folders = FolderTree::new(storage, crypto)?;
+    let mut folder_ids = HashMap::new();
+    for folder in &file.folders {
+        // "Work/Servers" is Bitwarden's way of nesting, each part becomes a folder
+        let mut parent = parent_id.map(str::to_string);
+        for part in folder.name.split('/').map(str::trim).filter(|part| !part.is_empty()) {
+            parent = Some(folders.child(parent.as_deref(), part)?);
+        }
+        folder_ids.insert(folder.id.as_str(), parent);
+    }
+    report.folders = folders.created;
+    let collections: HashMap<&str, &str> = file.collections.iter().map(|c| (c.id.as_str(), c.name.as_str())).collect();
+
+    for bitwarden in file.items {
+        let name = bitwarden.name.as_deref().map(str::trim).filter(|name| !name.is_empty()).unwrap_or("Untitled").to_string();
+        if bitwarden.deleted_date.is_some() {
+            report.skipped.push(format!("{}: in Bitwarden's trash", name));
+            continue;
+        }
+        let parent = match bitwarden.folder_id.as_deref() {
+            Some(id) => folder_ids.get(id).cloned().flatten().or_else(|| parent_id.map(str::to_string)),
+            None => parent_id.map(str::to_string),
+        };
+        let collection_tags = bitwarden.collection_ids.iter().filter_map(|id| collections.get(id.as_str())).map(|name| name.to_string()).collect();
+        match convert_item(bitwarden, &name, collection_tags) {
+            Ok((mut item, payload)) => {
+                item.parent_id = parent;
+                store_item(storage, crypto, &mut item, payload)?;
+                report.items += 1;
+            }
+            Err(reason) => report.skipped.push(format!("{}: {}", name, reason)),
+        }
+    }
+    info!("Imported {} Bitwarden items and {} folders, skipped {}", report.items, report.folders, report.skipped.len());
+    Ok(report)
+}
+
+fn text(value: Option<String>) -> String {
+    value.map(|value| value.trim().to_string()).unwrap_or_default()
+}
+
+fn convert_item(bitwarden: BitwardenItem, name: &str, extra_tags: Vec<String>) -> std::result::Result<(VaultItem, Payload), String> {
+    if name.len() > MAX_NAME_LENGTH {
+        return Err("name too long (max 255 characters)".into());
+    }
+    let notes = bitwarden.notes.unwrap_or_default();
+    let mut totp_secret = None;
+    let mut custom_fields = Vec::new();
+    let mut tags = extra_tags;
+    if bitwarden.favorite {
+        tags.push(FAVORITE_TAG.to_string());
+    }
+
+    for field in bitwarden.fields {
+        let label = field.name.as_deref().map(str::trim).unwrap_or_default().to_string();
+        let value = field.value.unwrap_or_default();
+        if label == TAGS_FIELD && field.kind == FIELD_TEXT {
+            tags.extend(value.split(',').map(|tag| tag.trim().to_string()));
+            continue;
+        }
+        let kind = match field.kind {
+            FIELD_TEXT | FIELD_BOOLEAN => CustomFieldKind::Text,
+            FIELD_HIDDEN => CustomFieldKind::Hidden,
+            _ => continue,
+        };
+        custom_fields.push(CustomField { label: if label.is_empty() { "Field".to_string() } else { label }, kind, value });
+    }
+
+    let (item_type, payload) = match bitwarden.kind {
+        LOGIN => {
+            let login = bitwarden.login.unwrap_or_default();
+            if let Some(totp) = login.totp.as_deref().map(str::trim).filter(|totp| !totp.is_empty()) {
+                match totp_secret_from_text(totp) {
+                    Some(secret) => totp_secret = Some(secret),
+                    // steam:// and otpauth URIs with other digits or algorithms, kept so nothing is lost
+                    None => custom_fields.push(CustomField { label: "TOTP".to_string(), kind: CustomFieldKind::Hidden, value: totp.to_string() }),
+                }
+            }
+            let login = LoginData {
+                username: text(login.username),
+                password: login.password.unwrap_or_default(),
+                urls: login.uris.into_iter().filter_map(|uri| uri.uri).collect(),
+                notes,
+            }
+            .normalized();
+            login.validate().map_err(|e| e.to_string())?;
+            (LOGIN_ITEM_TYPE, Payload::Login(login))
+        }
+        SECURE_NOTE => ("text/plain", Payload::Note(notes)),
+        CARD => {
+            let card = bitwarden.card.unwrap_or_default();
+            let expiry_year = card.exp_year.as_deref().and_then(|year| year.trim().parse::<i32>().ok()).map(|year| if year < 100 { 2000 + year } else { year });
+            let record = ItemRecord::Card(CardData {
+                cardholder_name: text(card.cardholder_name),
+                number: text(card.number),
+                expiry_month: card.exp_month.as_deref().and_then(|month| month.trim().parse().ok()),
+                expiry_year,
+                security_code: text(card.code),
+                pin: String::new(),
+                notes,
+            })
+            .normalized();
+            record.validate().map_err(|e| e.to_string())?;
+            (record.item_type(), Payload::Record(Box::new(record)))
+        }
+        IDENTITY => {
+            let identity = bitwarden.identity.unwrap_or_default();
+            if let Some(username) = identity.username.filter(|username| !username.trim().is_empty()) {
+                custom_fields.push(CustomField { label: "Username".to_string(), kind: CustomFieldKind::Text, value: username });
+            }
+            let address_line2 = [identity.address2, identity.address3]
+                .into_iter()
+                .map(text)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let record = ItemRecord::Identity(IdentityData {
+                title: text(identity.title),
+                first_name: text(identity.first_name),
+                middle_name: text(identity.middle_name),
+                last_name: text(identity.last_name),
+                email: text(identity.email),
+                phone: text(identity.phone),
+                company: text(identity.company),
+                address_line1: text(identity.address1),
+                address_line2,
+                city: text(identity.city),
+                state: text(identity.state),
+                postal_code: text(identity.postal_code),
+                country: text(identity.country),
+                passport_number: text(identity.passport_number),
+                license_number: text(identity.license_number),
+                national_id: text(identity.ssn),
+                date_of_birth: String::new(),
+                notes,
+            })
+            .normalized();
+            record.validate().map_err(|e| e.to_string())?;
+            (record.item_type(), Payload::Record(Box::new(record)))
+        }
+        kind => return Err(format!("Bitwarden item type {} is not supported", kind)),
+    };
+
+    let custom_fields = normalize_custom_fields(custom_fields);
+    validate_custom_fields(&custom_fields).map_err(|e| e.to_string())?;
+
+    let now = Utc::now();
+    let created_at = bitwarden.creation_date.unwrap_or(now);
+    let item = VaultItem {
+        id: Uuid::new_v4().to_string(),
+        parent_id: None,
+        name: name.to_string(),
+        data_path: Uuid::new_v4().to_string(),
+        item_type: item_type.to_string(),
+        folder_type: None,
+        tags: clean_tags(tags),
+        created_at,
+        updated_at: bitwarden.revision_date.unwrap_or(created_at),
+        deleted_at: None,
+        totp_secret,
+        custom_fields,
+        expires_at: None,
+    };
+    Ok((item, payload))
+}
+
+/// Writes `items` as a Bitwarden unencrypted JSON export and returns how many went in. Folders
+/// keep their full path as the name; files and images have no Bitwarden equivalent and are left out.
+pub(crate) fn write_export(storage: &Storage, crypto: &Crypto, items: &[VaultItem], writer: &mut impl Write, control: &mut ExportControl<'_>) -> Result<usize> {
+    let paths = FolderPaths::new(items);
+    let folders: Vec<BitwardenFolder> = items
+        .iter()
+        .filter(|item| item.item_type == "folder")
+        .map(|folder| {
+            let mut path = paths.of(folder);
+            path.push(folder.name.clone());
+            BitwardenFolder { id: folder.id.clone(), name: path.join("/") }
+        })
+        .collect();
+    let folder_ids: HashSet<&str> = folders.iter().map(|folder| folder.id.as_str()).collect();
+
+    let ciphers = Ciphers {
+        storage,
+        crypto,
+        items: items.iter().filter(|item| !Storage::is_folder_like(&item.item_type)).collect(),
+        folder_ids,
+        control: RefCell::new(control),
+        written: RefCell::new(0),
+        failure: RefCell::new(None),
+    };
+    let mut serializer = serde_json::Serializer::pretty(writer);
+    let mut map = serializer.serialize_map(Some(3))?;
+    map.serialize_entry("encrypted", &false)?;
+    map.serialize_entry("folders", &folders)?;
+    let written = map.serialize_entry("items", &ciphers).and_then(|_| SerializeMap::end(map));
+    if let Some(e) = ciphers.failure.take() {
+        return Err(e);
+    }
+    written?;
+    Ok(ciphers.written.into_inner())
+}
+
+// Converts each item as the array is serialized, so only one is decrypted at a time. Errors
+// from the vault are kept aside; serde can only carry a message.
+struct Ciphers<'a, 'c> {
+    storage: &'a Storage,
+    crypto: &'a Crypto,
+    items: Vec<&'a VaultItem>,
+    folder_ids: HashSet<&'a str>,
+    control: RefCell<&'a mut ExportControl<'c>>,
+    written: RefCell<usize>,
+    failure: RefCell<Option<Error>>,
+}
+
+impl Serialize for Ciphers<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        let mut control = self.control.borrow_mut();
+        for (index, item) in self.items.iter().enumerate() {
+            let converted = control.check().and_then(|_| to_bitwarden(self.storage, self.crypto, item, &self.folder_ids));
+            match converted {
+                Ok(Some(cipher)) => {
+                    seq.serialize_element(&cipher)?;
+                    *self.written.borrow_mut() += 1;
+                }
+                Ok(None) => warn!("Left {} out of the Bitwarden export, {} has no Bitwarden equivalent", item.id, item.item_type),
+                Err(e) => {
+                    let message = e.to_string();
+                    *self.failure.borrow_mut() = Some(e);
+                    return Err(S::Error::custom(message));
+                }
+            }
+            control.report(index + 1, self.items.len(), &item.name);
+        }
+        seq.end()
+    }
+}
+
+fn optional(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn to_bitwarden(storage: &Storage, crypto: &Crypto, item: &VaultItem, folder_ids: &HashSet<&str>) -> Result<Option<BitwardenItem>> {
+    let mut fields: Vec<BitwardenField> = item
+        .custom_fields
+        .iter()
+        .map(|field| {
+            let (kind, value) = match field.kind {
+                CustomFieldKind::Hidden => (FIELD_HIDDEN, field.value.clone()),
+                CustomFieldKind::Totp => (FIELD_HIDDEN, totp_secret_to_base32(&field.value)),
+                _ => (FIELD_TEXT, field.value.clone()),
+            };
+            BitwardenField { name: Some(field.label.clone()), value: Some(value), kind, linked_id: None }
+        })
+        .collect();
+    let tags: Vec<&str> = item.tags.iter().map(String::as_str).filter(|tag| !tag.eq_ignore_ascii_case(FAVORITE_TAG)).collect();
+    if !tags.is_empty() {
+        fields.push(BitwardenField { name: Some(TAGS_FIELD.to_string()), value: Some(tags.join(", ")), kind: FIELD_TEXT, linked_id: None });
+    }
+    let totp = item.totp_secret.as_deref().map(totp_secret_to_base32);
+
+    let mut cipher = BitwardenItem {
+        id: Some(item.id.clone()),
+        folder_id: item.parent_id.clone().filter(|parent| folder_ids.contains(parent.as_str())),
+        name: Some(item.name.clone()),
+        favorite: item.tags.len() != tags.len(),
+        revision_date: Some(item.updated_at),
+        creation_date: Some(item.created_at),
+        ..Default::default()
+    };
+
+    if item.item_type == LOGIN_ITEM_TYPE {
+        let login = storage.read_login(item, crypto)?;
+        cipher.kind = LOGIN;
+        cipher.notes = optional(&login.notes);
+        cipher.login = Some(BitwardenLogin {
+            uris: login.urls.iter().map(|uri| BitwardenUri { match_type: None, uri: Some(uri.clone()) }).collect(),
+            username: optional(&login.username),
+            password: optional(&login.password),
+            totp,
+        });
+    } else {
+        // only logins have a TOTP slot in Bitwarden
+        if let Some(totp) = totp {
+            fields.push(BitwardenField { name: Some("TOTP".to_string()), value: Some(totp), kind: FIELD_HIDDEN, linked_id: None });
+        }
+        if is_record_type(&item.item_type) {
+            match storage.read_record(item, crypto)? {
+                ItemRecord::Card(card) => {
+                    cipher.kind = CARD;
+                    cipher.notes = optional(&card.notes);
+                    if !card.pin.is_empty() {
+                        fields.push(BitwardenField { name: Some("PIN".to_string()), value: Some(card.pin.clone()), kind: FIELD_HIDDEN, linked_id: None });
+                    }
+                    cipher.card = Some(BitwardenCard {
+                        brand: card.brand().map(str::to_string),
+                        cardholder_name: optional(&card.cardholder_name),
+                        number: optional(&card.number),
+                        exp_month: card.expiry_month.map(|month| month.to_string()),
+                        exp_year: card.expiry_year.map(|year| year.to_string()),
+                        code: optional(&card.security_code),
+                    });
+                }
+                ItemRecord::Identity(identity) => {
+                    cipher.kind = IDENTITY;
+                    cipher.notes = optional(&identity.notes);
+                    if !identity.date_of_birth.is_empty() {
+                        fields.push(BitwardenField { name: Some("Date of Birth".to_string()), value: Some(identity.date_of_birth.clone()), kind: FIELD_TEXT, linked_id: None });
+                    }
+                    cipher.identity = Some(BitwardenIdentity {
+                        title: optional(&identity.title),
+                        first_name: optional(&identity.first_name),
+                        middle_name: optional(&identity.middle_name),
+                        last_name: optional(&identity.last_name),
+                        address1: optional(&identity.address_line1),
+                        address2: optional(&identity.address_line2),
+                        address3: None,
+                        city: optional(&identity.city),
+                        state: optional(&identity.state),
+                        postal_code: optional(&identity.postal_code),
+                        country: optional(&identity.country),
+                        company: optional(&identity.company),
+                        email: optional(&identity.email),
+                        phone: optional(&identity.phone),
+                        ssn: optional(&identity.national_id),
+                        username: None,
+                        passport_number: optional(&identity.passport_number),
+                        license_number: optional(&identity.license_number),
+                    });
+                }
+                // bank accounts and licenses become secure notes with a field per value
+                record => {
+                    cipher.kind = SECURE_NOTE;
+                    cipher.secure_note = Some(BitwardenSecureNote::default());
+                    for (label, value) in record.labelled_fields() {
+                        if label == "Notes" {
+                            cipher.notes = Some(value);
+                        } else {
+                            let kind = if matches!(label, "PIN" | "Account Number" | "License Key") { FIELD_HIDDEN } else { FIELD_TEXT };
+                            fields.push(BitwardenField { name: Some(label.to_string()), value: Some(value), kind, linked_id: None });
+                        }
+                    }
+                }
+            }
+        } else if is_searchable_content_type(&item.item_type) {
+            cipher.kind = SECURE_NOTE;
+            cipher.secure_note = Some(BitwardenSecureNote::default());
+            if !item.data_path.is_empty() {
+                let content = storage.read_encrypted_file(&item.data_path, crypto)?;
+                cipher.notes = optional(&String::from_utf8_lossy(&content));
+            }
+        } else {
+            return Ok(None);
+        }
+    }
+    cipher.fields = fields;
+    Ok(Some(cipher))
+}
@@ -8,6 +8,7 @@ use argon2::{
 };
 use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use zeroize::Zeroize;
 
 use crate::error::Error;
@@ -98,6 +99,13 @@ impl Crypto {
         self.zeroize();
     }
 
+    /// A separate handle on the unlocked key, for long work that shouldn't hold the vault's
+    /// lock or stop when the vault is locked meanwhile.
+    pub fn try_clone(&self) -> Result<Self> {
+        let cipher = self.cipher.clone().ok_or(Error::VaultLocked)?;
+        Ok(Self { cipher: Some(cipher) })
+    }
+
     pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
         let cipher = self.cipher.as_ref().ok_or(Error::VaultLocked)?;
         
@@ -144,4 +152,13 @@ impl Crypto {
         OsRng.fill_bytes(&mut token);
         token
     }
-}
\ No newline at end of file
+}
+/// Lowercase hex of `bytes`, e.g. a digest or a signature.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Lowercase hex SHA-256 of `data`, as backup manifests, the sync log and S3 requests carry it.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
@@ -21,6 +21,9 @@ pub enum Error {
     #[error("CSV error: {0}")]
     Csv(String),
 
+    #[error("Network error: {0}")]
+    Network(String),
+
     #[error("Invalid key")]
     InvalidKey,
 
@@ -56,6 +59,9 @@ pub enum Error {
 
     #[error("Tauri error: {0}")]
     TauriError(String),
+
+    #[error("Operation cancelled")]
+    Cancelled,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -96,6 +102,12 @@ impl From<rusqlite::Error> for Error {
     }
 }
 
+impl From<r2d2::Error> for Error {
+    fn from(err: r2d2::Error) -> Self {
+        Error::Storage(format!("Connection pool error: {}", err))
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(err: serde_json::Error) -> Self {
         Error::Serialization(err.to_string())
@@ -118,4 +130,9 @@ impl From<csv::Error> for Error {
     fn from(err: csv::Error) -> Self {
         Error::Csv(err.to_string())
     }
-}
\ No newline at end of file
+}
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Network(err.to_string())
+    }
+}
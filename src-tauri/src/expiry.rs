@@ -0,0 +1,134 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::storage::{ItemSummary, VaultItem};
+use crate::Result;
+
+pub const DEFAULT_REMINDER_DAYS: u32 = 30;
+const MAX_REMINDER_DAYS: u32 = 3650;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ReminderSettings {
+    pub enabled: bool,
+    // how far ahead of an expiry the reminders start
+    pub days_before: u32,
+}
+
+impl Default for ReminderSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            days_before: DEFAULT_REMINDER_DAYS,
+        }
+    }
+}
+
+impl ReminderSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.days_before == 0 || self.days_before > MAX_REMINDER_DAYS {
+            return Err(Error::InvalidInput("Reminder days must be between 1 and 3650".into()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExpiringItem {
+    pub item: ItemSummary,
+    pub expires_at: DateTime<Utc>,
+    // negative once the item has expired
+    pub days_left: i64,
+    pub expired: bool,
+}
+
+/// Items that have expired or expire within `days` of `now`, soonest first. Deleted items are
+/// left out so the trash doesn't keep nagging.
+pub fn expiring_items(items: &[VaultItem], days: u32, now: DateTime<Utc>) -> Vec<ExpiringItem> {
+    let horizon = now + Duration::days(days as i64);
+    let mut expiring: Vec<ExpiringItem> = items
+        .iter()
+        .filter(|item| item.deleted_at.is_none())
+        .filter_map(|item| {
+            let expires_at = item.expires_at.filter(|expires_at| *expires_at <= horizon)?;
+            Some(ExpiringItem {
+                item: ItemSummary::from(item),
+                expires_at,
+                days_left: (expires_at.date_naive() - now.date_naive()).num_days(),
+                expired: expires_at <= now,
+            })
+        })
+        .collect();
+    expiring.sort_by_key(|expiring| expiring.expires_at);
+    expiring
+}
+
+/// Short human readable description used by reminders, e.g. "expires in 3 days".
+pub fn describe_expiry(expiring: &ExpiringItem) -> String {
+    match expiring.days_left {
+        _ if expiring.expired => "has expired".to_string(),
+        0 => "expires today".to_string(),
+        1 => "expires tomorrow".to_string(),
+        days => format!("expires in {} days", days),
+    }
+}
+
+/// iCalendar feed with one all-day event per expiry, each with an alarm `alarm_days` before it.
+/// Only names and dates go in here, never item contents.
+pub fn to_ics(items: &[ExpiringItem], alarm_days: u32, now: DateTime<Utc>) -> String {
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Fetch//Expiry Reminders//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    for expiring in items {
+        let day = expiring.expires_at.date_naive();
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}-expiry@fetch", expiring.item.id),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART;VALUE=DATE:{}", day.format("%Y%m%d")),
+            format!("DTEND;VALUE=DATE:{}", (day + Duration::days(1)).format("%Y%m%d")),
+            format!("SUMMARY:{}", escape_text(&format!("{} expires", expiring.item.name))),
+            "TRANSP:TRANSPARENT".to_string(),
+            "BEGIN:VALARM".to_string(),
+            "ACTION:DISPLAY".to_string(),
+            format!("DESCRIPTION:{}", escape_text(&format!("{} expires on {}", expiring.item.name, day))),
+            format!("TRIGGER:-P{}D", alarm_days),
+            "END:VALARM".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_line(line)).collect::<Vec<_>>().join("\r\n") + "\r\n"
+}
+
+// RFC 5545 3.3.11
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+// content lines are limited to 75 octets, longer ones continue on lines starting with a space
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / 74 * 3);
+    let mut width = 0;
+    for c in line.chars() {
+        let len = c.len_utf8();
+        if width + len > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += len;
+    }
+    folded
+}
@@ -0,0 +1,650 @@
+use base64::display::Base64Display;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::ser::{SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+use zeroize::Zeroize;
+use zip::write::{FileOptions, ZipWriter};
+
+use crate::crypto::Crypto;
+use crate::error::Error;
+use crate::fields::custom_fields_to_text;
+use crate::login::LOGIN_ITEM_TYPE;
+use crate::records::is_record_type;
+use crate::search::{search_items, SearchOptions};
+use crate::storage::{Storage, VaultItem};
+use crate::Result;
+
+// progress is reported at most this often, a vault of small items would flood the UI otherwise
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+// SQLite's side files; the archive gets a consistent copy of the database instead
+const DATABASE_SIDE_FILES: [&str; 3] = ["vault.db-wal", "vault.db-shm", "vault.db-journal"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    Txt,
+    Md,
+    Bitwarden,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "txt" => Ok(Self::Txt),
+            "md" => Ok(Self::Md),
+            "bitwarden" => Ok(Self::Bitwarden),
+            _ => Err(Error::InvalidInput("Unsupported export format".into())),
+        }
+    }
+}
+
+/// Which items an export covers: those matching any of the selectors, plus the folders they are
+/// in so the hierarchy survives. With no selectors it's the whole vault.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExportSelection {
+    // whole subtrees, the folders themselves included
+    #[serde(default, rename = "folderIds")]
+    pub folder_ids: Vec<String>,
+    // items carrying any of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // in the query_items syntax
+    #[serde(default)]
+    pub query: Option<String>,
+    // the search bar's fuzzy full-text search
+    #[serde(default)]
+    pub search: Option<String>,
+    #[serde(default, rename = "itemIds")]
+    pub item_ids: Vec<String>,
+    // items in the recycle bin are left out unless this is set
+    #[serde(default, rename = "includeDeleted")]
+    pub include_deleted: bool,
+}
+
+impl ExportSelection {
+    pub fn is_everything(&self) -> bool {
+        self.folder_ids.is_empty()
+            && self.tags.is_empty()
+            && self.query.as_deref().map_or(true, |query| query.trim().is_empty())
+            && self.search.as_deref().map_or(true, |search| search.trim().is_empty())
+            && self.item_ids.is_empty()
+    }
+}
+
+/// The items `selection` covers, each folder before its contents and siblings in the order the
+/// vault lists them.
+pub fn select_items(storage: &Storage, crypto: &Crypto, selection: &ExportSelection) -> Result<Vec<VaultItem>> {
+    let all = storage.get_all_items_recursive(crypto)?;
+    let by_id: HashMap<&str, &VaultItem> = all.iter().map(|item| (item.id.as_str(), item)).collect();
+    let included = |item: &VaultItem| selection.include_deleted || item.deleted_at.is_none();
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    for item in all.iter().filter(|item| included(item)) {
+        if let Some(parent) = item.parent_id.as_deref() {
+            children.entry(parent).or_default().push(&item.id);
+        }
+    }
+
+    let mut selected: HashSet<&str> = HashSet::new();
+    if selection.is_everything() {
+        selected.extend(all.iter().filter(|item| included(item)).map(|item| item.id.as_str()));
+    } else {
+        for id in selection.folder_ids.iter().chain(&selection.item_ids) {
+            if !by_id.contains_key(id.as_str()) {
+                return Err(Error::ItemNotFound(id.clone()));
+            }
+        }
+        // a folder's subtree, walked down from the folder
+        let mut pending: Vec<&str> = selection.folder_ids.iter().map(String::as_str).collect();
+        while let Some(id) = pending.pop() {
+            if selected.insert(id) {
+                pending.extend(children.get(id).into_iter().flatten());
+            }
+        }
+        selected.extend(selection.item_ids.iter().map(String::as_str));
+        if !selection.tags.is_empty() {
+            selected.extend(all.iter().filter(|item| item.tags.iter().any(|tag| selection.tags.contains(tag))).map(|item| item.id.as_str()));
+        }
+        if let Some(query) = selection.query.as_deref().filter(|query| !query.trim().is_empty()) {
+            let query = storage.parse_query(query)?;
+            let matched: HashSet<String> = query.filter(&all).into_iter().map(|item| item.id).collect();
+            selected.extend(all.iter().filter(|item| matched.contains(&item.id)).map(|item| item.id.as_str()));
+        }
+        if let Some(search) = selection.search.as_deref().filter(|search| !search.trim().is_empty()) {
+            let options = SearchOptions {
+                query: search.to_string(),
+                fuzzy: true,
+                include_content: true,
+                include_deleted: selection.include_deleted,
+                limit: Some(usize::MAX),
+            };
+            let hits: HashSet<String> = search_items(storage, crypto, &options)?.into_iter().map(|hit| hit.item.id).collect();
+            selected.extend(all.iter().filter(|item| hits.contains(&item.id)).map(|item| item.id.as_str()));
+        }
+        selected.retain(|id| by_id.get(id).is_some_and(|item| included(item)));
+
+        // the folders the selected items are in, so they keep their place in the tree
+        for id in selected.clone() {
+            let mut parent = by_id.get(id).and_then(|item| item.parent_id.as_deref());
+            while let Some(parent_id) = parent {
+                let Some(folder) = by_id.get(parent_id).filter(|folder| included(folder)) else {
+                    break;
+                };
+                if !selected.insert(parent_id) {
+                    break;
+                }
+                parent = folder.parent_id.as_deref();
+            }
+        }
+    }
+
+    // depth first from the items whose parent isn't exported
+    let mut ordered = Vec::with_capacity(selected.len());
+    let mut visited = HashSet::new();
+    let roots = all.iter().filter(|item| selected.contains(item.id.as_str()) && !item.parent_id.as_deref().is_some_and(|parent| selected.contains(parent)));
+    let mut stack: Vec<&str> = roots.map(|item| item.id.as_str()).collect();
+    stack.reverse();
+    while let Some(id) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        ordered.push(by_id[id].clone());
+        if let Some(kids) = children.get(id) {
+            stack.extend(kids.iter().rev().filter(|kid| selected.contains(*kid)));
+        }
+    }
+    Ok(ordered)
+}
+
+// Folder names from the top of the export down to each item's parent.
+pub(crate) struct FolderPaths<'a> {
+    by_id: HashMap<&'a str, &'a VaultItem>,
+}
+
+impl<'a> FolderPaths<'a> {
+    pub(crate) fn new(items: &'a [VaultItem]) -> Self {
+        Self { by_id: items.iter().map(|item| (item.id.as_str(), item)).collect() }
+    }
+
+    pub(crate) fn of(&self, item: &VaultItem) -> Vec<String> {
+        let mut path = Vec::new();
+        let mut parent = item.parent_id.as_deref();
+        while let Some(folder) = parent.and_then(|id| self.by_id.get(id)) {
+            // a parent cycle would loop forever otherwise
+            if path.len() >= self.by_id.len() {
+                break;
+            }
+            path.push(folder.name.clone());
+            parent = folder.parent_id.as_deref();
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExportProgress {
+    pub current: usize,
+    pub total: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExportSummary {
+    pub path: PathBuf,
+    // items for a decrypted export, files for an encrypted one
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Reports progress, throttled, and stops the export once `cancelled` is set.
+pub struct ExportControl<'a> {
+    cancelled: &'a AtomicBool,
+    on_progress: &'a mut dyn FnMut(ExportProgress),
+    last_report: Option<Instant>,
+}
+
+impl<'a> ExportControl<'a> {
+    pub fn new(cancelled: &'a AtomicBool, on_progress: &'a mut dyn FnMut(ExportProgress)) -> Self {
+        Self { cancelled, on_progress, last_report: None }
+    }
+
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn report(&mut self, current: usize, total: usize, message: &str) {
+        let due = self.last_report.map_or(true, |last| last.elapsed() >= PROGRESS_INTERVAL);
+        if due || current == total {
+            self.last_report = Some(Instant::now());
+            (self.on_progress)(ExportProgress { current, total, message: message.to_string() });
+        }
+    }
+}
+
+// The export is written next to its destination and renamed into place once complete, so a
+// cancelled or failed export never leaves a truncated file where the user expects one.
+struct PartialFile {
+    temp: PathBuf,
+    destination: PathBuf,
+    finished: bool,
+}
+
+impl PartialFile {
+    fn create(destination: &Path) -> Result<(Self, File)> {
+        let name = destination.file_name().and_then(|name| name.to_str()).ok_or_else(|| Error::InvalidInput("Export path has no file name".into()))?;
+        let temp = destination.with_file_name(format!(".{}.{}.part", name, &uuid::Uuid::new_v4().simple().to_string()[..8]));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        // Security: an export holds decrypted secrets, so like the vault database only the owner may read it
+        #[cfg(unix)]
+        options.mode(0o600);
+        let file = options.open(&temp)?;
+        Ok((Self { temp, destination: destination.to_path_buf(), finished: false }, file))
+    }
+
+    fn finish(mut self) -> Result<u64> {
+        fs::rename(&self.temp, &self.destination)?;
+        self.finished = true;
+        Ok(fs::metadata(&self.destination)?.len())
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(e) = fs::remove_file(&self.temp) {
+                warn!("Failed to remove unfinished export {}: {}", self.temp.display(), e);
+            }
+        }
+    }
+}
+
+/// Where an export may go: an absolute path to a file in an existing folder outside the vault.
+pub fn check_destination(storage: &Storage, destination: &Path) -> Result<()> {
+    if !destination.is_absolute() {
+        return Err(Error::InvalidInput("Export path must be absolute".into()));
+    }
+    if destination.is_dir() {
+        return Err(Error::InvalidInput(format!("{} is a folder", destination.display())));
+    }
+    if !destination.parent().is_some_and(Path::is_dir) {
+        return Err(Error::InvalidInput(format!("The folder for {} does not exist", destination.display())));
+    }
+    if destination.starts_with(storage.get_vault_path()) {
+        return Err(Error::InvalidInput("Exports can't be written into the vault folder".into()));
+    }
+    Ok(())
+}
+
+// Serializes straight from the decrypted bytes into the output, without a base64 copy in memory.
+struct Base64Content(Vec<u8>);
+
+impl Serialize for Base64Content {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&Base64Display::new(&self.0, &STANDARD))
+    }
+}
+
+#[derive(Serialize)]
+struct JsonAttachment {
+    name: String,
+    mime_type: String,
+    size: u64,
+    created_at: DateTime<Utc>,
+    content: Base64Content,
+}
+
+#[derive(Serialize)]
+struct JsonItem {
+    #[serde(flatten)]
+    item: serde_json::Value,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    folder_path: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<Base64Content>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<JsonAttachment>,
+}
+
+/// Writes `items` decrypted to `destination`, one item in memory at a time.
+pub fn export_decrypted(
+    storage: &Storage,
+    crypto: &Crypto,
+    items: &[VaultItem],
+    format: ExportFormat,
+    destination: &Path,
+    control: &mut ExportControl<'_>,
+) -> Result<ExportSummary> {
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    check_destination(storage, destination)?;
+    let (partial, file) = PartialFile::create(destination)?;
+    let mut writer = BufWriter::new(file);
+
+    let entries = match format {
+        ExportFormat::Json => write_json(storage, crypto, items, &mut writer, control)?,
+        ExportFormat::Csv => write_text(
+            storage,
+            crypto,
+            items,
+            &mut writer,
+            control,
+            b"Name,Type,Content,Tags,Custom Fields,Attachments,Created At,Updated At,Folder\n",
+            write_csv_item,
+        )?,
+        ExportFormat::Txt => write_text(storage, crypto, items, &mut writer, control, b"", write_txt_item)?,
+        ExportFormat::Md => write_text(storage, crypto, items, &mut writer, control, b"# Vault Export\n\n", write_md_item)?,
+        // files and images have no place in a Bitwarden export, so it can hold fewer items
+        ExportFormat::Bitwarden => crate::bitwarden::write_export(storage, crypto, items, &mut writer, control)?,
+    };
+
+    writer.flush()?;
+    writer.into_inner().map_err(|e| Error::from(e.into_error()))?.sync_all()?;
+    // the last check: a cancel that came in while the file was being flushed still wins
+    control.check()?;
+    let bytes = partial.finish()?;
+    info!("Exported {} items to {}", entries, destination.display());
+    Ok(ExportSummary { path: destination.to_path_buf(), entries, bytes })
+}
+
+fn write_json(storage: &Storage, crypto: &Crypto, items: &[VaultItem], writer: &mut impl Write, control: &mut ExportControl<'_>) -> Result<usize> {
+    let mut serializer = serde_json::Serializer::pretty(writer);
+    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+    let folders = FolderPaths::new(items);
+    for (index, item) in items.iter().enumerate() {
+        control.check()?;
+        let mut value = serde_json::to_value(item)?;
+        let mut content = None;
+        if item.item_type == LOGIN_ITEM_TYPE {
+            value["login"] = serde_json::to_value(storage.read_login(item, crypto)?)?;
+        } else if is_record_type(&item.item_type) {
+            value["record"] = serde_json::to_value(storage.read_record(item, crypto)?)?["data"].take();
+        } else if !item.data_path.is_empty() {
+            content = Some(Base64Content(storage.read_encrypted_file(&item.data_path, crypto)?));
+        }
+
+        let mut attachments = Vec::new();
+        for attachment in storage.list_attachments(&item.id, crypto)? {
+            let content = Base64Content(storage.read_attachment(&attachment, crypto)?);
+            attachments.push(JsonAttachment {
+                name: attachment.name,
+                mime_type: attachment.mime_type,
+                size: attachment.size,
+                created_at: attachment.created_at,
+                content,
+            });
+        }
+        seq.serialize_element(&JsonItem { item: value, folder_path: folders.of(item), content, attachments })?;
+        control.report(index + 1, items.len(), &item.name);
+    }
+    seq.end()?;
+    Ok(items.len())
+}
+
+fn write_text<W: Write>(
+    storage: &Storage,
+    crypto: &Crypto,
+    items: &[VaultItem],
+    writer: &mut W,
+    control: &mut ExportControl<'_>,
+    header: &[u8],
+    write_item: fn(&Storage, &Crypto, &VaultItem, &str, &mut W) -> Result<()>,
+) -> Result<usize> {
+    writer.write_all(header)?;
+    let folders = FolderPaths::new(items);
+    for (index, item) in items.iter().enumerate() {
+        control.check()?;
+        write_item(storage, crypto, item, &folders.of(item).join(" / "), writer)?;
+        control.report(index + 1, items.len(), &item.name);
+    }
+    Ok(items.len())
+}
+
+/// "name (size)" for each attachment; text exports list attachments but can't embed binaries.
+fn attachment_names(storage: &Storage, item: &VaultItem, crypto: &Crypto) -> Result<Vec<String>> {
+    Ok(storage
+        .list_attachments(&item.id, crypto)?
+        .iter()
+        .map(|attachment| format!("{} ({} bytes)", attachment.name, attachment.size))
+        .collect())
+}
+
+fn export_text_content(storage: &Storage, item: &VaultItem, crypto: &Crypto) -> Result<String> {
+    if item.item_type == LOGIN_ITEM_TYPE {
+        return Ok(storage.read_login(item, crypto)?.to_text());
+    }
+    if is_record_type(&item.item_type) {
+        return Ok(storage.read_record(item, crypto)?.to_text());
+    }
+    if item.data_path.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(String::from_utf8_lossy(&storage.read_encrypted_file(&item.data_path, crypto)?).to_string())
+}
+
+fn write_csv_item(storage: &Storage, crypto: &Crypto, item: &VaultItem, folder: &str, writer: &mut impl Write) -> Result<()> {
+    let content = export_text_content(storage, item, crypto)?;
+    let tags = item.tags.join(";");
+    let custom_fields = custom_fields_to_text(&item.custom_fields);
+    let attachments = attachment_names(storage, item, crypto)?.join(";");
+    writeln!(
+        writer,
+        "\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"",
+        item.name.replace('"', "\"\""),
+        item.item_type.replace('"', "\"\""),
+        content.replace('"', "\"\""),
+        tags.replace('"', "\"\""),
+        custom_fields.replace('"', "\"\""),
+        attachments.replace('"', "\"\""),
+        item.created_at,
+        item.updated_at,
+        folder.replace('"', "\"\"")
+    )?;
+    Ok(())
+}
+
+fn write_txt_item(storage: &Storage, crypto: &Crypto, item: &VaultItem, folder: &str, writer: &mut impl Write) -> Result<()> {
+    writeln!(writer, "=== {} ===", item.name)?;
+    writeln!(writer, "Type: {}", item.item_type)?;
+    if !folder.is_empty() {
+        writeln!(writer, "Folder: {}", folder)?;
+    }
+    if !item.tags.is_empty() {
+        writeln!(writer, "Tags: {}", item.tags.join(", "))?;
+    }
+    writeln!(writer, "Created: {}", item.created_at)?;
+    writeln!(writer, "Updated: {}", item.updated_at)?;
+    if let Some(expires_at) = item.expires_at {
+        writeln!(writer, "Expires: {}", expires_at)?;
+    }
+    if !item.custom_fields.is_empty() {
+        writeln!(writer, "\nFields:\n{}", custom_fields_to_text(&item.custom_fields))?;
+    }
+    let attachments = attachment_names(storage, item, crypto)?;
+    if !attachments.is_empty() {
+        writeln!(writer, "Attachments: {}", attachments.join(", "))?;
+    }
+    if !item.data_path.is_empty() {
+        write!(writer, "\nContent:\n{}", export_text_content(storage, item, crypto)?)?;
+    }
+    writer.write_all(b"\n\n")?;
+    Ok(())
+}
+
+fn write_md_item(storage: &Storage, crypto: &Crypto, item: &VaultItem, folder: &str, writer: &mut impl Write) -> Result<()> {
+    write!(writer, "## {}\n\n", item.name)?;
+    write!(writer, "**Type:** {}\n\n", item.item_type)?;
+    if !folder.is_empty() {
+        write!(writer, "**Folder:** {}\n\n", folder)?;
+    }
+    if !item.tags.is_empty() {
+        let tags: Vec<String> = item.tags.iter().map(|tag| format!("`{}`", tag)).collect();
+        write!(writer, "**Tags:** {}\n\n", tags.join(", "))?;
+    }
+    write!(writer, "**Created:** {}\n\n", item.created_at)?;
+    write!(writer, "**Updated:** {}\n\n", item.updated_at)?;
+    if let Some(expires_at) = item.expires_at {
+        write!(writer, "**Expires:** {}\n\n", expires_at)?;
+    }
+
+    if !item.custom_fields.is_empty() {
+        writer.write_all(b"### Fields\n\n")?;
+        for field in &item.custom_fields {
+            writeln!(writer, "- **{}** ({}): `{}`", field.label, field.kind.as_str(), field.value)?;
+        }
+        writer.write_all(b"\n")?;
+    }
+
+    let attachments = attachment_names(storage, item, crypto)?;
+    if !attachments.is_empty() {
+        writer.write_all(b"### Attachments\n\n")?;
+        for attachment in &attachments {
+            writeln!(writer, "- {}", attachment)?;
+        }
+        writer.write_all(b"\n")?;
+    }
+
+    if !item.data_path.is_empty() {
+        write!(writer, "### Content\n\n```\n{}\n```\n\n", export_text_content(storage, item, crypto)?)?;
+    }
+    writer.write_all(b"---\n\n")?;
+    Ok(())
+}
+
+/// Zips the vault folder as it is on disk, files encrypted as they are, into `destination`.
+/// Files are copied through one at a time and the database goes in as a consistent copy.
+pub fn export_encrypted(storage: &Storage, destination: &Path, control: &mut ExportControl<'_>) -> Result<ExportSummary> {
+    check_destination(storage, destination)?;
+    let vault_path = storage.get_vault_path();
+    let mut entries = Vec::new();
+    for entry in WalkDir::new(vault_path).min_depth(1) {
+        let entry = entry.map_err(|e| Error::Io(e.to_string()))?;
+        let relative = entry.path().strip_prefix(vault_path).map_err(|e| Error::Internal(e.to_string()))?;
+        let Some(name) = zip_name(relative) else {
+            warn!("Skipping {} in the export, its name isn't valid UTF-8", entry.path().display());
+            continue;
+        };
+        if DATABASE_SIDE_FILES.contains(&name.as_str()) {
+            continue;
+        }
+        entries.push((name, entry.path().to_path_buf(), entry.file_type().is_dir()));
+    }
+
+    let database_copy = std::env::temp_dir().join(format!("fetch-export-{}.db", uuid::Uuid::new_v4()));
+    let written = write_vault_archive(storage, &entries, &database_copy, destination, control);
+    if let Err(e) = fs::remove_file(&database_copy) {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("Failed to remove temporary database copy {}: {}", database_copy.display(), e);
+        }
+    }
+    let bytes = written?;
+    let files = entries.iter().filter(|(_, _, is_dir)| !is_dir).count();
+    info!("Exported encrypted vault ({} files) to {}", files, destination.display());
+    Ok(ExportSummary { path: destination.to_path_buf(), entries: files, bytes })
+}
+
+/// Archives only `items`: they are copied into a vault of their own in a temporary folder,
+/// under the same master key, and that vault is zipped. Links come along when both ends do.
+pub fn export_encrypted_selection(storage: &Storage, crypto: &Crypto, items: &[VaultItem], destination: &Path, control: &mut ExportControl<'_>) -> Result<ExportSummary> {
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    check_destination(storage, destination)?;
+    let temp_dir = std::env::temp_dir().join(format!("fetch-export-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&temp_dir)?;
+    let exported = Storage::new(temp_dir.clone()).and_then(|partial| {
+        copy_selection(storage, crypto, items, &partial, control)?;
+        export_encrypted(&partial, destination, control)
+    });
+    if let Err(e) = fs::remove_dir_all(&temp_dir) {
+        warn!("Failed to remove temporary export vault {}: {}", temp_dir.display(), e);
+    }
+    let mut summary = exported?;
+    summary.entries = items.len();
+    Ok(summary)
+}
+
+fn copy_selection(storage: &Storage, crypto: &Crypto, items: &[VaultItem], partial: &Storage, control: &mut ExportControl<'_>) -> Result<()> {
+    partial.initialize(&storage.get_salt()?, storage.get_key_derivation_strength()?)?;
+    partial.store_verification_token(&storage.get_verification_token()?)?;
+    for (index, item) in items.iter().enumerate() {
+        control.check()?;
+        if !item.data_path.is_empty() {
+            let mut content = storage.read_encrypted_file(&item.data_path, crypto)?;
+            let encrypted = crypto.encrypt(&content);
+            content.zeroize();
+            partial.write_encrypted_file(&encrypted?, &item.data_path)?;
+        }
+        partial.add_item(item, crypto)?;
+        for attachment in storage.list_attachments(&item.id, crypto)? {
+            let mut content = storage.read_attachment(&attachment, crypto)?;
+            let added = partial.add_attachment(&item.id, &attachment.name, &attachment.mime_type, &content, crypto);
+            content.zeroize();
+            added?;
+        }
+        control.report(index + 1, items.len(), &item.name);
+    }
+
+    let ids: HashSet<&str> = items.iter().map(|item| item.id.as_str()).collect();
+    for link in storage.list_all_links(crypto)? {
+        if ids.contains(link.source_id.as_str()) && ids.contains(link.target_id.as_str()) {
+            partial.add_link(&link.source_id, &link.target_id, link.label.as_deref(), crypto)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_vault_archive(
+    storage: &Storage,
+    entries: &[(String, PathBuf, bool)],
+    database_copy: &Path,
+    destination: &Path,
+    control: &mut ExportControl<'_>,
+) -> Result<u64> {
+    let (partial, file) = PartialFile::create(destination)?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored).large_file(true);
+    for (index, (name, path, is_dir)) in entries.iter().enumerate() {
+        control.check()?;
+        if *is_dir {
+            zip.add_directory(name.as_str(), options)?;
+        } else if name == "vault.db" {
+            zip.start_file(name.as_str(), options)?;
+            storage.snapshot_database(database_copy)?;
+            io::copy(&mut File::open(database_copy)?, &mut zip)?;
+        } else {
+            zip.start_file(name.as_str(), options)?;
+            io::copy(&mut File::open(path)?, &mut zip)?;
+        }
+        control.report(index + 1, entries.len(), name);
+    }
+    let mut writer = zip.finish()?;
+    writer.flush()?;
+    writer.into_inner().map_err(|e| Error::from(e.into_error()))?.sync_all()?;
+    control.check()?;
+    partial.finish()
+}
+
+// zip entry names always use '/', whatever the platform
+fn zip_name(relative: &Path) -> Option<String> {
+    let parts: Option<Vec<&str>> = relative.components().map(|component| component.as_os_str().to_str()).collect();
+    parts.map(|parts| parts.join("/"))
+}
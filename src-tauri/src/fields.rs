@@ -0,0 +1,215 @@
+use crate::error::Error;
+use crate::links::FieldReference;
+use crate::Result;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+const MAX_FIELDS: usize = 50;
+const MAX_LABEL_LENGTH: usize = 100;
+const MAX_VALUE_LENGTH: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomFieldKind {
+    Text,
+    Hidden,
+    Url,
+    Email,
+    Date,
+    Totp,
+}
+
+impl CustomFieldKind {
+    /// Hidden and one-time-password values are secrets and get masked by the UI.
+    pub fn is_concealed(self) -> bool {
+        matches!(self, CustomFieldKind::Hidden | CustomFieldKind::Totp)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CustomFieldKind::Text => "text",
+            CustomFieldKind::Hidden => "hidden",
+            CustomFieldKind::Url => "url",
+            CustomFieldKind::Email => "email",
+            CustomFieldKind::Date => "date",
+            CustomFieldKind::Totp => "totp",
+        }
+    }
+}
+
+/// User-defined field on an item. The whole list is stored as one encrypted JSON column.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CustomField {
+    pub label: String,
+    #[serde(rename = "type")]
+    pub kind: CustomFieldKind,
+    #[serde(default)]
+    pub value: String,
+}
+
+impl CustomField {
+    pub fn validate(&self) -> Result<()> {
+        let label = self.label.trim();
+        if label.is_empty() {
+            return Err(Error::InvalidInput("Custom field label cannot be empty".into()));
+        }
+        if label.len() > MAX_LABEL_LENGTH {
+            return Err(Error::InvalidInput("Custom field label too long (max 100 characters)".into()));
+        }
+        if self.label.contains('\0') || self.label.contains('\n') || self.label.contains('\r') {
+            return Err(Error::InvalidInput("Custom field label contains invalid characters".into()));
+        }
+        if self.value.len() > MAX_VALUE_LENGTH {
+            return Err(Error::InvalidInput(format!("Value of '{}' too long (max 64KB)", label)));
+        }
+
+        // empty values are allowed so a field can be added before it is filled in, and references
+        // are checked when they are resolved rather than against this field's kind
+        let value = self.value.trim();
+        if value.is_empty() || FieldReference::parse(value).is_some() {
+            return Ok(());
+        }
+        match self.kind {
+            CustomFieldKind::Text | CustomFieldKind::Hidden => {}
+            CustomFieldKind::Url => {
+                let has_scheme = value
+                    .split_once("://")
+                    .is_some_and(|(scheme, rest)| !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.') && !rest.is_empty());
+                if !has_scheme || value.chars().any(char::is_whitespace) {
+                    return Err(Error::InvalidInput(format!("'{}' is not a valid URL", label)));
+                }
+            }
+            CustomFieldKind::Email => {
+                let valid = value
+                    .split_once('@')
+                    .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.') && !domain.contains('@'));
+                if !valid || value.chars().any(char::is_whitespace) {
+                    return Err(Error::InvalidInput(format!("'{}' is not a valid email address", label)));
+                }
+            }
+            CustomFieldKind::Date => {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .map_err(|_| Error::InvalidInput(format!("'{}' must be a date in YYYY-MM-DD format", label)))?;
+            }
+            CustomFieldKind::Totp => {
+                // same encoding as the item-level totp_secret so generate_totp can use it as is
+                STANDARD
+                    .decode(value)
+                    .map_err(|_| Error::InvalidInput(format!("'{}' is not a valid TOTP secret", label)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_custom_fields(fields: &[CustomField]) -> Result<()> {
+    if fields.len() > MAX_FIELDS {
+        return Err(Error::InvalidInput("Too many custom fields (max 50)".into()));
+    }
+    fields.iter().try_for_each(CustomField::validate)
+}
+
+/// Trims labels and values; applied before anything is stored.
+pub fn normalize_custom_fields(fields: Vec<CustomField>) -> Vec<CustomField> {
+    fields
+        .into_iter()
+        .map(|field| CustomField {
+            label: field.label.trim().to_string(),
+            kind: field.kind,
+            value: if field.kind == CustomFieldKind::Text || field.kind == CustomFieldKind::Hidden {
+                field.value
+            } else {
+                field.value.trim().to_string()
+            },
+        })
+        .collect()
+}
+
+/// One "label: value" line per field, used by the text-based exports.
+pub fn custom_fields_to_text(fields: &[CustomField]) -> String {
+    fields
+        .iter()
+        .map(|field| format!("{} ({}): {}", field.label, field.kind.as_str(), field.value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn zeroize_custom_fields(fields: &mut [CustomField]) {
+    for field in fields {
+        field.label.zeroize();
+        field.value.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(kind: CustomFieldKind, value: &str) -> CustomField {
+        CustomField { label: "Field".into(), kind, value: value.into() }
+    }
+
+    #[test]
+    fn values_are_checked_against_their_kind() {
+        let valid = [
+            (CustomFieldKind::Text, "anything at all"),
+            (CustomFieldKind::Hidden, "  s3cret  "),
+            (CustomFieldKind::Url, "https://example.com/login"),
+            (CustomFieldKind::Url, "ssh+git://host"),
+            (CustomFieldKind::Email, "ops@example.com"),
+            (CustomFieldKind::Date, "2024-02-29"),
+            (CustomFieldKind::Totp, "SGVsbG8h"),
+        ];
+        for (kind, value) in valid {
+            assert!(field(kind, value).validate().is_ok(), "{:?} {:?}", kind, value);
+        }
+
+        let invalid = [
+            (CustomFieldKind::Url, "example.com"),
+            (CustomFieldKind::Url, "https://exa mple.com"),
+            (CustomFieldKind::Email, "ops"),
+            (CustomFieldKind::Email, "ops@example."),
+            (CustomFieldKind::Email, "ops@@example.com"),
+            (CustomFieldKind::Date, "2023-02-29"),
+            (CustomFieldKind::Date, "29.02.2024"),
+            (CustomFieldKind::Totp, "not base64!"),
+        ];
+        for (kind, value) in invalid {
+            assert!(field(kind, value).validate().is_err(), "{:?} {:?}", kind, value);
+        }
+    }
+
+    #[test]
+    fn empty_values_and_references_skip_the_kind_check() {
+        for kind in [CustomFieldKind::Url, CustomFieldKind::Email, CustomFieldKind::Date, CustomFieldKind::Totp] {
+            assert!(field(kind, "").validate().is_ok());
+            assert!(field(kind, "{ref:other-item:password}").validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn labels_and_limits() {
+        let labelled = |label: &str| CustomField { label: label.into(), ..field(CustomFieldKind::Text, "") };
+        assert!(labelled("  ").validate().is_err());
+        assert!(labelled("two\nlines").validate().is_err());
+        assert!(labelled(&"x".repeat(MAX_LABEL_LENGTH + 1)).validate().is_err());
+        assert!(field(CustomFieldKind::Text, &"x".repeat(MAX_VALUE_LENGTH + 1)).validate().is_err());
+
+        let fields = vec![field(CustomFieldKind::Text, "value"); MAX_FIELDS];
+        assert!(validate_custom_fields(&fields).is_ok());
+        assert!(validate_custom_fields(&[fields, vec![field(CustomFieldKind::Text, "one more")]].concat()).is_err());
+    }
+
+    #[test]
+    fn normalizing_keeps_free_text_as_typed() {
+        let normalized = normalize_custom_fields(vec![
+            CustomField { label: " PIN ".into(), kind: CustomFieldKind::Hidden, value: " 1234 ".into() },
+            CustomField { label: "Site".into(), kind: CustomFieldKind::Url, value: " https://example.com ".into() },
+        ]);
+        assert_eq!((normalized[0].label.as_str(), normalized[0].value.as_str()), ("PIN", " 1234 "));
+        assert_eq!(normalized[1].value, "https://example.com");
+        assert_eq!(custom_fields_to_text(&normalized), "PIN (hidden):  1234 \nSite (url): https://example.com");
+    }
+}
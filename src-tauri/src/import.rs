@@ -1,94 +1,161 @@
-use log::{info, error};
-use crate::error::Error;
-use crate::storage::VaultItem;
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::fs::File;
-use std::io::BufReader;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
 
-pub type Result<T> = std::result::Result<T, Error>;
+use crate::crypto::Crypto;
+use crate::login::LoginData;
+use crate::records::ItemRecord;
+use crate::storage::{Storage, VaultItem};
+use crate::Result;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ImportResult {
-    pub success_count: usize,
-    pub error_count: usize,
-    pub errors: Vec<String>,
+// what the importers share: folder creation, storing items, tag clean-up and TOTP secrets
+
+pub(crate) const MAX_NAME_LENGTH: usize = 255;
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LENGTH: usize = 50;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// The vault's folders by parent and name, so an import adds to a folder that's already there
+/// instead of making a second one with the same name.
+pub(crate) struct FolderTree<'a> {
+    storage: &'a Storage,
+    crypto: &'a Crypto,
+    by_name: HashMap<(Option<String>, String), String>,
+    pub(crate) created: usize,
 }
 
-#[derive(Debug)]
-pub struct ImportedItem {
-    pub vault_item: VaultItem,
-    pub password_data: Option<String>,
+impl<'a> FolderTree<'a> {
+    pub(crate) fn new(storage: &'a Storage, crypto: &'a Crypto) -> Result<Self> {
+        let by_name = storage
+            .get_all_items_recursive(crypto)?
+            .into_iter()
+            .filter(|item| item.item_type == "folder" && item.deleted_at.is_none())
+            .map(|item| ((item.parent_id, item.name.to_lowercase()), item.id))
+            .collect();
+        Ok(Self { storage, crypto, by_name, created: 0 })
+    }
+
+    /// The id of the folder `name` in `parent`, which is created if needed.
+    pub(crate) fn child(&mut self, parent: Option<&str>, name: &str) -> Result<String> {
+        let name: String = name.trim().chars().filter(|c| *c != '\\' && *c != '\0').take(MAX_NAME_LENGTH).collect();
+        let key = (parent.map(str::to_string), name.to_lowercase());
+        if let Some(id) = self.by_name.get(&key) {
+            return Ok(id.clone());
+        }
+        let now = Utc::now();
+        let folder = VaultItem {
+            id: Uuid::new_v4().to_string(),
+            parent_id: key.0.clone(),
+            name,
+            data_path: String::new(),
+            item_type: "folder".to_string(),
+            folder_type: None,
+            tags: vec![],
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            totp_secret: None,
+            custom_fields: vec![],
+            expires_at: None,
+        };
+        self.storage.add_item(&folder, self.crypto)?;
+        self.created += 1;
+        self.by_name.insert(key, folder.id.clone());
+        Ok(folder.id)
+    }
 }
 
-pub struct Importer;
+// What an imported item is stored as.
+pub(crate) enum Payload {
+    Login(LoginData),
+    Record(Box<ItemRecord>),
+    Note(String),
+}
 
-impl Importer {
-    pub fn import_csv(file_path: &str) -> Result<(Vec<ImportedItem>, ImportResult)> {
-        info!("Attempting to open CSV file: {}", file_path);
-        
-        // convert the path to a pathbuf and normalize it
-        let path = PathBuf::from(file_path);
-        info!("File path exists: {}", path.exists());
-        info!("File path is absolute: {}", path.is_absolute());
-        info!("File path components: {:?}", path.components().collect::<Vec<_>>());
-        
-        // get the canonical path (resolves any .. or . in the path)
-        let canonical_path = path.canonicalize()?;
-        info!("Canonical path: {}", canonical_path.display());
-        
-        // open and read the file
-        let file = File::open(&canonical_path)?;
-        let reader = BufReader::new(file);
-        
-        let mut imported_items = Vec::new();
-        let mut success_count = 0;
-        let mut error_count = 0;
-        let mut errors = Vec::new();
-        
-        // read the csv records
-        let mut csv_reader = csv::Reader::from_reader(reader);
-        
-        
-        for result in csv_reader.records() {
-            match result {
-                Ok(record) => {
-                    match parse_csv_record(&record) {
-                        Ok(item) => {
-                            imported_items.push(item);
-                            success_count += 1;
-                        }
-                        Err(e) => {
-                            error!("Failed to parse CSV record: {}", e);
-                            errors.push(format!("Failed to parse record: {}", e));
-                            error_count += 1;
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to read CSV record: {}", e);
-                    errors.push(format!("Failed to read record: {}", e));
-                    error_count += 1;
+pub(crate) fn store_item(storage: &Storage, crypto: &Crypto, item: &mut VaultItem, payload: Payload) -> Result<()> {
+    match payload {
+        Payload::Login(login) => storage.write_login(&item.data_path, &login, crypto)?,
+        Payload::Record(record) => {
+            item.expires_at = record.expires_at();
+            storage.write_record(&item.data_path, &record, crypto)?;
+        }
+        Payload::Note(notes) => storage.write_encrypted_file(&crypto.encrypt(notes.as_bytes())?, &item.data_path)?,
+    }
+    storage.add_item(item, crypto)
+}
+
+/// Drops tags the vault wouldn't accept and repeats, and keeps at most the first 20.
+pub(crate) fn clean_tags(mut tags: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.retain(|tag| !tag.is_empty() && tag.len() <= MAX_TAG_LENGTH && !tag.contains(['\0', '\n', '\r']) && seen.insert(tag.to_lowercase()));
+    tags.truncate(MAX_TAGS);
+    tags
+}
+
+/// Fetch keeps TOTP secrets as base64 of the raw key; other managers have the base32 text or a
+/// whole otpauth URI. URIs asking for anything but the usual 6 digits, 30 seconds and SHA1 give `None`.
+pub(crate) fn totp_secret_from_text(value: &str) -> Option<String> {
+    let value = value.trim();
+    let secret = match value.strip_prefix("otpauth://") {
+        Some(uri) => {
+            let (_, query) = uri.split_once('?')?;
+            let mut secret = None;
+            for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+                match (key.to_ascii_lowercase().as_str(), value.to_ascii_uppercase().as_str()) {
+                    ("secret", _) => secret = Some(value.replace("%3D", "=").replace("%3d", "=")),
+                    ("digits", "6") | ("period", "30") | ("algorithm", "SHA1") => {}
+                    ("digits" | "period" | "algorithm", _) => return None,
+                    _ => {}
                 }
             }
+            secret?
         }
-        
-        let content = std::fs::read_to_string(&canonical_path)?;
-        info!("Successfully read CSV file, content length: {}", content.len());
-        
-        let import_result = ImportResult {
-            success_count,
-            error_count,
-            errors,
-        };
-        
-        info!("CSV import completed: {} successful, {} errors", success_count, error_count);
-        
-        Ok((imported_items, import_result))
+        None if value.contains("://") => return None,
+        None => value.to_string(),
+    };
+    base32_decode(&secret).filter(|key| !key.is_empty()).map(|key| STANDARD.encode(key))
+}
+
+/// The base32 text other managers expect for a stored secret.
+pub(crate) fn totp_secret_to_base32(secret: &str) -> String {
+    match STANDARD.decode(secret.trim()) {
+        Ok(key) => base32_encode(&key),
+        Err(_) => secret.to_string(),
     }
 }
 
-/*fn parse_csv_record(record: &csv::StringRecord) -> Result<ImportedItem> {
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in text.chars().filter(|c| !c.is_whitespace() && *c != '-' && *c != '=') {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c.to_ascii_uppercase() as u8)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
 
-    todo!("Implement CSV record parsing")
-}*/
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            text.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        text.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    text
+}
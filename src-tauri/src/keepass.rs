@@ -0,0 +1,828 @@
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecryptMut, BlockEncrypt, KeyInit, KeyIvInit, StreamCipher};
+use aes::Aes256;
+use argon2::{Algorithm, Argon2, AssociatedData, ParamsBuilder, Version};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20::ChaCha20;
+use chrono::{DateTime, TimeZone, Utc};
+use flate2::read::GzDecoder;
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::io::Read;
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+use crate::crypto::Crypto;
+use crate::error::Error;
+use crate::fields::{normalize_custom_fields, validate_custom_fields, CustomField, CustomFieldKind};
+use crate::import::{clean_tags, store_item, totp_secret_from_text, FolderTree, Payload, MAX_NAME_LENGTH};
+use crate::login::{LoginData, LOGIN_ITEM_TYPE};
+use crate::storage::{Storage, VaultItem};
+use crate::Result;
+
+const SIGNATURE: [u32; 2] = [0x9AA2_D903, 0xB54B_FB67];
+
+const CIPHER_AES256: [u8; 16] = [0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff];
+const CIPHER_CHACHA20: [u8; 16] = [0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a];
+const CIPHER_TWOFISH: [u8; 16] = [0xad, 0x68, 0xf2, 0x9f, 0x57, 0x6f, 0x4b, 0xb9, 0xa3, 0x6a, 0xd4, 0x7a, 0xf9, 0x65, 0x34, 0x6c];
+const KDF_AES: [u8; 16] = [0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea];
+const KDF_ARGON2D: [u8; 16] = [0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c];
+const KDF_ARGON2ID: [u8; 16] = [0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6];
+
+// outer header fields
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER: u8 = 2;
+const HEADER_COMPRESSION: u8 = 3;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_IV: u8 = 7;
+const HEADER_KDF: u8 = 11;
+
+// inner header fields, at the start of the decrypted payload
+const INNER_END: u8 = 0;
+const INNER_STREAM_ID: u8 = 1;
+const INNER_STREAM_KEY: u8 = 2;
+const INNER_BINARY: u8 = 3;
+
+// protected values are XORed with this stream; KDBX 4 writers all use ChaCha20
+const STREAM_NONE: u32 = 0;
+const STREAM_CHACHA20: u32 = 3;
+
+// KDBX 4 counts time in seconds from 0001-01-01
+const SECONDS_TO_UNIX_EPOCH: i64 = 62_135_596_800;
+// a file asking Argon2 for more than this is refused rather than risk running out of memory
+const MAX_KDF_MEMORY: u64 = 2 * 1024 * 1024 * 1024;
+// KeePassXC tunes both to about a second on the machine that saved the file; far above that
+// is a file that would keep the import busy for hours
+const MAX_AES_KDF_ROUNDS: u64 = 1_000_000_000;
+const MAX_ARGON2_ITERATIONS: u64 = 1_000;
+
+/// What an import did; entries that couldn't be brought over are listed with the reason.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct KeePassImportReport {
+    pub folders: usize,
+    pub items: usize,
+    pub attachments: usize,
+    pub skipped: Vec<String>,
+}
+
+/// A decrypted KDBX 4 database: its group tree and the attachments the entries refer to.
+pub struct KeePassDatabase {
+    root: Group,
+    recycle_bin: Option<String>,
+    binaries: Vec<Vec<u8>>,
+}
+
+impl Drop for KeePassDatabase {
+    fn drop(&mut self) {
+        for binary in &mut self.binaries {
+            binary.zeroize();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Group {
+    uuid: String,
+    name: String,
+    groups: Vec<Group>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Default)]
+struct Entry {
+    uuid: String,
+    // key, value, whether KeePass protects it in memory
+    strings: Vec<(String, String, bool)>,
+    // file name and index into the database's binaries
+    attachments: Vec<(String, usize)>,
+    tags: String,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    expires: bool,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl Entry {
+    fn string(&self, key: &str) -> Option<&str> {
+        self.strings.iter().find(|(name, _, _)| name == key).map(|(_, value, _)| value.as_str())
+    }
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        for (_, value, _) in &mut self.strings {
+            value.zeroize();
+        }
+    }
+}
+
+fn corrupt(detail: &str) -> Error {
+    Error::InvalidInput(format!("Not a valid KeePass database: {}", detail))
+}
+
+// Little-endian reads that fail instead of panicking on a short file.
+struct Bytes<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.data.len()).ok_or_else(|| corrupt("the file is cut short"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+}
+
+struct OuterHeader {
+    cipher: Vec<u8>,
+    compressed: bool,
+    master_seed: Vec<u8>,
+    iv: Vec<u8>,
+    kdf: HashMap<String, Vec<u8>>,
+}
+
+fn read_outer_header(bytes: &mut Bytes) -> Result<OuterHeader> {
+    let mut header = OuterHeader { cipher: vec![], compressed: false, master_seed: vec![], iv: vec![], kdf: HashMap::new() };
+    loop {
+        let id = bytes.u8()?;
+        let size = bytes.u32()? as usize;
+        let data = bytes.take(size)?;
+        match id {
+            HEADER_END => break,
+            HEADER_CIPHER => header.cipher = data.to_vec(),
+            HEADER_COMPRESSION => header.compressed = data.first().is_some_and(|flag| *flag != 0),
+            HEADER_MASTER_SEED => header.master_seed = data.to_vec(),
+            HEADER_IV => header.iv = data.to_vec(),
+            HEADER_KDF => header.kdf = read_variant_dictionary(data)?,
+            _ => {}
+        }
+    }
+    if header.master_seed.len() != 32 || header.kdf.is_empty() {
+        return Err(corrupt("the header is incomplete"));
+    }
+    Ok(header)
+}
+
+// KeePass's typed key/value list; only the raw bytes are kept, the reader knows what to expect
+fn read_variant_dictionary(data: &[u8]) -> Result<HashMap<String, Vec<u8>>> {
+    let mut bytes = Bytes::new(data);
+    if bytes.u16()? >> 8 != 1 {
+        return Err(corrupt("unknown key derivation parameters"));
+    }
+    let mut values = HashMap::new();
+    loop {
+        let kind = bytes.u8()?;
+        if kind == 0 {
+            break;
+        }
+        let name_len = bytes.u32()? as usize;
+        let name = String::from_utf8_lossy(bytes.take(name_len)?).into_owned();
+        let value_len = bytes.u32()? as usize;
+        values.insert(name, bytes.take(value_len)?.to_vec());
+    }
+    Ok(values)
+}
+
+fn uint_parameter(kdf: &HashMap<String, Vec<u8>>, name: &str) -> Result<u64> {
+    match kdf.get(name).map(Vec::as_slice) {
+        Some(&[a, b, c, d]) => Ok(u32::from_le_bytes([a, b, c, d]) as u64),
+        Some(&[a, b, c, d, e, f, g, h]) => Ok(u64::from_le_bytes([a, b, c, d, e, f, g, h])),
+        _ => Err(corrupt(&format!("key derivation parameter {} is missing", name))),
+    }
+}
+
+// The cipher key and the base of the per-block HMAC keys, both derived from the master key.
+struct Keys {
+    cipher: [u8; 32],
+    hmac: [u8; 64],
+}
+
+impl Keys {
+    fn derive(header: &OuterHeader, password: &str, key_file: Option<&[u8]>) -> Result<Self> {
+        let mut composite = composite_key(password, key_file)?;
+        let transformed = transform_key(&composite, &header.kdf);
+        composite.zeroize();
+        let mut transformed = transformed?;
+        let mut seeded = header.master_seed.clone();
+        seeded.extend_from_slice(&transformed);
+        transformed.zeroize();
+        let cipher = Sha256::digest(&seeded).into();
+        seeded.push(1);
+        let hmac = Sha512::digest(&seeded).into();
+        seeded.zeroize();
+        Ok(Self { cipher, hmac })
+    }
+
+    // the header's is block u64::MAX
+    fn block_mac(&self, index: u64) -> Result<Hmac<Sha256>> {
+        let mut key: [u8; 64] = Sha512::new().chain_update(index.to_le_bytes()).chain_update(self.hmac).finalize().into();
+        let mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).map_err(|e| Error::Crypto(e.to_string()));
+        key.zeroize();
+        mac
+    }
+}
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        self.cipher.zeroize();
+        self.hmac.zeroize();
+    }
+}
+
+fn composite_key(password: &str, key_file: Option<&[u8]>) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    // a database can be locked by a key file alone
+    if !password.is_empty() || key_file.is_none() {
+        hasher.update(Sha256::digest(password.as_bytes()));
+    }
+    if let Some(key_file) = key_file {
+        let mut key = key_file_key(key_file)?;
+        hasher.update(&key);
+        key.zeroize();
+    }
+    Ok(hasher.finalize().into())
+}
+
+// KeePass key files: XML holding the key (base64 in version 1, hex in version 2), 32 raw bytes,
+// 64 hex digits, or any other file, which is hashed.
+fn key_file_key(data: &[u8]) -> Result<Vec<u8>> {
+    let text = std::str::from_utf8(data).map(str::trim).unwrap_or_default();
+    if text.starts_with("<?xml") || text.starts_with("<KeyFile") {
+        if let Some(key) = xml_key_file_key(text)? {
+            return Ok(key);
+        }
+    }
+    if data.len() == 32 {
+        return Ok(data.to_vec());
+    }
+    if let Some(key) = (text.len() == 64).then(|| hex_decode(text)).flatten() {
+        return Ok(key);
+    }
+    Ok(Sha256::digest(data).to_vec())
+}
+
+fn xml_key_file_key(xml: &str) -> Result<Option<Vec<u8>>> {
+    let mut reader = Reader::from_str(xml);
+    let (mut element, mut version, mut data) = (Vec::new(), String::new(), None);
+    loop {
+        match reader.read_event().map_err(|e| Error::InvalidInput(format!("Invalid key file: {}", e)))? {
+            Event::Start(tag) => element = tag.local_name().as_ref().to_vec(),
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| Error::InvalidInput(format!("Invalid key file: {}", e)))?;
+                match element.as_slice() {
+                    b"Version" => version = text.trim().to_string(),
+                    b"Data" => data = Some(text.trim().to_string()),
+                    _ => {}
+                }
+            }
+            Event::End(_) => element.clear(),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    let Some(mut data) = data else {
+        return Ok(None);
+    };
+    let key = if version.starts_with("2.") {
+        hex_decode(&data.split_whitespace().collect::<String>())
+    } else {
+        STANDARD.decode(&data).ok()
+    };
+    data.zeroize();
+    key.map(Some).ok_or_else(|| Error::InvalidInput("Invalid key file: the key isn't readable".into()))
+}
+
+fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| text.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok())).collect()
+}
+
+fn transform_key(composite: &[u8; 32], kdf: &HashMap<String, Vec<u8>>) -> Result<Vec<u8>> {
+    let uuid = kdf.get("$UUID").map(Vec::as_slice).unwrap_or_default();
+    let salt = kdf.get("S").ok_or_else(|| corrupt("the key derivation has no salt"))?;
+    if uuid == KDF_AES {
+        let rounds = uint_parameter(kdf, "R")?;
+        if rounds > MAX_AES_KDF_ROUNDS {
+            return Err(Error::InvalidInput(format!("The database needs {} key derivation rounds to unlock, more than the import allows", rounds)));
+        }
+        return Ok(aes_kdf(composite, salt, rounds)?.to_vec());
+    }
+    let algorithm = if uuid == KDF_ARGON2D {
+        Algorithm::Argon2d
+    } else if uuid == KDF_ARGON2ID {
+        Algorithm::Argon2id
+    } else {
+        return Err(Error::InvalidInput("The database uses an unknown key derivation".into()));
+    };
+    let memory = uint_parameter(kdf, "M")?;
+    if memory > MAX_KDF_MEMORY {
+        return Err(Error::InvalidInput(format!("The database needs {} MB to unlock, more than the import allows", memory / (1024 * 1024))));
+    }
+    let iterations = uint_parameter(kdf, "I")?;
+    if iterations > MAX_ARGON2_ITERATIONS {
+        return Err(Error::InvalidInput(format!("The database needs {} Argon2 iterations to unlock, more than the import allows", iterations)));
+    }
+    let version = match kdf.get("V").map(|_| uint_parameter(kdf, "V")).transpose()?.unwrap_or(0x13) {
+        0x10 => Version::V0x10,
+        0x13 => Version::V0x13,
+        _ => return Err(corrupt("unknown Argon2 version")),
+    };
+    let to_u32 = |value: u64| u32::try_from(value).map_err(|_| corrupt("Argon2 parameter out of range"));
+    let mut params = ParamsBuilder::new();
+    params
+        .m_cost(to_u32(memory / 1024)?)
+        .t_cost(to_u32(iterations)?)
+        .p_cost(to_u32(uint_parameter(kdf, "P")?)?)
+        .output_len(32);
+    if let Some(data) = kdf.get("A").filter(|data| !data.is_empty()) {
+        params.data(AssociatedData::new(data).map_err(|e| Error::KeyDerivation(e.to_string()))?);
+    }
+    let params = params.build().map_err(|e| Error::KeyDerivation(e.to_string()))?;
+    let secret = kdf.get("K").map(Vec::as_slice).unwrap_or_default();
+    let argon2 = Argon2::new_with_secret(secret, algorithm, version, params).map_err(|e| Error::KeyDerivation(e.to_string()))?;
+    let mut key = vec![0u8; 32];
+    argon2.hash_password_into(composite, salt, &mut key).map_err(|e| Error::KeyDerivation(e.to_string()))?;
+    Ok(key)
+}
+
+// KDBX 3's key derivation, still allowed in version 4: the key encrypted with itself `rounds` times
+fn aes_kdf(composite: &[u8; 32], seed: &[u8], rounds: u64) -> Result<[u8; 32]> {
+    let cipher = Aes256::new_from_slice(seed).map_err(|_| corrupt("the AES key derivation seed is invalid"))?;
+    let mut key = *composite;
+    for _ in 0..rounds {
+        for half in key.chunks_exact_mut(16) {
+            cipher.encrypt_block(GenericArray::from_mut_slice(half));
+        }
+    }
+    let transformed = Sha256::digest(key).into();
+    key.zeroize();
+    Ok(transformed)
+}
+
+impl KeePassDatabase {
+    /// Decrypts and reads a KDBX 4 file. A wrong password or key file is `Error::InvalidKey`.
+    pub fn open(data: &[u8], password: &str, key_file: Option<&[u8]>) -> Result<Self> {
+        let mut bytes = Bytes::new(data);
+        if bytes.u32()? != SIGNATURE[0] || bytes.u32()? != SIGNATURE[1] {
+            return Err(Error::InvalidInput("Not a KeePass database".into()));
+        }
+        let major = bytes.u32()? >> 16;
+        if major != 4 {
+            return Err(Error::InvalidInput(format!("Only KDBX 4 databases can be imported, this one is version {}; saving it in a current KeePass or KeePassXC upgrades it", major)));
+        }
+        let header = read_outer_header(&mut bytes)?;
+        let header_bytes = &data[..bytes.position];
+        if Sha256::digest(header_bytes).as_slice() != bytes.take(32)? {
+            return Err(corrupt("the header is damaged"));
+        }
+        let header_mac = bytes.take(32)?;
+
+        let keys = Keys::derive(&header, password, key_file)?;
+        let mut mac = keys.block_mac(u64::MAX)?;
+        mac.update(header_bytes);
+        mac.verify_slice(header_mac).map_err(|_| Error::InvalidKey)?;
+
+        let mut payload = read_blocks(&mut bytes, &keys)?;
+        decrypt_payload(&header, &keys, &mut payload)?;
+        if header.compressed {
+            let mut inflated = Vec::new();
+            let read = GzDecoder::new(payload.as_slice()).read_to_end(&mut inflated);
+            payload.zeroize();
+            if read.is_err() {
+                inflated.zeroize();
+                return Err(corrupt("the compressed data is damaged"));
+            }
+            payload = inflated;
+        }
+        let database = read_payload(&payload);
+        payload.zeroize();
+        database
+    }
+}
+
+// The payload comes in blocks, each with its own HMAC so tampering or truncation shows.
+fn read_blocks(bytes: &mut Bytes, keys: &Keys) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    for index in 0u64.. {
+        let stored = bytes.take(32)?;
+        let size = bytes.take(4)?;
+        let block = bytes.take(u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize)?;
+        let mut mac = keys.block_mac(index)?;
+        mac.update(&index.to_le_bytes());
+        mac.update(size);
+        mac.update(block);
+        mac.verify_slice(stored).map_err(|_| corrupt("a block failed its integrity check"))?;
+        if block.is_empty() {
+            break;
+        }
+        payload.extend_from_slice(block);
+    }
+    Ok(payload)
+}
+
+fn decrypt_payload(header: &OuterHeader, keys: &Keys, payload: &mut Vec<u8>) -> Result<()> {
+    if header.cipher == CIPHER_CHACHA20 {
+        let mut cipher = ChaCha20::new_from_slices(&keys.cipher, &header.iv).map_err(|_| corrupt("the ChaCha20 nonce has the wrong length"))?;
+        cipher.apply_keystream(payload);
+        return Ok(());
+    }
+    if header.cipher == CIPHER_TWOFISH {
+        return Err(Error::InvalidInput("Twofish-encrypted databases can't be imported; switch the database to AES or ChaCha20 in KeePass first".into()));
+    }
+    if header.cipher != CIPHER_AES256 {
+        return Err(Error::InvalidInput("The database uses an unknown cipher".into()));
+    }
+
+    // AES-256 in CBC mode with PKCS#7 padding
+    let cipher = cbc::Decryptor::<Aes256>::new_from_slices(&keys.cipher, &header.iv).map_err(|_| corrupt("the AES IV has the wrong length"))?;
+    if payload.is_empty() || payload.len() % 16 != 0 {
+        return Err(corrupt("the encrypted data has the wrong length"));
+    }
+    let len = cipher.decrypt_padded_mut::<Pkcs7>(payload).map_err(|_| corrupt("the encrypted data is damaged"))?.len();
+    payload.truncate(len);
+    Ok(())
+}
+
+// The inner header (the stream for protected values and the attachments), then the XML.
+fn read_payload(payload: &[u8]) -> Result<KeePassDatabase> {
+    let mut bytes = Bytes::new(payload);
+    let (mut stream_id, mut stream_key, mut binaries) = (STREAM_NONE, Vec::new(), Vec::new());
+    loop {
+        let id = bytes.u8()?;
+        let size = bytes.u32()? as usize;
+        let data = bytes.take(size)?;
+        match id {
+            INNER_END => break,
+            INNER_STREAM_ID => stream_id = Bytes::new(data).u32()?,
+            INNER_STREAM_KEY => stream_key = data.to_vec(),
+            // the first byte is a flag for KeePass's in-memory protection
+            INNER_BINARY => binaries.push(data.get(1..).unwrap_or_default().to_vec()),
+            _ => {}
+        }
+    }
+    let stream = match stream_id {
+        STREAM_NONE => None,
+        STREAM_CHACHA20 => {
+            // the key and nonce are the first 44 bytes of the key's SHA-512
+            let mut hash = Sha512::digest(&stream_key);
+            stream_key.zeroize();
+            let stream = ChaCha20::new(GenericArray::from_slice(&hash[..32]), GenericArray::from_slice(&hash[32..44]));
+            hash.zeroize();
+            Some(stream)
+        }
+        _ => return Err(Error::InvalidInput("The database protects its values with an unsupported stream cipher".into())),
+    };
+
+    let xml = std::str::from_utf8(bytes.rest()).map_err(|_| corrupt("the XML isn't UTF-8"))?;
+    let mut reader = XmlReader { stream, ..Default::default() };
+    reader.read(xml)?;
+    let root = reader.root.ok_or_else(|| corrupt("there is no root group"))?;
+    Ok(KeePassDatabase { root, recycle_bin: reader.recycle_bin, binaries })
+}
+
+// Walks the XML once, in order: protected values have to be decrypted in the order they appear,
+// those in entry history included, or every value after them comes out wrong.
+#[derive(Default)]
+struct XmlReader {
+    stream: Option<ChaCha20>,
+    path: Vec<Vec<u8>>,
+    groups: Vec<Group>,
+    // the entry being read, then any of its history versions
+    entries: Vec<Entry>,
+    root: Option<Group>,
+    recycle_bin: Option<String>,
+    text: String,
+    protected: bool,
+    reference: Option<usize>,
+    key: String,
+    value: String,
+    history: usize,
+}
+
+impl XmlReader {
+    fn read(&mut self, xml: &str) -> Result<()> {
+        let mut reader = Reader::from_str(xml);
+        loop {
+            match reader.read_event().map_err(|e| corrupt(&e.to_string()))? {
+                Event::Start(tag) => self.start(&tag),
+                Event::Empty(tag) => {
+                    self.start(&tag);
+                    self.end()?;
+                }
+                Event::Text(text) => self.text.push_str(&text.unescape().map_err(|e| corrupt(&e.to_string()))?),
+                Event::CData(text) => self.text.push_str(&String::from_utf8_lossy(&text)),
+                Event::End(_) => self.end()?,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        if self.history > 0 {
+            info!("Left out {} history versions of KeePass entries", self.history);
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, tag: &BytesStart) {
+        let name = tag.local_name().as_ref().to_vec();
+        match name.as_slice() {
+            b"Group" => self.groups.push(Group::default()),
+            b"Entry" => self.entries.push(Entry::default()),
+            b"String" | b"Binary" => {
+                self.key.clear();
+                self.protected = false;
+                self.value.zeroize();
+                self.value.clear();
+                self.reference = None;
+            }
+            b"Value" => {
+                let attribute = |name: &[u8]| tag.try_get_attribute(name).ok().flatten().and_then(|value| value.unescape_value().ok().map(|value| value.into_owned()));
+                self.protected = attribute(b"Protected").is_some_and(|value| value.eq_ignore_ascii_case("true"));
+                self.reference = attribute(b"Ref").and_then(|value| value.parse().ok());
+            }
+            _ => {}
+        }
+        self.text.zeroize();
+        self.text.clear();
+        self.path.push(name);
+    }
+
+    fn end(&mut self) -> Result<()> {
+        let Some(name) = self.path.pop() else {
+            return Ok(());
+        };
+        let mut text = std::mem::take(&mut self.text);
+        let parent = self.path.last().cloned();
+        let grandparent = self.path.len().checked_sub(2).map(|i| self.path[i].clone());
+        match (grandparent.as_deref(), parent.as_deref(), name.as_slice()) {
+            (_, _, b"Group") => {
+                let group = self.groups.pop().unwrap_or_default();
+                match self.groups.last_mut() {
+                    Some(parent) => parent.groups.push(group),
+                    None => self.root = Some(group),
+                }
+            }
+            (_, _, b"Entry") => {
+                let entry = self.entries.pop().unwrap_or_default();
+                if !self.entries.is_empty() {
+                    // Fetch keeps no item history, the entry's older versions are read past
+                    self.history += 1;
+                } else if let Some(group) = self.groups.last_mut() {
+                    group.entries.push(entry);
+                }
+            }
+            (_, Some(b"Meta"), b"RecycleBinUUID") => self.recycle_bin = Some(text.trim().to_string()),
+            (_, Some(b"Group"), b"UUID") => self.set_group(|group| group.uuid = text.trim().to_string()),
+            (_, Some(b"Group"), b"Name") => self.set_group(|group| group.name = std::mem::take(&mut text)),
+            (_, Some(b"Entry"), b"UUID") => self.set_entry(|entry| entry.uuid = text.trim().to_string()),
+            (_, Some(b"Entry"), b"Tags") => self.set_entry(|entry| entry.tags = std::mem::take(&mut text)),
+            (Some(b"Entry"), Some(b"Times"), field) => {
+                let time = parse_time(&text);
+                match field {
+                    b"CreationTime" => self.set_entry(|entry| entry.created_at = time),
+                    b"LastModificationTime" => self.set_entry(|entry| entry.updated_at = time),
+                    b"ExpiryTime" => self.set_entry(|entry| entry.expires_at = time),
+                    b"Expires" => self.set_entry(|entry| entry.expires = text.trim().eq_ignore_ascii_case("true")),
+                    _ => {}
+                }
+            }
+            (_, Some(b"String" | b"Binary"), b"Key") => self.key = std::mem::take(&mut text),
+            (_, Some(b"String"), b"Value") => self.value = self.unprotect(std::mem::take(&mut text))?,
+            (_, Some(b"Entry"), b"String") => {
+                let string = (std::mem::take(&mut self.key), std::mem::take(&mut self.value), self.protected);
+                self.set_entry(|entry| entry.strings.push(string));
+            }
+            (_, Some(b"Entry"), b"Binary") => {
+                if let Some(reference) = self.reference.take() {
+                    let attachment = (std::mem::take(&mut self.key), reference);
+                    self.set_entry(|entry| entry.attachments.push(attachment));
+                }
+            }
+            _ => {}
+        }
+        text.zeroize();
+        Ok(())
+    }
+
+    fn set_group(&mut self, update: impl FnOnce(&mut Group)) {
+        if let Some(group) = self.groups.last_mut() {
+            update(group);
+        }
+    }
+
+    fn set_entry(&mut self, update: impl FnOnce(&mut Entry)) {
+        if let Some(entry) = self.entries.last_mut() {
+            update(entry);
+        }
+    }
+
+    fn unprotect(&mut self, mut text: String) -> Result<String> {
+        if !self.protected {
+            return Ok(text);
+        }
+        let decoded = STANDARD.decode(text.trim());
+        text.zeroize();
+        let mut bytes = decoded.map_err(|_| corrupt("a protected value isn't base64"))?;
+        if let Some(stream) = self.stream.as_mut() {
+            stream.apply_keystream(&mut bytes);
+        }
+        String::from_utf8(bytes).map_err(|e| {
+            e.into_bytes().zeroize();
+            corrupt("a protected value isn't text")
+        })
+    }
+}
+
+// KDBX 4 writes times as base64 of the seconds since 0001-01-01, older files as ISO 8601
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(bytes) = STANDARD.decode(text) {
+        let seconds = i64::from_le_bytes(bytes.try_into().ok()?);
+        return Utc.timestamp_opt(seconds.checked_sub(SECONDS_TO_UNIX_EPOCH)?, 0).single();
+    }
+    DateTime::parse_from_rfc3339(text).ok().map(|time| time.with_timezone(&Utc))
+}
+
+/// Imports a decrypted database under `parent_id`: its root group becomes a folder there, each
+/// group a folder inside it, each entry a login (or a note when it holds only notes), and each
+/// attachment a file item next to its entry and linked from it. KeePass's recycle bin is left out.
+/// An import that fails part way is rolled back, like a merge.
+pub fn import_keepass(storage: &Storage, crypto: &Crypto, database: &KeePassDatabase, parent_id: Option<&str>) -> Result<KeePassImportReport> {
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    let report = storage.with_rollback(|written| import_groups(storage, crypto, database, parent_id, written))?;
+    info!(
+        "Imported {} KeePass entries with {} attachments into {} new folders, skipped {}",
+        report.items,
+        report.attachments,
+        report.folders,
+        report.skipped.len()
+    );
+    Ok(report)
+}
+
+// notes every data file it writes in `written`
+fn import_groups(storage: &Storage, crypto: &Crypto, database: &KeePassDatabase, parent_id: Option<&str>, written: &mut Vec<String>) -> Result<KeePassImportReport> {
+    let mut report = KeePassImportReport::default();
+    let mut folders = FolderTree::new(storage, crypto)?;
+    let mut pending = vec![(&database.root, parent_id.map(str::to_string))];
+    while let Some((group, parent)) = pending.pop() {
+        if database.recycle_bin.as_deref() == Some(group.uuid.as_str()) {
+            info!("Leaving KeePass's recycle bin out of the import");
+            continue;
+        }
+        let name = Some(group.name.trim()).filter(|name| !name.is_empty()).unwrap_or("Untitled");
+        let folder = folders.child(parent.as_deref(), name)?;
+        for entry in &group.entries {
+            import_entry(storage, crypto, database, entry, &folder, &mut report, written)?;
+        }
+        pending.extend(group.groups.iter().rev().map(|child| (child, Some(folder.clone()))));
+    }
+    report.folders = folders.created;
+    Ok(report)
+}
+
+fn import_entry(
+    storage: &Storage,
+    crypto: &Crypto,
+    database: &KeePassDatabase,
+    entry: &Entry,
+    folder: &str,
+    report: &mut KeePassImportReport,
+    written: &mut Vec<String>,
+) -> Result<()> {
+    let name = entry.string("Title").map(str::trim).filter(|title| !title.is_empty()).unwrap_or("Untitled").to_string();
+    let (mut item, payload) = match convert_entry(entry, &name) {
+        Ok(converted) => converted,
+        Err(reason) => {
+            report.skipped.push(format!("{}: {}", name, reason));
+            return Ok(());
+        }
+    };
+    item.parent_id = Some(folder.to_string());
+    written.push(item.data_path.clone());
+    store_item(storage, crypto, &mut item, payload)?;
+    report.items += 1;
+
+    for (file_name, index) in &entry.attachments {
+        let Some(content) = database.binaries.get(*index) else {
+            warn!("KeePass entry {} refers to attachment {} that isn't in the file", entry.uuid, index);
+            report.skipped.push(format!("{}: attachment {} is missing from the database", name, file_name));
+            continue;
+        };
+        let file_name: String = file_name.trim().chars().take(MAX_NAME_LENGTH).collect();
+        let file_name = if file_name.is_empty() { "Attachment".to_string() } else { file_name };
+        let file = VaultItem {
+            id: Uuid::new_v4().to_string(),
+            parent_id: item.parent_id.clone(),
+            item_type: mime_guess::from_path(&file_name).first_or_octet_stream().to_string(),
+            name: file_name,
+            data_path: Uuid::new_v4().to_string(),
+            folder_type: None,
+            tags: vec![],
+            created_at: item.created_at,
+            updated_at: item.updated_at,
+            deleted_at: None,
+            totp_secret: None,
+            custom_fields: vec![],
+            expires_at: None,
+        };
+        written.push(file.data_path.clone());
+        storage.write_encrypted_file(&crypto.encrypt(content)?, &file.data_path)?;
+        storage.add_item(&file, crypto)?;
+        storage.add_link(&item.id, &file.id, Some("Attachment"), crypto)?;
+        report.attachments += 1;
+    }
+    Ok(())
+}
+
+fn convert_entry(entry: &Entry, name: &str) -> std::result::Result<(VaultItem, Payload), String> {
+    if name.len() > MAX_NAME_LENGTH {
+        return Err("title too long (max 255 characters)".into());
+    }
+    let mut login = LoginData::default();
+    let mut notes = String::new();
+    let mut totp_secret = None;
+    let mut custom_fields = Vec::new();
+    for (key, value, protected) in &entry.strings {
+        match key.as_str() {
+            "Title" => {}
+            "UserName" => login.username = value.clone(),
+            "Password" => login.password = value.clone(),
+            "URL" => login.urls.insert(0, value.clone()),
+            "Notes" => notes = value.clone(),
+            // KeePassXC keeps TOTP in "otp" as an otpauth URI, KeePass 2.47+ in TimeOtp-* fields
+            "otp" | "TimeOtp-Secret-Base32" | "TOTP Seed" if totp_secret.is_none() && !value.trim().is_empty() => match totp_secret_from_text(value) {
+                Some(secret) => totp_secret = Some(secret),
+                None => custom_fields.push(CustomField { label: key.clone(), kind: CustomFieldKind::Hidden, value: value.clone() }),
+            },
+            // extra URLs as KeePassXC and Keepass2Android store them
+            key if key.starts_with("KP2A_URL") => login.urls.push(value.clone()),
+            _ => {
+                let kind = if *protected { CustomFieldKind::Hidden } else { CustomFieldKind::Text };
+                custom_fields.push(CustomField { label: key.clone(), kind, value: value.clone() });
+            }
+        }
+    }
+
+    let mut login = login.normalized();
+    let (item_type, payload) = if login.username.is_empty() && login.password.is_empty() && login.urls.is_empty() && totp_secret.is_none() {
+        ("text/plain", Payload::Note(notes))
+    } else {
+        login.notes = notes;
+        login.validate().map_err(|e| e.to_string())?;
+        (LOGIN_ITEM_TYPE, Payload::Login(login))
+    };
+    let custom_fields = normalize_custom_fields(custom_fields);
+    validate_custom_fields(&custom_fields).map_err(|e| e.to_string())?;
+
+    let tags = entry.tags.split([';', ',']).map(|tag| tag.trim().to_string()).collect();
+    let created_at = entry.created_at.unwrap_or_else(Utc::now);
+    let item = VaultItem {
+        id: Uuid::new_v4().to_string(),
+        parent_id: None,
+        name: name.to_string(),
+        data_path: Uuid::new_v4().to_string(),
+        item_type: item_type.to_string(),
+        folder_type: None,
+        tags: clean_tags(tags),
+        created_at,
+        updated_at: entry.updated_at.unwrap_or(created_at),
+        deleted_at: None,
+        totp_secret,
+        custom_fields,
+        expires_at: entry.expires.then_some(entry.expires_at).flatten(),
+    };
+    Ok((item, payload))
+}
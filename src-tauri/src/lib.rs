@@ -1,6 +1,24 @@
+pub mod backup;
+pub mod bitwarden;
 pub mod crypto;
+pub mod db;
 pub mod error;
+pub mod expiry;
+pub mod export;
+pub mod fields;
+pub mod import;
+pub mod keepass;
+pub mod links;
+pub mod login;
+pub mod merge;
+pub mod query;
+pub mod records;
+pub mod registry;
+pub mod s3;
+pub mod search;
 pub mod storage;
+pub mod sync;
+pub mod webdav;
 
 use error::Error;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,79 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::ItemSummary;
+
+// references nest (a field pointing at a field pointing at ...), this bounds the chain and breaks cycles
+pub const MAX_REFERENCE_DEPTH: usize = 4;
+
+/// Explicit, directed link between two items, e.g. an API key pointing at the account it belongs to.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ItemLink {
+    pub id: String,
+    pub source_id: String,
+    pub target_id: String,
+    pub label: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One end of a link as seen from the other item. `item` is `None` once the other side has been
+/// permanently deleted; a soft-deleted item shows up with `deleted: true` so restoring it
+/// reconnects the link.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkedItem {
+    pub link: ItemLink,
+    pub item: Option<ItemSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ItemLinks {
+    pub outgoing: Vec<LinkedItem>,
+    pub backlinks: Vec<LinkedItem>,
+    // items whose custom fields hold a `{ref:...}` to this item
+    pub referenced_by: Vec<ItemSummary>,
+}
+
+/// A custom field value of the form `{ref:<item id>:<field>}`. The field is `name`, `totp`,
+/// `content`, a login field (`username`, `password`, `url`, `notes`), a typed item field such as
+/// `number` or `iban`, or `field:<label>` for another custom field.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FieldReference {
+    pub item_id: String,
+    pub field: String,
+}
+
+impl FieldReference {
+    pub fn parse(value: &str) -> Option<Self> {
+        let inner = value.trim().strip_prefix("{ref:")?.strip_suffix('}')?;
+        let (item_id, field) = inner.split_once(':')?;
+        let (item_id, field) = (item_id.trim(), field.trim());
+        if item_id.is_empty() || field.is_empty() {
+            return None;
+        }
+        Some(Self { item_id: item_id.to_string(), field: field.to_string() })
+    }
+
+    pub fn to_value(&self) -> String {
+        format!("{{ref:{}:{}}}", self.item_id, self.field)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceStatus {
+    Resolved,
+    // the target is in the trash; restoring it makes the reference work again
+    TargetDeleted,
+    MissingItem,
+    MissingField,
+    TooDeep,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResolvedReference {
+    // label of the custom field holding the reference
+    pub label: String,
+    pub reference: FieldReference,
+    pub status: ReferenceStatus,
+    pub value: Option<String>,
+}
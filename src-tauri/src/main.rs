@@ -1,25 +1,55 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::path::Path;
-use std::sync::Mutex;
-use tauri::{AppHandle, Manager, State, Wry};
-use walkdir::WalkDir;
-use zip::write::{FileOptions, ZipWriter};
-use chrono::Utc;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State, Wry};
+use tauri_plugin_notification::NotificationExt;
+use chrono::{DateTime, NaiveDate, Utc};
 use log::{error, info, warn, debug, trace};
 use uuid::Uuid;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use csv::ReaderBuilder;
+use zeroize::{Zeroize, Zeroizing};
 
 use fetch::crypto::{Crypto, KeyDerivationStrength};
 use fetch::error::{Error, Result};
-use fetch::storage::{Storage, VaultItem, SortOrder};
+use fetch::storage::{Attachment, Storage, VaultItem, SortOrder, BulkAction, BulkOperationResult, ItemPage, ItemSummary, SMART_FOLDER_TYPE};
+use fetch::search::{SearchHit, SearchOptions};
+use fetch::query::Query;
+use fetch::login::{LoginData, LOGIN_ITEM_TYPE};
+use fetch::bitwarden::BitwardenImportReport;
+use fetch::keepass::{KeePassDatabase, KeePassImportReport};
+use fetch::backup::{BackupSchedule, BackupSummary, LocalBackup, StagedRestore};
+use fetch::merge::{MergeReport, MergeSource, MergeStrategy};
+use fetch::sync::{sync_folder, SyncReport, SyncSettings};
+use fetch::webdav::{TransferReport, WebDavClient, WebDavConfig, WEBDAV_CONFIG_KEY};
+use fetch::s3::{ObjectLock, S3BackupReport, S3BucketStatus, S3Client, S3Config, S3RestoreReport, S3Snapshot, S3_CONFIG_KEY};
+use fetch::records::{is_record_type, ItemRecord};
+use fetch::registry::{is_vault_dir, VaultEntry, VaultRegistry, DEFAULT_VAULT_NAME};
+use fetch::export::{ExportControl, ExportFormat, ExportProgress, ExportSelection, ExportSummary};
+use fetch::expiry::{describe_expiry, to_ics, ExpiringItem, ReminderSettings};
+use fetch::links::{ItemLink, ItemLinks, ResolvedReference};
+use fetch::fields::{normalize_custom_fields, validate_custom_fields, CustomField};
 
 use chrono::{Duration as ChronoDuration};
 
+// how often the background task looks for expiring items while the app is running
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// more than this many due reminders are collapsed into a single notification
+const MAX_EXPIRY_NOTIFICATIONS: usize = 3;
+const SYNC_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// how often the background task checks whether a scheduled S3 backup is due
+const S3_BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// how often the background task checks whether a local backup is due, short enough that
+// "every N writes" doesn't lag far behind
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LockoutStatus {
     pub is_locked_out: bool,
@@ -100,9 +130,240 @@ impl PersistentRateLimiter {
     }
 }
 
+/// One open vault. Each has its own key, so several can be unlocked at the same time.
 pub struct VaultState {
-    storage: Mutex<Storage>,
-    crypto: Mutex<Crypto>,
+    // Storage does its own locking, reads run concurrently with a write
+    storage: Storage,
+    crypto: RwLock<Crypto>,
+    // item id -> day a reminder was last shown, so each expiry nags at most once a day
+    reminded_expiries: Mutex<HashMap<String, NaiveDate>>,
+    // the timer and a manual sync must not write to the change log at the same time
+    sync_lock: Mutex<()>,
+    // the storage write count when the last local backup was taken
+    writes_at_last_backup: AtomicU64,
+}
+
+impl VaultState {
+    fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            storage: Storage::new(path.to_path_buf())?,
+            crypto: RwLock::new(Crypto::new()),
+            reminded_expiries: Mutex::new(HashMap::new()),
+            sync_lock: Mutex::new(()),
+            writes_at_last_backup: AtomicU64::new(0),
+        })
+    }
+
+    fn close(&self) {
+        self.crypto.write().unwrap().lock();
+        self.storage.clear_metadata_cache();
+    }
+}
+
+/// The vault registry plus every vault opened this session. Commands act on the active vault;
+/// switching leaves the others open (and unlocked, if they were).
+pub struct AppState {
+    registry: Mutex<VaultRegistry>,
+    open_vaults: RwLock<HashMap<String, Arc<VaultState>>>,
+    active_vault: RwLock<Option<String>>,
+    // running exports by id, so cancel_export can reach them
+    exports: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl AppState {
+    fn active_vault(&self) -> Result<Arc<VaultState>> {
+        let id = self.active_vault.read().unwrap().clone().ok_or_else(|| Error::Internal("No vault is open".into()))?;
+        self.open_vaults.read().unwrap().get(&id).cloned().ok_or_else(|| Error::Internal("No vault is open".into()))
+    }
+
+    fn open_vaults(&self) -> Vec<Arc<VaultState>> {
+        self.open_vaults.read().unwrap().values().cloned().collect()
+    }
+
+    fn active_vault_id(&self) -> Result<String> {
+        self.active_vault.read().unwrap().clone().ok_or_else(|| Error::Internal("No vault is open".into()))
+    }
+
+    /// Syncs an open vault with its sync folder. Returns `None` if it has none or is locked.
+    fn sync_vault(&self, id: &str, vault: &VaultState) -> Result<Option<SyncReport>> {
+        let Some(sync) = self.registry.lock().unwrap().get(id).and_then(|entry| entry.sync.clone()) else {
+            return Ok(None);
+        };
+        let _guard = vault.sync_lock.lock().unwrap();
+        let crypto = vault.crypto.read().unwrap();
+        if !crypto.is_unlocked() {
+            return Ok(None);
+        }
+        let report = sync_folder(&vault.storage, &crypto, &sync.folder, &sync.device_id)?;
+        drop(crypto);
+
+        let mut registry = self.registry.lock().unwrap();
+        registry.mark_synced(id);
+        registry.save()?;
+        Ok(Some(report))
+    }
+
+    /// Writes a local backup of an open vault and records how it went in the registry. Returns
+    /// `None` if the vault has no backup schedule.
+    async fn backup_vault(&self, id: &str, vault: &Arc<VaultState>, crypto: Crypto) -> Result<Option<LocalBackup>> {
+        let Some(schedule) = self.registry.lock().unwrap().get(id).and_then(|entry| entry.backup.clone()) else {
+            return Ok(None);
+        };
+        let backup = write_snapshot_in_background(vault, crypto, move |storage, crypto| fetch::backup::write_local_backup(storage, crypto, &schedule)).await;
+        if backup.is_ok() {
+            vault.writes_at_last_backup.store(vault.storage.write_count(), Ordering::Relaxed);
+        }
+
+        let mut registry = self.registry.lock().unwrap();
+        registry.record_backup(id, backup.as_ref().map(|backup| backup.created_at).map_err(|e| e.to_string()));
+        registry.save()?;
+        backup.map(Some)
+    }
+
+    /// Backs the vault up before `operation` if its schedule asks for it. A failed backup
+    /// stops the operation, that's the point of taking it.
+    async fn backup_before(&self, id: &str, vault: &Arc<VaultState>, crypto: &Crypto, operation: &str) -> Result<()> {
+        if !self.registry.lock().unwrap().get(id).and_then(|entry| entry.backup.as_ref()).is_some_and(|backup| backup.before_destructive) {
+            return Ok(());
+        }
+        info!("Backing up vault before {}", operation);
+        self.backup_vault(id, vault, crypto.try_clone()?)
+            .await
+            .map(|_| ())
+            .map_err(|e| Error::Internal(format!("Could not back up the vault before {}, nothing was changed: {}", operation, e)))
+    }
+
+    /// Takes a local backup if one is due by the vault's schedule. Returns `None` if nothing
+    /// was due or the vault is locked.
+    async fn run_scheduled_backup(&self, id: &str, vault: &Arc<VaultState>) -> Result<Option<LocalBackup>> {
+        let Some(schedule) = self.registry.lock().unwrap().get(id).and_then(|entry| entry.backup.clone()) else {
+            return Ok(None);
+        };
+        let writes_since_last = vault.storage.write_count().saturating_sub(vault.writes_at_last_backup.load(Ordering::Relaxed));
+        if !schedule.is_due(Utc::now(), writes_since_last) {
+            return Ok(None);
+        }
+        let crypto = {
+            let crypto = vault.crypto.read().unwrap();
+            if !crypto.is_unlocked() {
+                return Ok(None);
+            }
+            crypto.try_clone()?
+        };
+        self.backup_vault(id, vault, crypto).await
+    }
+
+    /// Opens the vault if it isn't yet, makes it the active one and remembers it for next start.
+    fn activate(&self, entry: &VaultEntry) -> Result<()> {
+        if !entry.path.is_dir() {
+            return Err(Error::InvalidInput(format!("Vault folder {} is not available", entry.path.display())));
+        }
+        {
+            let mut open_vaults = self.open_vaults.write().unwrap();
+            if !open_vaults.contains_key(&entry.id) {
+                open_vaults.insert(entry.id.clone(), Arc::new(VaultState::open(&entry.path)?));
+                info!("Opened vault '{}' at {}", entry.name, entry.path.display());
+            }
+        }
+        *self.active_vault.write().unwrap() = Some(entry.id.clone());
+
+        let mut registry = self.registry.lock().unwrap();
+        registry.mark_opened(&entry.id);
+        registry.save()
+    }
+
+    fn begin_export(&self, export_id: &str) -> Result<Arc<AtomicBool>> {
+        let mut exports = self.exports.lock().unwrap();
+        if exports.contains_key(export_id) {
+            return Err(Error::InvalidInput("An export with this id is already running".into()));
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        exports.insert(export_id.to_string(), cancelled.clone());
+        Ok(cancelled)
+    }
+
+    fn end_export(&self, export_id: &str) {
+        self.exports.lock().unwrap().remove(export_id);
+    }
+
+    // Exports run on a blocking thread so a large vault doesn't hold up other commands, including
+    // the cancel_export that stops them. With `with_key` the export gets its own copy of the vault
+    // key, so locking the vault meanwhile doesn't pull it away.
+    async fn run_export(
+        &self,
+        state: &VaultState,
+        export_id: &str,
+        with_key: bool,
+        app_handle: AppHandle<Wry>,
+        export: impl FnOnce(Option<&Crypto>, &mut ExportControl<'_>) -> Result<ExportSummary> + Send + 'static,
+    ) -> Result<ExportSummary> {
+        let crypto = if with_key { Some(state.crypto.read().unwrap().try_clone()?) } else { None };
+        let cancelled = self.begin_export(export_id)?;
+        let id = export_id.to_string();
+        let exported = tauri::async_runtime::spawn_blocking(move || {
+            let mut on_progress = |progress| emit_export_progress(&app_handle, &id, progress);
+            let mut control = ExportControl::new(&cancelled, &mut on_progress);
+            export(crypto.as_ref(), &mut control)
+        })
+        .await;
+        self.end_export(export_id);
+        exported?
+    }
+
+    fn close(&self, id: &str) {
+        if let Some(vault) = self.open_vaults.write().unwrap().remove(id) {
+            vault.close();
+        }
+    }
+
+    fn vault_info(&self, entry: &VaultEntry) -> VaultInfo {
+        let open_vault = self.open_vaults.read().unwrap().get(&entry.id).cloned();
+        VaultInfo {
+            entry: entry.clone(),
+            active: self.active_vault.read().unwrap().as_deref() == Some(entry.id.as_str()),
+            open: open_vault.is_some(),
+            unlocked: open_vault.as_ref().is_some_and(|vault| vault.crypto.read().unwrap().is_unlocked()),
+            initialized: entry.path.join("salt").exists() && entry.path.join("verify").exists(),
+            available: entry.path.is_dir(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct VaultInfo {
+    #[serde(flatten)]
+    entry: VaultEntry,
+    active: bool,
+    open: bool,
+    unlocked: bool,
+    initialized: bool,
+    // false when the folder is gone, e.g. the USB stick it lives on is unplugged
+    available: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CreateVaultArgs {
+    name: String,
+    path: String,
+}
+
+#[derive(Deserialize)]
+pub struct OpenVaultArgs {
+    // defaults to the folder name
+    name: Option<String>,
+    path: String,
+}
+
+#[derive(Deserialize)]
+pub struct RenameVaultArgs {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct VaultSyncedEvent {
+    vault_id: String,
+    report: SyncReport,
 }
 
 #[derive(Deserialize)]
@@ -115,6 +376,10 @@ pub struct AddTextItemArgs {
     parent_id: Option<String>,
     #[serde(rename = "totpSecret")]
     totp_secret: Option<String>,
+    #[serde(default, rename = "customFields")]
+    custom_fields: Vec<CustomField>,
+    #[serde(default, rename = "expiresAt")]
+    expires_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize)]
@@ -135,6 +400,127 @@ pub struct AddFolderArgs {
     folder_type: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct AddSmartFolderArgs {
+    name: String,
+    query: String,
+    #[serde(rename = "parentId")]
+    parent_id: Option<String>,
+    #[serde(rename = "folderType")]
+    folder_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateSmartFolderArgs {
+    id: String,
+    name: String,
+    query: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddLoginItemArgs {
+    name: String,
+    #[serde(flatten)]
+    login: LoginData,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(rename = "parentId")]
+    parent_id: Option<String>,
+    #[serde(rename = "totpSecret")]
+    totp_secret: Option<String>,
+    #[serde(default, rename = "customFields")]
+    custom_fields: Vec<CustomField>,
+    #[serde(default, rename = "expiresAt")]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateLoginItemArgs {
+    id: String,
+    name: String,
+    #[serde(flatten)]
+    login: LoginData,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(rename = "parentId")]
+    parent_id: Option<String>,
+    #[serde(rename = "totpSecret")]
+    totp_secret: Option<String>,
+    // leaving this out keeps the item's current fields
+    #[serde(default, rename = "customFields")]
+    custom_fields: Option<Vec<CustomField>>,
+}
+
+#[derive(Deserialize)]
+pub struct AddTypedItemArgs {
+    name: String,
+    #[serde(flatten)]
+    record: ItemRecord,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(rename = "parentId")]
+    parent_id: Option<String>,
+    #[serde(default, rename = "customFields")]
+    custom_fields: Vec<CustomField>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateTypedItemArgs {
+    id: String,
+    name: String,
+    #[serde(flatten)]
+    record: ItemRecord,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(rename = "parentId")]
+    parent_id: Option<String>,
+    // leaving this out keeps the item's current fields
+    #[serde(default, rename = "customFields")]
+    custom_fields: Option<Vec<CustomField>>,
+}
+
+#[derive(Serialize)]
+pub struct TypedItem {
+    item: VaultItem,
+    #[serde(flatten)]
+    record: ItemRecord,
+    // only meaningful for cards, false for everything else
+    expired: bool,
+}
+
+#[derive(Deserialize)]
+pub struct AddAttachmentArgs {
+    #[serde(rename = "itemId")]
+    item_id: String,
+    #[serde(rename = "filePath")]
+    file_path: String,
+    // defaults to the file's own name
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetItemExpiryArgs {
+    id: String,
+    // null clears the expiry
+    #[serde(rename = "expiresAt")]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct LinkItemsArgs {
+    #[serde(rename = "sourceId")]
+    source_id: String,
+    #[serde(rename = "targetId")]
+    target_id: String,
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LoginItem {
+    item: VaultItem,
+    login: LoginData,
+}
+
 #[derive(Deserialize)]
 pub struct UpdateItemArgs {
     id: String,
@@ -146,6 +532,9 @@ pub struct UpdateItemArgs {
     parent_id: Option<String>,
     #[serde(rename = "totpSecret")]
     totp_secret: Option<String>,
+    // leaving this out keeps the item's current fields
+    #[serde(default, rename = "customFields")]
+    custom_fields: Option<Vec<CustomField>>,
 }
 
 #[derive(serde::Deserialize)]
@@ -159,6 +548,30 @@ pub struct InitializeVaultArgs {
 pub struct ExportVaultArgs {
     master_key: String,
     format: String,
+    // the file to write, picked by the user
+    path: String,
+    // lets the frontend match progress events and cancel the export
+    #[serde(rename = "exportId")]
+    export_id: String,
+    // the whole vault when left out
+    #[serde(default)]
+    selection: ExportSelection,
+}
+
+#[derive(Deserialize)]
+pub struct ExportEncryptedVaultArgs {
+    path: String,
+    #[serde(rename = "exportId")]
+    export_id: String,
+    #[serde(default)]
+    selection: ExportSelection,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ExportProgressEvent {
+    export_id: String,
+    #[serde(flatten)]
+    progress: ExportProgress,
 }
 
 #[derive(serde::Deserialize)]
@@ -189,6 +602,52 @@ pub struct DeleteTagArgs {
     tag_name: String,
 }
 
+#[derive(Deserialize)]
+pub struct BulkOperationArgs {
+    ids: Vec<String>,
+    action: BulkAction,
+}
+
+#[derive(Deserialize)]
+pub struct ListItemsPageArgs {
+    #[serde(rename = "parentId")]
+    parent_id: Option<String>,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(rename = "itemType")]
+    item_type: Option<String>,
+    #[serde(rename = "orderBy")]
+    order_by: Option<SortOrder>,
+    cursor: Option<String>,
+    #[serde(rename = "pageSize")]
+    page_size: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct StreamItemsArgs {
+    #[serde(rename = "requestId")]
+    request_id: String,
+    #[serde(flatten)]
+    listing: ListItemsPageArgs,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ItemStreamEvent {
+    request_id: String,
+    items: Vec<VaultItem>,
+    total: usize,
+    done: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct QueryItemsArgs {
+    query: String,
+    #[serde(rename = "orderBy")]
+    order_by: Option<SortOrder>,
+}
+
 #[derive(Deserialize)]
 pub struct CsvImportArgs {
     #[serde(rename = "csvContent")]
@@ -197,6 +656,146 @@ pub struct CsvImportArgs {
     parent_id: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct BitwardenImportArgs {
+    // the unencrypted JSON export as Bitwarden writes it
+    content: String,
+    #[serde(rename = "parentId")]
+    parent_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct KeePassImportArgs {
+    // the .kdbx file
+    path: String,
+    password: String,
+    #[serde(rename = "keyFilePath")]
+    key_file_path: Option<String>,
+    #[serde(rename = "parentId")]
+    parent_id: Option<String>,
+}
+
+// emitted after a scheduled S3 backup
+#[derive(Serialize, Clone)]
+pub struct VaultBackedUpEvent {
+    vault_id: String,
+    report: S3BackupReport,
+}
+
+// emitted after a scheduled local backup
+#[derive(Serialize, Clone)]
+pub struct LocalBackupEvent {
+    vault_id: String,
+    backup: LocalBackup,
+}
+
+#[derive(Deserialize)]
+pub struct BackupScheduleArgs {
+    // defaults to a folder per vault in the app data directory
+    directory: Option<String>,
+    daily: bool,
+    #[serde(rename = "everyWrites")]
+    every_writes: Option<u32>,
+    #[serde(rename = "beforeDestructive")]
+    before_destructive: bool,
+    #[serde(rename = "keepDaily")]
+    keep_daily: Option<u32>,
+    #[serde(rename = "keepWeekly")]
+    keep_weekly: Option<u32>,
+    #[serde(rename = "keepMonthly")]
+    keep_monthly: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreLocalBackupArgs {
+    path: String,
+    // only needed for backups made before a master key change
+    #[serde(rename = "masterKey")]
+    master_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct WebDavConfigArgs {
+    url: String,
+    username: String,
+    // empty keeps the stored password, so the url can be changed without typing it again
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct S3ConfigArgs {
+    endpoint: String,
+    region: String,
+    bucket: String,
+    #[serde(default)]
+    prefix: String,
+    #[serde(rename = "accessKeyId")]
+    access_key_id: String,
+    // empty keeps the stored secret
+    #[serde(rename = "secretAccessKey", default)]
+    secret_access_key: String,
+    #[serde(rename = "pathStyle")]
+    path_style: bool,
+    #[serde(default)]
+    retention: u32,
+    #[serde(rename = "intervalHours")]
+    interval_hours: Option<u32>,
+    #[serde(rename = "objectLock")]
+    object_lock: Option<ObjectLock>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreS3SnapshotArgs {
+    key: String,
+    // only needed for snapshots made before a master key change
+    #[serde(rename = "masterKey")]
+    master_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreEncryptedVaultArgs {
+    // an archive produced by export_encrypted_vault
+    path: String,
+    #[serde(rename = "masterKey")]
+    master_key: String,
+}
+
+#[derive(Deserialize)]
+pub struct ExportBackupArgs {
+    path: String,
+    // its own passphrase, independent of the master key
+    passphrase: String,
+    strength: Option<KeyDerivationStrength>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyBackupArgs {
+    path: String,
+    // without it only the manifest and checksums are checked
+    passphrase: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreBackupArgs {
+    path: String,
+    passphrase: String,
+    strategy: MergeStrategy,
+    #[serde(rename = "dryRun", default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+pub struct MergeVaultArgs {
+    // a vault directory or an archive produced by export_encrypted_vault
+    path: String,
+    #[serde(rename = "masterKey")]
+    master_key: String,
+    strategy: MergeStrategy,
+    #[serde(rename = "dryRun", default)]
+    dry_run: bool,
+}
+
 #[derive(Deserialize)]
 pub struct CsvRow {
     #[serde(rename = "Account")]
@@ -253,7 +852,17 @@ fn main() {
             
             info!("App data directory: {}", app_data_dir.display());
             
-            let vault_path = app_data_dir.join("vault");
+            let default_vault_path = app_data_dir.join("vault");
+            let mut registry = VaultRegistry::load(app_data_dir.join("vaults.json"), &default_vault_path)?;
+            let entry = match registry.startup_vault() {
+                Some(entry) => entry.clone(),
+                // none of the registered vaults is reachable, fall back to the default location
+                None => match registry.find_by_path(&default_vault_path) {
+                    Some(entry) => entry.clone(),
+                    None => registry.add(DEFAULT_VAULT_NAME, default_vault_path.clone())?,
+                },
+            };
+            let vault_path = entry.path.clone();
             info!("Vault path: {}", vault_path.display());
             
             if !vault_path.exists() {
@@ -282,26 +891,103 @@ fn main() {
                 }
             }
 
-            let storage = match Storage::new(vault_path) {
-                Ok(s) => {
-                    info!("Storage initialized successfully");
-                    s
-                }
+            let app_state = AppState {
+                registry: Mutex::new(registry),
+                open_vaults: RwLock::new(HashMap::new()),
+                active_vault: RwLock::new(None),
+                exports: Mutex::new(HashMap::new()),
+            };
+            match app_state.activate(&entry) {
+                Ok(()) => info!("Storage initialized successfully"),
                 Err(e) => {
                     error!("Failed to initialize storage: {}", e);
                     return Err(Box::new(e));
                 }
-            };
-            
-            let crypto = Crypto::new();
-            let vault_state = VaultState {
-                storage: Mutex::new(storage),
-                crypto: Mutex::new(crypto),
-            };
+            }
 
-            app.manage(vault_state);
+            app.manage(app_state);
             info!("Vault state managed successfully");
 
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(EXPIRY_CHECK_INTERVAL).await;
+                    for vault in app_handle.state::<AppState>().open_vaults() {
+                        if let Err(e) = send_expiry_reminders(&app_handle, &vault) {
+                            error!("Failed to send expiry reminders: {}", e);
+                        }
+                    }
+                }
+            });
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(SYNC_INTERVAL).await;
+                    let app_state = app_handle.state::<AppState>();
+                    let vaults: Vec<(String, Arc<VaultState>)> =
+                        app_state.open_vaults.read().unwrap().iter().map(|(id, vault)| (id.clone(), vault.clone())).collect();
+                    for (vault_id, vault) in vaults {
+                        match app_state.sync_vault(&vault_id, &vault) {
+                            Ok(Some(report)) => {
+                                if let Err(e) = app_handle.emit("vault-synced", VaultSyncedEvent { vault_id, report }) {
+                                    error!("Failed to emit sync event: {}", e);
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => error!("Failed to sync vault {}: {}", vault_id, e),
+                        }
+                    }
+                }
+            });
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(S3_BACKUP_CHECK_INTERVAL).await;
+                    let vaults: Vec<(String, Arc<VaultState>)> = app_handle
+                        .state::<AppState>()
+                        .open_vaults
+                        .read()
+                        .unwrap()
+                        .iter()
+                        .map(|(id, vault)| (id.clone(), vault.clone()))
+                        .collect();
+                    for (vault_id, vault) in vaults {
+                        match run_scheduled_s3_backup(&vault).await {
+                            Ok(Some(report)) => {
+                                if let Err(e) = app_handle.emit("vault-backed-up", VaultBackedUpEvent { vault_id, report }) {
+                                    error!("Failed to emit backup event: {}", e);
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => error!("Scheduled S3 backup of vault {} failed: {}", vault_id, e),
+                        }
+                    }
+                }
+            });
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(BACKUP_CHECK_INTERVAL).await;
+                    let app_state = app_handle.state::<AppState>();
+                    let vaults: Vec<(String, Arc<VaultState>)> =
+                        app_state.open_vaults.read().unwrap().iter().map(|(id, vault)| (id.clone(), vault.clone())).collect();
+                    for (vault_id, vault) in vaults {
+                        match app_state.run_scheduled_backup(&vault_id, &vault).await {
+                            Ok(Some(backup)) => {
+                                if let Err(e) = app_handle.emit("vault-backup-written", LocalBackupEvent { vault_id, backup }) {
+                                    error!("Failed to emit backup event: {}", e);
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => error!("Scheduled backup of vault {} failed: {}", vault_id, e),
+                        }
+                    }
+                }
+            });
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -309,23 +995,84 @@ fn main() {
             initialize_vault,
             unlock_vault,
             lock_vault,
+            list_vaults,
+            create_vault,
+            open_vault,
+            switch_vault,
+            rename_vault,
+            forget_vault,
+            lock_all_vaults,
+            connect_sync_folder,
+            disconnect_sync_folder,
+            sync_now,
             get_lockout_status,
             get_brute_force_config,
             set_brute_force_config,
             reset_failed_attempts,
             get_vault_items,
+            get_vault_items_page,
+            get_vault_item_summaries,
+            stream_vault_items,
             add_text_item,
             add_file_item,
             add_folder,
+            add_smart_folder,
+            update_smart_folder,
+            add_login_item,
+            update_login_item,
+            get_login_item,
+            migrate_legacy_logins,
+            add_typed_item,
+            update_typed_item,
+            get_typed_item,
+            add_attachment,
+            list_attachments,
+            remove_attachment,
+            download_attachment,
+            link_items,
+            unlink_items,
+            get_item_links,
+            resolve_item_references,
+            set_item_expiry,
+            get_expiring_items,
+            get_reminder_settings,
+            set_reminder_settings,
+            export_expiry_calendar,
             get_item_content,
             delete_item,
             permanently_delete_item,
             permanently_delete_all_items,
             restore_item,
+            bulk_update_items,
             get_deleted_items,
             update_master_key,
             export_decrypted_vault,
             export_encrypted_vault,
+            cancel_export,
+            restore_encrypted_vault,
+            export_backup,
+            verify_backup,
+            restore_backup,
+            merge_vault,
+            set_webdav_config,
+            get_webdav_config,
+            remove_webdav_config,
+            test_webdav_connection,
+            webdav_push,
+            webdav_pull,
+            set_s3_config,
+            get_s3_config,
+            remove_s3_config,
+            get_s3_bucket_status,
+            s3_backup_now,
+            list_s3_snapshots,
+            restore_s3_snapshot,
+            get_backup_schedule,
+            set_backup_schedule,
+            disable_backups,
+            backup_now,
+            list_local_backups,
+            restore_local_backup,
             delete_vault,
             get_vault_status,
             get_key_derivation_strength,
@@ -333,7 +1080,11 @@ fn main() {
             rename_tag,
             delete_tag,
             import_csv,
+            import_bitwarden,
+            import_keepass,
             get_all_vault_items,
+            search_items,
+            query_items,
             get_theme,
             set_theme,
             update_item,
@@ -346,9 +1097,10 @@ fn main() {
 }
 
 #[tauri::command]
-fn get_vault_status(state: State<'_, VaultState>) -> Result<VaultStatus> {
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+fn get_vault_status(app_state: State<'_, AppState>) -> Result<VaultStatus> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
     let strength = if storage.is_initialized() {
         Some(storage.get_key_derivation_strength()?)
     } else {
@@ -362,8 +1114,9 @@ fn get_vault_status(state: State<'_, VaultState>) -> Result<VaultStatus> {
 }
 
 #[tauri::command]
-fn get_key_derivation_strength(state: State<'_, VaultState>) -> Result<KeyDerivationStrength> {
-    let storage = state.storage.lock().unwrap();
+fn get_key_derivation_strength(app_state: State<'_, AppState>) -> Result<KeyDerivationStrength> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
     if !storage.is_initialized() {
         return Err(Error::Internal("Vault not initialized".to_string()));
     }
@@ -372,16 +1125,18 @@ fn get_key_derivation_strength(state: State<'_, VaultState>) -> Result<KeyDeriva
 
 
 #[tauri::command]
-async fn is_vault_initialized(state: State<'_, VaultState>) -> Result<bool> {
-    let storage = state.storage.lock().unwrap();
+async fn is_vault_initialized(app_state: State<'_, AppState>) -> Result<bool> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
     Ok(storage.is_initialized())
 }
 
 #[tauri::command]
-async fn initialize_vault(args: InitializeVaultArgs, state: State<'_, VaultState>) -> Result<()> {
+async fn initialize_vault(args: InitializeVaultArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Initializing vault.");
-    let storage = state.storage.lock().unwrap();
-    let mut crypto = state.crypto.lock().unwrap();
+    let storage = &state.storage;
+    let mut crypto = state.crypto.write().unwrap();
 
     if storage.is_initialized() {
         error!("Attempted to initialize an already initialized vault.");
@@ -409,13 +1164,14 @@ async fn initialize_vault(args: InitializeVaultArgs, state: State<'_, VaultState
 }
 
 #[tauri::command]
-async fn unlock_vault(master_key: String, state: State<'_, VaultState>) -> Result<()> {
+async fn unlock_vault(master_key: String, app_handle: AppHandle<Wry>, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Attempting to unlock vault.");
 
-    let storage = state.storage.lock().unwrap();
+    let storage = &state.storage;
 
     // Security: Check persistent rate limiting
-    let lockout_status = PersistentRateLimiter::check_and_update_lockout(&storage)?;
+    let lockout_status = PersistentRateLimiter::check_and_update_lockout(storage)?;
     if lockout_status.is_locked_out {
         error!("Account locked due to too many failed attempts. Remaining time: {} seconds", lockout_status.remaining_seconds);
         return Err(Error::InvalidInput(format!(
@@ -424,7 +1180,7 @@ async fn unlock_vault(master_key: String, state: State<'_, VaultState>) -> Resul
         )));
     }
 
-    let mut crypto = state.crypto.lock().unwrap();
+    let mut crypto = state.crypto.write().unwrap();
 
     let salt = storage.get_salt()?;
     let strength = storage.get_key_derivation_strength()?;
@@ -435,41 +1191,232 @@ async fn unlock_vault(master_key: String, state: State<'_, VaultState>) -> Resul
 
     if crypto.decrypt(&verification_token).is_ok() {
         // Success: Reset failed attempts
-        PersistentRateLimiter::reset_attempts(&storage)?;
+        PersistentRateLimiter::reset_attempts(storage)?;
+        // never serve entries decrypted under a previous session
+        storage.clear_metadata_cache();
+        // a failed migration must not keep the user out of their vault, it is retried next unlock
+        if let Err(e) = storage.migrate_legacy_logins(&crypto) {
+            error!("Failed to migrate legacy login items: {}", e);
+        }
         info!("Vault unlocked successfully with strength {:?}", strength);
+
+        drop(crypto);
+        if let Err(e) = send_expiry_reminders(&app_handle, &state) {
+            error!("Failed to send expiry reminders: {}", e);
+        }
+        // pick up what the other devices changed while this one was locked
+        if let Err(e) = app_state.active_vault_id().and_then(|id| app_state.sync_vault(&id, &state)) {
+            error!("Failed to sync vault after unlock: {}", e);
+        }
         return Ok(());
     }
 
     // Failed attempt: Record it
     crypto.lock();
-    PersistentRateLimiter::record_failed_attempt(&storage)?;
+    PersistentRateLimiter::record_failed_attempt(storage)?;
     error!("Invalid master key provided during unlock attempt.");
     Err(Error::InvalidMasterKey)
 }
 
+fn validate_vault_path(path: &str) -> Result<PathBuf> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Err(Error::InvalidInput("Vault folder cannot be empty".into()));
+    }
+    // Security: Validate path to prevent directory traversal attacks
+    if path.contains("..") || path.contains('\0') {
+        return Err(Error::InvalidInput("Vault folder contains invalid characters".into()));
+    }
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(Error::InvalidInput("Vault folder must be an absolute path".into()));
+    }
+    Ok(path)
+}
+
 #[tauri::command]
-async fn lock_vault(state: State<'_, VaultState>) -> Result<()> {
-    info!("Locking vault.");
-    state.crypto.lock().unwrap().lock();
-    Ok(())
+async fn list_vaults(app_state: State<'_, AppState>) -> Result<Vec<VaultInfo>> {
+    let entries = app_state.registry.lock().unwrap().vaults.clone();
+    Ok(entries.iter().map(|entry| app_state.vault_info(entry)).collect())
 }
 
 #[tauri::command]
-async fn get_lockout_status(state: State<'_, VaultState>) -> Result<LockoutStatus> {
-    let storage = state.storage.lock().unwrap();
-    PersistentRateLimiter::check_and_update_lockout(&storage)
+async fn create_vault(args: CreateVaultArgs, app_state: State<'_, AppState>) -> Result<VaultInfo> {
+    info!("Creating vault '{}' at {}", args.name, args.path);
+    let path = validate_vault_path(&args.path)?;
+    if is_vault_dir(&path) {
+        return Err(Error::InvalidInput("This folder already contains a vault, open it instead".into()));
+    }
+    fs::create_dir_all(&path)?;
+    let path = path.canonicalize()?;
+
+    let entry = {
+        let mut registry = app_state.registry.lock().unwrap();
+        let entry = registry.add(&args.name, path)?;
+        registry.save()?;
+        entry
+    };
+    // the new vault becomes the active one, the frontend then runs initialize_vault on it
+    app_state.activate(&entry)?;
+    Ok(app_state.vault_info(&entry))
 }
 
 #[tauri::command]
-async fn get_brute_force_config(state: State<'_, VaultState>) -> Result<fetch::storage::BruteForceConfig> {
-    let storage = state.storage.lock().unwrap();
-    storage.get_brute_force_config()
+async fn open_vault(args: OpenVaultArgs, app_state: State<'_, AppState>) -> Result<VaultInfo> {
+    info!("Opening vault at {}", args.path);
+    let path = validate_vault_path(&args.path)?;
+    if !is_vault_dir(&path) {
+        return Err(Error::InvalidInput("No vault found in this folder".into()));
+    }
+    let path = path.canonicalize()?;
+
+    let entry = {
+        let mut registry = app_state.registry.lock().unwrap();
+        match registry.find_by_path(&path) {
+            Some(entry) => entry.clone(),
+            None => {
+                let name = match args.name {
+                    Some(name) => name,
+                    None => path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| DEFAULT_VAULT_NAME.to_string()),
+                };
+                let entry = registry.add(&name, path)?;
+                registry.save()?;
+                entry
+            }
+        }
+    };
+    app_state.activate(&entry)?;
+    Ok(app_state.vault_info(&entry))
 }
 
 #[tauri::command]
-async fn set_brute_force_config(config: fetch::storage::BruteForceConfig, state: State<'_, VaultState>) -> Result<()> {
+async fn switch_vault(id: String, app_state: State<'_, AppState>) -> Result<VaultInfo> {
+    info!("Switching to vault: {}", id);
+    let entry = app_state.registry.lock().unwrap().get(&id).cloned().ok_or_else(|| Error::ItemNotFound(id.clone()))?;
+    app_state.activate(&entry)?;
+    Ok(app_state.vault_info(&entry))
+}
+
+#[tauri::command]
+async fn rename_vault(args: RenameVaultArgs, app_state: State<'_, AppState>) -> Result<()> {
+    info!("Renaming vault {} to '{}'", args.id, args.name);
+    let mut registry = app_state.registry.lock().unwrap();
+    registry.rename(&args.id, &args.name)?;
+    registry.save()
+}
+
+/// Removes a vault from the list and closes it. Nothing on disk is touched.
+#[tauri::command]
+async fn forget_vault(id: String, app_state: State<'_, AppState>) -> Result<()> {
+    info!("Forgetting vault: {}", id);
+    if app_state.active_vault.read().unwrap().as_deref() == Some(id.as_str()) {
+        return Err(Error::InvalidInput("Switch to another vault before removing this one".into()));
+    }
+    {
+        let mut registry = app_state.registry.lock().unwrap();
+        registry.forget(&id)?;
+        registry.save()?;
+    }
+    app_state.close(&id);
+    Ok(())
+}
+
+#[tauri::command]
+async fn lock_all_vaults(app_state: State<'_, AppState>) -> Result<()> {
+    info!("Locking all vaults.");
+    for vault in app_state.open_vaults() {
+        vault.close();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn connect_sync_folder(path: String, app_state: State<'_, AppState>) -> Result<SyncReport> {
+    let state = app_state.active_vault()?;
+    let id = app_state.active_vault_id()?;
+    info!("Connecting vault {} to sync folder {}", id, path);
+
+    if !state.crypto.read().unwrap().is_unlocked() {
+        error!("Vault is locked, cannot connect a sync folder.");
+        return Err(Error::VaultLocked);
+    }
+    let folder = validate_vault_path(&path)?;
+    if folder.starts_with(state.storage.get_vault_path()) {
+        return Err(Error::InvalidInput("The sync folder cannot be inside the vault folder".into()));
+    }
+
+    // revisions recorded for a previous folder mean nothing in this one
+    state.storage.clear_sync_state()?;
+    {
+        let mut registry = app_state.registry.lock().unwrap();
+        registry.set_sync(&id, Some(SyncSettings::new(folder)))?;
+        registry.save()?;
+    }
+    match app_state.sync_vault(&id, &state) {
+        Ok(report) => Ok(report.unwrap_or_default()),
+        Err(e) => {
+            error!("First sync failed, disconnecting sync folder: {}", e);
+            let mut registry = app_state.registry.lock().unwrap();
+            registry.set_sync(&id, None)?;
+            registry.save()?;
+            Err(e)
+        }
+    }
+}
+
+#[tauri::command]
+async fn disconnect_sync_folder(app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    let id = app_state.active_vault_id()?;
+    info!("Disconnecting vault {} from its sync folder", id);
+    {
+        let mut registry = app_state.registry.lock().unwrap();
+        registry.set_sync(&id, None)?;
+        registry.save()?;
+    }
+    state.storage.clear_sync_state()
+}
+
+#[tauri::command]
+async fn sync_now(app_state: State<'_, AppState>) -> Result<SyncReport> {
+    let state = app_state.active_vault()?;
+    let id = app_state.active_vault_id()?;
+
+    if !state.crypto.read().unwrap().is_unlocked() {
+        error!("Vault is locked, cannot sync.");
+        return Err(Error::VaultLocked);
+    }
+    app_state.sync_vault(&id, &state)?.ok_or_else(|| Error::InvalidInput("This vault is not connected to a sync folder".into()))
+}
+
+#[tauri::command]
+async fn lock_vault(app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Locking vault.");
+    state.crypto.write().unwrap().lock();
+    state.storage.clear_metadata_cache();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_lockout_status(app_state: State<'_, AppState>) -> Result<LockoutStatus> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    PersistentRateLimiter::check_and_update_lockout(storage)
+}
+
+#[tauri::command]
+async fn get_brute_force_config(app_state: State<'_, AppState>) -> Result<fetch::storage::BruteForceConfig> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    storage.get_brute_force_config()
+}
+
+#[tauri::command]
+async fn set_brute_force_config(config: fetch::storage::BruteForceConfig, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Setting brute force protection configuration: {:?}", config);
-    let storage = state.storage.lock().unwrap();
+    let storage = &state.storage;
     storage.set_brute_force_config(config)?;
 
     // If brute force protection is disabled, reset any existing lockout
@@ -483,10 +1430,11 @@ async fn set_brute_force_config(config: fetch::storage::BruteForceConfig, state:
 }
 
 #[tauri::command]
-async fn reset_failed_attempts(state: State<'_, VaultState>) -> Result<()> {
+async fn reset_failed_attempts(app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Manually resetting failed login attempts.");
-    let storage = state.storage.lock().unwrap();
-    PersistentRateLimiter::reset_attempts(&storage)
+    let storage = &state.storage;
+    PersistentRateLimiter::reset_attempts(storage)
 }
 
 #[tauri::command]
@@ -494,10 +1442,11 @@ async fn get_vault_items(
     parent_id: Option<String>,
     item_type: Option<String>,
     order_by: Option<SortOrder>,
-    state: State<'_, VaultState>,
+    app_state: State<'_, AppState>,
 ) -> Result<Vec<VaultItem>> {
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
     if !crypto.is_unlocked() {
         return Err(Error::VaultLocked);
     }
@@ -505,7 +1454,108 @@ async fn get_vault_items(
 }
 
 #[tauri::command]
-async fn add_text_item(args: AddTextItemArgs, state: State<'_, VaultState>) -> Result<()> {
+async fn get_vault_items_page(args: ListItemsPageArgs, app_state: State<'_, AppState>) -> Result<ItemPage> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    storage.get_items_page(
+        args.parent_id,
+        args.recursive,
+        args.item_type,
+        args.order_by,
+        args.cursor.as_deref(),
+        args.page_size,
+        &crypto,
+    )
+}
+
+#[tauri::command]
+async fn get_vault_item_summaries(
+    parent_id: Option<String>,
+    recursive: Option<bool>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<ItemSummary>> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    storage.get_item_summaries(parent_id, recursive.unwrap_or(false), &crypto)
+}
+
+/// Emits the listing as a series of `vault-items-page` events, one per page, so the UI can render
+/// the first rows while the rest are still being decrypted. Locks are released between pages.
+#[tauri::command]
+async fn stream_vault_items(args: StreamItemsArgs, app_handle: AppHandle<Wry>, app_state: State<'_, AppState>) -> Result<usize> {
+    let state = app_state.active_vault()?;
+    info!("Streaming vault items for request {}", args.request_id);
+    let listing = args.listing;
+    let mut cursor = listing.cursor.clone();
+    let mut sent = 0;
+
+    loop {
+        let page = {
+            let storage = &state.storage;
+            let crypto = state.crypto.read().unwrap();
+            if !crypto.is_unlocked() {
+                Err(Error::VaultLocked)
+            } else {
+                storage.get_items_page(
+                    listing.parent_id.clone(),
+                    listing.recursive,
+                    listing.item_type.clone(),
+                    listing.order_by,
+                    cursor.as_deref(),
+                    listing.page_size,
+                    &crypto,
+                )
+            }
+        };
+
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => {
+                error!("Streaming request {} failed after {} items: {}", args.request_id, sent, e);
+                app_handle.emit("vault-items-page", ItemStreamEvent {
+                    request_id: args.request_id.clone(),
+                    items: Vec::new(),
+                    total: sent,
+                    done: true,
+                    error: Some(e.to_string()),
+                })?;
+                return Err(e);
+            }
+        };
+
+        sent += page.items.len();
+        let done = page.next_cursor.is_none();
+        app_handle.emit("vault-items-page", ItemStreamEvent {
+            request_id: args.request_id.clone(),
+            items: page.items,
+            total: page.total,
+            done,
+            error: None,
+        })?;
+
+        if done {
+            break;
+        }
+        cursor = page.next_cursor;
+        // let other commands get at the vault between pages
+        tokio::task::yield_now().await;
+    }
+
+    info!("Finished streaming {} items for request {}", sent, args.request_id);
+    Ok(sent)
+}
+
+#[tauri::command]
+async fn add_text_item(args: AddTextItemArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Adding text item: {}", args.name);
     trace!("Received content length: {}", args.content.len());
 
@@ -538,14 +1588,16 @@ async fn add_text_item(args: AddTextItemArgs, state: State<'_, VaultState>) -> R
     if args.tags.len() > 20 {
         return Err(Error::InvalidInput("Too many tags (max 20)".into()));
     }
+    validate_custom_fields(&args.custom_fields)?;
     
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
     if !crypto.is_unlocked() {
         error!("Vault is locked, cannot add item.");
         return Err(Error::VaultLocked);
     }
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
 
     let now = Utc::now();
     let data_path = Uuid::new_v4().to_string(); 
@@ -563,6 +1615,8 @@ async fn add_text_item(args: AddTextItemArgs, state: State<'_, VaultState>) -> R
         updated_at: now,
         deleted_at: None,
         totp_secret: args.totp_secret,
+        custom_fields: normalize_custom_fields(args.custom_fields),
+        expires_at: args.expires_at,
     };
 
     let encrypted_content = crypto.encrypt(args.content.as_bytes())?;
@@ -580,16 +1634,18 @@ async fn add_text_item(args: AddTextItemArgs, state: State<'_, VaultState>) -> R
 }
 
 #[tauri::command]
-async fn add_file_item(args: AddFileItemArgs, state: State<'_, VaultState>) -> Result<()> {
+async fn add_file_item(args: AddFileItemArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Adding file item: {}", args.name);
 
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
     if !crypto.is_unlocked() {
         error!("Vault is locked, cannot add file item.");
         return Err(Error::VaultLocked);
     }
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
 
     let file_path = Path::new(&args.file_path);
 
@@ -622,6 +1678,8 @@ async fn add_file_item(args: AddFileItemArgs, state: State<'_, VaultState>) -> R
         updated_at: now,
         deleted_at: None,
         totp_secret: None, // Files don't have TOTP
+        custom_fields: vec![],
+        expires_at: None,
     };
 
     let encrypted_content = crypto.encrypt(&file_content)?;
@@ -639,7 +1697,8 @@ async fn add_file_item(args: AddFileItemArgs, state: State<'_, VaultState>) -> R
 }
 
 #[tauri::command]
-async fn add_folder(args: AddFolderArgs, state: State<'_, VaultState>) -> Result<()> {
+async fn add_folder(args: AddFolderArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Adding folder: {}", args.name);
 
     // Security: Enhanced folder validation
@@ -657,13 +1716,14 @@ async fn add_folder(args: AddFolderArgs, state: State<'_, VaultState>) -> Result
         return Err(Error::InvalidInput("Folder name contains invalid characters".into()));
     }
     
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
     if !crypto.is_unlocked() {
         error!("Vault is locked, cannot add folder.");
         return Err(Error::VaultLocked);
     }
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
 
     let now = Utc::now();
 
@@ -679,6 +1739,8 @@ async fn add_folder(args: AddFolderArgs, state: State<'_, VaultState>) -> Result
         updated_at: now,
         deleted_at: None,
         totp_secret: None, // Folders don't have TOTP
+        custom_fields: vec![],
+        expires_at: None,
     };
     
     storage.add_item(&item, &crypto)?;
@@ -687,184 +1749,848 @@ async fn add_folder(args: AddFolderArgs, state: State<'_, VaultState>) -> Result
     Ok(())
 }
 
-#[tauri::command]
-async fn update_item(args: UpdateItemArgs, state: State<'_, VaultState>) -> Result<()> {
-    info!("Updating item: {}", args.name);
+fn ensure_not_smart_folder(storage: &Storage, parent_id: Option<&str>, crypto: &Crypto) -> Result<()> {
+    if let Some(pid) = parent_id {
+        if storage.is_smart_folder(pid, crypto)? {
+            warn!("Attempted to place an item inside smart folder {}", pid);
+            return Err(Error::InvalidInput("Items cannot be placed inside a smart folder".into()));
+        }
+    }
+    Ok(())
+}
 
-    if args.name.trim().is_empty() {
-        warn!("Attempted to update item with empty name.");
-        return Err(Error::InvalidInput("Item name cannot be empty".into()));
+fn validate_smart_folder(name: &str, query: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(Error::InvalidInput("Smart folder name cannot be empty".into()));
+    }
+    if name.len() > 255 {
+        return Err(Error::InvalidInput("Smart folder name too long (max 255 characters)".into()));
+    }
+    if query.trim().is_empty() {
+        return Err(Error::InvalidInput("Smart folder query cannot be empty".into()));
+    }
+    if query.len() > 1024 {
+        return Err(Error::InvalidInput("Query too long (max 1024 characters)".into()));
     }
+    // reject bad queries up front instead of when the folder is opened
+    Query::parse(query)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn add_smart_folder(args: AddSmartFolderArgs, app_state: State<'_, AppState>) -> Result<VaultItem> {
+    let state = app_state.active_vault()?;
+    info!("Adding smart folder: {}", args.name);
+    validate_smart_folder(&args.name, &args.query)?;
 
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
     if !crypto.is_unlocked() {
-        error!("Vault is locked, cannot update item.");
+        error!("Vault is locked, cannot add smart folder.");
         return Err(Error::VaultLocked);
     }
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
 
-    // get the existing item to preserve its data_path
-    let existing_item = storage.get_item(&args.id, &crypto)?.ok_or_else(|| Error::ItemNotFound(args.id.clone()))?;
-    
     let now = Utc::now();
-    let item_type = args.item_type.clone(); // clone it so we can use it later
+    let data_path = Uuid::new_v4().to_string();
+
     let item = VaultItem {
-        id: args.id,
+        id: Uuid::new_v4().to_string(),
         parent_id: args.parent_id,
-        name: args.name,
-        data_path: existing_item.data_path.clone(), // keep the same data_path
-        item_type: args.item_type,
-        folder_type: existing_item.folder_type, // preserve folder_type
-        tags: args.tags,
-        created_at: existing_item.created_at, // preserve creation date
+        name: args.name.trim().to_string(),
+        data_path: data_path.clone(),
+        item_type: SMART_FOLDER_TYPE.to_string(),
+        folder_type: args.folder_type,
+        tags: vec![],
+        created_at: now,
         updated_at: now,
-        deleted_at: existing_item.deleted_at,
-        totp_secret: args.totp_secret.or(existing_item.totp_secret), // Update if provided, else keep existing
+        deleted_at: None,
+        totp_secret: None,
+        custom_fields: vec![],
+        expires_at: None,
     };
 
-    // update the encrypted content if it's a text item
-    if item_type == "text" || item_type == "key" || item_type == "text/plain" {
-        let encrypted_content = crypto.encrypt(args.content.as_bytes())?;
-        storage.write_encrypted_file(&encrypted_content, &existing_item.data_path)?;
-    }
+    // the saved query lives in the item's data file so it is encrypted like any other content
+    let encrypted_query = crypto.encrypt(args.query.trim().as_bytes())?;
+    storage.write_encrypted_file(&encrypted_query, &data_path)?;
+    storage.add_item(&item, &crypto)?;
 
-    // update the item metadata
-    storage.update_item_fields(&item, &crypto)?;
-    
-    info!("Item '{}' updated successfully.", item.name);
-    Ok(())
+    info!("Smart folder '{}' added successfully.", item.name);
+    Ok(item)
 }
 
 #[tauri::command]
-async fn get_item_content(id: String, state: State<'_, VaultState>) -> Result<Vec<u8>> {
-    info!("Getting content for item: {}", id);
-    
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+async fn update_smart_folder(args: UpdateSmartFolderArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Updating smart folder: {}", args.id);
+    validate_smart_folder(&args.name, &args.query)?;
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
     if !crypto.is_unlocked() {
-        error!("Vault is locked, cannot get item content");
+        error!("Vault is locked, cannot update smart folder.");
         return Err(Error::VaultLocked);
     }
 
-    let item = match storage.get_item(&id, &crypto) {
-        Ok(Some(item)) => {
-            info!("Found item: {} (type: {})", item.name, item.item_type);
-            item
-        }
-        Ok(None) => {
-            error!("Item not found: {}", id);
-            return Err(Error::ItemNotFound(id.clone()));
-        }
-        Err(e) => {
-            error!("Failed to get item {}: {}", id, e);
-            return Err(e);
-        }
-    };
-    
-    info!("Reading encrypted file: {}", item.data_path);
-    match storage.read_encrypted_file(&item.data_path, &crypto) {
-        Ok(content) => {
-            info!("Successfully read {} bytes for item: {}", content.len(), item.name);
-            Ok(content)
+    let mut item = storage.get_item(&args.id, &crypto)?.ok_or_else(|| Error::ItemNotFound(args.id.clone()))?;
+    if item.item_type != SMART_FOLDER_TYPE {
+        return Err(Error::InvalidInput("Item is not a smart folder".into()));
+    }
+
+    let encrypted_query = crypto.encrypt(args.query.trim().as_bytes())?;
+    storage.write_encrypted_file(&encrypted_query, &item.data_path)?;
+
+    item.name = args.name.trim().to_string();
+    item.updated_at = Utc::now();
+    storage.update_item_fields(&item, &crypto)?;
+
+    info!("Smart folder '{}' updated successfully.", item.name);
+    Ok(())
+}
+
+fn validate_item_metadata(name: &str, tags: &[String]) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(Error::InvalidInput("Item name cannot be empty".into()));
+    }
+    if name.len() > 255 {
+        return Err(Error::InvalidInput("Item name too long (max 255 characters)".into()));
+    }
+    for tag in tags {
+        if tag.len() > 50 {
+            return Err(Error::InvalidInput("Tag name too long (max 50 characters)".into()));
         }
-        Err(e) => {
-            error!("Failed to read encrypted file for item {}: {}", item.name, e);
-            Err(e)
+        if tag.contains('\0') || tag.contains('\n') || tag.contains('\r') {
+            return Err(Error::InvalidInput("Tag contains invalid characters".into()));
         }
     }
+    if tags.len() > 20 {
+        return Err(Error::InvalidInput("Too many tags (max 20)".into()));
+    }
+    Ok(())
 }
 
 #[tauri::command]
-async fn delete_item(id: String, state: State<'_, VaultState>) -> Result<bool> {
-    info!("Soft deleting item with id: {}", id);
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+async fn add_login_item(args: AddLoginItemArgs, app_state: State<'_, AppState>) -> Result<VaultItem> {
+    let state = app_state.active_vault()?;
+    info!("Adding login item: {}", args.name);
+    validate_item_metadata(&args.name, &args.tags)?;
+    args.login.validate()?;
+    validate_custom_fields(&args.custom_fields)?;
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
     if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot add login item.");
         return Err(Error::VaultLocked);
     }
-    storage.delete_item_and_descendants(&id, &crypto)?;
-    Ok(true)
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
+
+    let now = Utc::now();
+    let data_path = Uuid::new_v4().to_string();
+
+    let item = VaultItem {
+        id: Uuid::new_v4().to_string(),
+        parent_id: args.parent_id,
+        name: args.name.trim().to_string(),
+        data_path: data_path.clone(),
+        item_type: LOGIN_ITEM_TYPE.to_string(),
+        folder_type: None,
+        tags: args.tags,
+        created_at: now,
+        updated_at: now,
+        deleted_at: None,
+        totp_secret: args.totp_secret.filter(|secret| !secret.trim().is_empty()),
+        custom_fields: normalize_custom_fields(args.custom_fields),
+        expires_at: args.expires_at,
+    };
+
+    storage.write_login(&data_path, &args.login.normalized(), &crypto)?;
+    storage.add_item(&item, &crypto)?;
+
+    info!("Login item '{}' added successfully.", item.name);
+    Ok(item)
 }
 
 #[tauri::command]
-async fn permanently_delete_item(id: String, state: State<'_, VaultState>) -> Result<bool> {
-    info!("Permanently deleting item with id: {}", id);
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+async fn update_login_item(args: UpdateLoginItemArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Updating login item: {}", args.id);
+    validate_item_metadata(&args.name, &args.tags)?;
+    args.login.validate()?;
+    if let Some(fields) = &args.custom_fields {
+        validate_custom_fields(fields)?;
+    }
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
     if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot update login item.");
         return Err(Error::VaultLocked);
     }
-    storage.permanently_delete_item_and_descendants(&id, &crypto)?;
-    Ok(true)
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
+
+    let mut item = storage.get_item(&args.id, &crypto)?.ok_or_else(|| Error::ItemNotFound(args.id.clone()))?;
+    if item.item_type != LOGIN_ITEM_TYPE {
+        return Err(Error::InvalidInput("Item is not a login".into()));
+    }
+
+    storage.write_login(&item.data_path, &args.login.normalized(), &crypto)?;
+
+    item.name = args.name.trim().to_string();
+    item.tags = args.tags;
+    item.parent_id = args.parent_id;
+    if let Some(fields) = args.custom_fields {
+        item.custom_fields = normalize_custom_fields(fields);
+    }
+    // an empty string clears the secret, leaving it out keeps the current one
+    if let Some(secret) = args.totp_secret {
+        item.totp_secret = if secret.trim().is_empty() { None } else { Some(secret) };
+    }
+    item.updated_at = Utc::now();
+    storage.update_item_fields(&item, &crypto)?;
+
+    info!("Login item '{}' updated successfully.", item.name);
+    Ok(())
 }
 
 #[tauri::command]
-async fn permanently_delete_all_items(state: State<'_, VaultState>) -> Result<bool> {
-    info!("Permanently deleting all items in recycling bin");
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+async fn get_login_item(id: String, app_state: State<'_, AppState>) -> Result<LoginItem> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
     if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot get login item.");
         return Err(Error::VaultLocked);
     }
-    storage.permanently_delete_all_deleted_items(&crypto)?;
-    Ok(true)
+
+    let item = storage.get_item(&id, &crypto)?.ok_or_else(|| Error::ItemNotFound(id.clone()))?;
+    let login = storage.read_login(&item, &crypto)?;
+    Ok(LoginItem { item, login })
 }
 
 #[tauri::command]
-async fn restore_item(id: String, state: State<'_, VaultState>) -> Result<bool> {
-    info!("Restoring item with id: {}", id);
-    let storage = state.storage.lock().unwrap();
-    if !state.crypto.lock().unwrap().is_unlocked() {
+async fn migrate_legacy_logins(app_state: State<'_, AppState>) -> Result<usize> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot migrate login items.");
         return Err(Error::VaultLocked);
     }
-    storage.restore_item(&id)
+    storage.migrate_legacy_logins(&crypto)
 }
 
 #[tauri::command]
-async fn restore_item_to_root(id: String, state: State<'_, VaultState>) -> Result<bool> {
-    info!("Restoring item to root with id: {}", id);
-    let storage = state.storage.lock().unwrap();
-    if !state.crypto.lock().unwrap().is_unlocked() {
+async fn add_typed_item(args: AddTypedItemArgs, app_state: State<'_, AppState>) -> Result<VaultItem> {
+    let state = app_state.active_vault()?;
+    info!("Adding {} item: {}", args.record.item_type(), args.name);
+    validate_item_metadata(&args.name, &args.tags)?;
+    validate_custom_fields(&args.custom_fields)?;
+    args.record.validate()?;
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot add item.");
         return Err(Error::VaultLocked);
     }
-    storage.restore_item_to_root(&id)
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
+
+    let now = Utc::now();
+    let data_path = Uuid::new_v4().to_string();
+
+    let item = VaultItem {
+        id: Uuid::new_v4().to_string(),
+        parent_id: args.parent_id,
+        name: args.name.trim().to_string(),
+        data_path: data_path.clone(),
+        item_type: args.record.item_type().to_string(),
+        folder_type: None,
+        tags: args.tags,
+        created_at: now,
+        updated_at: now,
+        deleted_at: None,
+        totp_secret: None,
+        custom_fields: normalize_custom_fields(args.custom_fields),
+        expires_at: args.record.expires_at(),
+    };
+
+    storage.write_record(&data_path, &args.record.normalized(), &crypto)?;
+    storage.add_item(&item, &crypto)?;
+
+    info!("{} item '{}' added successfully.", item.item_type, item.name);
+    Ok(item)
 }
 
 #[tauri::command]
-async fn get_deleted_items(state: State<'_, VaultState>) -> Result<Vec<VaultItem>> {
-    info!("Getting all deleted items");
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+async fn update_typed_item(args: UpdateTypedItemArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Updating typed item: {}", args.id);
+    validate_item_metadata(&args.name, &args.tags)?;
+    if let Some(fields) = &args.custom_fields {
+        validate_custom_fields(fields)?;
+    }
+    args.record.validate()?;
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
     if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot update item.");
         return Err(Error::VaultLocked);
     }
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
 
-    let all_items = storage.get_all_items_recursive(&crypto)?;
-    let deleted_items = all_items.into_iter().filter(|item| item.deleted_at.is_some()).collect();
-    Ok(deleted_items)
+    let mut item = storage.get_item(&args.id, &crypto)?.ok_or_else(|| Error::ItemNotFound(args.id.clone()))?;
+    if item.item_type != args.record.item_type() {
+        return Err(Error::InvalidInput(format!("Item is a '{}', not a '{}'", item.item_type, args.record.item_type())));
+    }
+
+    let record_expiry = args.record.expires_at();
+    // an unreadable old record is replaced below anyway
+    let previous_record_expiry = storage.read_record(&item, &crypto).ok().and_then(|record| record.expires_at());
+    storage.write_record(&item.data_path, &args.record.normalized(), &crypto)?;
+
+    item.name = args.name.trim().to_string();
+    item.tags = args.tags;
+    item.parent_id = args.parent_id;
+    if let Some(fields) = args.custom_fields {
+        item.custom_fields = normalize_custom_fields(fields);
+    }
+    // an expiry on the record itself wins; one that came from the record goes when the record's is
+    // cleared, one set by hand stays
+    item.expires_at = match record_expiry {
+        Some(expiry) => Some(expiry),
+        None if item.expires_at == previous_record_expiry => None,
+        None => item.expires_at,
+    };
+    item.updated_at = Utc::now();
+    storage.update_item_fields(&item, &crypto)?;
+
+    info!("{} item '{}' updated successfully.", item.item_type, item.name);
+    Ok(())
 }
 
 #[tauri::command]
-async fn update_master_key(args: UpdateMasterKeyArgs, state: State<'_, VaultState>) -> Result<()> {
-    info!("Starting master key update process.");
-    let storage = state.storage.lock().unwrap();
-    let mut crypto = state.crypto.lock().unwrap();
+async fn get_typed_item(id: String, app_state: State<'_, AppState>) -> Result<TypedItem> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
-    let current_salt = storage.get_salt()?;
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot get item.");
+        return Err(Error::VaultLocked);
+    }
+
+    let item = storage.get_item(&id, &crypto)?.ok_or_else(|| Error::ItemNotFound(id.clone()))?;
+    let record = storage.read_record(&item, &crypto)?;
+    let expired = matches!(&record, ItemRecord::Card(card) if card.is_expired());
+    Ok(TypedItem { item, record, expired })
+}
+
+#[tauri::command]
+async fn add_attachment(args: AddAttachmentArgs, app_state: State<'_, AppState>) -> Result<Attachment> {
+    let state = app_state.active_vault()?;
+    info!("Adding attachment to item: {}", args.item_id);
+
+    // Security: Validate file path to prevent directory traversal attacks
+    if args.file_path.contains("..") || args.file_path.contains("~") {
+        return Err(Error::InvalidInput("File path contains invalid characters".into()));
+    }
+    let canonical_path = Path::new(&args.file_path).canonicalize()
+        .map_err(|_| Error::InvalidInput("Invalid file path or file does not exist".into()))?;
+
+    let name = match args.name {
+        Some(name) => name.trim().to_string(),
+        None => canonical_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+    };
+    if name.is_empty() {
+        return Err(Error::InvalidInput("Attachment name cannot be empty".into()));
+    }
+    if name.len() > 255 || name.contains('\0') || name.contains('/') || name.contains('\\') {
+        return Err(Error::InvalidInput("Invalid attachment name".into()));
+    }
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot add attachment.");
+        return Err(Error::VaultLocked);
+    }
+
+    if fs::metadata(&canonical_path)?.len() > fetch::storage::MAX_ATTACHMENT_SIZE as u64 {
+        return Err(Error::InvalidInput("Attachment too large (max 100MB)".into()));
+    }
+    let mut content = fs::read(&canonical_path)?;
+    let mime_type = mime_guess::from_path(&canonical_path).first_or_octet_stream().to_string();
+
+    let attachment = storage.add_attachment(&args.item_id, &name, &mime_type, &content, &crypto);
+    content.zeroize();
+    attachment
+}
+
+#[tauri::command]
+async fn list_attachments(item_id: String, app_state: State<'_, AppState>) -> Result<Vec<Attachment>> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot list attachments.");
+        return Err(Error::VaultLocked);
+    }
+    storage.list_attachments(&item_id, &crypto)
+}
+
+#[tauri::command]
+async fn remove_attachment(id: String, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Removing attachment: {}", id);
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot remove attachment.");
+        return Err(Error::VaultLocked);
+    }
+    if !storage.remove_attachment(&id, &crypto)? {
+        return Err(Error::ItemNotFound(id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn download_attachment(id: String, app_state: State<'_, AppState>) -> Result<Vec<u8>> {
+    let state = app_state.active_vault()?;
+    info!("Downloading attachment: {}", id);
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot download attachment.");
+        return Err(Error::VaultLocked);
+    }
+    let attachment = storage.get_attachment(&id, &crypto)?.ok_or_else(|| Error::ItemNotFound(id.clone()))?;
+    storage.read_attachment(&attachment, &crypto)
+}
+
+#[tauri::command]
+async fn link_items(args: LinkItemsArgs, app_state: State<'_, AppState>) -> Result<ItemLink> {
+    let state = app_state.active_vault()?;
+    info!("Linking item {} to {}", args.source_id, args.target_id);
+    if args.label.as_ref().is_some_and(|label| label.len() > 255 || label.contains('\0')) {
+        return Err(Error::InvalidInput("Invalid link label".into()));
+    }
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot link items.");
+        return Err(Error::VaultLocked);
+    }
+    storage.add_link(&args.source_id, &args.target_id, args.label.as_deref(), &crypto)
+}
+
+#[tauri::command]
+async fn unlink_items(id: String, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Removing link: {}", id);
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot unlink items.");
+        return Err(Error::VaultLocked);
+    }
+    if !storage.remove_link(&id)? {
+        return Err(Error::ItemNotFound(id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_item_links(item_id: String, app_state: State<'_, AppState>) -> Result<ItemLinks> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot get item links.");
+        return Err(Error::VaultLocked);
+    }
+    storage.get_links(&item_id, &crypto)
+}
+
+#[tauri::command]
+async fn resolve_item_references(id: String, app_state: State<'_, AppState>) -> Result<Vec<ResolvedReference>> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot resolve references.");
+        return Err(Error::VaultLocked);
+    }
+    let item = storage.get_item(&id, &crypto)?.ok_or_else(|| Error::ItemNotFound(id.clone()))?;
+    storage.resolve_references(&item, &crypto)
+}
+
+/// Shows a notification for every item that expires within the configured window and hasn't
+/// been reminded about today. Does nothing while the vault is locked.
+fn send_expiry_reminders(app_handle: &AppHandle<Wry>, state: &VaultState) -> Result<usize> {
+    let due: Vec<ExpiringItem> = {
+        let storage = &state.storage;
+        let crypto = state.crypto.read().unwrap();
+        if !crypto.is_unlocked() {
+            return Ok(0);
+        }
+        let settings = storage.get_reminder_settings()?;
+        if !settings.enabled {
+            return Ok(0);
+        }
+        storage.get_expiring_items(settings.days_before, &crypto)?
+    };
+
+    let today = Utc::now().date_naive();
+    let due: Vec<ExpiringItem> = {
+        let mut reminded = state.reminded_expiries.lock().unwrap();
+        due.into_iter()
+            .filter(|expiring| reminded.insert(expiring.item.id.clone(), today) != Some(today))
+            .collect()
+    };
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let notification = if due.len() > MAX_EXPIRY_NOTIFICATIONS {
+        let expired = due.iter().filter(|expiring| expiring.expired).count();
+        let body = due.iter().take(MAX_EXPIRY_NOTIFICATIONS).map(|expiring| expiring.item.name.as_str()).collect::<Vec<_>>().join(", ");
+        vec![(
+            format!("{} items need attention ({} expired)", due.len(), expired),
+            format!("{} and {} more", body, due.len() - MAX_EXPIRY_NOTIFICATIONS),
+        )]
+    } else {
+        due.iter().map(|expiring| ("Expiry reminder".to_string(), format!("{} {}", expiring.item.name, describe_expiry(expiring)))).collect()
+    };
+    for (title, body) in notification {
+        app_handle
+            .notification()
+            .builder()
+            .title(title)
+            .body(body)
+            .show()
+            .map_err(|e| Error::TauriError(e.to_string()))?;
+    }
+
+    info!("Sent expiry reminders for {} items.", due.len());
+    Ok(due.len())
+}
+
+#[tauri::command]
+async fn set_item_expiry(args: SetItemExpiryArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Setting expiry of item: {}", args.id);
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot set item expiry.");
+        return Err(Error::VaultLocked);
+    }
+    storage.set_item_expiry(&args.id, args.expires_at, &crypto)?;
+    // a new date deserves a new reminder
+    state.reminded_expiries.lock().unwrap().remove(&args.id);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_expiring_items(days: Option<u32>, app_state: State<'_, AppState>) -> Result<Vec<ExpiringItem>> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot get expiring items.");
+        return Err(Error::VaultLocked);
+    }
+    let days = match days {
+        Some(days) => days,
+        None => storage.get_reminder_settings()?.days_before,
+    };
+    storage.get_expiring_items(days, &crypto)
+}
+
+#[tauri::command]
+async fn get_reminder_settings(app_state: State<'_, AppState>) -> Result<ReminderSettings> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    storage.get_reminder_settings()
+}
+
+#[tauri::command]
+async fn set_reminder_settings(settings: ReminderSettings, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Setting expiry reminder settings: {:?}", settings);
+    settings.validate()?;
+    let storage = &state.storage;
+    storage.set_reminder_settings(settings)
+}
+
+#[tauri::command]
+async fn export_expiry_calendar(days: Option<u32>, app_state: State<'_, AppState>) -> Result<String> {
+    let state = app_state.active_vault()?;
+    info!("Exporting expiry calendar.");
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot export expiry calendar.");
+        return Err(Error::VaultLocked);
+    }
+    let settings = storage.get_reminder_settings()?;
+    // a year ahead by default, a calendar is meant to be subscribed to and looked at later
+    let items = storage.get_expiring_items(days.unwrap_or(365), &crypto)?;
+    Ok(to_ics(&items, settings.days_before, Utc::now()))
+}
+
+#[tauri::command]
+async fn update_item(args: UpdateItemArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Updating item: {}", args.name);
+
+    if args.name.trim().is_empty() {
+        warn!("Attempted to update item with empty name.");
+        return Err(Error::InvalidInput("Item name cannot be empty".into()));
+    }
+    if let Some(fields) = &args.custom_fields {
+        validate_custom_fields(fields)?;
+    }
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot update item.");
+        return Err(Error::VaultLocked);
+    }
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
+
+    // get the existing item to preserve its data_path
+    let existing_item = storage.get_item(&args.id, &crypto)?.ok_or_else(|| Error::ItemNotFound(args.id.clone()))?;
+    if existing_item.item_type == LOGIN_ITEM_TYPE || args.item_type == LOGIN_ITEM_TYPE {
+        return Err(Error::InvalidInput("Login items are updated with update_login_item".into()));
+    }
+    if is_record_type(&existing_item.item_type) || is_record_type(&args.item_type) {
+        return Err(Error::InvalidInput("Typed items are updated with update_typed_item".into()));
+    }
+    
+    let now = Utc::now();
+    let item_type = args.item_type.clone(); // clone it so we can use it later
+    let item = VaultItem {
+        id: args.id,
+        parent_id: args.parent_id,
+        name: args.name,
+        data_path: existing_item.data_path.clone(), // keep the same data_path
+        item_type: args.item_type,
+        folder_type: existing_item.folder_type, // preserve folder_type
+        tags: args.tags,
+        created_at: existing_item.created_at, // preserve creation date
+        updated_at: now,
+        deleted_at: existing_item.deleted_at,
+        totp_secret: args.totp_secret.or(existing_item.totp_secret), // Update if provided, else keep existing
+        custom_fields: args.custom_fields.map(normalize_custom_fields).unwrap_or(existing_item.custom_fields),
+        expires_at: existing_item.expires_at,
+    };
+
+    // update the encrypted content if it's a text item
+    if item_type == "text" || item_type == "key" || item_type == "text/plain" {
+        let encrypted_content = crypto.encrypt(args.content.as_bytes())?;
+        storage.write_encrypted_file(&encrypted_content, &existing_item.data_path)?;
+    }
+
+    // update the item metadata
+    storage.update_item_fields(&item, &crypto)?;
+    
+    info!("Item '{}' updated successfully.", item.name);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_item_content(id: String, app_state: State<'_, AppState>) -> Result<Vec<u8>> {
+    let state = app_state.active_vault()?;
+    info!("Getting content for item: {}", id);
+    
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot get item content");
+        return Err(Error::VaultLocked);
+    }
+
+    let item = match storage.get_item(&id, &crypto) {
+        Ok(Some(item)) => {
+            info!("Found item: {} (type: {})", item.name, item.item_type);
+            item
+        }
+        Ok(None) => {
+            error!("Item not found: {}", id);
+            return Err(Error::ItemNotFound(id.clone()));
+        }
+        Err(e) => {
+            error!("Failed to get item {}: {}", id, e);
+            return Err(e);
+        }
+    };
+    
+    info!("Reading encrypted file: {}", item.data_path);
+    match storage.read_encrypted_file(&item.data_path, &crypto) {
+        Ok(content) => {
+            info!("Successfully read {} bytes for item: {}", content.len(), item.name);
+            Ok(content)
+        }
+        Err(e) => {
+            error!("Failed to read encrypted file for item {}: {}", item.name, e);
+            Err(e)
+        }
+    }
+}
+
+#[tauri::command]
+async fn delete_item(id: String, app_state: State<'_, AppState>) -> Result<bool> {
+    let state = app_state.active_vault()?;
+    info!("Soft deleting item with id: {}", id);
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    storage.delete_item_and_descendants(&id, &crypto)?;
+    Ok(true)
+}
+
+#[tauri::command]
+async fn permanently_delete_item(id: String, app_state: State<'_, AppState>) -> Result<bool> {
+    let state = app_state.active_vault()?;
+    info!("Permanently deleting item with id: {}", id);
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    storage.permanently_delete_item_and_descendants(&id, &crypto)?;
+    Ok(true)
+}
+
+#[tauri::command]
+async fn permanently_delete_all_items(app_state: State<'_, AppState>) -> Result<bool> {
+    let state = app_state.active_vault()?;
+    info!("Permanently deleting all items in recycling bin");
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap().try_clone()?;
+    app_state.backup_before(&app_state.active_vault_id()?, &state, &crypto, "emptying the recycling bin").await?;
+    storage.permanently_delete_all_deleted_items(&crypto)?;
+    Ok(true)
+}
+
+#[tauri::command]
+async fn restore_item(id: String, app_state: State<'_, AppState>) -> Result<bool> {
+    let state = app_state.active_vault()?;
+    info!("Restoring item with id: {}", id);
+    let storage = &state.storage;
+    if !state.crypto.read().unwrap().is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    storage.restore_item(&id)
+}
+
+#[tauri::command]
+async fn restore_item_to_root(id: String, app_state: State<'_, AppState>) -> Result<bool> {
+    let state = app_state.active_vault()?;
+    info!("Restoring item to root with id: {}", id);
+    let storage = &state.storage;
+    if !state.crypto.read().unwrap().is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    storage.restore_item_to_root(&id)
+}
+
+#[tauri::command]
+async fn bulk_update_items(args: BulkOperationArgs, app_state: State<'_, AppState>) -> Result<BulkOperationResult> {
+    let state = app_state.active_vault()?;
+    info!("Running bulk action {:?} on {} items", args.action, args.ids.len());
+
+    if args.ids.is_empty() {
+        return Err(Error::InvalidInput("No items selected".into()));
+    }
+
+    if let BulkAction::AddTags { tags } = &args.action {
+        for tag in tags {
+            if tag.trim().is_empty() || tag.len() > 50 {
+                return Err(Error::InvalidInput("Tag name must be between 1 and 50 characters".into()));
+            }
+            if tag.contains('\0') || tag.contains('\n') || tag.contains('\r') {
+                return Err(Error::InvalidInput("Tag contains invalid characters".into()));
+            }
+        }
+    }
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    storage.bulk_update(&args.ids, &args.action, &crypto)
+}
+
+#[tauri::command]
+async fn get_deleted_items(app_state: State<'_, AppState>) -> Result<Vec<VaultItem>> {
+    let state = app_state.active_vault()?;
+    info!("Getting all deleted items");
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+
+    let all_items = storage.get_all_items_recursive(&crypto)?;
+    let deleted_items = all_items.into_iter().filter(|item| item.deleted_at.is_some()).collect();
+    Ok(deleted_items)
+}
+
+#[tauri::command]
+async fn update_master_key(args: UpdateMasterKeyArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Starting master key update process.");
+    let storage = &state.storage;
+
+    let current_salt = storage.get_salt()?;
     let current_strength = storage.get_key_derivation_strength()?;
     let verification_token = storage.get_verification_token()?;
-    let derived_key = crypto.derive_key(&args.current_key, &current_salt, current_strength)?;
-    
-    crypto.unlock(&derived_key)?;
-    if crypto.decrypt(&verification_token).is_err() {
-        crypto.lock();
+    let mut current_crypto = Crypto::new();
+    let derived_key = current_crypto.derive_key(&args.current_key, &current_salt, current_strength)?;
+
+    current_crypto.unlock(&derived_key)?;
+    if current_crypto.decrypt(&verification_token).is_err() {
+        state.crypto.write().unwrap().lock();
         error!("Invalid current master key during update attempt.");
         return Err(Error::InvalidMasterKey);
     }
     info!("Current master key verified.");
+    app_state.backup_before(&app_state.active_vault_id()?, &state, &current_crypto, "changing the master key").await?;
+
+    let mut crypto = state.crypto.write().unwrap();
+    *crypto = current_crypto;
 
     let new_strength = args.strength.unwrap_or(current_strength);
     let new_salt = Crypto::generate_salt();
@@ -885,6 +2611,11 @@ async fn update_master_key(args: UpdateMasterKeyArgs, state: State<'_, VaultStat
         }
     }
     
+    let rekeyed_attachments = storage.rekey_attachments(&crypto, &temp_crypto_for_reencrypt)?;
+    info!("Re-encrypted {} attachments.", rekeyed_attachments);
+    storage.rekey_links(&crypto, &temp_crypto_for_reencrypt)?;
+    storage.rekey_encrypted_meta(&crypto, &temp_crypto_for_reencrypt)?;
+
     let decrypted_verification_token = crypto.decrypt(&verification_token)?;
     let new_encrypted_token = temp_crypto_for_reencrypt.encrypt(&decrypted_verification_token)?;
     storage.store_verification_token(&new_encrypted_token)?;
@@ -894,22 +2625,39 @@ async fn update_master_key(args: UpdateMasterKeyArgs, state: State<'_, VaultStat
 
     *crypto = temp_crypto_for_reencrypt;
 
+    // the other devices still write the sync folder with the old key, it has to be set up again
+    let id = app_state.active_vault_id()?;
+    let mut registry = app_state.registry.lock().unwrap();
+    if registry.get(&id).is_some_and(|entry| entry.sync.is_some()) {
+        info!("Disconnecting sync folder after the master key change.");
+        registry.set_sync(&id, None)?;
+        registry.save()?;
+        drop(registry);
+        storage.clear_sync_state()?;
+    }
+
     info!("Master key updated successfully.");
     Ok(())
 }
 
 #[tauri::command]
-async fn export_decrypted_vault(args: ExportVaultArgs, state: State<'_, VaultState>) -> Result<String> {
+async fn export_decrypted_vault(mut args: ExportVaultArgs, app_handle: AppHandle<Wry>, app_state: State<'_, AppState>) -> Result<ExportSummary> {
+    let state = app_state.active_vault()?;
     info!("Exporting decrypted vault in {} format.", args.format);
+    let format = ExportFormat::parse(&args.format)?;
+    let destination = PathBuf::from(&args.path);
+    fetch::export::check_destination(&state.storage, &destination)?;
 
-    let derived_key = {
-        let storage = state.storage.lock().unwrap();
+    let mut derived_key = {
+        let storage = &state.storage;
         let salt = storage.get_salt()?;
         let temp_crypto = Crypto::new();
         let strength = storage.get_key_derivation_strength()?;
         let verification_token = storage.get_verification_token()?;
 
-        let key = temp_crypto.derive_key(&args.master_key, &salt, strength)?;
+        let key = temp_crypto.derive_key(&args.master_key, &salt, strength);
+        args.master_key.zeroize();
+        let key = key?;
         let mut checker_crypto = Crypto::new();
         checker_crypto.unlock(&key)?;
         
@@ -918,161 +2666,537 @@ async fn export_decrypted_vault(args: ExportVaultArgs, state: State<'_, VaultSta
         }
         key
     };
+    let unlocked = state.crypto.write().unwrap().unlock(&derived_key);
+    derived_key.zeroize();
+    unlocked?;
+
+    let selection = std::mem::take(&mut args.selection);
+    let vault = state.clone();
+    app_state
+        .run_export(&state, &args.export_id, true, app_handle, move |crypto, control| {
+            let crypto = crypto.ok_or(Error::VaultLocked)?;
+            let items = fetch::export::select_items(&vault.storage, crypto, &selection)?;
+            fetch::export::export_decrypted(&vault.storage, crypto, &items, format, &destination, control)
+        })
+        .await
+}
 
-    let mut crypto = state.crypto.lock().unwrap();
-    crypto.unlock(&derived_key)?;
-    
-    let storage = state.storage.lock().unwrap();
-    let items = storage.get_all_items_recursive(&crypto)?;
-    
-    match args.format.as_str() {
-        "json" => {
-            // JSON format (pretty-printed)
-            let mut decrypted_items = Vec::new();
-            for item in items {
-                if !item.data_path.is_empty() {
-                    let content = storage.read_encrypted_file(&item.data_path, &crypto)?;
-                    let mut decrypted_item = serde_json::to_value(item)?;
-                    decrypted_item["content"] = serde_json::Value::String(STANDARD.encode(&content));
-                    decrypted_items.push(decrypted_item);
-                } else {
-                    decrypted_items.push(serde_json::to_value(item)?);
-                }
-            }
-            Ok(serde_json::to_string_pretty(&decrypted_items)?)
-        },
-        "csv" => {
-            // CSV format
-            let mut csv_output = String::new();
-            csv_output.push_str("Name,Type,Content,Tags,Created At,Updated At\n");
-            
-            for item in items {
-                let content = if !item.data_path.is_empty() {
-                    String::from_utf8_lossy(&storage.read_encrypted_file(&item.data_path, &crypto)?).to_string()
-                } else {
-                    String::new()
-                };
-                
-                let tags = item.tags.join(";");
-                let csv_line = format!(
-                    "\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"\n",
-                    item.name.replace("\"", "\"\""),
-                    item.item_type.replace("\"", "\"\""),
-                    content.replace("\"", "\"\""),
-                    tags.replace("\"", "\"\""),
-                    item.created_at,
-                    item.updated_at
-                );
-                csv_output.push_str(&csv_line);
-            }
-            Ok(csv_output)
-        },
-        "txt" => {
-            // Plain text format
-            let mut text_output = String::new();
-            for item in items {
-                text_output.push_str(&format!("=== {} ===\n", item.name));
-                text_output.push_str(&format!("Type: {}\n", item.item_type));
-                if !item.tags.is_empty() {
-                    text_output.push_str(&format!("Tags: {}\n", item.tags.join(", ")));
-                }
-                text_output.push_str(&format!("Created: {}\n", item.created_at));
-                text_output.push_str(&format!("Updated: {}\n", item.updated_at));
-                
-                if !item.data_path.is_empty() {
-                    let encrypted_content = storage.read_encrypted_file(&item.data_path, &crypto)?;
-                    let content = String::from_utf8_lossy(&encrypted_content);
-                    text_output.push_str("\nContent:\n");
-                    text_output.push_str(&content);
-                }
-                text_output.push_str("\n\n");
-            }
-            Ok(text_output)
-        },
-        "md" => {
-            // Markdown format
-            let mut md_output = String::new();
-            md_output.push_str("# Vault Export\n\n");
-            
-            for item in items {
-                md_output.push_str(&format!("## {}\n\n", item.name));
-                md_output.push_str(&format!("**Type:** {}\n\n", item.item_type));
-                
-                if !item.tags.is_empty() {
-                    md_output.push_str("**Tags:** ");
-                    for (i, tag) in item.tags.iter().enumerate() {
-                        md_output.push_str(&format!("`{}`", tag));
-                        if i < item.tags.len() - 1 {
-                            md_output.push_str(", ");
-                        }
-                    }
-                    md_output.push_str("\n\n");
-                }
-                
-                md_output.push_str(&format!("**Created:** {}\n\n", item.created_at));
-                md_output.push_str(&format!("**Updated:** {}\n\n", item.updated_at));
-                
-                if !item.data_path.is_empty() {
-                    let encrypted_content = storage.read_encrypted_file(&item.data_path, &crypto)?;
-                    let content = String::from_utf8_lossy(&encrypted_content);
-                    md_output.push_str("### Content\n\n");
-                    md_output.push_str("```\n");
-                    md_output.push_str(&content);
-                    md_output.push_str("\n```\n\n");
-                }
-                
-                md_output.push_str("---\n\n");
-            }
-            Ok(md_output)
-        },
-        _ => Err(Error::InvalidInput("Unsupported export format".into())),
+#[tauri::command]
+async fn export_encrypted_vault(mut args: ExportEncryptedVaultArgs, app_handle: AppHandle<Wry>, app_state: State<'_, AppState>) -> Result<ExportSummary> {
+    let state = app_state.active_vault()?;
+    info!("Exporting encrypted vault as a zip archive.");
+    let destination = PathBuf::from(&args.path);
+    fetch::export::check_destination(&state.storage, &destination)?;
+    // the whole vault, recycle bin included, is copied as it is on disk; anything less has to be
+    // read, so needs the key
+    let selection = std::mem::take(&mut args.selection);
+    let with_key = !(selection.is_everything() && selection.include_deleted);
+
+    let vault = state.clone();
+    app_state
+        .run_export(&state, &args.export_id, with_key, app_handle, move |crypto, control| {
+            let Some(crypto) = crypto else {
+                return fetch::export::export_encrypted(&vault.storage, &destination, control);
+            };
+            let items = fetch::export::select_items(&vault.storage, crypto, &selection)?;
+            fetch::export::export_encrypted_selection(&vault.storage, crypto, &items, &destination, control)
+        })
+        .await
+}
+
+fn emit_export_progress(app_handle: &AppHandle<Wry>, export_id: &str, progress: ExportProgress) {
+    if let Err(e) = app_handle.emit("export-progress", ExportProgressEvent { export_id: export_id.to_string(), progress }) {
+        error!("Failed to emit export progress: {}", e);
     }
 }
 
+/// Asks a running export to stop; it removes what it wrote so far. Returns false if no export
+/// with that id is running.
 #[tauri::command]
-async fn export_encrypted_vault(state: State<'_, VaultState>) -> Result<Vec<u8>> {
-    info!("Exporting encrypted vault as a zip archive.");
-    let storage = state.storage.lock().unwrap();
-    let vault_path = storage.get_vault_path();
-    
-    let buffer = {
-        let buffer: Vec<u8> = Vec::new();
-        let cursor = std::io::Cursor::new(buffer);
-        let mut zip = ZipWriter::new(cursor);
-        
-        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
-
-        let walkdir = WalkDir::new(vault_path);
-        let it = walkdir.into_iter();
-
-        for entry in it.filter_map(|e| e.ok()) {
-            let path = entry.path();
-            let name = path.strip_prefix(Path::new(vault_path)).unwrap();
-
-            if path.is_file() {
-                zip.start_file(name.to_str().unwrap(), options)?;
-                let mut f = File::open(path)?;
-                let mut contents = Vec::new();
-                f.read_to_end(&mut contents)?;
-                zip.write_all(&contents)?;
-            } else if !name.as_os_str().is_empty() {
-                zip.add_directory(name.to_str().unwrap(), options)?;
-            }
+async fn cancel_export(export_id: String, app_state: State<'_, AppState>) -> Result<bool> {
+    match app_state.exports.lock().unwrap().get(&export_id) {
+        Some(cancelled) => {
+            info!("Cancelling export {}", export_id);
+            cancelled.store(true, Ordering::Relaxed);
+            Ok(true)
         }
-        
-        let cursor = zip.finish()?;
-        cursor.into_inner()
+        None => Ok(false),
+    }
+}
+
+/// Reads remote storage settings kept encrypted in the vault.
+fn load_encrypted_config<T: DeserializeOwned>(state: &VaultState, key: &str) -> Result<Option<T>> {
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot read {}.", key);
+        return Err(Error::VaultLocked);
+    }
+    let Some(mut json) = state.storage.get_encrypted_meta_value(key, &crypto)? else {
+        return Ok(None);
     };
-    
-    info!("Encrypted vault export successful.");
-    Ok(buffer)
+    let config = serde_json::from_slice(&json);
+    json.zeroize();
+    Ok(Some(config?))
+}
+
+fn store_encrypted_config<T: Serialize>(state: &VaultState, key: &str, config: &T) -> Result<()> {
+    let crypto = state.crypto.read().unwrap();
+    let mut json = serde_json::to_vec(config)?;
+    let stored = state.storage.set_encrypted_meta_value(key, &json, &crypto);
+    json.zeroize();
+    stored
+}
+
+/// Replaces the active vault with an archive from `export_encrypted_vault`. The previous vault is
+/// kept next to it and its new location returned. The restored vault is unlocked with the
+/// archive's master key.
+#[tauri::command]
+async fn restore_encrypted_vault(mut args: RestoreEncryptedVaultArgs, app_state: State<'_, AppState>) -> Result<Option<String>> {
+    let id = app_state.active_vault_id()?;
+    let state = app_state.active_vault()?;
+    info!("Restoring vault from archive {}", args.path);
+
+    // Security: only whoever can unlock the vault may replace it, unless there is nothing to lose
+    if state.storage.is_initialized() && !state.crypto.read().unwrap().is_unlocked() {
+        error!("Vault is locked, cannot restore over it.");
+        return Err(Error::VaultLocked);
+    }
+    let entry = app_state.registry.lock().unwrap().get(&id).cloned().ok_or_else(|| Error::Internal("Active vault is not registered".into()))?;
+
+    let staged = StagedRestore::prepare(Path::new(args.path.trim()), &entry.path, &args.master_key);
+    args.master_key.zeroize();
+    let previous = commit_restore(&app_state, &entry, state, staged?)?;
+    if let Some(previous) = &previous {
+        info!("Previous vault moved to {}", previous.display());
+    }
+    Ok(previous.map(|path| path.display().to_string()))
+}
+
+/// Puts a staged vault in place of the open vault `entry` and opens it again. It is unlocked
+/// with the key the restore was verified with, stays unlocked if it has the same key as the
+/// vault it replaces, and is locked otherwise. Returns where the previous vault was moved.
+fn commit_restore(app_state: &AppState, entry: &VaultEntry, state: Arc<VaultState>, staged: StagedRestore) -> Result<Option<PathBuf>> {
+    let key = staged.key().map(|key| Zeroizing::new(key.to_vec()));
+    let kept_crypto = {
+        // let a running sync finish before the vault goes away
+        let _guard = state.sync_lock.lock().unwrap();
+        // the unlocked key moves over to the reopened vault rather than being asked for again
+        let kept = staged.keeps_key().then(|| std::mem::replace(&mut *state.crypto.write().unwrap(), Crypto::new()));
+        app_state.close(&entry.id);
+        kept
+    };
+    // the database must be closed before its folder can be moved
+    drop(state);
+    let committed = staged.commit();
+    // reopen whichever vault is now in place
+    let reopened = app_state.activate(entry);
+    if let (Ok(()), Some(crypto)) = (&reopened, kept_crypto) {
+        *app_state.active_vault()?.crypto.write().unwrap() = crypto;
+    }
+    let previous = committed?;
+    reopened?;
+    if let Some(key) = key {
+        app_state.active_vault()?.crypto.write().unwrap().unlock(&key)?;
+    }
+    Ok(previous)
+}
+
+// Swaps the snapshot at `path` in for the active vault. Returns whether it was made with another
+// master key, in which case the vault reopens locked.
+fn restore_snapshot_file(app_state: &AppState, path: &Path, master_key: &mut Option<String>) -> Result<bool> {
+    let id = app_state.active_vault_id()?;
+    let state = app_state.active_vault()?;
+    let staged = {
+        let crypto = state.crypto.read().unwrap();
+        StagedRestore::from_snapshot(path, &state.storage, &crypto, master_key.as_deref())
+    };
+    if let Some(master_key) = master_key.as_mut() {
+        master_key.zeroize();
+    }
+    let staged = staged?;
+    let key_changed = !staged.keeps_key();
+    let entry = app_state.registry.lock().unwrap().get(&id).cloned().ok_or_else(|| Error::Internal("Active vault is not registered".into()))?;
+    if let Some(previous) = commit_restore(app_state, &entry, state, staged)? {
+        info!("Previous vault moved to {}", previous.display());
+    }
+    Ok(key_changed)
+}
+
+#[tauri::command]
+async fn export_backup(mut args: ExportBackupArgs, app_state: State<'_, AppState>) -> Result<BackupSummary> {
+    let state = app_state.active_vault()?;
+    info!("Writing portable backup to {}", args.path);
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot write a backup.");
+        return Err(Error::VaultLocked);
+    }
+    let summary = fetch::backup::write_backup(&state.storage, &crypto, Path::new(args.path.trim()), &args.passphrase, args.strength.unwrap_or_default());
+    args.passphrase.zeroize();
+    summary
+}
+
+/// Checks a portable backup without restoring it; needs no open or unlocked vault.
+#[tauri::command]
+async fn verify_backup(mut args: VerifyBackupArgs) -> Result<BackupSummary> {
+    info!("Verifying backup {}", args.path);
+    let summary = fetch::backup::verify_backup(Path::new(args.path.trim()), args.passphrase.as_deref());
+    if let Some(passphrase) = args.passphrase.as_mut() {
+        passphrase.zeroize();
+    }
+    summary
+}
+
+/// Brings the items of a portable backup into the open vault, resolving conflicts like a merge.
+#[tauri::command]
+async fn restore_backup(mut args: RestoreBackupArgs, app_state: State<'_, AppState>) -> Result<MergeReport> {
+    let state = app_state.active_vault()?;
+    info!("Restoring backup {} with strategy {:?} (dry run: {})", args.path, args.strategy, args.dry_run);
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot restore a backup into it.");
+        return Err(Error::VaultLocked);
+    }
+    let source = fetch::backup::open_backup(Path::new(args.path.trim()), &args.passphrase);
+    args.passphrase.zeroize();
+    fetch::merge::merge_vault(&state.storage, &crypto, &source?, args.strategy, args.dry_run)
+}
+
+fn load_webdav_config(state: &VaultState) -> Result<Option<WebDavConfig>> {
+    load_encrypted_config(state, WEBDAV_CONFIG_KEY)
+}
+
+fn require_webdav_config(state: &VaultState) -> Result<WebDavConfig> {
+    load_webdav_config(state)?.ok_or_else(|| Error::InvalidInput("No WebDAV server is set up for this vault".into()))
+}
+
+#[tauri::command]
+async fn set_webdav_config(mut args: WebDavConfigArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Saving WebDAV settings for {}", args.url);
+
+    let password = if args.password.is_empty() {
+        load_webdav_config(&state)?.map(|existing| existing.password.clone()).unwrap_or_default()
+    } else {
+        std::mem::take(&mut args.password)
+    };
+    let config = WebDavConfig { url: args.url.trim().to_string(), username: args.username.trim().to_string(), password };
+    config.validate()?;
+    store_encrypted_config(&state, WEBDAV_CONFIG_KEY, &config)
+}
+
+#[tauri::command]
+async fn get_webdav_config(app_state: State<'_, AppState>) -> Result<Option<WebDavConfig>> {
+    let state = app_state.active_vault()?;
+    Ok(load_webdav_config(&state)?.map(|config| config.redacted()))
+}
+
+#[tauri::command]
+async fn remove_webdav_config(app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Removing WebDAV settings.");
+    if !state.crypto.read().unwrap().is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    state.storage.remove_meta_value(WEBDAV_CONFIG_KEY)
+}
+
+#[tauri::command]
+async fn test_webdav_connection(app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    let config = require_webdav_config(&state)?;
+    WebDavClient::new(&config)?.check_connection().await
+}
+
+#[tauri::command]
+async fn webdav_push(force: Option<bool>, app_state: State<'_, AppState>) -> Result<TransferReport> {
+    let state = app_state.active_vault()?;
+    info!("Pushing vault to WebDAV.");
+    let config = require_webdav_config(&state)?;
+    fetch::webdav::push(&state.storage, &config, force.unwrap_or(false)).await
+}
+
+#[tauri::command]
+async fn webdav_pull(force: Option<bool>, app_state: State<'_, AppState>) -> Result<TransferReport> {
+    let id = app_state.active_vault_id()?;
+    let state = app_state.active_vault()?;
+    info!("Pulling vault from WebDAV.");
+    let config = require_webdav_config(&state)?;
+    let (report, staged) = fetch::webdav::pull(&state.storage, &config, force.unwrap_or(false)).await?;
+    let Some(staged) = staged else {
+        return Ok(report);
+    };
+    let entry = app_state.registry.lock().unwrap().get(&id).cloned().ok_or_else(|| Error::Internal("Active vault is not registered".into()))?;
+    if report.key_changed {
+        // the pulled items are encrypted with the key set on the other device
+        info!("Pulled vault uses a different master key, it opens locked.");
+    }
+    // the pulled vault replaces the local one, which is what a pull means, so it isn't kept
+    if let Some(previous) = commit_restore(&app_state, &entry, state, staged)? {
+        if let Err(e) = fs::remove_dir_all(&previous) {
+            warn!("Failed to remove the vault as it was before the pull at {}: {}", previous.display(), e);
+        }
+    }
+    Ok(report)
+}
+
+fn require_s3_config(state: &VaultState) -> Result<S3Config> {
+    load_encrypted_config(state, S3_CONFIG_KEY)?.ok_or_else(|| Error::InvalidInput("No S3 storage is set up for this vault".into()))
+}
+
+// where a snapshot on its way to or from S3 is kept
+fn s3_snapshot_path() -> PathBuf {
+    std::env::temp_dir().join(format!("fetch-s3-{}.fsnap", Uuid::new_v4()))
+}
+
+fn remove_s3_snapshot(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove temporary snapshot {}: {}", path.display(), e);
+        }
+    }
+}
+
+// Writes a snapshot of the vault on a blocking thread with `crypto`, its own copy of the key, so
+// writing out a large vault holds up neither the async runtime nor the vault's lock.
+async fn write_snapshot_in_background<T: Send + 'static>(
+    vault: &Arc<VaultState>,
+    crypto: Crypto,
+    write: impl FnOnce(&Storage, &Crypto) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let vault = vault.clone();
+    tauri::async_runtime::spawn_blocking(move || write(&vault.storage, &crypto)).await?
+}
+
+async fn backup_vault_to_s3(state: &Arc<VaultState>, config: &S3Config) -> Result<S3BackupReport> {
+    let path = s3_snapshot_path();
+    let crypto = state.crypto.read().unwrap().try_clone()?;
+    let snapshot_path = path.clone();
+    let created = write_snapshot_in_background(state, crypto, move |storage, crypto| fetch::backup::create_snapshot(storage, crypto, &snapshot_path)).await;
+    let uploaded = match created {
+        Ok(_) => fetch::s3::upload_snapshot(config, &path).await,
+        Err(e) => Err(e),
+    };
+    remove_s3_snapshot(&path);
+    uploaded
+}
+
+/// Uploads a snapshot if the vault has an S3 backup schedule and the newest snapshot is older
+/// than its interval. Returns `None` if nothing was due or the vault is locked.
+async fn run_scheduled_s3_backup(state: &Arc<VaultState>) -> Result<Option<S3BackupReport>> {
+    if !state.crypto.read().unwrap().is_unlocked() {
+        return Ok(None);
+    }
+    let Some(config) = load_encrypted_config::<S3Config>(state, S3_CONFIG_KEY)? else {
+        return Ok(None);
+    };
+    let Some(interval_hours) = config.interval_hours else {
+        return Ok(None);
+    };
+    let latest = fetch::s3::list_snapshots(&config).await?.first().map(|snapshot| snapshot.created_at);
+    if latest.is_some_and(|latest| Utc::now() - latest < ChronoDuration::hours(i64::from(interval_hours))) {
+        return Ok(None);
+    }
+    backup_vault_to_s3(state, &config).await.map(Some)
+}
+
+#[tauri::command]
+async fn set_s3_config(mut args: S3ConfigArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Saving S3 settings for bucket {} at {}", args.bucket, args.endpoint);
+
+    let secret_access_key = if args.secret_access_key.is_empty() {
+        load_encrypted_config::<S3Config>(&state, S3_CONFIG_KEY)?.map(|existing| existing.secret_access_key.clone()).unwrap_or_default()
+    } else {
+        std::mem::take(&mut args.secret_access_key)
+    };
+    let config = S3Config {
+        endpoint: args.endpoint.trim().to_string(),
+        region: args.region.trim().to_string(),
+        bucket: args.bucket.trim().to_string(),
+        prefix: args.prefix.trim().to_string(),
+        access_key_id: args.access_key_id.trim().to_string(),
+        secret_access_key,
+        path_style: args.path_style,
+        retention: args.retention,
+        interval_hours: args.interval_hours,
+        object_lock: args.object_lock,
+    };
+    config.validate()?;
+    store_encrypted_config(&state, S3_CONFIG_KEY, &config)
+}
+
+#[tauri::command]
+async fn get_s3_config(app_state: State<'_, AppState>) -> Result<Option<S3Config>> {
+    let state = app_state.active_vault()?;
+    Ok(load_encrypted_config::<S3Config>(&state, S3_CONFIG_KEY)?.map(|config| config.redacted()))
+}
+
+#[tauri::command]
+async fn remove_s3_config(app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
+    info!("Removing S3 settings.");
+    if !state.crypto.read().unwrap().is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    state.storage.remove_meta_value(S3_CONFIG_KEY)
+}
+
+#[tauri::command]
+async fn get_s3_bucket_status(app_state: State<'_, AppState>) -> Result<S3BucketStatus> {
+    let state = app_state.active_vault()?;
+    let config = require_s3_config(&state)?;
+    S3Client::new(&config)?.bucket_status().await
+}
+
+#[tauri::command]
+async fn s3_backup_now(app_state: State<'_, AppState>) -> Result<S3BackupReport> {
+    let state = app_state.active_vault()?;
+    info!("Backing up vault to S3.");
+    let config = require_s3_config(&state)?;
+    backup_vault_to_s3(&state, &config).await
+}
+
+#[tauri::command]
+async fn list_s3_snapshots(app_state: State<'_, AppState>) -> Result<Vec<S3Snapshot>> {
+    let state = app_state.active_vault()?;
+    let config = require_s3_config(&state)?;
+    fetch::s3::list_snapshots(&config).await
+}
+
+#[tauri::command]
+async fn restore_s3_snapshot(mut args: RestoreS3SnapshotArgs, app_state: State<'_, AppState>) -> Result<S3RestoreReport> {
+    info!("Restoring vault from S3 snapshot {}", args.key);
+    let config = {
+        // the vault is closed for the swap, so this handle on it must be gone by then
+        let state = app_state.active_vault()?;
+        require_s3_config(&state)?
+    };
+    let path = s3_snapshot_path();
+    let restored = match fetch::s3::download_snapshot(&config, &args.key, &path).await {
+        Ok(()) => restore_snapshot_file(&app_state, &path, &mut args.master_key),
+        Err(e) => Err(e),
+    };
+    remove_s3_snapshot(&path);
+    if let Some(master_key) = args.master_key.as_mut() {
+        master_key.zeroize();
+    }
+    let key_changed = restored?;
+    if key_changed {
+        info!("Restored snapshot uses a different master key, it opens locked.");
+    }
+    Ok(S3RestoreReport { key: args.key, key_changed })
+}
+
+#[tauri::command]
+async fn get_backup_schedule(app_state: State<'_, AppState>) -> Result<Option<BackupSchedule>> {
+    let id = app_state.active_vault_id()?;
+    Ok(app_state.registry.lock().unwrap().get(&id).and_then(|entry| entry.backup.clone()))
+}
+
+#[tauri::command]
+async fn set_backup_schedule(args: BackupScheduleArgs, app_handle: AppHandle<Wry>, app_state: State<'_, AppState>) -> Result<BackupSchedule> {
+    let id = app_state.active_vault_id()?;
+    let mut registry = app_state.registry.lock().unwrap();
+    let entry = registry.get(&id).ok_or_else(|| Error::ItemNotFound(id.clone()))?;
+
+    let directory = match args.directory.as_deref().map(str::trim).filter(|directory| !directory.is_empty()) {
+        Some(directory) => PathBuf::from(directory),
+        None => app_handle.path().app_data_dir().map_err(|e| Error::Internal(e.to_string()))?.join("backups").join(&id),
+    };
+    // a restore replaces the vault folder, backups inside it would go with it
+    if directory.starts_with(&entry.path) {
+        return Err(Error::InvalidInput("Backups can't be kept inside the vault folder".into()));
+    }
+    info!("Saving backup schedule for vault '{}', backups go to {}", entry.name, directory.display());
+
+    // the status of earlier backups carries over
+    let mut schedule = entry.backup.clone().unwrap_or_else(|| BackupSchedule::new(directory.clone()));
+    schedule.directory = directory;
+    schedule.daily = args.daily;
+    schedule.every_writes = args.every_writes;
+    schedule.before_destructive = args.before_destructive;
+    schedule.keep_daily = args.keep_daily.unwrap_or(schedule.keep_daily);
+    schedule.keep_weekly = args.keep_weekly.unwrap_or(schedule.keep_weekly);
+    schedule.keep_monthly = args.keep_monthly.unwrap_or(schedule.keep_monthly);
+    schedule.validate()?;
+
+    registry.set_backup(&id, Some(schedule.clone()))?;
+    registry.save()?;
+    Ok(schedule)
+}
+
+/// Stops automatic backups. The backups already written stay where they are.
+#[tauri::command]
+async fn disable_backups(app_state: State<'_, AppState>) -> Result<()> {
+    let id = app_state.active_vault_id()?;
+    info!("Turning off automatic backups.");
+    let mut registry = app_state.registry.lock().unwrap();
+    registry.set_backup(&id, None)?;
+    registry.save()
+}
+
+#[tauri::command]
+async fn backup_now(app_state: State<'_, AppState>) -> Result<LocalBackup> {
+    let id = app_state.active_vault_id()?;
+    let state = app_state.active_vault()?;
+    info!("Backing up vault.");
+    let crypto = state.crypto.read().unwrap().try_clone()?;
+    app_state
+        .backup_vault(&id, &state, crypto)
+        .await?
+        .ok_or_else(|| Error::InvalidInput("No backup directory is set up for this vault".into()))
+}
+
+#[tauri::command]
+async fn list_local_backups(app_state: State<'_, AppState>) -> Result<Vec<LocalBackup>> {
+    let id = app_state.active_vault_id()?;
+    let directory = app_state.registry.lock().unwrap().get(&id).and_then(|entry| entry.backup.as_ref().map(|backup| backup.directory.clone()));
+    match directory {
+        Some(directory) => fetch::backup::list_local_backups(&directory),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Replaces the vault with a local backup. Returns whether the vault now needs the master key
+/// the backup was made with, in which case it is locked.
+#[tauri::command]
+async fn restore_local_backup(mut args: RestoreLocalBackupArgs, app_state: State<'_, AppState>) -> Result<bool> {
+    info!("Restoring vault from backup {}", args.path);
+    let key_changed = restore_snapshot_file(&app_state, Path::new(&args.path), &mut args.master_key)?;
+    if key_changed {
+        info!("Restored backup uses a different master key, it opens locked.");
+    }
+    Ok(key_changed)
+}
+
+#[tauri::command]
+async fn merge_vault(mut args: MergeVaultArgs, app_state: State<'_, AppState>) -> Result<MergeReport> {
+    let state = app_state.active_vault()?;
+    info!("Merging vault from {} with strategy {:?} (dry run: {})", args.path, args.strategy, args.dry_run);
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+
+    if !crypto.is_unlocked() {
+        error!("Vault is locked, cannot merge another vault into it.");
+        return Err(Error::VaultLocked);
+    }
+
+    let path = validate_vault_path(&args.path)?;
+    let source = MergeSource::open(&path, &args.master_key);
+    args.master_key.zeroize();
+    let source = source?;
+    let report = fetch::merge::merge_vault(storage, &crypto, &source, args.strategy, args.dry_run)?;
+    Ok(report)
 }
 
 #[tauri::command]
-async fn delete_vault(args: DeleteVaultArgs, _app_handle: AppHandle<Wry>, state: State<'_, VaultState>) -> Result<()> {
+async fn delete_vault(args: DeleteVaultArgs, _app_handle: AppHandle<Wry>, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Starting vault deletion process.");
     
-    let storage = state.storage.lock().unwrap();
+    let storage = &state.storage;
     let salt = storage.get_salt()?;
     let strength = storage.get_key_derivation_strength()?;
     let temp_crypto = Crypto::new();
@@ -1085,21 +3209,23 @@ async fn delete_vault(args: DeleteVaultArgs, _app_handle: AppHandle<Wry>, state:
     if checker_crypto.decrypt(&verification_token).is_err() {
         return Err(Error::InvalidMasterKey);
     }
+    app_state.backup_before(&app_state.active_vault_id()?, &state, &checker_crypto, "deleting the vault").await?;
 
     // reset the storage state (clear database and data files)
     storage.reset()?;
     
     // lock the crypto state (security first!)
-    state.crypto.lock().unwrap().lock();
+    state.crypto.write().unwrap().lock();
     
     info!("Vault deleted and state reset successfully.");
     Ok(())
 }
 
 #[tauri::command]
-async fn get_all_tags(state: State<'_, VaultState>) -> Result<Vec<String>> {
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+async fn get_all_tags(app_state: State<'_, AppState>) -> Result<Vec<String>> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
     if !crypto.is_unlocked() {
         return Err(Error::VaultLocked);
     }
@@ -1116,15 +3242,16 @@ async fn get_all_tags(state: State<'_, VaultState>) -> Result<Vec<String>> {
 }
 
 #[tauri::command]
-async fn rename_tag(args: RenameTagArgs, state: State<'_, VaultState>) -> Result<()> {
+async fn rename_tag(args: RenameTagArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Renaming tag from '{}' to '{}'", args.old_tag_name, args.new_tag_name);
     if args.new_tag_name.trim().is_empty() {
         warn!("Attempted to rename tag to an empty string.");
         return Err(Error::InvalidInput("New tag name cannot be empty".into()));
     }
     
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
     if !crypto.is_unlocked() {
         error!("Vault is locked, cannot rename tag.");
@@ -1137,11 +3264,12 @@ async fn rename_tag(args: RenameTagArgs, state: State<'_, VaultState>) -> Result
 }
 
 #[tauri::command]
-async fn delete_tag(args: DeleteTagArgs, state: State<'_, VaultState>) -> Result<()> {
+async fn delete_tag(args: DeleteTagArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Deleting tag: {}", args.tag_name);
     
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
 
     if !crypto.is_unlocked() {
         error!("Vault is locked, cannot delete tag.");
@@ -1154,16 +3282,20 @@ async fn delete_tag(args: DeleteTagArgs, state: State<'_, VaultState>) -> Result
 }
 
 #[tauri::command]
-async fn import_csv(args: CsvImportArgs, state: State<'_, VaultState>) -> Result<()> {
+async fn import_csv(args: CsvImportArgs, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Importing CSV content.");
 
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
-
-    if !crypto.is_unlocked() {
-        error!("Vault is locked, cannot import CSV content.");
-        return Err(Error::VaultLocked);
-    }
+    let storage = &state.storage;
+    let crypto = {
+        let crypto = state.crypto.read().unwrap();
+        if !crypto.is_unlocked() {
+            error!("Vault is locked, cannot import CSV content.");
+            return Err(Error::VaultLocked);
+        }
+        crypto.try_clone()?
+    };
+    app_state.backup_before(&app_state.active_vault_id()?, &state, &crypto, "importing CSV content").await?;
 
     let csv_content = &args.csv_content;
     let parent_id = args.parent_id;
@@ -1217,37 +3349,24 @@ async fn import_csv(args: CsvImportArgs, state: State<'_, VaultState>) -> Result
                     continue;
                 }
 
-                // create the content for the password item (let's organize this mess)
-                let mut content = String::new();
-                if let Some(username_val) = &username {
-                    if !username_val.trim().is_empty() {
-                        content.push_str(&format!("Username: {}\n\n", username_val.trim()));
-                    }
-                }
-                if let Some(password_val) = &password {
-                    if !password_val.trim().is_empty() {
-                        content.push_str(&format!("Password: {}\n\n", password_val.trim()));
-                    }
-                }
-                if let Some(url_val) = &url {
-                    if !url_val.trim().is_empty() {
-                        content.push_str(&format!("URL: {}\n\n", url_val.trim()));
-                    }
-                }
-                if let Some(notes_val) = &notes {
-                    if !notes_val.trim().is_empty() {
-                        content.push_str(&format!("Notes: {}\n\n", notes_val.trim()));
-                    }
+                let login = LoginData {
+                    username: username.unwrap_or_default(),
+                    password: password.map(|p| p.trim().to_string()).unwrap_or_default(),
+                    urls: url.into_iter().collect(),
+                    notes: notes.map(|n| n.trim().to_string()).unwrap_or_default(),
                 }
-                let content = content.trim_end().to_string();
-                info!("Created content for row {} (length: {})", row_count, content.len());
-                
+                .normalized();
+
                 // skip rows with no content
-                if content.trim().is_empty() {
+                if login.username.is_empty() && login.password.is_empty() && login.urls.is_empty() && login.notes.is_empty() {
                     info!("Skipping row {} - no content to store", row_count);
                     continue;
                 }
-                
+                if let Err(e) = login.validate() {
+                    warn!("Skipping row {} - {}", row_count, e);
+                    continue;
+                }
+
                 // parse tags
                 let tags_vec: Vec<String> = tags
                     .unwrap_or_default()
@@ -1263,35 +3382,24 @@ async fn import_csv(args: CsvImportArgs, state: State<'_, VaultState>) -> Result
                     id: Uuid::new_v4().to_string(),
                     parent_id: parent_id.clone(),
                     name: title.unwrap().trim().to_string(),
-                    data_path: "".to_string(),
-                    item_type: "key".to_string(),
+                    data_path: Uuid::new_v4().to_string(),
+                    item_type: LOGIN_ITEM_TYPE.to_string(),
                     folder_type: None,
                     tags: tags_vec,
                     created_at: Utc::now(),
                     updated_at: Utc::now(),
                     deleted_at: None,
                     totp_secret: None,
+                    custom_fields: vec![],
+                    expires_at: None,
                 };
 
                 info!("Created vault item for row {}: {} (id: {})", row_count, item.name, item.id);
 
-                // add the item to storage
+                storage.write_login(&item.data_path, &login, &crypto)?;
                 storage.add_item(&item, &crypto)?;
-                info!("Added item to storage for row {}", row_count);
-                
-                // write the content to a file
-                let file_name = format!("{}.txt", item.id);
-                info!("Writing content to file: {} (content length: {})", file_name, content.len());
-                let encrypted_content = crypto.encrypt(content.as_bytes())?;
-                storage.write_encrypted_file(&encrypted_content, &file_name)?;
-                info!("Successfully wrote encrypted content to file: {}", file_name);
-                
-                // update the item with the correct data path
-                let mut updated_item = item.clone();
-                updated_item.data_path = file_name.clone();
-                storage.update_item_fields(&updated_item, &crypto)?;
-                info!("Updated item data_path to: {}", file_name);
-                
+                info!("Added login item to storage for row {}", row_count);
+
                 imported_count += 1;
                 info!("Successfully imported row {}: {}", row_count, item.name);
             }
@@ -1307,9 +3415,56 @@ async fn import_csv(args: CsvImportArgs, state: State<'_, VaultState>) -> Result
 }
 
 #[tauri::command]
-async fn get_all_vault_items(state: State<'_, VaultState>) -> Result<Vec<VaultItem>> {
-    let storage = state.storage.lock().unwrap();
-    let crypto = state.crypto.lock().unwrap();
+async fn import_bitwarden(args: BitwardenImportArgs, app_state: State<'_, AppState>) -> Result<BitwardenImportReport> {
+    let state = app_state.active_vault()?;
+    info!("Importing a Bitwarden export ({} bytes).", args.content.len());
+
+    let storage = &state.storage;
+    let crypto = {
+        let crypto = state.crypto.read().unwrap();
+        if !crypto.is_unlocked() {
+            error!("Vault is locked, cannot import a Bitwarden export.");
+            return Err(Error::VaultLocked);
+        }
+        crypto.try_clone()?
+    };
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
+    app_state.backup_before(&app_state.active_vault_id()?, &state, &crypto, "importing a Bitwarden export").await?;
+    fetch::bitwarden::import_bitwarden(storage, &crypto, &args.content, args.parent_id.as_deref())
+}
+
+// The database is unlocked on a blocking thread; its key derivation is meant to take a while.
+#[tauri::command]
+async fn import_keepass(mut args: KeePassImportArgs, app_state: State<'_, AppState>) -> Result<KeePassImportReport> {
+    let state = app_state.active_vault()?;
+    info!("Importing KeePass database {}.", args.path);
+    if !state.crypto.read().unwrap().is_unlocked() {
+        error!("Vault is locked, cannot import a KeePass database.");
+        return Err(Error::VaultLocked);
+    }
+
+    let data = fs::read(&args.path)?;
+    let key_file = args.key_file_path.as_ref().map(fs::read).transpose()?;
+    let mut password = std::mem::take(&mut args.password);
+    let database = tauri::async_runtime::spawn_blocking(move || {
+        let database = KeePassDatabase::open(&data, &password, key_file.as_deref());
+        password.zeroize();
+        database
+    })
+    .await??;
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap().try_clone()?;
+    ensure_not_smart_folder(storage, args.parent_id.as_deref(), &crypto)?;
+    app_state.backup_before(&app_state.active_vault_id()?, &state, &crypto, "importing a KeePass database").await?;
+    fetch::keepass::import_keepass(storage, &crypto, &database, args.parent_id.as_deref())
+}
+
+#[tauri::command]
+async fn get_all_vault_items(app_state: State<'_, AppState>) -> Result<Vec<VaultItem>> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
     if !crypto.is_unlocked() {
         return Err(Error::VaultLocked);
     }
@@ -1317,16 +3472,54 @@ async fn get_all_vault_items(state: State<'_, VaultState>) -> Result<Vec<VaultIt
 }
 
 #[tauri::command]
-async fn get_theme(state: State<'_, VaultState>) -> Result<String> {
-    let storage = state.storage.lock().unwrap();
+async fn search_items(args: SearchOptions, app_state: State<'_, AppState>) -> Result<Vec<SearchHit>> {
+    let state = app_state.active_vault()?;
+    // Security: never log the query itself, it may be a password being looked up
+    info!("Searching vault items (query length: {})", args.query.len());
+
+    if args.query.len() > 1024 {
+        return Err(Error::InvalidInput("Search query too long (max 1024 characters)".into()));
+    }
+
+    let storage = &state.storage;
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    fetch::search::search_items(storage, &crypto, &args)
+}
+
+#[tauri::command]
+async fn query_items(args: QueryItemsArgs, app_state: State<'_, AppState>) -> Result<Vec<VaultItem>> {
+    let state = app_state.active_vault()?;
+    info!("Running item query (length: {})", args.query.len());
+
+    if args.query.len() > 1024 {
+        return Err(Error::InvalidInput("Query too long (max 1024 characters)".into()));
+    }
+    let storage = &state.storage;
+    let query = storage.parse_query(&args.query)?;
+
+    let crypto = state.crypto.read().unwrap();
+    if !crypto.is_unlocked() {
+        return Err(Error::VaultLocked);
+    }
+    storage.query_items(&query, args.order_by, &crypto)
+}
+
+#[tauri::command]
+async fn get_theme(app_state: State<'_, AppState>) -> Result<String> {
+    let state = app_state.active_vault()?;
+    let storage = &state.storage;
     let theme = storage.get_theme()?;
     Ok(theme)
 }
 
 #[tauri::command]
-async fn set_theme(theme: String, state: State<'_, VaultState>) -> Result<()> {
+async fn set_theme(theme: String, app_state: State<'_, AppState>) -> Result<()> {
+    let state = app_state.active_vault()?;
     info!("Setting theme to: {}", theme);
-    let storage = state.storage.lock().unwrap();
+    let storage = &state.storage;
     storage.set_theme(&theme)
 }
 
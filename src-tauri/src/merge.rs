@@ -0,0 +1,547 @@
+use chrono::Utc;
+use log::{info, warn};
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::crypto::Crypto;
+use crate::error::Error;
+use crate::links::FieldReference;
+use crate::registry::is_vault_dir;
+use crate::storage::{Storage, VaultItem};
+use crate::Result;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    // import the incoming item next to the existing one under a new id
+    KeepBoth,
+    // replace the existing item if the incoming one was updated more recently
+    PreferNewer,
+    // leave the existing item alone
+    Skip,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    // the same item exists on both sides, e.g. a vault merged back into its own copy
+    SameId,
+    SameNameAndType,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MergeConflict {
+    pub source_id: String,
+    pub target_id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub kind: ConflictKind,
+    // "added", "replaced" or "skipped"
+    pub resolution: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MergeReport {
+    pub added: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+    // folders that already existed and had the incoming items merged into them
+    pub folders_merged: usize,
+    pub attachments: usize,
+    pub links: usize,
+    pub conflicts: Vec<MergeConflict>,
+    pub dry_run: bool,
+}
+
+enum Action {
+    Add { id: String, name: String },
+    Replace { target: Box<VaultItem> },
+    MergeFolder,
+    Skip,
+}
+
+struct PlannedItem {
+    source: VaultItem,
+    target_parent: Option<String>,
+    action: Action,
+}
+
+/// The vault being merged in, opened under its own master key. It is always a copy in a
+/// temporary directory, unpacked from an archive or copied from a vault folder, so opening it
+/// never changes the original; the copy is removed again on drop.
+pub struct MergeSource {
+    storage: Option<Storage>,
+    crypto: Crypto,
+    temp_dir: Option<PathBuf>,
+    // the vault folder the copy was made from, to refuse merging a vault into itself
+    origin: Option<PathBuf>,
+}
+
+impl MergeSource {
+    /// A vault built in `temp_dir` for the merge, e.g. from a portable backup; removed on drop.
+    pub(crate) fn from_temporary(storage: Storage, crypto: Crypto, temp_dir: PathBuf) -> Self {
+        Self { storage: Some(storage), crypto, temp_dir: Some(temp_dir), origin: None }
+    }
+
+    pub fn open(path: &Path, master_key: &str) -> Result<Self> {
+        let temp_dir = std::env::temp_dir().join(format!("fetch-merge-{}", uuid::Uuid::new_v4()));
+        let mut source = Self { storage: None, crypto: Crypto::new(), temp_dir: None, origin: None };
+        if path.is_dir() {
+            if !is_vault_dir(path) {
+                return Err(Error::InvalidInput("No vault found at this location".into()));
+            }
+            source.origin = Some(fs::canonicalize(path)?);
+            source.temp_dir = Some(temp_dir.clone());
+            copy_vault(path, &temp_dir)?;
+        } else if path.is_file() {
+            source.temp_dir = Some(temp_dir.clone());
+            extract_archive(File::open(path)?, &temp_dir)?;
+        } else {
+            return Err(Error::InvalidInput("Vault to merge not found".into()));
+        }
+        if !is_vault_dir(&temp_dir) {
+            return Err(Error::InvalidInput("No vault found at this location".into()));
+        }
+
+        let storage = Storage::new(temp_dir)?;
+        if !storage.is_initialized() {
+            return Err(Error::InvalidInput("The vault to merge has not been set up".into()));
+        }
+        let key = source.crypto.derive_key(master_key, &storage.get_salt()?, storage.get_key_derivation_strength()?)?;
+        source.crypto.unlock(&key)?;
+        if source.crypto.decrypt(&storage.get_verification_token()?).is_err() {
+            return Err(Error::InvalidMasterKey);
+        }
+        source.storage = Some(storage);
+        Ok(source)
+    }
+}
+
+impl Drop for MergeSource {
+    fn drop(&mut self) {
+        self.crypto.lock();
+        // close the database before its directory goes away
+        self.storage.take();
+        if let Some(temp_dir) = &self.temp_dir {
+            if let Err(e) = fs::remove_dir_all(temp_dir) {
+                warn!("Failed to remove temporary merge directory {}: {}", temp_dir.display(), e);
+            }
+        }
+    }
+}
+
+// copies the vault at `source` into `destination`, reading the database through a read-only
+// connection so nothing in the original is touched, not even its schema
+fn copy_vault(source: &Path, destination: &Path) -> Result<()> {
+    fs::create_dir_all(destination.join("data"))?;
+    let conn = Connection::open_with_flags(source.join("vault.db"), OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+    conn.backup(DatabaseName::Main, destination.join("vault.db"), None)?;
+    for name in ["salt", "verify"] {
+        if source.join(name).is_file() {
+            fs::copy(source.join(name), destination.join(name))?;
+        }
+    }
+    if source.join("data").is_dir() {
+        for entry in fs::read_dir(source.join("data"))? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                fs::copy(entry.path(), destination.join("data").join(entry.file_name()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn extract_archive<R: io::Read + io::Seek>(reader: R, destination: &Path) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        // Security: never let an entry escape the destination (zip slip)
+        let Some(relative) = entry.enclosed_name().map(Path::to_path_buf) else {
+            return Err(Error::InvalidInput("Archive contains an invalid path".into()));
+        };
+        let out_path = destination.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&out_path)?;
+            continue;
+        }
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&out_path)?)?;
+    }
+    Ok(())
+}
+
+/// Merges every live item of `source` into `target`, re-encrypting it under `target_crypto`.
+/// Folders are matched by id or by name within the same parent and merged rather than
+/// duplicated, so the source's folder structure ends up inside the target's. With `dry_run`
+/// nothing is written and the report describes what would happen. A merge that fails part way
+/// is rolled back, the target is either fully merged or left as it was.
+pub fn merge_vault(target: &Storage, target_crypto: &Crypto, source: &MergeSource, strategy: MergeStrategy, dry_run: bool) -> Result<MergeReport> {
+    let source_storage = source.storage.as_ref().ok_or_else(|| Error::Internal("Merge source is not open".into()))?;
+    if source.origin.is_some() && source.origin == fs::canonicalize(target.get_vault_path()).ok() {
+        return Err(Error::InvalidInput("A vault cannot be merged into itself".into()));
+    }
+
+    let source_items: Vec<VaultItem> = source_storage
+        .get_all_items_recursive(&source.crypto)?
+        .into_iter()
+        .filter(|item| item.deleted_at.is_none())
+        .collect();
+    let target_items: Vec<VaultItem> = target.get_all_items_recursive(target_crypto)?.into_iter().filter(|item| item.deleted_at.is_none()).collect();
+
+    let mut report = MergeReport { dry_run, ..Default::default() };
+    let (plan, id_map) = plan_merge(parents_first(source_items), target_items, strategy, &mut report);
+    if dry_run {
+        return Ok(report);
+    }
+
+    let replaced_paths = target.with_rollback(|written| apply_merge(target, target_crypto, source, &plan, &id_map, &mut report, written))?;
+    // replaced content went to new files, the ones it replaced are only unused now
+    target.shred_data_files(&replaced_paths);
+
+    info!(
+        "Merged vault: {} added, {} replaced, {} skipped, {} folders merged, {} attachments, {} links",
+        report.added, report.replaced, report.skipped, report.folders_merged, report.attachments, report.links
+    );
+    Ok(report)
+}
+
+// writes the planned items, attachments and links, noting every data file it writes in `written`;
+// returns the data files of replaced items
+fn apply_merge(
+    target: &Storage,
+    target_crypto: &Crypto,
+    source: &MergeSource,
+    plan: &[PlannedItem],
+    id_map: &HashMap<String, String>,
+    report: &mut MergeReport,
+    written: &mut Vec<String>,
+) -> Result<Vec<String>> {
+    let source_storage = source.storage.as_ref().ok_or_else(|| Error::Internal("Merge source is not open".into()))?;
+    let mut replaced_paths = Vec::new();
+    for planned in plan {
+        let target_id = match &planned.action {
+            Action::Skip | Action::MergeFolder => continue,
+            Action::Add { id, name } => {
+                let mut item = planned.source.clone();
+                item.id = id.clone();
+                item.name = name.clone();
+                item.parent_id = planned.target_parent.clone();
+                if !item.data_path.is_empty() {
+                    item.data_path = uuid::Uuid::new_v4().to_string();
+                    written.push(item.data_path.clone());
+                    copy_content(source_storage, &source.crypto, &planned.source.data_path, target, target_crypto, &item.data_path)?;
+                }
+                remap_references(&mut item, id_map);
+                target.add_item(&item, target_crypto)?;
+                item.id
+            }
+            Action::Replace { target: existing } => {
+                let mut item = planned.source.clone();
+                item.id = existing.id.clone();
+                item.parent_id = existing.parent_id.clone();
+                item.created_at = existing.created_at;
+                item.data_path = existing.data_path.clone();
+                if !planned.source.data_path.is_empty() {
+                    // a new file, so a rollback still finds the old content where it was
+                    item.data_path = uuid::Uuid::new_v4().to_string();
+                    written.push(item.data_path.clone());
+                    copy_content(source_storage, &source.crypto, &planned.source.data_path, target, target_crypto, &item.data_path)?;
+                    if !existing.data_path.is_empty() {
+                        replaced_paths.push(existing.data_path.clone());
+                    }
+                }
+                remap_references(&mut item, id_map);
+                target.update_item_fields(&item, target_crypto)?;
+                item.id
+            }
+        };
+        report.attachments += copy_attachments(source_storage, &source.crypto, &planned.source.id, target, target_crypto, &target_id, written)?;
+    }
+
+    for link in source_storage.list_all_links(&source.crypto)? {
+        let (Some(source_id), Some(target_id)) = (id_map.get(&link.source_id), id_map.get(&link.target_id)) else {
+            continue;
+        };
+        // already linked on our side is fine, the link is what we wanted either way
+        if target.add_link(source_id, target_id, link.label.as_deref(), target_crypto).is_ok() {
+            report.links += 1;
+        }
+    }
+    Ok(replaced_paths)
+}
+
+// orders items so every folder comes before its contents; items whose parent isn't part of
+// the merge (e.g. it is in the trash) are treated as top level
+fn parents_first(items: Vec<VaultItem>) -> Vec<VaultItem> {
+    let ids: HashSet<String> = items.iter().map(|item| item.id.clone()).collect();
+    let mut children: HashMap<Option<String>, Vec<VaultItem>> = HashMap::new();
+    for item in items {
+        let parent = item.parent_id.clone().filter(|parent| ids.contains(parent));
+        children.entry(parent).or_default().push(item);
+    }
+
+    let mut ordered = Vec::with_capacity(ids.len());
+    let mut queue: VecDeque<VaultItem> = children.remove(&None).unwrap_or_default().into();
+    while let Some(item) = queue.pop_front() {
+        if let Some(kids) = children.remove(&Some(item.id.clone())) {
+            queue.extend(kids);
+        }
+        ordered.push(item);
+    }
+    // whatever is left hangs off a parent cycle; import it at the top level rather than lose it
+    for (_, mut rest) in children {
+        for item in &mut rest {
+            item.parent_id = None;
+        }
+        ordered.extend(rest);
+    }
+    ordered
+}
+
+fn plan_merge(source_items: Vec<VaultItem>, target_items: Vec<VaultItem>, strategy: MergeStrategy, report: &mut MergeReport) -> (Vec<PlannedItem>, HashMap<String, String>) {
+    let mut by_id: HashMap<String, VaultItem> = target_items.iter().map(|item| (item.id.clone(), item.clone())).collect();
+    let key = |parent: &Option<String>, name: &str, item_type: &str| (parent.clone(), name.to_lowercase(), item_type.to_string());
+    let mut by_name: HashMap<(Option<String>, String, String), String> =
+        target_items.iter().map(|item| (key(&item.parent_id, &item.name, &item.item_type), item.id.clone())).collect();
+    // source id -> id the item has (or will have) in the target
+    let mut id_map: HashMap<String, String> = HashMap::new();
+    let mut plan = Vec::with_capacity(source_items.len());
+
+    for source in source_items {
+        let target_parent = source.parent_id.as_ref().and_then(|parent| id_map.get(parent)).cloned();
+        let name_key = key(&target_parent, &source.name, &source.item_type);
+        let existing = match by_id.get(&source.id) {
+            Some(existing) => Some((existing.clone(), ConflictKind::SameId)),
+            None => by_name.get(&name_key).and_then(|id| by_id.get(id)).map(|existing| (existing.clone(), ConflictKind::SameNameAndType)),
+        };
+
+        let action = match existing {
+            Some((existing, _)) if Storage::is_folder_like(&source.item_type) && existing.item_type == source.item_type => {
+                id_map.insert(source.id.clone(), existing.id.clone());
+                report.folders_merged += 1;
+                Action::MergeFolder
+            }
+            None => {
+                id_map.insert(source.id.clone(), source.id.clone());
+                report.added += 1;
+                Action::Add { id: source.id.clone(), name: source.name.clone() }
+            }
+            Some((existing, kind)) => {
+                let unchanged = kind == ConflictKind::SameId && existing.updated_at == source.updated_at;
+                let action = match strategy {
+                    _ if unchanged => Action::Skip,
+                    MergeStrategy::Skip => Action::Skip,
+                    MergeStrategy::PreferNewer if source.updated_at > existing.updated_at => Action::Replace { target: Box::new(existing.clone()) },
+                    MergeStrategy::PreferNewer => Action::Skip,
+                    MergeStrategy::KeepBoth => Action::Add {
+                        // keeping the source id when it is free lets a repeated merge recognise the copy
+                        id: if by_id.contains_key(&source.id) { uuid::Uuid::new_v4().to_string() } else { source.id.clone() },
+                        name: format!("{} (merged {})", source.name, Utc::now().format("%Y-%m-%d")),
+                    },
+                };
+                let resolution = match &action {
+                    Action::Add { id, .. } => {
+                        id_map.insert(source.id.clone(), id.clone());
+                        report.added += 1;
+                        "added"
+                    }
+                    Action::Replace { target } => {
+                        id_map.insert(source.id.clone(), target.id.clone());
+                        report.replaced += 1;
+                        "replaced"
+                    }
+                    _ => {
+                        // links and references to a skipped item point at the one we kept
+                        id_map.insert(source.id.clone(), existing.id.clone());
+                        report.skipped += 1;
+                        "skipped"
+                    }
+                };
+                report.conflicts.push(MergeConflict {
+                    source_id: source.id.clone(),
+                    target_id: existing.id.clone(),
+                    name: source.name.clone(),
+                    item_type: source.item_type.clone(),
+                    kind,
+                    resolution: resolution.to_string(),
+                });
+                action
+            }
+        };
+
+        // later items in the same merge conflict with what this one is about to become
+        if let Action::Add { id, name } = &action {
+            let mut added = source.clone();
+            added.id = id.clone();
+            added.name = name.clone();
+            added.parent_id = target_parent.clone();
+            by_name.insert(key(&target_parent, name, &source.item_type), id.clone());
+            by_id.insert(id.clone(), added);
+        }
+        plan.push(PlannedItem { source, target_parent, action });
+    }
+    (plan, id_map)
+}
+
+fn copy_content(source: &Storage, source_crypto: &Crypto, source_path: &str, target: &Storage, target_crypto: &Crypto, target_path: &str) -> Result<()> {
+    let mut content = source.read_encrypted_file(source_path, source_crypto)?;
+    let encrypted = target_crypto.encrypt(&content);
+    zeroize::Zeroize::zeroize(&mut content);
+    target.write_encrypted_file(&encrypted?, target_path)
+}
+
+fn copy_attachments(source: &Storage, source_crypto: &Crypto, source_id: &str, target: &Storage, target_crypto: &Crypto, target_id: &str, written: &mut Vec<String>) -> Result<usize> {
+    let existing: HashSet<String> = target.list_attachments(target_id, target_crypto)?.into_iter().map(|attachment| attachment.name).collect();
+    let mut copied = 0;
+    for attachment in source.list_attachments(source_id, source_crypto)? {
+        if existing.contains(&attachment.name) {
+            continue;
+        }
+        let mut content = source.read_attachment(&attachment, source_crypto)?;
+        let added = target.add_attachment(target_id, &attachment.name, &attachment.mime_type, &content, target_crypto);
+        zeroize::Zeroize::zeroize(&mut content);
+        written.push(added?.data_path);
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+// `{ref:...}` values follow the items they point at when those got a new id
+fn remap_references(item: &mut VaultItem, id_map: &HashMap<String, String>) {
+    for field in &mut item.custom_fields {
+        if let Some(mut reference) = FieldReference::parse(&field.value) {
+            if let Some(mapped) = id_map.get(&reference.item_id) {
+                reference.item_id = mapped.clone();
+                field.value = reference.to_value();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, TimeZone};
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap() + Duration::minutes(minute)
+    }
+
+    fn item(id: &str, parent: Option<&str>, name: &str, item_type: &str, updated_minute: i64) -> VaultItem {
+        VaultItem {
+            id: id.into(),
+            parent_id: parent.map(Into::into),
+            name: name.into(),
+            data_path: String::new(),
+            item_type: item_type.into(),
+            folder_type: None,
+            tags: vec![],
+            created_at: at(0),
+            updated_at: at(updated_minute),
+            deleted_at: None,
+            totp_secret: None,
+            custom_fields: vec![],
+            expires_at: None,
+        }
+    }
+
+    fn plan(source: Vec<VaultItem>, target: Vec<VaultItem>, strategy: MergeStrategy) -> (Vec<(String, String)>, HashMap<String, String>, MergeReport) {
+        let mut report = MergeReport::default();
+        let (plan, id_map) = plan_merge(parents_first(source), target, strategy, &mut report);
+        let actions = plan
+            .iter()
+            .map(|planned| {
+                let action = match &planned.action {
+                    Action::Add { id, name } => format!("add {} as {}", id, name),
+                    Action::Replace { target } => format!("replace {}", target.id),
+                    Action::MergeFolder => "merge folder".to_string(),
+                    Action::Skip => "skip".to_string(),
+                };
+                (planned.source.id.clone(), action)
+            })
+            .collect();
+        (actions, id_map, report)
+    }
+
+    fn target() -> Vec<VaultItem> {
+        vec![item("work", None, "Work", "folder", 0), item("aws", Some("work"), "AWS", "key", 10)]
+    }
+
+    #[test]
+    fn parents_come_first() {
+        let ordered = parents_first(vec![
+            item("deep", Some("sub"), "deep", "key", 0),
+            item("sub", Some("top"), "sub", "folder", 0),
+            item("top", None, "top", "folder", 0),
+            // its parent isn't part of the merge
+            item("orphan", Some("trashed"), "orphan", "key", 0),
+            item("loop-a", Some("loop-b"), "a", "folder", 0),
+            item("loop-b", Some("loop-a"), "b", "folder", 0),
+        ]);
+        let position = |id: &str| ordered.iter().position(|item| item.id == id).unwrap();
+        assert!(position("top") < position("sub") && position("sub") < position("deep"));
+        assert!(position("orphan") < position("sub"));
+        // a parent cycle is broken up at the top level rather than dropped
+        assert!(ordered.iter().filter(|item| item.id.starts_with("loop")).all(|item| item.parent_id.is_none()));
+        assert_eq!(ordered.len(), 6);
+    }
+
+    #[test]
+    fn folders_are_merged_by_name() {
+        let source = vec![item("their-work", None, "work", "folder", 0), item("db", Some("their-work"), "DB", "key", 0)];
+        let (actions, id_map, report) = plan(source, target(), MergeStrategy::KeepBoth);
+        assert_eq!(actions[0], ("their-work".to_string(), "merge folder".to_string()));
+        assert_eq!(actions[1].1, "add db as DB");
+        assert_eq!(id_map["their-work"], "work");
+        assert_eq!((report.added, report.folders_merged), (1, 1));
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn conflicts_follow_the_strategy() {
+        let source = |updated_minute| vec![item("their-work", None, "Work", "folder", 0), item("their-aws", Some("their-work"), "aws", "key", updated_minute)];
+
+        let (actions, id_map, report) = plan(source(20), target(), MergeStrategy::KeepBoth);
+        assert!(actions[1].1.starts_with("add their-aws as aws (merged "));
+        assert_eq!(id_map["their-aws"], "their-aws");
+        assert_eq!((report.conflicts[0].kind, report.conflicts[0].resolution.as_str()), (ConflictKind::SameNameAndType, "added"));
+
+        let (actions, id_map, report) = plan(source(20), target(), MergeStrategy::PreferNewer);
+        assert_eq!(actions[1].1, "replace aws");
+        assert_eq!((id_map["their-aws"].as_str(), report.replaced), ("aws", 1));
+        let (actions, _, report) = plan(source(5), target(), MergeStrategy::PreferNewer);
+        assert_eq!((actions[1].1.as_str(), report.skipped), ("skip", 1));
+
+        // links and references to a skipped item point at the one that was kept
+        let (actions, id_map, _) = plan(source(20), target(), MergeStrategy::Skip);
+        assert_eq!((actions[1].1.as_str(), id_map["their-aws"].as_str()), ("skip", "aws"));
+    }
+
+    #[test]
+    fn repeated_merges_recognise_their_items() {
+        // the same item on both sides and not changed since is left alone whatever the strategy
+        let source = vec![item("aws", None, "AWS", "key", 10)];
+        let (actions, _, report) = plan(source, vec![item("aws", None, "AWS", "key", 10)], MergeStrategy::KeepBoth);
+        assert_eq!(actions[0].1, "skip");
+        assert_eq!(report.conflicts[0].kind, ConflictKind::SameId);
+
+        // a changed copy keeps both under a new id, since the source id is taken
+        let (actions, id_map, _) = plan(vec![item("aws", None, "AWS", "key", 20)], vec![item("aws", None, "AWS", "key", 10)], MergeStrategy::KeepBoth);
+        assert!(actions[0].1.starts_with("add ") && !actions[0].1.starts_with("add aws "));
+        assert_ne!(id_map["aws"], "aws");
+
+        // two incoming items with the same name conflict with each other too
+        let source = vec![item("one", None, "Same", "key", 0), item("two", None, "Same", "key", 0)];
+        let (actions, _, report) = plan(source, vec![], MergeStrategy::Skip);
+        assert_eq!((actions[0].1.as_str(), actions[1].1.as_str()), ("add one as Same", "skip"));
+        assert_eq!(report.conflicts.len(), 1);
+    }
+}
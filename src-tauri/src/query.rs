@@ -0,0 +1,402 @@
+use crate::error::Error;
+use crate::expiry::DEFAULT_REMINDER_DAYS;
+use crate::records::is_record_type;
+use crate::search::fuzzy_matches;
+use crate::storage::VaultItem;
+use crate::Result;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::collections::HashMap;
+
+// query syntax, all clauses are ANDed and any clause can be negated with a leading '-':
+//   tag:aws  tag:~aws  type:key  folder:"Prod"  name:github  name:~github
+//   updated:<2025-01-01  created:>=30d  expires:<60d  has:totp  has:tags  has:expiry
+//   is:deleted  is:expired  is:expiring  plain words
+// is:expiring means "expires within the reminder window", see Storage::parse_query
+// relative dates (30d, 12h, 2w, 6m, 1y) mean "that long ago", so updated:>7d is "changed in the last week";
+// for expires: they point into the future instead, expires:<60d is "expires within 60 days"
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    Updated,
+    Deleted,
+    Expires,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Before,
+    BeforeOrOn,
+    After,
+    AfterOrOn,
+    On,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Text(String),
+    Name { value: String, fuzzy: bool },
+    Tag { value: String, fuzzy: bool },
+    Type(String),
+    Folder(String),
+    Date { field: DateField, comparison: Comparison, value: DateTime<Utc> },
+    Has(String),
+    Deleted,
+    Expired(DateTime<Utc>),
+    // not expired yet at `now` but will be by `until`
+    Expiring { now: DateTime<Utc>, until: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub negated: bool,
+    pub predicate: Predicate,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Query {
+    pub clauses: Vec<Clause>,
+}
+
+struct Token {
+    negated: bool,
+    key: Option<String>,
+    value: String,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self> {
+        Self::parse_at(input, Utc::now(), DEFAULT_REMINDER_DAYS)
+    }
+
+    /// Parses as of `now`, with `is:expiring` looking `expiring_days` ahead.
+    pub fn parse_at(input: &str, now: DateTime<Utc>, expiring_days: u32) -> Result<Self> {
+        let mut clauses = Vec::new();
+        for token in tokenize(input)? {
+            let predicate = match token.key.as_deref() {
+                None => Predicate::Text(token.value.to_lowercase()),
+                Some(key) => parse_predicate(key, &token.value, now, expiring_days)?,
+            };
+            clauses.push(Clause { negated: token.negated, predicate });
+        }
+        Ok(Self { clauses })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+
+    /// Deleted items only show up when the query explicitly asks for them with `is:deleted`.
+    pub fn includes_deleted(&self) -> bool {
+        self.clauses.iter().any(|c| !c.negated && c.predicate == Predicate::Deleted)
+    }
+
+    /// Evaluates the query against every item; `items` has to contain the full vault so
+    /// `folder:` clauses can walk up the parent chain.
+    pub fn filter(&self, items: &[VaultItem]) -> Vec<VaultItem> {
+        let by_id: HashMap<&str, &VaultItem> = items.iter().map(|item| (item.id.as_str(), item)).collect();
+        let include_deleted = self.includes_deleted();
+
+        items
+            .iter()
+            .filter(|item| include_deleted || item.deleted_at.is_none())
+            .filter(|item| self.matches(item, &by_id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn matches(&self, item: &VaultItem, by_id: &HashMap<&str, &VaultItem>) -> bool {
+        self.clauses.iter().all(|clause| clause.predicate.matches(item, by_id) != clause.negated)
+    }
+}
+
+impl Predicate {
+    fn matches(&self, item: &VaultItem, by_id: &HashMap<&str, &VaultItem>) -> bool {
+        match self {
+            Predicate::Text(value) => {
+                item.name.to_lowercase().contains(value)
+                    || item.tags.iter().any(|tag| tag.to_lowercase().contains(value))
+            }
+            Predicate::Name { value, fuzzy } => text_matches(&item.name, value, *fuzzy),
+            Predicate::Tag { value, fuzzy } => item.tags.iter().any(|tag| {
+                if *fuzzy {
+                    text_matches(tag, value, true)
+                } else {
+                    tag.to_lowercase() == *value
+                }
+            }),
+            Predicate::Type(value) => type_matches(item, value),
+            Predicate::Folder(value) => {
+                let mut parent_id = item.parent_id.as_deref();
+                // bounded walk so a corrupted parent cycle can't hang the query
+                for _ in 0..by_id.len() {
+                    let Some(parent) = parent_id.and_then(|id| by_id.get(id)) else {
+                        return false;
+                    };
+                    if parent.name.to_lowercase() == *value {
+                        return true;
+                    }
+                    parent_id = parent.parent_id.as_deref();
+                }
+                false
+            }
+            Predicate::Date { field, comparison, value } => {
+                let timestamp = match field {
+                    DateField::Created => Some(item.created_at),
+                    DateField::Updated => Some(item.updated_at),
+                    DateField::Deleted => item.deleted_at,
+                    DateField::Expires => item.expires_at,
+                };
+                match timestamp {
+                    Some(timestamp) => compare_dates(timestamp, *comparison, *value),
+                    None => false,
+                }
+            }
+            Predicate::Has(what) => match what.as_str() {
+                "totp" => item.totp_secret.as_deref().is_some_and(|s| !s.is_empty()),
+                "tags" | "tag" => !item.tags.is_empty(),
+                "content" => !item.data_path.is_empty(),
+                "expiry" | "expires" => item.expires_at.is_some(),
+                _ => false,
+            },
+            Predicate::Deleted => item.deleted_at.is_some(),
+            Predicate::Expired(now) => item.expires_at.is_some_and(|expires_at| expires_at <= *now),
+            Predicate::Expiring { now, until } => item.expires_at.is_some_and(|expires_at| expires_at > *now && expires_at <= *until),
+        }
+    }
+}
+
+fn text_matches(haystack: &str, value: &str, fuzzy: bool) -> bool {
+    let haystack = haystack.to_lowercase();
+    haystack.contains(value) || (fuzzy && fuzzy_matches(&haystack, value))
+}
+
+fn type_matches(item: &VaultItem, value: &str) -> bool {
+    let item_type = item.item_type.to_lowercase();
+    match value {
+        "folder" => item_type == "folder",
+        "text" | "note" => item_type == "text" || item_type == "text/plain",
+        "file" => !matches!(item_type.as_str(), "folder" | "key" | "text" | "text/plain" | "login" | "smart_folder") && !is_record_type(&item_type),
+        "bank" => item_type == "bank_account",
+        "password" => item_type == "login",
+        _ => {
+            if item_type == "folder" {
+                item.folder_type.as_deref().map(str::to_lowercase).as_deref() == Some(value)
+            } else {
+                item_type == value || item_type.starts_with(&format!("{}/", value))
+            }
+        }
+    }
+}
+
+fn compare_dates(timestamp: DateTime<Utc>, comparison: Comparison, value: DateTime<Utc>) -> bool {
+    match comparison {
+        Comparison::Before => timestamp < value,
+        Comparison::BeforeOrOn => timestamp <= value,
+        Comparison::After => timestamp > value,
+        Comparison::AfterOrOn => timestamp >= value,
+        Comparison::On => timestamp.date_naive() == value.date_naive(),
+    }
+}
+
+fn parse_predicate(key: &str, value: &str, now: DateTime<Utc>, expiring_days: u32) -> Result<Predicate> {
+    if value.is_empty() {
+        return Err(Error::InvalidInput(format!("Missing value for '{}:'", key)));
+    }
+
+    let lowered = value.to_lowercase();
+    let (fuzzy, fuzzy_value) = match lowered.strip_prefix('~') {
+        Some(rest) => (true, rest.to_string()),
+        None => (false, lowered.clone()),
+    };
+
+    Ok(match key.to_lowercase().as_str() {
+        "name" | "title" => Predicate::Name { value: fuzzy_value, fuzzy },
+        "tag" | "tags" => Predicate::Tag { value: fuzzy_value, fuzzy },
+        "type" => Predicate::Type(lowered),
+        "folder" | "in" => Predicate::Folder(lowered),
+        "has" => match lowered.as_str() {
+            "totp" | "tags" | "tag" | "content" | "expiry" | "expires" => Predicate::Has(lowered),
+            _ => return Err(Error::InvalidInput(format!("Unknown has: value '{}'", value))),
+        },
+        "is" => match lowered.as_str() {
+            "deleted" | "trashed" => Predicate::Deleted,
+            "expired" => Predicate::Expired(now),
+            "expiring" => {
+                let until = now.checked_add_signed(Duration::days(expiring_days as i64));
+                Predicate::Expiring { now, until: until.ok_or_else(|| Error::InvalidInput("The reminder window is out of range".into()))? }
+            }
+            _ => return Err(Error::InvalidInput(format!("Unknown is: value '{}'", value))),
+        },
+        "created" | "updated" | "modified" | "deleted" | "expires" | "expiry" => {
+            let field = match key.to_lowercase().as_str() {
+                "created" => DateField::Created,
+                "deleted" => DateField::Deleted,
+                "expires" | "expiry" => DateField::Expires,
+                _ => DateField::Updated,
+            };
+            let (comparison, date) = parse_comparison(value);
+            Predicate::Date { field, comparison, value: parse_date(date, now, field == DateField::Expires)? }
+        }
+        // not a filter we know (e.g. the "https" in a pasted url), search it as plain text
+        _ => Predicate::Text(format!("{}:{}", key, value).to_lowercase()),
+    })
+}
+
+fn parse_comparison(value: &str) -> (Comparison, &str) {
+    if let Some(rest) = value.strip_prefix("<=") {
+        (Comparison::BeforeOrOn, rest)
+    } else if let Some(rest) = value.strip_prefix(">=") {
+        (Comparison::AfterOrOn, rest)
+    } else if let Some(rest) = value.strip_prefix('<') {
+        (Comparison::Before, rest)
+    } else if let Some(rest) = value.strip_prefix('>') {
+        (Comparison::After, rest)
+    } else {
+        (Comparison::On, value.strip_prefix('=').unwrap_or(value))
+    }
+}
+
+fn parse_date(value: &str, now: DateTime<Utc>, relative_to_future: bool) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        if let Some(midnight) = date.and_hms_opt(0, 0, 0) {
+            return Ok(DateTime::from_naive_utc_and_offset(midnight, Utc));
+        }
+    }
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    match value {
+        "today" => return parse_date(&now.format("%Y-%m-%d").to_string(), now, false),
+        "yesterday" => return parse_date(&(now - Duration::days(1)).format("%Y-%m-%d").to_string(), now, false),
+        "tomorrow" => return parse_date(&(now + Duration::days(1)).format("%Y-%m-%d").to_string(), now, false),
+        _ => {}
+    }
+
+    let split_at = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split_at);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| Error::InvalidInput(format!("Invalid date '{}' (use YYYY-MM-DD or a relative value like 7d)", value)))?;
+    let out_of_range = || Error::InvalidInput(format!("Date '{}' is out of range", value));
+    let ago = match unit {
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        "m" => amount.checked_mul(30).and_then(Duration::try_days),
+        "y" => amount.checked_mul(365).and_then(Duration::try_days),
+        _ => return Err(Error::InvalidInput(format!("Invalid date unit in '{}' (use h, d, w, m or y)", value))),
+    }
+    .ok_or_else(out_of_range)?;
+    let date = if relative_to_future { now.checked_add_signed(ago) } else { now.checked_sub_signed(ago) };
+    date.ok_or_else(out_of_range)
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+
+        let mut negated = false;
+        if first == '-' {
+            negated = true;
+            chars.next();
+        }
+
+        let mut key: Option<String> = None;
+        let mut current = String::new();
+        let mut quoted_any = false;
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            match c {
+                '"' => {
+                    quoted_any = true;
+                    let mut closed = false;
+                    for inner in chars.by_ref() {
+                        if inner == '"' {
+                            closed = true;
+                            break;
+                        }
+                        current.push(inner);
+                    }
+                    if !closed {
+                        return Err(Error::InvalidInput("Unterminated quote in query".into()));
+                    }
+                }
+                ':' if key.is_none() && !quoted_any && !current.is_empty() => {
+                    key = Some(std::mem::take(&mut current));
+                }
+                _ => current.push(c),
+            }
+        }
+
+        if key.is_none() && current.is_empty() {
+            // a lone '-' or an empty pair of quotes
+            continue;
+        }
+        tokens.push(Token { negated, key, value: current });
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2025-06-15T12:00:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    fn date(query: &str) -> Result<(Comparison, DateTime<Utc>)> {
+        match Query::parse_at(query, now(), 14)?.clauses.remove(0).predicate {
+            Predicate::Date { comparison, value, .. } => Ok((comparison, value)),
+            other => panic!("{} parsed as {:?}", query, other),
+        }
+    }
+
+    #[test]
+    fn relative_dates() {
+        assert_eq!(date("updated:>7d").unwrap(), (Comparison::After, now() - Duration::days(7)));
+        assert_eq!(date("created:<=12h").unwrap(), (Comparison::BeforeOrOn, now() - Duration::hours(12)));
+        assert_eq!(date("updated:2w").unwrap(), (Comparison::On, now() - Duration::weeks(2)));
+        assert_eq!(date("created:<6m").unwrap().1, now() - Duration::days(180));
+        assert_eq!(date("created:<1y").unwrap().1, now() - Duration::days(365));
+        // expiry dates point ahead
+        assert_eq!(date("expires:<60d").unwrap().1, now() + Duration::days(60));
+        assert_eq!(date("updated:<2025-01-01").unwrap().1.to_rfc3339(), "2025-01-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn out_of_range_dates_are_rejected() {
+        for query in [
+            "updated:<100000000d",
+            "created:>99999999999999y",
+            "created:>99999999999999m",
+            "updated:>9223372036854775807h",
+            "updated:>9223372036854775807w",
+            "expires:<100000000d",
+            "expires:<9223372036854775807y",
+            // doesn't fit an i64
+            "updated:<99999999999999999999d",
+        ] {
+            assert!(matches!(Query::parse_at(query, now(), 14), Err(Error::InvalidInput(_))), "{}", query);
+        }
+        assert!(matches!(Query::parse_at("is:expiring", now(), u32::MAX), Err(Error::InvalidInput(_))));
+        assert!(matches!(Query::parse_at("updated:<7x", now(), 14), Err(Error::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,656 @@
+use crate::error::Error;
+use crate::Result;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+pub const CARD_ITEM_TYPE: &str = "card";
+pub const IDENTITY_ITEM_TYPE: &str = "identity";
+pub const BANK_ACCOUNT_ITEM_TYPE: &str = "bank_account";
+pub const LICENSE_ITEM_TYPE: &str = "license";
+
+pub const RECORD_ITEM_TYPES: [&str; 4] = [CARD_ITEM_TYPE, IDENTITY_ITEM_TYPE, BANK_ACCOUNT_ITEM_TYPE, LICENSE_ITEM_TYPE];
+
+const MAX_FIELD_LENGTH: usize = 4096;
+const MAX_NOTES_LENGTH: usize = 1024 * 1024;
+
+pub fn is_record_type(item_type: &str) -> bool {
+    RECORD_ITEM_TYPES.contains(&item_type)
+}
+
+/// Payload of a built-in typed item. Like logins it is serialized as JSON into the item's
+/// encrypted data file, and `type` always matches the item's `item_type`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ItemRecord {
+    Card(CardData),
+    Identity(IdentityData),
+    BankAccount(BankAccountData),
+    License(LicenseData),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct CardData {
+    #[serde(default)]
+    pub cardholder_name: String,
+    #[serde(default)]
+    pub number: String,
+    pub expiry_month: Option<u32>,
+    pub expiry_year: Option<i32>,
+    #[serde(default)]
+    pub security_code: String,
+    #[serde(default)]
+    pub pin: String,
+    #[serde(default)]
+    pub notes: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct IdentityData {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub first_name: String,
+    #[serde(default)]
+    pub middle_name: String,
+    #[serde(default)]
+    pub last_name: String,
+    #[serde(default)]
+    pub date_of_birth: String,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub phone: String,
+    #[serde(default)]
+    pub company: String,
+    #[serde(default)]
+    pub address_line1: String,
+    #[serde(default)]
+    pub address_line2: String,
+    #[serde(default)]
+    pub city: String,
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub postal_code: String,
+    #[serde(default)]
+    pub country: String,
+    #[serde(default)]
+    pub passport_number: String,
+    #[serde(default)]
+    pub license_number: String,
+    #[serde(default)]
+    pub national_id: String,
+    #[serde(default)]
+    pub notes: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct BankAccountData {
+    #[serde(default)]
+    pub bank_name: String,
+    #[serde(default)]
+    pub account_holder: String,
+    #[serde(default)]
+    pub account_type: String,
+    #[serde(default)]
+    pub account_number: String,
+    #[serde(default)]
+    pub routing_number: String,
+    #[serde(default)]
+    pub iban: String,
+    #[serde(default)]
+    pub bic: String,
+    #[serde(default)]
+    pub pin: String,
+    #[serde(default)]
+    pub notes: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct LicenseData {
+    #[serde(default)]
+    pub product: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub license_key: String,
+    #[serde(default)]
+    pub licensed_to: String,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub company: String,
+    #[serde(default)]
+    pub order_number: String,
+    #[serde(default)]
+    pub purchase_date: String,
+    #[serde(default)]
+    pub expiry_date: String,
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl ItemRecord {
+    pub fn item_type(&self) -> &'static str {
+        match self {
+            ItemRecord::Card(_) => CARD_ITEM_TYPE,
+            ItemRecord::Identity(_) => IDENTITY_ITEM_TYPE,
+            ItemRecord::BankAccount(_) => BANK_ACCOUNT_ITEM_TYPE,
+            ItemRecord::License(_) => LICENSE_ITEM_TYPE,
+        }
+    }
+
+    /// Expiry implied by the record itself, kept in sync with the item's `expires_at`.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        let day = match self {
+            ItemRecord::Card(card) => {
+                // valid through the last day of the month, so it expires when the next one starts
+                let (month, year) = (card.expiry_month?, card.expiry_year?);
+                let (month, year) = if month == 12 { (1, year + 1) } else { (month + 1, year) };
+                NaiveDate::from_ymd_opt(year, month, 1)?
+            }
+            ItemRecord::License(license) => NaiveDate::parse_from_str(license.expiry_date.trim(), "%Y-%m-%d").ok()?,
+            ItemRecord::Identity(_) | ItemRecord::BankAccount(_) => return None,
+        };
+        Some(DateTime::from_naive_utc_and_offset(day.and_hms_opt(0, 0, 0)?, Utc))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ItemRecord::Card(card) => card.validate(),
+            ItemRecord::Identity(identity) => identity.validate(),
+            ItemRecord::BankAccount(account) => account.validate(),
+            ItemRecord::License(license) => license.validate(),
+        }
+    }
+
+    /// Strips the separators people type into card numbers and IBANs so they are stored in
+    /// one canonical form.
+    pub fn normalized(self) -> Self {
+        match self {
+            ItemRecord::Card(mut card) => {
+                card.number = digits_only(&card.number);
+                card.security_code = card.security_code.trim().to_string();
+                ItemRecord::Card(card)
+            }
+            ItemRecord::BankAccount(mut account) => {
+                account.iban = compact_upper(&account.iban);
+                account.bic = compact_upper(&account.bic);
+                ItemRecord::BankAccount(account)
+            }
+            other => other,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    /// Labelled, non-empty fields in display order; used by the text-based exports.
+    pub fn labelled_fields(&self) -> Vec<(&'static str, String)> {
+        let fields: Vec<(&'static str, String)> = match self {
+            ItemRecord::Card(card) => vec![
+                ("Cardholder", card.cardholder_name.clone()),
+                ("Brand", card.brand().unwrap_or_default().to_string()),
+                ("Number", card.number.clone()),
+                ("Expires", card.expiry_display().unwrap_or_default()),
+                ("Security Code", card.security_code.clone()),
+                ("PIN", card.pin.clone()),
+                ("Notes", card.notes.clone()),
+            ],
+            ItemRecord::Identity(identity) => vec![
+                ("Title", identity.title.clone()),
+                ("First Name", identity.first_name.clone()),
+                ("Middle Name", identity.middle_name.clone()),
+                ("Last Name", identity.last_name.clone()),
+                ("Date of Birth", identity.date_of_birth.clone()),
+                ("Email", identity.email.clone()),
+                ("Phone", identity.phone.clone()),
+                ("Company", identity.company.clone()),
+                ("Address", identity.address_line1.clone()),
+                ("Address 2", identity.address_line2.clone()),
+                ("City", identity.city.clone()),
+                ("State", identity.state.clone()),
+                ("Postal Code", identity.postal_code.clone()),
+                ("Country", identity.country.clone()),
+                ("Passport Number", identity.passport_number.clone()),
+                ("License Number", identity.license_number.clone()),
+                ("National ID", identity.national_id.clone()),
+                ("Notes", identity.notes.clone()),
+            ],
+            ItemRecord::BankAccount(account) => vec![
+                ("Bank", account.bank_name.clone()),
+                ("Account Holder", account.account_holder.clone()),
+                ("Account Type", account.account_type.clone()),
+                ("Account Number", account.account_number.clone()),
+                ("Routing Number", account.routing_number.clone()),
+                ("IBAN", account.iban.clone()),
+                ("BIC", account.bic.clone()),
+                ("PIN", account.pin.clone()),
+                ("Notes", account.notes.clone()),
+            ],
+            ItemRecord::License(license) => vec![
+                ("Product", license.product.clone()),
+                ("Version", license.version.clone()),
+                ("License Key", license.license_key.clone()),
+                ("Licensed To", license.licensed_to.clone()),
+                ("Email", license.email.clone()),
+                ("Company", license.company.clone()),
+                ("Order Number", license.order_number.clone()),
+                ("Purchase Date", license.purchase_date.clone()),
+                ("Expiry Date", license.expiry_date.clone()),
+                ("Notes", license.notes.clone()),
+            ],
+        };
+        fields.into_iter().filter(|(_, value)| !value.is_empty()).collect()
+    }
+
+    pub fn to_text(&self) -> String {
+        self.labelled_fields()
+            .iter()
+            .map(|(label, value)| format!("{}: {}", label, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Text that search may look at: names and descriptions, never numbers, codes or keys.
+    pub fn searchable_text(&self) -> String {
+        let parts: Vec<&str> = match self {
+            ItemRecord::Card(card) => vec![card.cardholder_name.as_str(), card.brand().unwrap_or_default(), card.last_four(), card.notes.as_str()],
+            ItemRecord::Identity(identity) => vec![
+                identity.first_name.as_str(),
+                identity.last_name.as_str(),
+                identity.email.as_str(),
+                identity.company.as_str(),
+                identity.city.as_str(),
+                identity.country.as_str(),
+                identity.notes.as_str(),
+            ],
+            ItemRecord::BankAccount(account) => vec![account.bank_name.as_str(), account.account_holder.as_str(), account.account_type.as_str(), account.notes.as_str()],
+            ItemRecord::License(license) => vec![
+                license.product.as_str(),
+                license.version.as_str(),
+                license.licensed_to.as_str(),
+                license.email.as_str(),
+                license.company.as_str(),
+                license.notes.as_str(),
+            ],
+        };
+        parts.into_iter().filter(|part| !part.is_empty()).collect::<Vec<_>>().join("\n")
+    }
+}
+
+impl CardData {
+    pub fn validate(&self) -> Result<()> {
+        check_lengths(&[&self.cardholder_name, &self.number, &self.security_code, &self.pin])?;
+        check_notes(&self.notes)?;
+
+        let number = digits_only(&self.number);
+        if self.number.chars().any(|c| !c.is_ascii_digit() && c != ' ' && c != '-') {
+            return Err(Error::InvalidInput("Card number may only contain digits, spaces and dashes".into()));
+        }
+        if !number.is_empty() {
+            if !(12..=19).contains(&number.len()) {
+                return Err(Error::InvalidInput("Card number must have between 12 and 19 digits".into()));
+            }
+            if !luhn_valid(&number) {
+                return Err(Error::InvalidInput("Card number failed the checksum, check for typos".into()));
+            }
+        }
+
+        match (self.expiry_month, self.expiry_year) {
+            (None, None) => {}
+            (Some(month), Some(year)) => {
+                if !(1..=12).contains(&month) {
+                    return Err(Error::InvalidInput("Expiry month must be between 1 and 12".into()));
+                }
+                if !(2000..=2100).contains(&year) {
+                    return Err(Error::InvalidInput("Expiry year must be a four digit year".into()));
+                }
+            }
+            _ => return Err(Error::InvalidInput("Expiry needs both a month and a year".into())),
+        }
+
+        let code = self.security_code.trim();
+        if !code.is_empty() && (!(3..=4).contains(&code.len()) || !code.chars().all(|c| c.is_ascii_digit())) {
+            return Err(Error::InvalidInput("Security code must be 3 or 4 digits".into()));
+        }
+        if !self.pin.is_empty() && !self.pin.chars().all(|c| c.is_ascii_digit()) {
+            return Err(Error::InvalidInput("PIN may only contain digits".into()));
+        }
+        Ok(())
+    }
+
+    /// Cards are valid through the last day of their expiry month.
+    pub fn is_expired(&self) -> bool {
+        match (self.expiry_month, self.expiry_year) {
+            (Some(month), Some(year)) => {
+                let today = Utc::now().date_naive();
+                (year, month) < (today.year(), today.month())
+            }
+            _ => false,
+        }
+    }
+
+    pub fn expiry_display(&self) -> Option<String> {
+        Some(format!("{:02}/{}", self.expiry_month?, self.expiry_year?))
+    }
+
+    pub fn brand(&self) -> Option<&'static str> {
+        let number = digits_only(&self.number);
+        let prefix = |len: usize| number.get(..len).and_then(|p| p.parse::<u32>().ok());
+        if number.starts_with('4') {
+            Some("Visa")
+        } else if prefix(2).is_some_and(|p| (51..=55).contains(&p)) || prefix(4).is_some_and(|p| (2221..=2720).contains(&p)) {
+            Some("Mastercard")
+        } else if number.starts_with("34") || number.starts_with("37") {
+            Some("American Express")
+        } else if number.starts_with("6011") || number.starts_with("65") || prefix(3).is_some_and(|p| (644..=649).contains(&p)) {
+            Some("Discover")
+        } else if prefix(4).is_some_and(|p| (3528..=3589).contains(&p)) {
+            Some("JCB")
+        } else {
+            None
+        }
+    }
+
+    pub fn last_four(&self) -> &str {
+        let number = self.number.trim();
+        number.get(number.len().saturating_sub(4)..).unwrap_or_default()
+    }
+}
+
+impl IdentityData {
+    pub fn validate(&self) -> Result<()> {
+        check_lengths(&[
+            &self.title,
+            &self.first_name,
+            &self.middle_name,
+            &self.last_name,
+            &self.email,
+            &self.phone,
+            &self.company,
+            &self.address_line1,
+            &self.address_line2,
+            &self.city,
+            &self.state,
+            &self.postal_code,
+            &self.country,
+            &self.passport_number,
+            &self.license_number,
+            &self.national_id,
+        ])?;
+        check_notes(&self.notes)?;
+        check_date("Date of birth", &self.date_of_birth)?;
+        check_email(&self.email)
+    }
+}
+
+impl BankAccountData {
+    pub fn validate(&self) -> Result<()> {
+        check_lengths(&[
+            &self.bank_name,
+            &self.account_holder,
+            &self.account_type,
+            &self.account_number,
+            &self.routing_number,
+            &self.iban,
+            &self.bic,
+            &self.pin,
+        ])?;
+        check_notes(&self.notes)?;
+
+        let iban = compact_upper(&self.iban);
+        if !iban.is_empty() && !iban_valid(&iban) {
+            return Err(Error::InvalidInput("IBAN is not valid, check the country code and digits".into()));
+        }
+        let bic = compact_upper(&self.bic);
+        if !bic.is_empty() && (!(bic.len() == 8 || bic.len() == 11) || !bic.chars().all(|c| c.is_ascii_alphanumeric())) {
+            return Err(Error::InvalidInput("BIC must be 8 or 11 letters and digits".into()));
+        }
+        Ok(())
+    }
+}
+
+impl LicenseData {
+    pub fn validate(&self) -> Result<()> {
+        if self.product.trim().is_empty() && self.license_key.trim().is_empty() {
+            return Err(Error::InvalidInput("A license needs a product or a license key".into()));
+        }
+        check_lengths(&[
+            &self.product,
+            &self.version,
+            &self.licensed_to,
+            &self.email,
+            &self.company,
+            &self.order_number,
+        ])?;
+        // keys for some products are whole certificate blocks
+        if self.license_key.len() > MAX_NOTES_LENGTH {
+            return Err(Error::InvalidInput("License key too long (max 1MB)".into()));
+        }
+        check_notes(&self.notes)?;
+        check_date("Purchase date", &self.purchase_date)?;
+        check_date("Expiry date", &self.expiry_date)?;
+        check_email(&self.email)
+    }
+}
+
+impl Drop for CardData {
+    fn drop(&mut self) {
+        self.number.zeroize();
+        self.security_code.zeroize();
+        self.pin.zeroize();
+    }
+}
+
+impl Drop for IdentityData {
+    fn drop(&mut self) {
+        self.passport_number.zeroize();
+        self.license_number.zeroize();
+        self.national_id.zeroize();
+    }
+}
+
+impl Drop for BankAccountData {
+    fn drop(&mut self) {
+        self.account_number.zeroize();
+        self.iban.zeroize();
+        self.pin.zeroize();
+    }
+}
+
+impl Drop for LicenseData {
+    fn drop(&mut self) {
+        self.license_key.zeroize();
+    }
+}
+
+pub fn luhn_valid(digits: &str) -> bool {
+    let mut sum = 0;
+    for (i, c) in digits.chars().rev().enumerate() {
+        let Some(mut digit) = c.to_digit(10) else {
+            return false;
+        };
+        if i % 2 == 1 {
+            digit *= 2;
+            if digit > 9 {
+                digit -= 9;
+            }
+        }
+        sum += digit;
+    }
+    !digits.is_empty() && sum % 10 == 0
+}
+
+/// ISO 13616 check: move the first four characters to the end, turn letters into numbers
+/// (A = 10 ... Z = 35) and the result mod 97 has to be 1.
+pub fn iban_valid(iban: &str) -> bool {
+    if !(15..=34).contains(&iban.len()) || !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    let (head, tail) = iban.split_at(4);
+    if !head[..2].chars().all(|c| c.is_ascii_uppercase()) || !head[2..].chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    let mut remainder: u32 = 0;
+    for c in tail.chars().chain(head.chars()) {
+        let value = match c.to_digit(36) {
+            Some(value) => value,
+            None => return false,
+        };
+        remainder = if value < 10 { (remainder * 10 + value) % 97 } else { (remainder * 100 + value) % 97 };
+    }
+    remainder == 1
+}
+
+fn digits_only(value: &str) -> String {
+    value.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+fn compact_upper(value: &str) -> String {
+    value.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase()
+}
+
+fn check_lengths(values: &[&String]) -> Result<()> {
+    if values.iter().any(|value| value.len() > MAX_FIELD_LENGTH) {
+        return Err(Error::InvalidInput("Field too long (max 4096 characters)".into()));
+    }
+    Ok(())
+}
+
+fn check_notes(notes: &str) -> Result<()> {
+    if notes.len() > MAX_NOTES_LENGTH {
+        return Err(Error::InvalidInput("Notes too long (max 1MB)".into()));
+    }
+    Ok(())
+}
+
+fn check_date(label: &str, value: &str) -> Result<()> {
+    if !value.trim().is_empty() && NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").is_err() {
+        return Err(Error::InvalidInput(format!("{} must be in YYYY-MM-DD format", label)));
+    }
+    Ok(())
+}
+
+fn check_email(value: &str) -> Result<()> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(());
+    }
+    let valid = value
+        .split_once('@')
+        .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.') && !domain.contains('@'));
+    if !valid {
+        return Err(Error::InvalidInput(format!("'{}' is not a valid email address", value)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the records zeroize on drop, so they can't be built with struct update syntax
+    fn card(number: &str, expiry: Option<(u32, i32)>, security_code: &str) -> CardData {
+        let mut card = CardData::default();
+        card.number = number.into();
+        card.expiry_month = expiry.map(|(month, _)| month);
+        card.expiry_year = expiry.map(|(_, year)| year);
+        card.security_code = security_code.into();
+        card
+    }
+
+    fn bank_account(iban: &str, bic: &str) -> ItemRecord {
+        let mut account = BankAccountData::default();
+        account.iban = iban.into();
+        account.bic = bic.into();
+        ItemRecord::BankAccount(account)
+    }
+
+    fn license(product: &str, license_key: &str, expiry_date: &str) -> LicenseData {
+        let mut license = LicenseData::default();
+        license.product = product.into();
+        license.license_key = license_key.into();
+        license.expiry_date = expiry_date.into();
+        license
+    }
+
+    #[test]
+    fn luhn_checksum() {
+        for number in ["4111111111111111", "5555555555554444", "378282246310005", "6011111111111117", "0"] {
+            assert!(luhn_valid(number), "{}", number);
+        }
+        for number in ["4111111111111112", "378282246310006", "4111-1111", ""] {
+            assert!(!luhn_valid(number), "{}", number);
+        }
+    }
+
+    #[test]
+    fn iban_checksum() {
+        for iban in ["GB82WEST12345698765432", "DE89370400440532013000", "NL91ABNA0417164300", "NO9386011117947"] {
+            assert!(iban_valid(iban), "{}", iban);
+        }
+        for iban in [
+            "GB82WEST12345698765433",
+            "DE89370400440532013001",
+            // the checks only apply to the compact uppercase form the record stores
+            "GB82 WEST 1234 5698 7654 32",
+            "gb82west12345698765432",
+            "1282WEST12345698765432",
+            "GB8",
+        ] {
+            assert!(!iban_valid(iban), "{}", iban);
+        }
+    }
+
+    #[test]
+    fn cards_are_validated_before_they_are_stored() {
+        assert!(card("4111 1111 1111 1111", Some((1, 2030)), "123").validate().is_ok());
+        assert!(card("", None, "").validate().is_ok());
+        assert!(card("4111 1111 1111 1112", None, "").validate().is_err());
+        assert!(card("4111.1111.1111.1111", None, "").validate().is_err());
+        assert!(card("4242", None, "").validate().is_err());
+        assert!(card("", Some((13, 2030)), "").validate().is_err());
+        assert!(card("", Some((12, 30)), "").validate().is_err());
+        assert!(card("", None, "12a").validate().is_err());
+        let mut month_only = card("", None, "");
+        month_only.expiry_month = Some(12);
+        assert!(month_only.validate().is_err());
+
+        let record = ItemRecord::Card(card("4111-1111-1111-1111", None, "")).normalized();
+        let ItemRecord::Card(normalized) = &record else { unreachable!() };
+        assert_eq!(normalized.number, "4111111111111111");
+        assert_eq!((normalized.brand(), normalized.last_four()), (Some("Visa"), "1111"));
+        assert_eq!(ItemRecord::from_bytes(&record.to_bytes().unwrap()).unwrap(), record);
+    }
+
+    #[test]
+    fn cards_expire_after_their_last_month() {
+        let record = ItemRecord::Card(card("", Some((12, 2024)), ""));
+        assert_eq!(record.expires_at().unwrap().to_rfc3339(), "2025-01-01T00:00:00+00:00");
+        let ItemRecord::Card(expired) = &record else { unreachable!() };
+        assert!(expired.is_expired());
+        assert_eq!(expired.expiry_display().as_deref(), Some("12/2024"));
+    }
+
+    #[test]
+    fn bank_accounts_and_licenses() {
+        assert!(bank_account("GB82 WEST 1234 5698 7654 32", "DEUTDEFF").validate().is_ok());
+        assert!(bank_account("GB82 WEST 1234 5698 7654 33", "").validate().is_err());
+        assert!(bank_account("", "DEUTDE").validate().is_err());
+        let ItemRecord::BankAccount(normalized) = bank_account("gb82 west 1234 5698 7654 32", "deut deff").normalized() else { unreachable!() };
+        assert_eq!((normalized.iban.as_str(), normalized.bic.as_str()), ("GB82WEST12345698765432", "DEUTDEFF"));
+
+        assert!(license("", "", "").validate().is_err());
+        assert!(license("", "ABCD-1234", "").validate().is_ok());
+        assert!(license("Editor", "", "next year").validate().is_err());
+        assert_eq!(ItemRecord::License(license("Editor", "", "2030-06-01")).expires_at().unwrap().to_rfc3339(), "2030-06-01T00:00:00+00:00");
+    }
+}
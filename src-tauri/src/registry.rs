@@ -0,0 +1,194 @@
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::backup::BackupSchedule;
+use crate::error::Error;
+use crate::sync::SyncSettings;
+use crate::Result;
+
+const MAX_VAULT_NAME_LENGTH: usize = 100;
+pub const DEFAULT_VAULT_NAME: &str = "Personal";
+
+/// A vault the app knows about. The registry only stores where vaults live; nothing in here is
+/// secret, the vault directories hold their own salt and encrypted database.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VaultEntry {
+    pub id: String,
+    pub name: String,
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+    pub last_opened_at: Option<DateTime<Utc>>,
+    // set once the vault is connected to a sync folder on this device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync: Option<SyncSettings>,
+    // set once automatic local backups are turned on for the vault
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<BackupSchedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct VaultRegistry {
+    #[serde(default)]
+    pub vaults: Vec<VaultEntry>,
+    #[serde(default)]
+    pub last_used: Option<String>,
+    #[serde(skip)]
+    file: PathBuf,
+}
+
+impl VaultRegistry {
+    /// Loads the registry from `file`. On first run the vault that used to be hard-coded at
+    /// `default_vault_path` is registered so existing installs keep working. A registry that
+    /// doesn't parse is moved aside to `vaults.json.corrupt` rather than written over, and one
+    /// that can't be read at all is an error.
+    pub fn load(file: PathBuf, default_vault_path: &Path) -> Result<Self> {
+        let mut registry = match fs::read_to_string(&file) {
+            Ok(json) => match serde_json::from_str::<VaultRegistry>(&json) {
+                Ok(registry) => registry,
+                Err(e) => {
+                    let corrupt = file.with_extension("json.corrupt");
+                    fs::rename(&file, &corrupt).map_err(|rename_error| {
+                        Error::Storage(format!("Vault registry at {} is corrupt ({}) and couldn't be moved aside: {}", file.display(), e, rename_error))
+                    })?;
+                    error!("Vault registry at {} is corrupt, moved it to {} and starting a new one: {}", file.display(), corrupt.display(), e);
+                    VaultRegistry::default()
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => VaultRegistry::default(),
+            Err(e) => return Err(Error::Storage(format!("Failed to read vault registry at {}: {}", file.display(), e))),
+        };
+        registry.file = file;
+
+        if registry.vaults.is_empty() {
+            info!("Registering default vault at {}", default_vault_path.display());
+            let entry = registry.add(DEFAULT_VAULT_NAME, default_vault_path.to_path_buf())?;
+            registry.last_used = Some(entry.id);
+            registry.save()?;
+        }
+        Ok(registry)
+    }
+
+    /// Writes the registry through a temp file so a crash never leaves a half-written one.
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = self.file.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&temp, &self.file)?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&VaultEntry> {
+        self.vaults.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn find_by_path(&self, path: &Path) -> Option<&VaultEntry> {
+        self.vaults.iter().find(|entry| entry.path == path)
+    }
+
+    pub fn add(&mut self, name: &str, path: PathBuf) -> Result<VaultEntry> {
+        let name = validate_vault_name(name)?;
+        if let Some(existing) = self.find_by_path(&path) {
+            return Err(Error::InvalidInput(format!("This vault is already registered as '{}'", existing.name)));
+        }
+        let entry = VaultEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            path,
+            created_at: Utc::now(),
+            last_opened_at: None,
+            sync: None,
+            backup: None,
+        };
+        self.vaults.push(entry.clone());
+        Ok(entry)
+    }
+
+    pub fn rename(&mut self, id: &str, name: &str) -> Result<()> {
+        let name = validate_vault_name(name)?;
+        let entry = self.vaults.iter_mut().find(|entry| entry.id == id).ok_or_else(|| Error::ItemNotFound(id.to_string()))?;
+        entry.name = name;
+        Ok(())
+    }
+
+    /// Removes the vault from the list. Its files stay where they are and it can be opened again.
+    pub fn forget(&mut self, id: &str) -> Result<VaultEntry> {
+        let index = self.vaults.iter().position(|entry| entry.id == id).ok_or_else(|| Error::ItemNotFound(id.to_string()))?;
+        let entry = self.vaults.remove(index);
+        if self.last_used.as_deref() == Some(id) {
+            self.last_used = None;
+        }
+        Ok(entry)
+    }
+
+    pub fn mark_opened(&mut self, id: &str) {
+        if let Some(entry) = self.vaults.iter_mut().find(|entry| entry.id == id) {
+            entry.last_opened_at = Some(Utc::now());
+            self.last_used = Some(id.to_string());
+        }
+    }
+
+    pub fn set_sync(&mut self, id: &str, sync: Option<SyncSettings>) -> Result<()> {
+        let entry = self.vaults.iter_mut().find(|entry| entry.id == id).ok_or_else(|| Error::ItemNotFound(id.to_string()))?;
+        entry.sync = sync;
+        Ok(())
+    }
+
+    pub fn mark_synced(&mut self, id: &str) {
+        if let Some(sync) = self.vaults.iter_mut().find(|entry| entry.id == id).and_then(|entry| entry.sync.as_mut()) {
+            sync.last_synced_at = Some(Utc::now());
+        }
+    }
+
+    pub fn set_backup(&mut self, id: &str, backup: Option<BackupSchedule>) -> Result<()> {
+        let entry = self.vaults.iter_mut().find(|entry| entry.id == id).ok_or_else(|| Error::ItemNotFound(id.to_string()))?;
+        entry.backup = backup;
+        Ok(())
+    }
+
+    pub fn record_backup(&mut self, id: &str, outcome: std::result::Result<DateTime<Utc>, String>) {
+        if let Some(backup) = self.vaults.iter_mut().find(|entry| entry.id == id).and_then(|entry| entry.backup.as_mut()) {
+            match outcome {
+                Ok(at) => {
+                    backup.last_backup_at = Some(at);
+                    backup.last_error = None;
+                }
+                Err(e) => backup.last_error = Some(e),
+            }
+        }
+    }
+
+    /// The vault to open at startup: the last used one if its directory is still there (a USB
+    /// stick may have been unplugged), otherwise the first one that is.
+    pub fn startup_vault(&self) -> Option<&VaultEntry> {
+        self.last_used
+            .as_deref()
+            .and_then(|id| self.get(id))
+            .filter(|entry| entry.path.is_dir())
+            .or_else(|| self.vaults.iter().find(|entry| entry.path.is_dir()))
+    }
+}
+
+fn validate_vault_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::InvalidInput("Vault name cannot be empty".into()));
+    }
+    if name.len() > MAX_VAULT_NAME_LENGTH {
+        return Err(Error::InvalidInput("Vault name too long (max 100 characters)".into()));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(Error::InvalidInput("Vault name contains invalid characters".into()));
+    }
+    Ok(name.to_string())
+}
+
+/// True if `path` looks like a vault directory, i.e. it has a vault database in it.
+pub fn is_vault_dir(path: &Path) -> bool {
+    path.join("vault.db").is_file()
+}
@@ -0,0 +1,359 @@
+use crate::crypto::Crypto;
+use crate::login::LOGIN_ITEM_TYPE;
+use crate::records::is_record_type;
+use crate::storage::{Storage, VaultItem};
+use crate::Result;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+// content blobs bigger than this are not scanned (text items are capped at 10MB on insert)
+const MAX_CONTENT_SCAN_BYTES: usize = 10 * 1024 * 1024;
+const SNIPPET_CONTEXT_CHARS: usize = 40;
+const DEFAULT_RESULT_LIMIT: usize = 200;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchOptions {
+    pub query: String,
+    #[serde(default = "default_true")]
+    pub fuzzy: bool,
+    #[serde(default = "default_true", rename = "includeContent")]
+    pub include_content: bool,
+    #[serde(default, rename = "includeDeleted")]
+    pub include_deleted: bool,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchField {
+    Name,
+    Tag,
+    Url,
+    Content,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SnippetSegment {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchMatch {
+    pub field: SearchField,
+    pub segments: Vec<SnippetSegment>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchHit {
+    pub item: VaultItem,
+    pub score: u32,
+    pub matches: Vec<SearchMatch>,
+}
+
+struct FieldMatch {
+    score: u32,
+    field: SearchField,
+    // char positions inside the source text that matched
+    positions: Vec<usize>,
+    source: Vec<char>,
+}
+
+pub fn is_searchable_content_type(item_type: &str) -> bool {
+    item_type == "key" || item_type == "text" || item_type.starts_with("text/")
+}
+
+pub fn search_items(storage: &Storage, crypto: &Crypto, options: &SearchOptions) -> Result<Vec<SearchHit>> {
+    let terms: Vec<Vec<char>> = options
+        .query
+        .split_whitespace()
+        .map(|term| term.chars().map(fold_char).collect())
+        .collect();
+
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let items = storage.get_all_items_recursive(crypto)?;
+    info!("Searching {} items for {} terms", items.len(), terms.len());
+
+    let mut hits = Vec::new();
+    for item in items {
+        if item.deleted_at.is_some() && !options.include_deleted {
+            continue;
+        }
+
+        // with content search off only names and tags are searched and no data file is read;
+        // a login's password is never searched
+        let content = if !options.include_content || item.data_path.is_empty() {
+            None
+        } else if item.item_type == LOGIN_ITEM_TYPE {
+            match storage.read_login(&item, crypto) {
+                Ok(login) => Some(login.searchable_text()),
+                Err(e) => {
+                    warn!("Could not read login {} while searching: {}", item.id, e);
+                    None
+                }
+            }
+        } else if is_record_type(&item.item_type) {
+            match storage.read_record(&item, crypto) {
+                Ok(record) => Some(record.searchable_text()),
+                Err(e) => {
+                    warn!("Could not read {} item {} while searching: {}", item.item_type, item.id, e);
+                    None
+                }
+            }
+        } else if is_searchable_content_type(&item.item_type) {
+            match storage.read_encrypted_file(&item.data_path, crypto) {
+                Ok(bytes) if bytes.len() <= MAX_CONTENT_SCAN_BYTES => String::from_utf8(bytes).ok(),
+                Ok(_) => {
+                    debug!("Skipping oversized content for item {}", item.id);
+                    None
+                }
+                Err(e) => {
+                    warn!("Could not read content of item {} while searching: {}", item.id, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(hit) = score_item(item, content.as_deref(), &terms, options.fuzzy) {
+            hits.push(hit);
+        }
+    }
+
+    hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.item.name.to_lowercase().cmp(&b.item.name.to_lowercase())));
+    hits.truncate(options.limit.unwrap_or(DEFAULT_RESULT_LIMIT));
+
+    info!("Search returned {} hits", hits.len());
+    Ok(hits)
+}
+
+/// Every term has to match at least one field; the item score is the sum of each term's best field score.
+pub fn score_item(item: VaultItem, content: Option<&str>, terms: &[Vec<char>], fuzzy: bool) -> Option<SearchHit> {
+    let name: Vec<char> = item.name.chars().collect();
+    let tags: Vec<Vec<char>> = item.tags.iter().map(|t| t.chars().collect()).collect();
+    let urls: Vec<Vec<char>> = extract_urls(&item.name, content).iter().map(|u| u.chars().collect()).collect();
+    let content: Option<Vec<char>> = content.map(|c| c.chars().collect());
+
+    let mut total = 0;
+    let mut matches: Vec<SearchMatch> = Vec::new();
+
+    for term in terms {
+        let mut best: Option<FieldMatch> = None;
+        let mut consider = |candidate: Option<FieldMatch>| {
+            if let Some(candidate) = candidate {
+                if !matches!(&best, Some(b) if b.score >= candidate.score) {
+                    best = Some(candidate);
+                }
+            }
+        };
+
+        consider(match_field(&name, term, SearchField::Name, fuzzy));
+        for tag in &tags {
+            consider(match_field(tag, term, SearchField::Tag, fuzzy));
+        }
+        for url in &urls {
+            consider(match_field(url, term, SearchField::Url, fuzzy));
+        }
+        if let Some(content) = &content {
+            // fuzzy matching over whole blobs is too noisy, only substrings count here
+            consider(match_field(content, term, SearchField::Content, false));
+        }
+
+        let best = best?;
+        total += best.score;
+        let segments = build_snippet(&best.source, &best.positions, best.field == SearchField::Content);
+        if !matches.iter().any(|m| m.field == best.field && m.segments == segments) {
+            matches.push(SearchMatch { field: best.field, segments });
+        }
+    }
+
+    Some(SearchHit { item, score: total, matches })
+}
+
+fn match_field(source: &[char], term: &[char], field: SearchField, fuzzy: bool) -> Option<FieldMatch> {
+    if term.is_empty() || source.is_empty() {
+        return None;
+    }
+
+    let folded: Vec<char> = source.iter().copied().map(fold_char).collect();
+    let weight = match field {
+        SearchField::Name => 10,
+        SearchField::Tag => 8,
+        SearchField::Url => 6,
+        SearchField::Content => 3,
+    };
+
+    let (base, positions) = if folded == term {
+        (10, (0..term.len()).collect())
+    } else if let Some(start) = find_chars(&folded, term) {
+        let at_word_start = start == 0 || !folded[start - 1].is_alphanumeric();
+        let base = if start == 0 { 8 } else if at_word_start { 6 } else { 4 };
+        (base, (start..start + term.len()).collect())
+    } else if fuzzy {
+        match fuzzy_subsequence(&folded, term).or_else(|| fuzzy_word_typo(&folded, term)) {
+            Some(positions) => (2, positions),
+            None => return None,
+        }
+    } else {
+        return None;
+    };
+
+    Some(FieldMatch {
+        score: base * weight,
+        field,
+        positions,
+        source: source.to_vec(),
+    })
+}
+
+/// Typo-tolerant match of a single term, shared with the `name:~` / `tag:~` query filters.
+pub fn fuzzy_matches(haystack: &str, term: &str) -> bool {
+    let haystack: Vec<char> = haystack.chars().map(fold_char).collect();
+    let term: Vec<char> = term.chars().map(fold_char).collect();
+    find_chars(&haystack, &term).is_some()
+        || fuzzy_subsequence(&haystack, &term).is_some()
+        || fuzzy_word_typo(&haystack, &term).is_some()
+}
+
+fn fold_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn find_chars(haystack: &[char], needle: &[char]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    (0..=haystack.len() - needle.len()).find(|&i| haystack[i..i + needle.len()] == *needle)
+}
+
+/// Matches the term as an in-order subsequence, but only if the gaps stay small enough that the
+/// result still looks like the term (e.g. "gthb" -> "github").
+fn fuzzy_subsequence(haystack: &[char], term: &[char]) -> Option<Vec<usize>> {
+    if term.len() < 2 {
+        return None;
+    }
+
+    let mut positions = Vec::with_capacity(term.len());
+    let mut next = 0;
+    for &c in term {
+        let offset = haystack[next..].iter().position(|&h| h == c)?;
+        positions.push(next + offset);
+        next += offset + 1;
+    }
+
+    let span = positions.last()? - positions.first()? + 1;
+    if span > term.len() * 2 {
+        return None;
+    }
+    Some(positions)
+}
+
+/// Allows a single typo (insert, delete, substitute or swap) against one word of the field.
+fn fuzzy_word_typo(haystack: &[char], term: &[char]) -> Option<Vec<usize>> {
+    if term.len() < 4 {
+        return None;
+    }
+
+    let mut start = 0;
+    while start < haystack.len() {
+        if !haystack[start].is_alphanumeric() {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < haystack.len() && haystack[end].is_alphanumeric() {
+            end += 1;
+        }
+        if damerau_levenshtein(&haystack[start..end], term) <= 1 {
+            return Some((start..end).collect());
+        }
+        start = end;
+    }
+    None
+}
+
+fn damerau_levenshtein(a: &[char], b: &[char]) -> usize {
+    if a.len().abs_diff(b.len()) > 1 {
+        return 2;
+    }
+
+    let mut dist = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in dist[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut value = (dist[i - 1][j] + 1).min(dist[i][j - 1] + 1).min(dist[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                value = value.min(dist[i - 2][j - 2] + 1);
+            }
+            dist[i][j] = value;
+        }
+    }
+    dist[a.len()][b.len()]
+}
+
+fn build_snippet(source: &[char], positions: &[usize], windowed: bool) -> Vec<SnippetSegment> {
+    let (start, end) = match (positions.first(), positions.last()) {
+        (Some(&first), Some(&last)) if windowed => (
+            first.saturating_sub(SNIPPET_CONTEXT_CHARS),
+            (last + 1 + SNIPPET_CONTEXT_CHARS).min(source.len()),
+        ),
+        _ => (0, source.len()),
+    };
+
+    let mut segments: Vec<SnippetSegment> = Vec::new();
+    for (i, &c) in source.iter().enumerate().take(end).skip(start) {
+        // keep snippets on one line
+        let c = if c == '\n' || c == '\r' { ' ' } else { c };
+        let highlighted = positions.binary_search(&i).is_ok();
+        match segments.last_mut() {
+            Some(last) if last.highlighted == highlighted => last.text.push(c),
+            _ => segments.push(SnippetSegment { text: c.to_string(), highlighted }),
+        }
+    }
+    if start > 0 {
+        segments.insert(0, SnippetSegment { text: "…".to_string(), highlighted: false });
+    }
+    if end < source.len() {
+        segments.push(SnippetSegment { text: "…".to_string(), highlighted: false });
+    }
+    segments
+}
+
+fn extract_urls(name: &str, content: Option<&str>) -> Vec<String> {
+    let mut urls = Vec::new();
+
+    let looks_like_url = |s: &str| s.starts_with("http://") || s.starts_with("https://") || s.starts_with("www.");
+    if looks_like_url(name.trim()) {
+        urls.push(name.trim().to_string());
+    }
+
+    if let Some(content) = content {
+        for line in content.lines() {
+            let line = line.trim();
+            if let Some(url) = line.strip_prefix("URL:") {
+                urls.push(url.trim().to_string());
+            } else {
+                urls.extend(line.split_whitespace().filter(|w| looks_like_url(w)).map(|w| w.to_string()));
+            }
+        }
+    }
+
+    urls.retain(|u| !u.is_empty());
+    urls.dedup();
+    urls
+}
@@ -1,15 +1,34 @@
-use crate::crypto::{Crypto, KeyDerivationStrength};
+use crate::crypto::{to_hex, Crypto, KeyDerivationStrength};
+use crate::db::{open_writer, ReadConnectionManager};
 use crate::error::Error;
+use crate::expiry::{expiring_items, ExpiringItem, ReminderSettings};
+use crate::fields::{zeroize_custom_fields, CustomField};
+use crate::links::{FieldReference, ItemLink, ItemLinks, LinkedItem, ReferenceStatus, ResolvedReference, MAX_REFERENCE_DEPTH};
+use crate::login::{LoginData, LOGIN_ITEM_TYPE};
+use crate::query::Query;
+use crate::records::{is_record_type, ItemRecord};
+use crate::search::is_searchable_content_type;
 use crate::Result;
 use chrono::{DateTime, Utc};
 use log::{error, info, debug, trace, warn};
-use rusqlite::{params, Connection, Result as RusqliteResult, Row};
+use r2d2::{Pool, PooledConnection};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rusqlite::backup::Progress;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, DatabaseName, Result as RusqliteResult, Row};
+use sha2::{Digest, Sha256};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::path::{PathBuf, Path};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
 use std::io::{Write, Seek, SeekFrom};
 use std::string::FromUtf8Error;
+use rayon::prelude::*;
+use zeroize::Zeroize;
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
@@ -33,9 +52,13 @@ pub struct VaultItem {
     pub deleted_at: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub totp_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_fields: Vec<CustomField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub enum SortOrder {
     #[default]
     CreatedAtDesc,
@@ -63,9 +86,302 @@ impl Default for BruteForceConfig {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    Delete,
+    Restore,
+    PermanentDelete,
+    Move {
+        #[serde(rename = "parentId")]
+        parent_id: Option<String>,
+    },
+    AddTags {
+        tags: Vec<String>,
+    },
+    RemoveTags {
+        tags: Vec<String>,
+    },
+    SetFolder {
+        #[serde(rename = "folderType")]
+        folder_type: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulkItemResult {
+    pub id: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulkOperationResult {
+    pub committed: bool,
+    pub results: Vec<BulkItemResult>,
+}
+
+/// An encrypted file hanging off an item. Like vault_items rows, everything except the ids is
+/// encrypted in the database and the content lives in its own file under `data/`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Attachment {
+    pub id: String,
+    pub item_id: String,
+    pub name: String,
+    pub mime_type: String,
+    pub size: u64,
+    pub data_path: String,
+    pub created_at: DateTime<Utc>,
+}
+
+pub const MAX_ATTACHMENT_SIZE: usize = 100 * 1024 * 1024;
+pub const MAX_ATTACHMENTS_PER_ITEM: usize = 50;
+
+/// Just enough of an item to draw a tree or a name list, see `Storage::get_item_summaries`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ItemSummary {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub folder_type: Option<String>,
+    pub deleted: bool,
+}
+
+impl From<&VaultItem> for ItemSummary {
+    fn from(item: &VaultItem) -> Self {
+        Self {
+            id: item.id.clone(),
+            parent_id: item.parent_id.clone(),
+            name: item.name.clone(),
+            item_type: item.item_type.clone(),
+            folder_type: item.folder_type.clone(),
+            deleted: item.deleted_at.is_some(),
+        }
+    }
+}
+
+pub const DEFAULT_PAGE_SIZE: usize = 200;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ItemPage {
+    pub items: Vec<VaultItem>,
+    pub next_cursor: Option<String>,
+    pub total: usize,
+}
+
+// position of an item in a listing; a cursor is the key of the last item handed out, so pages stay
+// stable when items are added or removed in between requests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PageKey {
+    folder: bool,
+    name: String,
+    timestamp: i64,
+    id: String,
+}
+
+// recursive, parent, type filter and order of a paged listing
+type ListingKey = (bool, Option<String>, Option<String>, SortOrder);
+
+// at most this many listings keep their sorted keys between pages
+const MAX_SORT_INDEXES: usize = 8;
+
+// sorted page keys of the listings being paged through, valid for one cache generation so later
+// pages don't decrypt every sort value again
+#[derive(Default)]
+struct SortIndexes {
+    generation: u64,
+    listings: HashMap<ListingKey, Arc<Vec<PageKey>>>,
+}
+
+impl SortIndexes {
+    fn clear(&mut self) {
+        for (_, mut keys) in self.listings.drain() {
+            if let Some(keys) = Arc::get_mut(&mut keys) {
+                keys.iter_mut().for_each(|key| key.name.zeroize());
+            }
+        }
+    }
+}
+
+// id, item_type, folder_type and the sort column, still encrypted
+type RawPageKeyRow = (String, Vec<u8>, Option<Vec<u8>>, Vec<u8>);
+
+// id, parent_id, name, item_type, folder_type (encrypted) and whether deleted_at is set
+type RawSummaryRow = (String, Option<String>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, bool);
+
+impl PageKey {
+    fn compare(&self, other: &PageKey, sort_order: SortOrder) -> Ordering {
+        other.folder.cmp(&self.folder)
+            .then_with(|| match sort_order {
+                SortOrder::CreatedAtDesc | SortOrder::UpdatedAtDesc => other.timestamp.cmp(&self.timestamp),
+                SortOrder::CreatedAtAsc | SortOrder::UpdatedAtAsc => self.timestamp.cmp(&other.timestamp),
+                SortOrder::NameAsc => self.name.cmp(&other.name),
+                SortOrder::NameDesc => other.name.cmp(&self.name),
+            })
+            .then_with(|| self.id.cmp(&other.id))
+    }
+
+    fn for_item(item: &VaultItem, sort_order: SortOrder) -> Self {
+        let timestamp = match sort_order {
+            SortOrder::UpdatedAtAsc | SortOrder::UpdatedAtDesc => item.updated_at,
+            _ => item.created_at,
+        };
+        Self {
+            folder: Storage::is_folder_like(&item.item_type),
+            name: Storage::clean_url_for_sorting(&item.name),
+            timestamp: timestamp.timestamp_nanos_opt().unwrap_or_default(),
+            id: item.id.clone(),
+        }
+    }
+
+    fn encode(&self) -> Result<String> {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+        Ok(URL_SAFE_NO_PAD.encode(serde_json::to_vec(self)?))
+    }
+
+    fn decode(cursor: &str) -> Result<Self> {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| Error::InvalidInput("Invalid page cursor".into()))?;
+        serde_json::from_slice(&bytes).map_err(|_| Error::InvalidInput("Invalid page cursor".into()))
+    }
+}
+
+// below this many rows the rayon hand-off costs more than it saves
+const PARALLEL_DECRYPT_THRESHOLD: usize = 64;
+// keeps `IN (...)` lists well under SQLite's bound parameter limit
+const ID_CHUNK_SIZE: usize = 500;
+// concurrent readers; writes always go through the single writer connection
+const READ_POOL_SIZE: u32 = 4;
+
+// a vault_items row straight out of SQLite, nothing decrypted yet
+struct RawItemRow {
+    id: String,
+    parent_id: Option<String>,
+    name: Vec<u8>,
+    item_type: Vec<u8>,
+    data_path: Vec<u8>,
+    folder_type: Option<Vec<u8>>,
+    tags: Vec<u8>,
+    created_at: Vec<u8>,
+    updated_at: Vec<u8>,
+    deleted_at: Option<Vec<u8>>,
+    totp_secret: Option<Vec<u8>>,
+    custom_fields: Option<Vec<u8>>,
+    expires_at: Option<Vec<u8>>,
+}
+
+impl RawItemRow {
+    fn from_row(row: &Row) -> RusqliteResult<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            parent_id: row.get(1)?,
+            name: row.get(2)?,
+            item_type: row.get(3)?,
+            data_path: row.get(4)?,
+            folder_type: row.get(5)?,
+            tags: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+            deleted_at: row.get(9)?,
+            totp_secret: row.get(10)?,
+            custom_fields: row.get(11)?,
+            expires_at: row.get(12)?,
+        })
+    }
+
+    fn decrypt_text(crypto: &Crypto, encrypted: &[u8], column: &str) -> Result<String> {
+        String::from_utf8(crypto.decrypt(encrypted)?)
+            .map_err(|e: FromUtf8Error| Error::Decryption(format!("Invalid UTF-8 in {}: {}", column, e)))
+    }
+
+    // empty blobs have been used for "unset" in the nullable columns
+    fn decrypt_optional_text(crypto: &Crypto, encrypted: &Option<Vec<u8>>, column: &str) -> Result<Option<String>> {
+        match encrypted {
+            Some(encrypted) if !encrypted.is_empty() => Self::decrypt_text(crypto, encrypted, column).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn decrypt_timestamp(crypto: &Crypto, encrypted: &[u8], column: &str) -> Result<DateTime<Utc>> {
+        Self::decrypt_text(crypto, encrypted, column)?
+            .parse()
+            .map_err(|e| Error::Decryption(format!("Invalid timestamp in {}: {}", column, e)))
+    }
+
+    fn decrypt(self, crypto: &Crypto) -> Result<VaultItem> {
+        let tags_json = Self::decrypt_text(crypto, &self.tags, "tags")?;
+        let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_else(|_| vec![]);
+
+        let deleted_at = match Self::decrypt_optional_text(crypto, &self.deleted_at, "deleted_at")? {
+            Some(deleted_at) => Some(deleted_at.parse().map_err(|e| Error::Decryption(format!("Invalid timestamp in deleted_at: {}", e)))?),
+            None => None,
+        };
+
+        let expires_at = match Self::decrypt_optional_text(crypto, &self.expires_at, "expires_at")? {
+            Some(expires_at) => Some(expires_at.parse().map_err(|e| Error::Decryption(format!("Invalid timestamp in expires_at: {}", e)))?),
+            None => None,
+        };
+
+        let custom_fields = match Self::decrypt_optional_text(crypto, &self.custom_fields, "custom_fields")? {
+            Some(fields_json) => serde_json::from_str(&fields_json)
+                .map_err(|e| Error::Decryption(format!("Invalid JSON in custom_fields: {}", e)))?,
+            None => vec![],
+        };
+
+        Ok(VaultItem {
+            id: self.id,
+            parent_id: self.parent_id,
+            name: Self::decrypt_text(crypto, &self.name, "name")?,
+            item_type: Self::decrypt_text(crypto, &self.item_type, "item_type")?,
+            data_path: Self::decrypt_text(crypto, &self.data_path, "data_path")?,
+            folder_type: Self::decrypt_optional_text(crypto, &self.folder_type, "folder_type")?,
+            tags,
+            created_at: Self::decrypt_timestamp(crypto, &self.created_at, "created_at")?,
+            updated_at: Self::decrypt_timestamp(crypto, &self.updated_at, "updated_at")?,
+            deleted_at,
+            totp_secret: Self::decrypt_optional_text(crypto, &self.totp_secret, "totp_secret")?,
+            custom_fields,
+            expires_at,
+        })
+    }
+}
+
+// meta values holding credentials; stored encrypted and re-encrypted on a master key change
+const ENCRYPTED_META_KEYS: [&str; 2] = ["webdav_config", "s3_config"];
+// meta values that describe this device rather than the vault; a restored snapshot keeps ours
+const DEVICE_META_KEYS: [&str; 2] = ["failed_login_attempts", "last_failed_attempt_timestamp"];
+
+pub const SMART_FOLDER_TYPE: &str = "smart_folder";
+
 pub struct Storage {
     vault_path: PathBuf,
-    conn: Mutex<Connection>,
+    // the only connection that writes; every write goes through it one at a time
+    writer: Mutex<Connection>,
+    // thread running `with_rollback`; writes from any other thread wait until it is done
+    held_by: Mutex<Option<ThreadId>>,
+    hold_released: Condvar,
+    // read-only connections, WAL lets them run next to the writer
+    readers: Pool<ReadConnectionManager>,
+    // decrypted items keyed by id; entries are dropped whenever their row is written
+    metadata_cache: Mutex<HashMap<String, VaultItem>>,
+    // bumped on every invalidation so a read that raced a write doesn't cache what it saw
+    cache_generation: AtomicU64,
+    sort_indexes: Mutex<SortIndexes>,
+    // times the writer was taken since the vault was opened, drives "every N writes" backups
+    writes: AtomicU64,
+}
+
+struct WriterHold<'a>(&'a Storage);
+
+impl Drop for WriterHold<'_> {
+    fn drop(&mut self) {
+        *self.0.held_by.lock().unwrap() = None;
+        self.0.hold_released.notify_all();
+    }
 }
 
 impl Storage {
@@ -73,7 +389,7 @@ impl Storage {
         fs::create_dir_all(&vault_path)?;
 
         let db_path = vault_path.join("vault.db");
-        let conn = Connection::open(&db_path)?;
+        let conn = open_writer(&db_path)?;
 
         #[cfg(unix)]
         {
@@ -83,6 +399,31 @@ impl Storage {
             }
         }
 
+        Self::create_schema(&conn)?;
+
+        fs::create_dir_all(vault_path.join("data"))?;
+
+        // opened after the writer so the schema and the WAL files already exist
+        let readers = Pool::builder()
+            .max_size(READ_POOL_SIZE)
+            .min_idle(Some(1))
+            .build(ReadConnectionManager::new(db_path))?;
+
+        Ok(Self {
+            vault_path,
+            writer: Mutex::new(conn),
+            held_by: Mutex::new(None),
+            hold_released: Condvar::new(),
+            readers,
+            metadata_cache: Mutex::new(HashMap::new()),
+            cache_generation: AtomicU64::new(0),
+            sort_indexes: Mutex::new(SortIndexes::default()),
+            writes: AtomicU64::new(0),
+        })
+    }
+
+    // creates missing tables and brings older databases up to the current columns
+    fn create_schema(conn: &Connection) -> Result<()> {
         conn.execute(
             "CREATE TABLE IF NOT EXISTS vault_items (
                 id TEXT PRIMARY KEY,
@@ -95,7 +436,9 @@ impl Storage {
                 created_at BLOB NOT NULL,
                 updated_at BLOB NOT NULL,
                 deleted_at BLOB,
-                totp_secret BLOB
+                totp_secret BLOB,
+                custom_fields BLOB,
+                expires_at BLOB
             )",
             [],
         )?;
@@ -116,6 +459,15 @@ impl Storage {
                 info!("Migrating database: Adding totp_secret column to vault_items");
                 conn.execute("ALTER TABLE vault_items ADD COLUMN totp_secret BLOB", [])?;
             }
+
+            if !columns.contains(&"custom_fields".to_string()) {
+                info!("Migrating database: Adding custom_fields column to vault_items");
+                conn.execute("ALTER TABLE vault_items ADD COLUMN custom_fields BLOB", [])?;
+            }
+            if !columns.contains(&"expires_at".to_string()) {
+                info!("Migrating database: Adding expires_at column to vault_items");
+                conn.execute("ALTER TABLE vault_items ADD COLUMN expires_at BLOB", [])?;
+            }
         }
 
         conn.execute(
@@ -125,13 +477,46 @@ impl Storage {
             )",
             [],
         )?;
+        Self::create_attachments_table(conn)?;
+        Self::create_links_table(conn)?;
+        Self::create_sync_state_table(conn)?;
+        Ok(())
+    }
 
-        fs::create_dir_all(vault_path.join("data"))?;
+    fn writer(&self) -> MutexGuard<'_, Connection> {
+        self.writes.fetch_add(1, AtomicOrdering::Relaxed);
+        self.lock_writer()
+    }
 
-        Ok(Self {
-            vault_path,
-            conn: Mutex::new(conn),
-        })
+    fn lock_writer(&self) -> MutexGuard<'_, Connection> {
+        let me = thread::current().id();
+        loop {
+            let conn = self.writer.lock().unwrap();
+            let held_by = self.held_by.lock().unwrap();
+            match *held_by {
+                Some(owner) if owner != me => {
+                    drop(conn);
+                    let _released = self.hold_released.wait_while(held_by, |owner| owner.is_some()).unwrap();
+                }
+                _ => return conn,
+            }
+        }
+    }
+
+    // keeps writes from other threads out until the returned hold is dropped; taken with the
+    // writer locked, so writes already under way are finished by then
+    fn hold_writer(&self) -> WriterHold<'_> {
+        let _conn = self.lock_writer();
+        *self.held_by.lock().unwrap() = Some(thread::current().id());
+        WriterHold(self)
+    }
+
+    pub fn write_count(&self) -> u64 {
+        self.writes.load(AtomicOrdering::Relaxed)
+    }
+
+    fn reader(&self) -> Result<PooledConnection<ReadConnectionManager>> {
+        Ok(self.readers.get()?)
     }
 
     fn clean_url_for_sorting(name: &str) -> String {
@@ -141,85 +526,156 @@ impl Storage {
             .to_lowercase()
     }
 
+    pub fn is_folder_like(item_type: &str) -> bool {
+        item_type == "folder" || item_type == SMART_FOLDER_TYPE
+    }
+
+    pub fn sort_items(items: &mut [VaultItem], sort_order: SortOrder) {
+        items.sort_by(|a, b| {
+            // folders always come first
+            if Self::is_folder_like(&a.item_type) && !Self::is_folder_like(&b.item_type) {
+                return std::cmp::Ordering::Less;
+            }
+            if !Self::is_folder_like(&a.item_type) && Self::is_folder_like(&b.item_type) {
+                return std::cmp::Ordering::Greater;
+            }
+            
+            // if both are folders or both are not folders, sort normally
+            match sort_order {
+                SortOrder::CreatedAtDesc => b.created_at.cmp(&a.created_at),
+                SortOrder::CreatedAtAsc => a.created_at.cmp(&b.created_at),
+                SortOrder::NameAsc => {
+                    let a_clean = Self::clean_url_for_sorting(&a.name);
+                    let b_clean = Self::clean_url_for_sorting(&b.name);
+                    a_clean.cmp(&b_clean)
+                },
+                SortOrder::NameDesc => {
+                    let a_clean = Self::clean_url_for_sorting(&a.name);
+                    let b_clean = Self::clean_url_for_sorting(&b.name);
+                    b_clean.cmp(&a_clean)
+                },
+                SortOrder::UpdatedAtDesc => b.updated_at.cmp(&a.updated_at),
+                SortOrder::UpdatedAtAsc => a.updated_at.cmp(&b.updated_at),
+            }
+        });
+    }
+
     fn row_to_vault_item(row: &Row, crypto: &Crypto) -> RusqliteResult<VaultItem> {
-        let encrypted_name: Vec<u8> = row.get(2)?;
-        let name = String::from_utf8(crypto.decrypt(&encrypted_name).map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Blob, e.into()))?)
-            .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Blob, Box::new(e)))?;
+        RawItemRow::from_row(row)?
+            .decrypt(crypto)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Blob, Box::new(e)))
+    }
 
-        let encrypted_item_type: Vec<u8> = row.get(3)?;
-        let item_type = String::from_utf8(crypto.decrypt(&encrypted_item_type).map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Blob, e.into()))?)
-            .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Blob, Box::new(e)))?;
-        
-        let encrypted_data_path: Vec<u8> = row.get(4)?;
-        let data_path = String::from_utf8(crypto.decrypt(&encrypted_data_path).map_err(|e| rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Blob, e.into()))?)
-            .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Blob, Box::new(e)))?;
-
-        let encrypted_folder_type: Option<Vec<u8>> = row.get(5)?;
-        let folder_type = match encrypted_folder_type {
-            Some(encrypted) => Some(String::from_utf8(crypto.decrypt(&encrypted).map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, rusqlite::types::Type::Blob, e.into()))?)
-                .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(5, rusqlite::types::Type::Blob, Box::new(e)))?),
-            None => None,
-        };
+    /// Decrypts rows on all cores once there are enough of them to be worth splitting up.
+    fn decrypt_rows(rows: Vec<RawItemRow>, crypto: &Crypto) -> Result<Vec<VaultItem>> {
+        if rows.len() < PARALLEL_DECRYPT_THRESHOLD {
+            rows.into_iter().map(|row| row.decrypt(crypto)).collect()
+        } else {
+            rows.into_par_iter().map(|row| row.decrypt(crypto)).collect()
+        }
+    }
 
-        let encrypted_tags: Vec<u8> = row.get(6)?;
-        let tags_json = String::from_utf8(crypto.decrypt(&encrypted_tags).map_err(|e| rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Blob, e.into()))?)
-            .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Blob, Box::new(e)))?;
-        let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_else(|_| vec![]);
+    /// Loads the items selected by `where_clause`, serving already-decrypted items from the
+    /// metadata cache and decrypting (then caching) the rest.
+    fn fetch_items(&self, conn: &Connection, where_clause: &str, where_params: &[&dyn rusqlite::ToSql], crypto: &Crypto) -> Result<Vec<VaultItem>> {
+        let ids: Vec<String> = {
+            let sql = format!("SELECT id FROM vault_items {}", where_clause);
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(where_params, |row| row.get(0))?;
+            rows.collect::<RusqliteResult<_>>()?
+        };
+        self.fetch_items_by_id(conn, &ids, crypto)
+    }
 
-        let encrypted_created_at: Vec<u8> = row.get(7)?;
-        let created_at_str = String::from_utf8(crypto.decrypt(&encrypted_created_at).map_err(|e| rusqlite::Error::FromSqlConversionFailure(7, rusqlite::types::Type::Blob, e.into()))?)
-            .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(7, rusqlite::types::Type::Blob, Box::new(e)))?;
-        let created_at = created_at_str.parse().map_err(|e| rusqlite::Error::FromSqlConversionFailure(7, rusqlite::types::Type::Text, Box::new(e)))?;
-        
-        let encrypted_updated_at: Vec<u8> = row.get(8)?;
-        let updated_at_str = String::from_utf8(crypto.decrypt(&encrypted_updated_at).map_err(|e| rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Blob, e.into()))?)
-            .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Blob, Box::new(e)))?;
-        let updated_at = updated_at_str.parse().map_err(|e| rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Text, Box::new(e)))?;
-
-        let encrypted_deleted_at: Option<Vec<u8>> = row.get(9)?;
-        let deleted_at = match encrypted_deleted_at {
-            Some(encrypted) => {
-                if encrypted.is_empty() {
-                    None
-                } else {
-                    let deleted_at_str = String::from_utf8(crypto.decrypt(&encrypted).map_err(|e| rusqlite::Error::FromSqlConversionFailure(9, rusqlite::types::Type::Blob, e.into()))?)
-                        .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(9, rusqlite::types::Type::Blob, Box::new(e)))?;
-                    Some(deleted_at_str.parse().map_err(|e| rusqlite::Error::FromSqlConversionFailure(9, rusqlite::types::Type::Text, Box::new(e)))?)
+    fn fetch_items_by_id(&self, conn: &Connection, ids: &[String], crypto: &Crypto) -> Result<Vec<VaultItem>> {
+        let generation = self.cache_generation.load(AtomicOrdering::Acquire);
+        let mut items = Vec::with_capacity(ids.len());
+        let mut missing: Vec<&String> = Vec::new();
+        {
+            let cache = self.metadata_cache.lock().unwrap();
+            for id in ids {
+                match cache.get(id) {
+                    Some(item) => items.push(item.clone()),
+                    None => missing.push(id),
                 }
-            },
-            None => None,
-        };
+            }
+        }
 
-        Ok(VaultItem {
-            id: row.get(0)?,
-            parent_id: row.get(1)?,
-            name,
-            item_type,
-            data_path,
-            folder_type,
-            tags,
-            created_at,
-            updated_at,
-            deleted_at,
-            totp_secret: {
-                let encrypted_totp_secret: Option<Vec<u8>> = row.get(10)?;
-                match encrypted_totp_secret {
-                    Some(encrypted) => {
-                        if encrypted.is_empty() {
-                            None
-                        } else {
-                            Some(String::from_utf8(crypto.decrypt(&encrypted).map_err(|e| rusqlite::Error::FromSqlConversionFailure(10, rusqlite::types::Type::Blob, e.into()))?)
-                                .map_err(|e: FromUtf8Error| rusqlite::Error::FromSqlConversionFailure(10, rusqlite::types::Type::Blob, Box::new(e)))?)
-                        }
-                    },
-                    None => None,
+        if missing.is_empty() {
+            return Ok(items);
+        }
+        trace!("Metadata cache: {} hits, {} misses", items.len(), missing.len());
+
+        let mut raw_rows = Vec::with_capacity(missing.len());
+        for chunk in missing.chunks(ID_CHUNK_SIZE) {
+            let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+            let sql = format!("SELECT * FROM vault_items WHERE id IN ({})", placeholders);
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(rusqlite::params_from_iter(chunk.iter()), RawItemRow::from_row)?;
+            for row in rows {
+                raw_rows.push(row?);
+            }
+        }
+
+        let decrypted = Self::decrypt_rows(raw_rows, crypto)?;
+        {
+            let mut cache = self.metadata_cache.lock().unwrap();
+            // a write landed while we were reading, what we have may already be stale
+            if self.cache_generation.load(AtomicOrdering::Acquire) == generation {
+                for item in &decrypted {
+                    cache.insert(item.id.clone(), item.clone());
                 }
             }
-        })
+        }
+        items.extend(decrypted);
+        Ok(items)
+    }
+
+    fn invalidate_cached_items<S: AsRef<str>>(&self, ids: &[S]) {
+        let mut cache = self.metadata_cache.lock().unwrap();
+        self.cache_generation.fetch_add(1, AtomicOrdering::AcqRel);
+        for id in ids {
+            if let Some(mut item) = cache.remove(id.as_ref()) {
+                Self::zeroize_item(&mut item);
+            }
+        }
+    }
+
+    /// Drops every decrypted item held in memory. Called on lock and after bulk writes.
+    pub fn clear_metadata_cache(&self) {
+        let mut cache = self.metadata_cache.lock().unwrap();
+        self.cache_generation.fetch_add(1, AtomicOrdering::AcqRel);
+        for (_, item) in cache.iter_mut() {
+            Self::zeroize_item(item);
+        }
+        cache.clear();
+        self.sort_indexes.lock().unwrap().clear();
+        debug!("Metadata cache cleared");
+    }
+
+    fn zeroize_item(item: &mut VaultItem) {
+        item.name.zeroize();
+        item.data_path.zeroize();
+        item.tags.iter_mut().for_each(|tag| tag.zeroize());
+        if let Some(secret) = item.totp_secret.as_mut() {
+            secret.zeroize();
+        }
+        zeroize_custom_fields(&mut item.custom_fields);
+    }
+
+    // no fields is stored as NULL, same as the other optional columns
+    fn encrypt_custom_fields(fields: &[CustomField], crypto: &Crypto) -> Result<Option<Vec<u8>>> {
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        let mut fields_json = serde_json::to_vec(fields)?;
+        let encrypted = crypto.encrypt(&fields_json);
+        fields_json.zeroize();
+        encrypted.map(Some)
     }
 
     pub fn add_item(&self, item: &VaultItem, crypto: &Crypto) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.writer();
         let tags_json = serde_json::to_string(&item.tags)?;
 
         let encrypted_name = crypto.encrypt(item.name.as_bytes())?;
@@ -240,9 +696,14 @@ impl Storage {
             Some(secret) => Some(crypto.encrypt(secret.as_bytes())?),
             None => None,
         };
+        let encrypted_custom_fields = Self::encrypt_custom_fields(&item.custom_fields, crypto)?;
+        let encrypted_expires_at = match &item.expires_at {
+            Some(dt) => Some(crypto.encrypt(dt.to_rfc3339().as_bytes())?),
+            None => None,
+        };
 
         conn.execute(
-            "INSERT INTO vault_items (id, parent_id, name, item_type, data_path, folder_type, tags, created_at, updated_at, deleted_at, totp_secret) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            "INSERT INTO vault_items (id, parent_id, name, item_type, data_path, folder_type, tags, created_at, updated_at, deleted_at, totp_secret, custom_fields, expires_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
             params![
                 item.id,
                 item.parent_id,
@@ -255,13 +716,16 @@ impl Storage {
                 encrypted_updated_at,
                 encrypted_deleted_at,
                 encrypted_totp_secret,
+                encrypted_custom_fields,
+                encrypted_expires_at,
             ],
         )?;
+        self.invalidate_cached_items(&[&item.id]);
         Ok(())
     }
     
     pub fn update_item_fields(&self, item: &VaultItem, crypto: &Crypto) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.writer();
         let tags_json = serde_json::to_string(&item.tags)?;
 
         let encrypted_name = crypto.encrypt(item.name.as_bytes())?;
@@ -282,9 +746,14 @@ impl Storage {
             Some(secret) => Some(crypto.encrypt(secret.as_bytes())?),
             None => None,
         };
+        let encrypted_custom_fields = Self::encrypt_custom_fields(&item.custom_fields, crypto)?;
+        let encrypted_expires_at = match &item.expires_at {
+            Some(dt) => Some(crypto.encrypt(dt.to_rfc3339().as_bytes())?),
+            None => None,
+        };
         
         conn.execute(
-            "UPDATE vault_items SET parent_id = ?2, name = ?3, item_type = ?4, data_path = ?5, folder_type = ?6, tags = ?7, created_at = ?8, updated_at = ?9, deleted_at = ?10, totp_secret = ?11 WHERE id = ?1",
+            "UPDATE vault_items SET parent_id = ?2, name = ?3, item_type = ?4, data_path = ?5, folder_type = ?6, tags = ?7, created_at = ?8, updated_at = ?9, deleted_at = ?10, totp_secret = ?11, custom_fields = ?12, expires_at = ?13 WHERE id = ?1",
             params![
                 item.id,
                 item.parent_id,
@@ -297,8 +766,11 @@ impl Storage {
                 encrypted_updated_at,
                 encrypted_deleted_at,
                 encrypted_totp_secret,
+                encrypted_custom_fields,
+                encrypted_expires_at,
             ],
         )?;
+        self.invalidate_cached_items(&[&item.id]);
 
         Ok(())
     }
@@ -310,86 +782,373 @@ impl Storage {
         order_by: Option<SortOrder>,
         crypto: &Crypto,
     ) -> Result<Vec<VaultItem>> {
-        let conn = self.conn.lock().unwrap();
+        // smart folders have no real children, their contents are the live result of their saved query
+        if let Some(pid) = parent_id.as_deref() {
+            if let Some(query) = self.get_smart_folder_query(pid, crypto)? {
+                let items = self.query_items(&query, order_by, crypto)?
+                    .into_iter()
+                    .filter(|item| item.item_type != SMART_FOLDER_TYPE)
+                    .collect();
+                return Ok(Self::filter_by_item_type(items, item_type_filter));
+            }
+        }
+
+        let conn = self.reader()?;
     
-        let all_items_result: RusqliteResult<Vec<VaultItem>> = if let Some(pid) = parent_id {
-            let sql = "SELECT * FROM vault_items WHERE parent_id = ?1";
-            let mut stmt = conn.prepare(sql)?;
-            let item_iter = stmt.query_map(params![pid], |row| Self::row_to_vault_item(row, crypto))?;
-            item_iter.collect()
+        let mut all_items = if let Some(pid) = parent_id {
+            self.fetch_items(&conn, "WHERE parent_id = ?1", &[&pid], crypto)?
         } else {
-            let sql = "SELECT * FROM vault_items WHERE parent_id IS NULL";
-            let mut stmt = conn.prepare(sql)?;
-            let item_iter = stmt.query_map(params![], |row| Self::row_to_vault_item(row, crypto))?;
-            item_iter.collect()
+            self.fetch_items(&conn, "WHERE parent_id IS NULL", &[], crypto)?
         };
         
-        let mut all_items = all_items_result?;
-        
         // sort by cleaned url after decryption
-        let sort_order = order_by.unwrap_or_default();
-        all_items.sort_by(|a, b| {
-            // folders always come first
-            if a.item_type == "folder" && b.item_type != "folder" {
-                return std::cmp::Ordering::Less;
-            }
-            if a.item_type != "folder" && b.item_type == "folder" {
-                return std::cmp::Ordering::Greater;
-            }
-            
-            // if both are folders or both are not folders, sort normally
-            match sort_order {
-                SortOrder::CreatedAtDesc => b.created_at.cmp(&a.created_at),
-                SortOrder::CreatedAtAsc => a.created_at.cmp(&b.created_at),
-                SortOrder::NameAsc => {
-                    let a_clean = Self::clean_url_for_sorting(&a.name);
-                    let b_clean = Self::clean_url_for_sorting(&b.name);
-                    a_clean.cmp(&b_clean)
-                },
-                SortOrder::NameDesc => {
-                    let a_clean = Self::clean_url_for_sorting(&a.name);
-                    let b_clean = Self::clean_url_for_sorting(&b.name);
-                    b_clean.cmp(&a_clean)
-                },
-                SortOrder::UpdatedAtDesc => b.updated_at.cmp(&a.updated_at),
-                SortOrder::UpdatedAtAsc => a.updated_at.cmp(&b.updated_at),
-            }
-        });
+        Self::sort_items(&mut all_items, order_by.unwrap_or_default());
     
-        if let Some(filter) = item_type_filter {
-            let filtered_items = all_items
-                .into_iter()
-                .filter(|item| {
-                    if item.item_type == "folder" {
-                        item.folder_type.as_deref() == Some(&filter)
-                    } else {
-                        item.item_type.starts_with(&filter)
-                    }
-                })
-                .collect();
-            Ok(filtered_items)
-        } else {
-            Ok(all_items)
+        Ok(Self::filter_by_item_type(all_items, item_type_filter))
+    }
+
+    fn filter_by_item_type(items: Vec<VaultItem>, item_type_filter: Option<String>) -> Vec<VaultItem> {
+        if item_type_filter.is_none() {
+            return items;
         }
+        items
+            .into_iter()
+            .filter(|item| Self::matches_item_type_filter(&item.item_type, item.folder_type.as_deref(), item_type_filter.as_deref()))
+            .collect()
     }
-    
-    pub fn get_all_items_recursive(&self, crypto: &Crypto) -> Result<Vec<VaultItem>> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM vault_items")?;
-        let item_iter = stmt.query_map([], |row| Self::row_to_vault_item(row, crypto))?;
 
-        let mut items = Vec::new();
-        for item in item_iter {
-            items.push(item?);
+    fn matches_item_type_filter(item_type: &str, folder_type: Option<&str>, filter: Option<&str>) -> bool {
+        match filter {
+            None => true,
+            Some(filter) if Self::is_folder_like(item_type) => folder_type == Some(filter),
+            Some(filter) => item_type.starts_with(filter),
         }
-        
-        // sort by cleaned url (default to nameasc)
-        items.sort_by(|a, b| {
-            // folders are always on top and not a bottom bitch 
-            if a.item_type == "folder" && b.item_type != "folder" {
-                return std::cmp::Ordering::Less;
-            }
-            if a.item_type != "folder" && b.item_type == "folder" {
+    }
+
+    /// Returns the parsed query if `id` is a smart folder, `None` for any other item.
+    pub fn get_smart_folder_query(&self, id: &str, crypto: &Crypto) -> Result<Option<Query>> {
+        let item = match self.get_item(id, crypto)? {
+            Some(item) if item.item_type == SMART_FOLDER_TYPE => item,
+            _ => return Ok(None),
+        };
+        let query_bytes = self.read_encrypted_file(&item.data_path, crypto)?;
+        let query = String::from_utf8(query_bytes)
+            .map_err(|e| Error::Storage(format!("Smart folder query is not valid UTF-8: {}", e)))?;
+        self.parse_query(&query).map(Some)
+    }
+
+    pub fn is_smart_folder(&self, id: &str, crypto: &Crypto) -> Result<bool> {
+        Ok(self.get_item(id, crypto)?.is_some_and(|item| item.item_type == SMART_FOLDER_TYPE))
+    }
+    
+    pub fn read_login(&self, item: &VaultItem, crypto: &Crypto) -> Result<LoginData> {
+        if item.item_type != LOGIN_ITEM_TYPE {
+            return Err(Error::InvalidInput("Item is not a login".into()));
+        }
+        let mut bytes = self.read_encrypted_file(&item.data_path, crypto)?;
+        let login = LoginData::from_bytes(&bytes);
+        bytes.zeroize();
+        login
+    }
+
+    pub fn write_login(&self, data_path: &str, login: &LoginData, crypto: &Crypto) -> Result<()> {
+        self.write_sensitive_file(login.to_bytes()?, data_path, crypto)
+    }
+
+    pub fn read_record(&self, item: &VaultItem, crypto: &Crypto) -> Result<ItemRecord> {
+        if !is_record_type(&item.item_type) {
+            return Err(Error::InvalidInput(format!("Item of type '{}' has no typed record", item.item_type)));
+        }
+        let mut bytes = self.read_encrypted_file(&item.data_path, crypto)?;
+        let record = ItemRecord::from_bytes(&bytes);
+        bytes.zeroize();
+
+        let record = record?;
+        if record.item_type() != item.item_type {
+            return Err(Error::Storage(format!("Record of item {} does not match its type", item.id)));
+        }
+        Ok(record)
+    }
+
+    pub fn write_record(&self, data_path: &str, record: &ItemRecord, crypto: &Crypto) -> Result<()> {
+        self.write_sensitive_file(record.to_bytes()?, data_path, crypto)
+    }
+
+    // encrypts and writes a plaintext buffer, wiping the plaintext whether or not that worked
+    fn write_sensitive_file(&self, mut plaintext: Vec<u8>, data_path: &str, crypto: &Crypto) -> Result<()> {
+        let encrypted = crypto.encrypt(&plaintext);
+        plaintext.zeroize();
+        self.write_encrypted_file(&encrypted?, data_path)
+    }
+
+    /// Converts `key` items holding the old "Username: ...\n\nPassword: ..." csv import blob into
+    /// login items. Runs once per vault; items that don't parse are left as they are.
+    pub fn migrate_legacy_logins(&self, crypto: &Crypto) -> Result<usize> {
+        if self.get_meta_value("login_migration_done")?.is_some() {
+            return Ok(0);
+        }
+
+        let candidates: Vec<VaultItem> = self
+            .get_all_items_recursive(crypto)?
+            .into_iter()
+            .filter(|item| item.item_type == "key" && !item.data_path.is_empty())
+            .collect();
+
+        let mut migrated = 0;
+        for mut item in candidates {
+            let mut content = match self.read_encrypted_file(&item.data_path, crypto) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    warn!("Skipping login migration for item {}: {}", item.id, e);
+                    continue;
+                }
+            };
+            let login = std::str::from_utf8(&content).ok().and_then(LoginData::from_legacy_text);
+            content.zeroize();
+
+            let Some(login) = login else {
+                continue;
+            };
+            // the login goes to a new file and the item switches path and type in one update, so
+            // a failure leaves it a key pointing at its old text and a rerun picks it up again
+            let legacy_path = std::mem::replace(&mut item.data_path, uuid::Uuid::new_v4().to_string());
+            self.write_login(&item.data_path, &login, crypto)?;
+            item.item_type = LOGIN_ITEM_TYPE.to_string();
+            if let Err(e) = self.update_item_fields(&item, crypto) {
+                self.shred_data_files(&[item.data_path]);
+                return Err(e);
+            }
+            self.shred_data_files(&[legacy_path]);
+            migrated += 1;
+        }
+
+        self.set_meta_value("login_migration_done", "1")?;
+        info!("Migrated {} legacy key items to login items", migrated);
+        Ok(migrated)
+    }
+
+    // `keys` must already be sorted in `sort_order`
+    fn page_window(keys: &[PageKey], after: Option<PageKey>, page_size: usize, sort_order: SortOrder) -> Result<(Vec<PageKey>, Option<String>, usize)> {
+        let total = keys.len();
+        let start = after.map_or(0, |after| keys.partition_point(|key| key.compare(&after, sort_order) != Ordering::Greater));
+        let mut page_keys: Vec<PageKey> = keys.iter().skip(start).take(page_size + 1).cloned().collect();
+        let has_more = page_keys.len() > page_size;
+        page_keys.truncate(page_size);
+
+        let next_cursor = match (has_more, page_keys.last()) {
+            (true, Some(last)) => Some(last.encode()?),
+            _ => None,
+        };
+        Ok((page_keys, next_cursor, total))
+    }
+
+    fn decrypt_column(crypto: &Crypto, encrypted: &[u8]) -> Result<String> {
+        String::from_utf8(crypto.decrypt(encrypted)?)
+            .map_err(|e| Error::Decryption(format!("Invalid UTF-8 in decrypted column: {}", e)))
+    }
+
+    /// Lists one page of a folder (or of the whole vault when `recursive` is set). Only the columns
+    /// needed to sort and filter are decrypted for the full listing, complete rows are decrypted
+    /// for the returned page only.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_items_page(
+        &self,
+        parent_id: Option<String>,
+        recursive: bool,
+        item_type_filter: Option<String>,
+        order_by: Option<SortOrder>,
+        cursor: Option<&str>,
+        page_size: Option<usize>,
+        crypto: &Crypto,
+    ) -> Result<ItemPage> {
+        let sort_order = order_by.unwrap_or_default();
+        let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let after = cursor.map(PageKey::decode).transpose()?;
+
+        // smart folder contents come from a query over every item, so there is nothing to save here
+        if let (false, Some(pid)) = (recursive, parent_id.as_deref()) {
+            if self.is_smart_folder(pid, crypto)? {
+                let items = self.get_items(parent_id, item_type_filter, order_by, crypto)?;
+                let mut keys: Vec<PageKey> = items.iter().map(|item| PageKey::for_item(item, sort_order)).collect();
+                keys.sort_by(|a, b| a.compare(b, sort_order));
+                let mut items_by_id: HashMap<String, VaultItem> = items.into_iter().map(|item| (item.id.clone(), item)).collect();
+                let (page_keys, next_cursor, total) = Self::page_window(&keys, after, page_size, sort_order)?;
+                let items = page_keys.iter().filter_map(|key| items_by_id.remove(&key.id)).collect();
+                return Ok(ItemPage { items, next_cursor, total });
+            }
+        }
+
+        let sort_column = match sort_order {
+            SortOrder::CreatedAtAsc | SortOrder::CreatedAtDesc => "created_at",
+            SortOrder::UpdatedAtAsc | SortOrder::UpdatedAtDesc => "updated_at",
+            SortOrder::NameAsc | SortOrder::NameDesc => "name",
+        };
+
+        let conn = self.reader()?;
+
+        let generation = self.cache_generation.load(AtomicOrdering::Acquire);
+        let listing: ListingKey = (recursive, parent_id.clone(), item_type_filter.clone(), sort_order);
+        let cached_keys = {
+            let indexes = self.sort_indexes.lock().unwrap();
+            if indexes.generation == generation { indexes.listings.get(&listing).cloned() } else { None }
+        };
+        let keys = match cached_keys {
+            Some(keys) => keys,
+            None => self.sort_index(&conn, listing, generation, sort_column, crypto)?,
+        };
+
+        let (page_keys, next_cursor, total) = Self::page_window(&keys, after, page_size, sort_order)?;
+        drop(keys);
+
+        if page_keys.is_empty() {
+            return Ok(ItemPage { items: Vec::new(), next_cursor, total });
+        }
+
+        let page_ids: Vec<String> = page_keys.iter().map(|key| key.id.clone()).collect();
+        let mut items_by_id: HashMap<String, VaultItem> = self
+            .fetch_items_by_id(&conn, &page_ids, crypto)?
+            .into_iter()
+            .map(|item| (item.id.clone(), item))
+            .collect();
+
+        let items = page_keys.iter().filter_map(|key| items_by_id.remove(&key.id)).collect();
+        Ok(ItemPage { items, next_cursor, total })
+    }
+
+    // decrypts the sort column of every row in a listing and keeps the sorted keys for the next pages
+    fn sort_index(&self, conn: &Connection, listing: ListingKey, generation: u64, sort_column: &str, crypto: &Crypto) -> Result<Arc<Vec<PageKey>>> {
+        let (recursive, parent_id, item_type_filter, sort_order) = listing.clone();
+        let mut keys: Vec<PageKey> = {
+            let (sql, bound_parent) = match (recursive, &parent_id) {
+                (true, _) => (format!("SELECT id, item_type, folder_type, {} FROM vault_items", sort_column), None),
+                (false, Some(pid)) => (format!("SELECT id, item_type, folder_type, {} FROM vault_items WHERE parent_id = ?1", sort_column), Some(pid.clone())),
+                (false, None) => (format!("SELECT id, item_type, folder_type, {} FROM vault_items WHERE parent_id IS NULL", sort_column), None),
+            };
+            let mut stmt = conn.prepare(&sql)?;
+            let raw_rows: Vec<RawPageKeyRow> = match bound_parent {
+                Some(pid) => stmt.query_map(params![pid], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?.collect::<RusqliteResult<_>>()?,
+                None => stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?.collect::<RusqliteResult<_>>()?,
+            };
+
+            let cache = self.metadata_cache.lock().unwrap();
+            let cached: &HashMap<String, VaultItem> = &cache;
+            let key_for_row = |(id, encrypted_item_type, encrypted_folder_type, encrypted_sort_value): RawPageKeyRow| -> Result<Option<PageKey>> {
+                if let Some(item) = cached.get(&id) {
+                    let keep = Self::matches_item_type_filter(&item.item_type, item.folder_type.as_deref(), item_type_filter.as_deref());
+                    return Ok(keep.then(|| PageKey::for_item(item, sort_order)));
+                }
+
+                let item_type = Self::decrypt_column(crypto, &encrypted_item_type)?;
+                if item_type_filter.is_some() {
+                    let folder_type = match &encrypted_folder_type {
+                        Some(encrypted) if Self::is_folder_like(&item_type) => Some(Self::decrypt_column(crypto, encrypted)?),
+                        _ => None,
+                    };
+                    if !Self::matches_item_type_filter(&item_type, folder_type.as_deref(), item_type_filter.as_deref()) {
+                        return Ok(None);
+                    }
+                }
+
+                let sort_value = Self::decrypt_column(crypto, &encrypted_sort_value)?;
+                let (name, timestamp) = if sort_column == "name" {
+                    (Self::clean_url_for_sorting(&sort_value), 0)
+                } else {
+                    let parsed: DateTime<Utc> = sort_value
+                        .parse()
+                        .map_err(|e| Error::Storage(format!("Failed to parse {}: {}", sort_column, e)))?;
+                    (String::new(), parsed.timestamp_nanos_opt().unwrap_or_default())
+                };
+
+                Ok(Some(PageKey { folder: Self::is_folder_like(&item_type), name, timestamp, id }))
+            };
+
+            let keys: Result<Vec<Option<PageKey>>> = if raw_rows.len() < PARALLEL_DECRYPT_THRESHOLD {
+                raw_rows.into_iter().map(key_for_row).collect()
+            } else {
+                raw_rows.into_par_iter().map(key_for_row).collect()
+            };
+            keys?.into_iter().flatten().collect()
+        };
+        keys.sort_by(|a, b| a.compare(b, sort_order));
+        let keys = Arc::new(keys);
+
+        let mut indexes = self.sort_indexes.lock().unwrap();
+        // a write landed while we were reading, the next page has to look again
+        if self.cache_generation.load(AtomicOrdering::Acquire) == generation {
+            if indexes.generation != generation || indexes.listings.len() >= MAX_SORT_INDEXES {
+                indexes.clear();
+                indexes.generation = generation;
+            }
+            indexes.listings.insert(listing, Arc::clone(&keys));
+        }
+        Ok(keys)
+    }
+
+    /// Name/type listing that only decrypts name, item_type and folder_type for rows that are not
+    /// already in the metadata cache.
+    pub fn get_item_summaries(&self, parent_id: Option<String>, recursive: bool, crypto: &Crypto) -> Result<Vec<ItemSummary>> {
+        let conn = self.reader()?;
+
+        let select = "SELECT id, parent_id, name, item_type, folder_type, (deleted_at IS NOT NULL AND length(deleted_at) > 0) FROM vault_items";
+        let mut stmt = match (recursive, &parent_id) {
+            (true, _) => conn.prepare(select)?,
+            (false, Some(_)) => conn.prepare(&format!("{} WHERE parent_id = ?1", select))?,
+            (false, None) => conn.prepare(&format!("{} WHERE parent_id IS NULL", select))?,
+        };
+        let map_row = |row: &Row| -> RusqliteResult<RawSummaryRow> {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+        };
+        let raw_rows: Vec<RawSummaryRow> = match (recursive, &parent_id) {
+            (false, Some(pid)) => stmt.query_map(params![pid], map_row)?.collect::<RusqliteResult<_>>()?,
+            _ => stmt.query_map([], map_row)?.collect::<RusqliteResult<_>>()?,
+        };
+
+        let cache = self.metadata_cache.lock().unwrap();
+        let cached: &HashMap<String, VaultItem> = &cache;
+        let to_summary = |(id, parent_id, name, item_type, folder_type, deleted): RawSummaryRow| -> Result<ItemSummary> {
+            if let Some(item) = cached.get(&id) {
+                return Ok(ItemSummary::from(item));
+            }
+            Ok(ItemSummary {
+                id,
+                parent_id,
+                name: Self::decrypt_column(crypto, &name)?,
+                item_type: Self::decrypt_column(crypto, &item_type)?,
+                folder_type: match folder_type {
+                    Some(encrypted) if !encrypted.is_empty() => Some(Self::decrypt_column(crypto, &encrypted)?),
+                    _ => None,
+                },
+                deleted,
+            })
+        };
+
+        let mut summaries: Vec<ItemSummary> = if raw_rows.len() < PARALLEL_DECRYPT_THRESHOLD {
+            raw_rows.into_iter().map(to_summary).collect::<Result<_>>()?
+        } else {
+            raw_rows.into_par_iter().map(to_summary).collect::<Result<_>>()?
+        };
+
+        summaries.sort_by(|a, b| {
+            Self::is_folder_like(&b.item_type)
+                .cmp(&Self::is_folder_like(&a.item_type))
+                .then_with(|| Self::clean_url_for_sorting(&a.name).cmp(&Self::clean_url_for_sorting(&b.name)))
+        });
+        Ok(summaries)
+    }
+
+    pub fn get_all_items_recursive(&self, crypto: &Crypto) -> Result<Vec<VaultItem>> {
+        let mut items = {
+            let conn = self.reader()?;
+            self.fetch_items(&conn, "", &[], crypto)?
+        };
+        
+        // sort by cleaned url (default to nameasc)
+        items.sort_by(|a, b| {
+            // folders are always on top and not a bottom bitch 
+            if Self::is_folder_like(&a.item_type) && !Self::is_folder_like(&b.item_type) {
+                return std::cmp::Ordering::Less;
+            }
+            if !Self::is_folder_like(&a.item_type) && Self::is_folder_like(&b.item_type) {
                 return std::cmp::Ordering::Greater;
             }
             
@@ -402,11 +1161,17 @@ impl Storage {
         Ok(items)
     }
 
+    pub fn query_items(&self, query: &Query, order_by: Option<SortOrder>, crypto: &Crypto) -> Result<Vec<VaultItem>> {
+        let all_items = self.get_all_items_recursive(crypto)?;
+        let mut matched = query.filter(&all_items);
+        Self::sort_items(&mut matched, order_by.unwrap_or_default());
+        Ok(matched)
+    }
+
     pub fn get_item(&self, id: &str, crypto: &Crypto) -> Result<Option<VaultItem>> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM vault_items WHERE id = ?1")?;
-        let mut rows = stmt.query_map(params![id], |row| Self::row_to_vault_item(row, crypto))?;
-        rows.next().transpose().map_err(Error::from)
+        let conn = self.reader()?;
+        let mut items = self.fetch_items(&conn, "WHERE id = ?1", &[&id], crypto)?;
+        Ok(items.pop())
     }
 
     fn write_shred_pattern(file_path: &Path, pattern_byte: u8) -> std::io::Result<()> {
@@ -464,7 +1229,7 @@ impl Storage {
     }
 
     pub fn delete_item_and_descendants(&self, id: &str, crypto: &Crypto) -> Result<()> {
-        let mut conn = self.conn.lock().unwrap();
+        let mut conn = self.writer();
         let tx = conn.transaction()?;
     
         let mut ids_to_delete = Vec::new();
@@ -499,171 +1264,836 @@ impl Storage {
             }
             tx.execute(&sql, rusqlite::params_from_iter(params_vec))?;
         }
-    
-        tx.commit()?;
-    
+    
+        tx.commit()?;
+        self.invalidate_cached_items(&ids_to_delete);
+    
+        Ok(())
+    }
+    
+    pub fn permanently_delete_item_and_descendants(&self, id: &str, crypto: &Crypto) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+
+        let mut ids_to_delete = Vec::new();
+        let mut queue = vec![id.to_string()];
+
+        {
+            let mut get_children_stmt = tx.prepare("SELECT id FROM vault_items WHERE parent_id = ?1")?;
+            while let Some(current_id) = queue.pop() {
+                let children_ids: Vec<String> = get_children_stmt
+                    .query_map(params![&current_id], |row| row.get(0))?
+                    .collect::<RusqliteResult<_>>()?;
+
+                queue.extend(children_ids);
+                ids_to_delete.push(current_id);
+            }
+        }
+
+        if ids_to_delete.is_empty() {
+            tx.commit()?;
+            return Ok(());
+        }
+
+        let data_paths: Vec<String> = {
+            let placeholders = ids_to_delete.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+            let sql = format!("SELECT * FROM vault_items WHERE id IN ({})", placeholders);
+            let params_from_ids = rusqlite::params_from_iter(ids_to_delete.iter());
+
+            let mut stmt = tx.prepare(&sql)?;
+            let item_iter = stmt.query_map(params_from_ids, |row| Self::row_to_vault_item(row, crypto))?;
+            
+            item_iter
+                .filter_map(|item_result| item_result.ok())
+                .map(|item| item.data_path)
+                .filter(|path| !path.is_empty())
+                .collect()
+        };
+
+        let attachment_paths = Self::delete_attachments_in_transaction(&tx, &ids_to_delete, crypto)?;
+        Self::delete_links_in_transaction(&tx, &ids_to_delete)?;
+
+        {
+            let placeholders = ids_to_delete.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+            let sql = format!("DELETE FROM vault_items WHERE id IN ({})", placeholders);
+            let params_from_ids = rusqlite::params_from_iter(ids_to_delete.iter());
+            tx.execute(&sql, params_from_ids)?;
+        }
+
+        tx.commit()?;
+        self.invalidate_cached_items(&ids_to_delete);
+
+        self.shred_data_files(&data_paths);
+        self.shred_data_files(&attachment_paths);
+
+        Ok(())
+    }
+
+    pub(crate) fn shred_data_files(&self, data_paths: &[String]) {
+        let data_dir = self.vault_path.join("data");
+        for path in data_paths {
+            if path.is_empty() { continue; }
+            let file_path = data_dir.join(path);
+            if file_path.exists() {
+                // Security: Use enhanced secure shredding
+                if let Err(e) = Self::secure_shred_file(&file_path) {
+                    error!("Failed to securely shred file {}: {}", file_path.display(), e);
+                    // Fallback to basic shredding
+                    if let Err(e2) = Self::write_shred_pattern(&file_path, 0x00) {
+                        error!("Failed fallback shred {}: {}", file_path.display(), e2);
+                    }
+                }
+                if let Err(e) = fs::remove_file(&file_path) {
+                    error!("Failed to delete file {}: {}", file_path.display(), e);
+                }
+            }
+        }
+    }
+
+    pub fn restore_item(&self, id: &str) -> Result<bool> {
+        let conn = self.writer();
+        let changes = conn.execute(
+            "UPDATE vault_items SET deleted_at = NULL WHERE id = ?1",
+            params![id],
+        )?;
+        self.invalidate_cached_items(&[id]);
+        Ok(changes > 0)
+    }
+
+    pub fn permanently_delete_all_deleted_items(&self, crypto: &Crypto) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+
+        // Get all deleted items
+        let deleted_items: Vec<(String, Vec<u8>)> = {
+            let mut stmt = tx.prepare("SELECT id, data_path FROM vault_items WHERE deleted_at IS NOT NULL")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            rows.collect::<RusqliteResult<_>>()?
+        }; // stmt is dropped here
+
+        if deleted_items.is_empty() {
+            tx.commit()?;
+            return Ok(());
+        }
+
+        // Delete all data files
+        for (_, encrypted_data_path) in &deleted_items {
+            if let Ok(data_path_bytes) = crypto.decrypt(encrypted_data_path) {
+                if let Ok(data_path) = String::from_utf8(data_path_bytes) {
+                    let file_path = self.vault_path.join("data").join(&data_path);
+                    if file_path.exists() {
+                        if let Err(e) = fs::remove_file(&file_path) {
+                            warn!("Failed to delete data file {}: {}", file_path.display(), e);
+                        }
+                    }
+                }
+            }
+        }
+
+        let deleted_ids: Vec<String> = deleted_items.iter().map(|(id, _)| id.clone()).collect();
+        let attachment_paths = Self::delete_attachments_in_transaction(&tx, &deleted_ids, crypto)?;
+        Self::delete_links_in_transaction(&tx, &deleted_ids)?;
+
+        // Delete all database records
+        tx.execute("DELETE FROM vault_items WHERE deleted_at IS NOT NULL", [])?;
+        tx.commit()?;
+        self.invalidate_cached_items(&deleted_ids);
+        self.shred_data_files(&attachment_paths);
+        Ok(())
+    }
+
+    pub fn restore_item_to_root(&self, id: &str) -> Result<bool> {
+        let conn = self.writer();
+        let changes = conn.execute(
+            "UPDATE vault_items SET deleted_at = NULL, parent_id = NULL WHERE id = ?1",
+            params![id],
+        )?;
+        self.invalidate_cached_items(&[id]);
+        Ok(changes > 0)
+    }
+
+    pub fn restore_item_and_descendants(&self, id: &str, _crypto: &Crypto) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+
+        let mut ids_to_restore = Vec::new();
+        let mut queue = vec![id.to_string()];
+
+        {
+            let mut get_children_stmt = tx.prepare("SELECT id FROM vault_items WHERE parent_id = ?1")?;
+            while let Some(current_id) = queue.pop() {
+                let children_ids: Vec<String> = get_children_stmt
+                    .query_map(params![&current_id], |row| row.get(0))?
+                    .collect::<RusqliteResult<_>>()?;
+
+                queue.extend(children_ids);
+                ids_to_restore.push(current_id);
+            }
+        }
+
+        if ids_to_restore.is_empty() {
+            tx.commit()?;
+            return Ok(());
+        }
+
+        {
+            let placeholders = ids_to_restore.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+            let sql = format!("UPDATE vault_items SET deleted_at = NULL WHERE id IN ({})", placeholders);
+            let params_from_ids = rusqlite::params_from_iter(ids_to_restore.iter());
+            tx.execute(&sql, params_from_ids)?;
+        }
+
+        tx.commit()?;
+        self.invalidate_cached_items(&ids_to_restore);
+        Ok(())
+    }
+
+    fn collect_subtree_ids(tx: &rusqlite::Transaction, id: &str) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        let mut queue = vec![id.to_string()];
+        let mut get_children_stmt = tx.prepare("SELECT id FROM vault_items WHERE parent_id = ?1")?;
+        while let Some(current_id) = queue.pop() {
+            let children_ids: Vec<String> = get_children_stmt
+                .query_map(params![&current_id], |row| row.get(0))?
+                .collect::<RusqliteResult<_>>()?;
+
+            queue.extend(children_ids);
+            ids.push(current_id);
+        }
+        Ok(ids)
+    }
+
+    fn get_item_in_transaction(tx: &rusqlite::Transaction, id: &str, crypto: &Crypto) -> Result<Option<VaultItem>> {
+        let mut stmt = tx.prepare("SELECT * FROM vault_items WHERE id = ?1")?;
+        let mut rows = stmt.query_map(params![id], |row| Self::row_to_vault_item(row, crypto))?;
+        rows.next().transpose().map_err(Error::from)
+    }
+
+    /// Applies one action to every id inside a single transaction. If any id fails the whole
+    /// batch is rolled back, so callers never see a half-applied result.
+    pub fn bulk_update(&self, ids: &[String], action: &BulkAction, crypto: &Crypto) -> Result<BulkOperationResult> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+
+        let now = Utc::now();
+        let mut handled: HashSet<String> = HashSet::new();
+        let mut data_paths: Vec<String> = Vec::new();
+        let mut results = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            let outcome = if handled.contains(id) {
+                // already covered as a descendant of an earlier id in this batch
+                Ok(())
+            } else {
+                self.apply_bulk_action(&tx, id, action, now, crypto, &mut handled, &mut data_paths)
+            };
+
+            match outcome {
+                Ok(()) => results.push(BulkItemResult { id: id.clone(), success: true, error: None }),
+                Err(e) => {
+                    warn!("Bulk action {:?} failed for item {}: {}", action, id, e);
+                    results.push(BulkItemResult { id: id.clone(), success: false, error: Some(e.to_string()) });
+                }
+            }
+        }
+
+        if results.iter().any(|r| !r.success) {
+            tx.rollback()?;
+            for result in results.iter_mut().filter(|r| r.success) {
+                result.success = false;
+                result.error = Some("Rolled back because another item in the batch failed".to_string());
+            }
+            info!("Bulk action {:?} rolled back ({} ids)", action, ids.len());
+            return Ok(BulkOperationResult { committed: false, results });
+        }
+
+        tx.commit()?;
+        let handled: Vec<String> = handled.into_iter().collect();
+        self.invalidate_cached_items(&handled);
+        drop(conn);
+
+        self.shred_data_files(&data_paths);
+
+        info!("Bulk action {:?} committed for {} ids", action, ids.len());
+        Ok(BulkOperationResult { committed: true, results })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn apply_bulk_action(
+        &self,
+        tx: &rusqlite::Transaction,
+        id: &str,
+        action: &BulkAction,
+        now: DateTime<Utc>,
+        crypto: &Crypto,
+        handled: &mut HashSet<String>,
+        data_paths: &mut Vec<String>,
+    ) -> Result<()> {
+        let mut item = Self::get_item_in_transaction(tx, id, crypto)?
+            .ok_or_else(|| Error::ItemNotFound(id.to_string()))?;
+
+        match action {
+            BulkAction::Delete | BulkAction::Restore | BulkAction::PermanentDelete => {
+                let subtree = Self::collect_subtree_ids(tx, id)?;
+                let placeholders = subtree.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+
+                match action {
+                    BulkAction::Delete => {
+                        let encrypted_deleted_at = crypto.encrypt(now.to_rfc3339().as_bytes())?;
+                        let sql = format!("UPDATE vault_items SET deleted_at = ?1 WHERE id IN ({})", placeholders);
+                        let mut params_vec: Vec<&dyn rusqlite::ToSql> = vec![&encrypted_deleted_at];
+                        for sub_id in &subtree {
+                            params_vec.push(sub_id);
+                        }
+                        tx.execute(&sql, rusqlite::params_from_iter(params_vec))?;
+                    }
+                    BulkAction::Restore => {
+                        let sql = format!("UPDATE vault_items SET deleted_at = NULL WHERE id IN ({})", placeholders);
+                        tx.execute(&sql, rusqlite::params_from_iter(subtree.iter()))?;
+                    }
+                    _ => {
+                        for sub_id in &subtree {
+                            if let Some(sub_item) = Self::get_item_in_transaction(tx, sub_id, crypto)? {
+                                if !sub_item.data_path.is_empty() {
+                                    data_paths.push(sub_item.data_path);
+                                }
+                            }
+                        }
+                        data_paths.extend(Self::delete_attachments_in_transaction(tx, &subtree, crypto)?);
+                        Self::delete_links_in_transaction(tx, &subtree)?;
+                        let sql = format!("DELETE FROM vault_items WHERE id IN ({})", placeholders);
+                        tx.execute(&sql, rusqlite::params_from_iter(subtree.iter()))?;
+                    }
+                }
+
+                handled.extend(subtree);
+                return Ok(());
+            }
+            BulkAction::Move { parent_id } => {
+                if let Some(pid) = parent_id {
+                    let target = Self::get_item_in_transaction(tx, pid, crypto)?
+                        .ok_or_else(|| Error::ItemNotFound(pid.clone()))?;
+                    if target.item_type != "folder" {
+                        return Err(Error::InvalidInput("Target is not a folder".into()));
+                    }
+                    if Self::collect_subtree_ids(tx, id)?.contains(pid) {
+                        return Err(Error::InvalidInput("Cannot move a folder into itself".into()));
+                    }
+                }
+                item.parent_id = parent_id.clone();
+            }
+            BulkAction::AddTags { tags } => {
+                for tag in tags {
+                    if !item.tags.contains(tag) {
+                        item.tags.push(tag.clone());
+                    }
+                }
+            }
+            BulkAction::RemoveTags { tags } => {
+                item.tags.retain(|tag| !tags.contains(tag));
+            }
+            BulkAction::SetFolder { folder_type } => {
+                if item.item_type != "folder" {
+                    return Err(Error::InvalidInput("Item is not a folder".into()));
+                }
+                item.folder_type = folder_type.clone();
+            }
+        }
+
+        item.updated_at = now;
+        self.update_item_fields_in_transaction(&item, crypto, tx)?;
+        handled.insert(id.to_string());
+        Ok(())
+    }
+
+    fn create_attachments_table(conn: &Connection) -> Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS attachments (
+                id TEXT PRIMARY KEY,
+                item_id TEXT NOT NULL,
+                name BLOB NOT NULL,
+                mime_type BLOB NOT NULL,
+                size BLOB NOT NULL,
+                data_path BLOB NOT NULL,
+                created_at BLOB NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute("CREATE INDEX IF NOT EXISTS idx_attachments_item_id ON attachments (item_id)", [])?;
+        Ok(())
+    }
+
+    fn row_to_attachment(row: &Row, crypto: &Crypto) -> Result<Attachment> {
+        let size: Vec<u8> = row.get(4)?;
+        let size = RawItemRow::decrypt_text(crypto, &size, "size")?
+            .parse()
+            .map_err(|e| Error::Decryption(format!("Invalid attachment size: {}", e)))?;
+        let name: Vec<u8> = row.get(2)?;
+        let mime_type: Vec<u8> = row.get(3)?;
+        let data_path: Vec<u8> = row.get(5)?;
+        let created_at: Vec<u8> = row.get(6)?;
+
+        Ok(Attachment {
+            id: row.get(0)?,
+            item_id: row.get(1)?,
+            name: RawItemRow::decrypt_text(crypto, &name, "name")?,
+            mime_type: RawItemRow::decrypt_text(crypto, &mime_type, "mime_type")?,
+            size,
+            data_path: RawItemRow::decrypt_text(crypto, &data_path, "data_path")?,
+            created_at: RawItemRow::decrypt_timestamp(crypto, &created_at, "created_at")?,
+        })
+    }
+
+    fn query_attachments(conn: &Connection, where_clause: &str, params: &[&dyn rusqlite::ToSql], crypto: &Crypto) -> Result<Vec<Attachment>> {
+        let sql = format!("SELECT id, item_id, name, mime_type, size, data_path, created_at FROM attachments {}", where_clause);
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params)?;
+        let mut attachments = Vec::new();
+        while let Some(row) = rows.next()? {
+            attachments.push(Self::row_to_attachment(row, crypto)?);
+        }
+        Ok(attachments)
+    }
+
+    /// Stores `content` encrypted as a new attachment of `item_id`.
+    pub fn add_attachment(&self, item_id: &str, name: &str, mime_type: &str, content: &[u8], crypto: &Crypto) -> Result<Attachment> {
+        let item = self.get_item(item_id, crypto)?.ok_or_else(|| Error::ItemNotFound(item_id.to_string()))?;
+        if Self::is_folder_like(&item.item_type) {
+            return Err(Error::InvalidInput("Folders cannot have attachments".into()));
+        }
+        if item.deleted_at.is_some() {
+            return Err(Error::InvalidInput("Restore the item before adding attachments".into()));
+        }
+        if content.len() > MAX_ATTACHMENT_SIZE {
+            return Err(Error::InvalidInput("Attachment too large (max 100MB)".into()));
+        }
+
+        let conn = self.writer();
+        let existing: usize = conn.query_row("SELECT COUNT(*) FROM attachments WHERE item_id = ?1", params![item_id], |row| row.get(0))?;
+        if existing >= MAX_ATTACHMENTS_PER_ITEM {
+            return Err(Error::InvalidInput("Too many attachments on this item (max 50)".into()));
+        }
+
+        let attachment = Attachment {
+            id: uuid::Uuid::new_v4().to_string(),
+            item_id: item_id.to_string(),
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+            size: content.len() as u64,
+            data_path: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+        };
+        self.write_attachment(&conn, &attachment, content, crypto)?;
+        info!("Added attachment {} to item {}", attachment.id, item_id);
+        Ok(attachment)
+    }
+
+    /// Stores an attachment that already has an id, as sync does for one added on another device.
+    /// The item is not checked; the attachment gets a fresh data path.
+    pub(crate) fn insert_attachment(&self, attachment: &Attachment, content: &[u8], crypto: &Crypto) -> Result<Attachment> {
+        let attachment = Attachment {
+            size: content.len() as u64,
+            data_path: uuid::Uuid::new_v4().to_string(),
+            ..attachment.clone()
+        };
+        let conn = self.writer();
+        self.write_attachment(&conn, &attachment, content, crypto)?;
+        Ok(attachment)
+    }
+
+    fn write_attachment(&self, conn: &Connection, attachment: &Attachment, content: &[u8], crypto: &Crypto) -> Result<()> {
+        self.write_encrypted_file(&crypto.encrypt(content)?, &attachment.data_path)?;
+        let inserted = conn.execute(
+            "INSERT INTO attachments (id, item_id, name, mime_type, size, data_path, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                attachment.id,
+                attachment.item_id,
+                crypto.encrypt(attachment.name.as_bytes())?,
+                crypto.encrypt(attachment.mime_type.as_bytes())?,
+                crypto.encrypt(attachment.size.to_string().as_bytes())?,
+                crypto.encrypt(attachment.data_path.as_bytes())?,
+                crypto.encrypt(attachment.created_at.to_rfc3339().as_bytes())?,
+            ],
+        );
+        if let Err(e) = inserted {
+            // don't leave an orphaned blob behind
+            self.shred_data_files(std::slice::from_ref(&attachment.data_path));
+            return Err(e.into());
+        }
         Ok(())
     }
-    
-    pub fn permanently_delete_item_and_descendants(&self, id: &str, crypto: &Crypto) -> Result<()> {
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction()?;
 
-        let mut ids_to_delete = Vec::new();
-        let mut queue = vec![id.to_string()];
+    pub fn list_attachments(&self, item_id: &str, crypto: &Crypto) -> Result<Vec<Attachment>> {
+        let conn = self.reader()?;
+        let mut attachments = Self::query_attachments(&conn, "WHERE item_id = ?1", &[&item_id], crypto)?;
+        attachments.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)));
+        Ok(attachments)
+    }
+
+    pub fn list_all_attachments(&self, crypto: &Crypto) -> Result<Vec<Attachment>> {
+        let conn = self.reader()?;
+        Self::query_attachments(&conn, "", &[], crypto)
+    }
+
+    pub fn get_attachment(&self, id: &str, crypto: &Crypto) -> Result<Option<Attachment>> {
+        let conn = self.reader()?;
+        Ok(Self::query_attachments(&conn, "WHERE id = ?1", &[&id], crypto)?.pop())
+    }
+
+    pub fn read_attachment(&self, attachment: &Attachment, crypto: &Crypto) -> Result<Vec<u8>> {
+        self.read_encrypted_file(&attachment.data_path, crypto)
+    }
 
+    /// Deletes the attachment row and shreds its file. Returns false if there was no such attachment.
+    pub fn remove_attachment(&self, id: &str, crypto: &Crypto) -> Result<bool> {
+        let Some(attachment) = self.get_attachment(id, crypto)? else {
+            return Ok(false);
+        };
         {
-            let mut get_children_stmt = tx.prepare("SELECT id FROM vault_items WHERE parent_id = ?1")?;
-            while let Some(current_id) = queue.pop() {
-                let children_ids: Vec<String> = get_children_stmt
-                    .query_map(params![&current_id], |row| row.get(0))?
-                    .collect::<RusqliteResult<_>>()?;
+            let conn = self.writer();
+            conn.execute("DELETE FROM attachments WHERE id = ?1", params![id])?;
+        }
+        self.shred_data_files(&[attachment.data_path]);
+        info!("Removed attachment {} from item {}", id, attachment.item_id);
+        Ok(true)
+    }
 
-                queue.extend(children_ids);
-                ids_to_delete.push(current_id);
+    // deletes the attachment rows of `item_ids` and hands back their files for shredding after commit
+    fn delete_attachments_in_transaction(tx: &rusqlite::Transaction, item_ids: &[String], crypto: &Crypto) -> Result<Vec<String>> {
+        let mut data_paths = Vec::new();
+        for chunk in item_ids.chunks(ID_CHUNK_SIZE) {
+            let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+            let select = format!("SELECT data_path FROM attachments WHERE item_id IN ({})", placeholders);
+            let encrypted_paths: Vec<Vec<u8>> = tx
+                .prepare(&select)?
+                .query_map(rusqlite::params_from_iter(chunk.iter()), |row| row.get(0))?
+                .collect::<RusqliteResult<_>>()?;
+            for encrypted in encrypted_paths {
+                data_paths.push(RawItemRow::decrypt_text(crypto, &encrypted, "data_path")?);
             }
+
+            let delete = format!("DELETE FROM attachments WHERE item_id IN ({})", placeholders);
+            tx.execute(&delete, rusqlite::params_from_iter(chunk.iter()))?;
         }
+        Ok(data_paths)
+    }
 
-        if ids_to_delete.is_empty() {
-            tx.commit()?;
-            return Ok(());
+    /// Re-encrypts every attachment row and file from `old` to `new`, used when the master key changes.
+    pub fn rekey_attachments(&self, old: &Crypto, new: &Crypto) -> Result<usize> {
+        let conn = self.writer();
+        let attachments = Self::query_attachments(&conn, "", &[], old)?;
+
+        for attachment in &attachments {
+            let mut content = self.read_encrypted_file(&attachment.data_path, old)?;
+            let encrypted = new.encrypt(&content);
+            content.zeroize();
+            self.write_encrypted_file(&encrypted?, &attachment.data_path)?;
+
+            conn.execute(
+                "UPDATE attachments SET name = ?2, mime_type = ?3, size = ?4, data_path = ?5, created_at = ?6 WHERE id = ?1",
+                params![
+                    attachment.id,
+                    new.encrypt(attachment.name.as_bytes())?,
+                    new.encrypt(attachment.mime_type.as_bytes())?,
+                    new.encrypt(attachment.size.to_string().as_bytes())?,
+                    new.encrypt(attachment.data_path.as_bytes())?,
+                    new.encrypt(attachment.created_at.to_rfc3339().as_bytes())?,
+                ],
+            )?;
         }
+        Ok(attachments.len())
+    }
 
-        let data_paths: Vec<String> = {
-            let placeholders = ids_to_delete.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-            let sql = format!("SELECT * FROM vault_items WHERE id IN ({})", placeholders);
-            let params_from_ids = rusqlite::params_from_iter(ids_to_delete.iter());
+    fn create_links_table(conn: &Connection) -> Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS item_links (
+                id TEXT PRIMARY KEY,
+                source_id TEXT NOT NULL,
+                target_id TEXT NOT NULL,
+                label BLOB,
+                created_at BLOB NOT NULL,
+                UNIQUE (source_id, target_id)
+            )",
+            [],
+        )?;
+        conn.execute("CREATE INDEX IF NOT EXISTS idx_item_links_source_id ON item_links (source_id)", [])?;
+        conn.execute("CREATE INDEX IF NOT EXISTS idx_item_links_target_id ON item_links (target_id)", [])?;
+        Ok(())
+    }
 
-            let mut stmt = tx.prepare(&sql)?;
-            let item_iter = stmt.query_map(params_from_ids, |row| Self::row_to_vault_item(row, crypto))?;
-            
-            item_iter
-                .filter_map(|item_result| item_result.ok())
-                .map(|item| item.data_path)
-                .filter(|path| !path.is_empty())
-                .collect()
-        };
+    fn query_links(conn: &Connection, where_clause: &str, params: &[&dyn rusqlite::ToSql], crypto: &Crypto) -> Result<Vec<ItemLink>> {
+        let sql = format!("SELECT id, source_id, target_id, label, created_at FROM item_links {}", where_clause);
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params)?;
+        let mut links = Vec::new();
+        while let Some(row) = rows.next()? {
+            let label: Option<Vec<u8>> = row.get(3)?;
+            let created_at: Vec<u8> = row.get(4)?;
+            links.push(ItemLink {
+                id: row.get(0)?,
+                source_id: row.get(1)?,
+                target_id: row.get(2)?,
+                label: RawItemRow::decrypt_optional_text(crypto, &label, "label")?,
+                created_at: RawItemRow::decrypt_timestamp(crypto, &created_at, "created_at")?,
+            });
+        }
+        Ok(links)
+    }
 
-        {
-            let placeholders = ids_to_delete.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-            let sql = format!("DELETE FROM vault_items WHERE id IN ({})", placeholders);
-            let params_from_ids = rusqlite::params_from_iter(ids_to_delete.iter());
-            tx.execute(&sql, params_from_ids)?;
+    pub fn add_link(&self, source_id: &str, target_id: &str, label: Option<&str>, crypto: &Crypto) -> Result<ItemLink> {
+        if source_id == target_id {
+            return Err(Error::InvalidInput("An item cannot link to itself".into()));
+        }
+        for id in [source_id, target_id] {
+            let item = self.get_item(id, crypto)?.ok_or_else(|| Error::ItemNotFound(id.to_string()))?;
+            if item.item_type == SMART_FOLDER_TYPE {
+                return Err(Error::InvalidInput("Smart folders cannot be linked".into()));
+            }
         }
 
-        tx.commit()?;
+        let link = ItemLink {
+            id: uuid::Uuid::new_v4().to_string(),
+            source_id: source_id.to_string(),
+            target_id: target_id.to_string(),
+            label: label.map(str::trim).filter(|l| !l.is_empty()).map(str::to_string),
+            created_at: Utc::now(),
+        };
+        let encrypted_label = match &link.label {
+            Some(label) => Some(crypto.encrypt(label.as_bytes())?),
+            None => None,
+        };
 
-        let data_dir = self.vault_path.join("data");
-        for path in data_paths {
-            if path.is_empty() { continue; }
-            let file_path = data_dir.join(path);
-            if file_path.exists() {
-                // Security: Use enhanced secure shredding
-                if let Err(e) = Self::secure_shred_file(&file_path) {
-                    error!("Failed to securely shred file {}: {}", file_path.display(), e);
-                    // Fallback to basic shredding
-                    if let Err(e2) = Self::write_shred_pattern(&file_path, 0x00) {
-                        error!("Failed fallback shred {}: {}", file_path.display(), e2);
-                    }
-                }
-                if let Err(e) = fs::remove_file(&file_path) {
-                    error!("Failed to delete file {}: {}", file_path.display(), e);
-                }
-            }
+        let conn = self.writer();
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM item_links WHERE source_id = ?1 AND target_id = ?2)",
+            params![source_id, target_id],
+            |row| row.get(0),
+        )?;
+        if exists {
+            return Err(Error::InvalidInput("These items are already linked".into()));
         }
+        conn.execute(
+            "INSERT INTO item_links (id, source_id, target_id, label, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![link.id, link.source_id, link.target_id, encrypted_label, crypto.encrypt(link.created_at.to_rfc3339().as_bytes())?],
+        )?;
+        info!("Linked item {} to {}", source_id, target_id);
+        Ok(link)
+    }
 
+    /// Stores a link that already has an id, replacing any other link between the same two items.
+    /// Used by sync, so neither item has to exist yet.
+    pub(crate) fn insert_link(&self, link: &ItemLink, crypto: &Crypto) -> Result<()> {
+        let encrypted_label = match &link.label {
+            Some(label) => Some(crypto.encrypt(label.as_bytes())?),
+            None => None,
+        };
+        let conn = self.writer();
+        conn.execute(
+            "INSERT OR REPLACE INTO item_links (id, source_id, target_id, label, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![link.id, link.source_id, link.target_id, encrypted_label, crypto.encrypt(link.created_at.to_rfc3339().as_bytes())?],
+        )?;
         Ok(())
     }
 
-    pub fn restore_item(&self, id: &str) -> Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let changes = conn.execute(
-            "UPDATE vault_items SET deleted_at = NULL WHERE id = ?1",
-            params![id],
-        )?;
-        Ok(changes > 0)
+    pub fn remove_link(&self, id: &str) -> Result<bool> {
+        let conn = self.writer();
+        Ok(conn.execute("DELETE FROM item_links WHERE id = ?1", params![id])? > 0)
     }
 
-    pub fn permanently_delete_all_deleted_items(&self, crypto: &Crypto) -> Result<()> {
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction()?;
+    /// Outgoing links, backlinks and field references for one item. Links to soft-deleted items
+    /// are kept so restoring the item brings them back; they only go away on permanent delete.
+    pub fn get_links(&self, item_id: &str, crypto: &Crypto) -> Result<ItemLinks> {
+        let (outgoing, incoming) = {
+            let conn = self.reader()?;
+            (
+                Self::query_links(&conn, "WHERE source_id = ?1", &[&item_id], crypto)?,
+                Self::query_links(&conn, "WHERE target_id = ?1", &[&item_id], crypto)?,
+            )
+        };
 
-        // Get all deleted items
-        let deleted_items: Vec<(String, Vec<u8>)> = {
-            let mut stmt = tx.prepare("SELECT id, data_path FROM vault_items WHERE deleted_at IS NOT NULL")?;
-            let rows = stmt.query_map([], |row| {
-                Ok((row.get(0)?, row.get(1)?))
-            })?;
-            rows.collect::<RusqliteResult<_>>()?
-        }; // stmt is dropped here
+        let all_items = self.get_all_items_recursive(crypto)?;
+        let by_id: HashMap<&str, &VaultItem> = all_items.iter().map(|item| (item.id.as_str(), item)).collect();
+        let linked = |link: ItemLink, other_id: String| LinkedItem {
+            item: by_id.get(other_id.as_str()).map(|item| ItemSummary::from(*item)),
+            link,
+        };
 
-        if deleted_items.is_empty() {
-            tx.commit()?;
-            return Ok(());
+        let mut links = ItemLinks {
+            outgoing: outgoing.into_iter().map(|link| { let other = link.target_id.clone(); linked(link, other) }).collect(),
+            backlinks: incoming.into_iter().map(|link| { let other = link.source_id.clone(); linked(link, other) }).collect(),
+            referenced_by: all_items
+                .iter()
+                .filter(|item| item.id != item_id)
+                .filter(|item| {
+                    item.custom_fields
+                        .iter()
+                        .any(|field| FieldReference::parse(&field.value).is_some_and(|r| r.item_id == item_id))
+                })
+                .map(ItemSummary::from)
+                .collect(),
+        };
+        links.outgoing.sort_by_key(|linked| linked.link.created_at);
+        links.backlinks.sort_by_key(|linked| linked.link.created_at);
+        Ok(links)
+    }
+
+    fn delete_links_in_transaction(tx: &rusqlite::Transaction, item_ids: &[String]) -> Result<()> {
+        for chunk in item_ids.chunks(ID_CHUNK_SIZE / 2) {
+            let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+            let sql = format!("DELETE FROM item_links WHERE source_id IN ({0}) OR target_id IN ({0})", placeholders);
+            tx.execute(&sql, rusqlite::params_from_iter(chunk.iter().chain(chunk.iter())))?;
         }
+        Ok(())
+    }
 
-        // Delete all data files
-        for (_, encrypted_data_path) in &deleted_items {
-            if let Ok(data_path_bytes) = crypto.decrypt(encrypted_data_path) {
-                if let Ok(data_path) = String::from_utf8(data_path_bytes) {
-                    let file_path = self.vault_path.join("data").join(&data_path);
-                    if file_path.exists() {
-                        if let Err(e) = fs::remove_file(&file_path) {
-                            warn!("Failed to delete data file {}: {}", file_path.display(), e);
-                        }
-                    }
-                }
-            }
+    pub fn list_all_links(&self, crypto: &Crypto) -> Result<Vec<ItemLink>> {
+        let conn = self.reader()?;
+        Self::query_links(&conn, "", &[], crypto)
+    }
+
+    pub(crate) fn list_links_from(&self, source_id: &str, crypto: &Crypto) -> Result<Vec<ItemLink>> {
+        let conn = self.reader()?;
+        Self::query_links(&conn, "WHERE source_id = ?1", &[&source_id], crypto)
+    }
+
+    pub fn rekey_links(&self, old: &Crypto, new: &Crypto) -> Result<usize> {
+        let conn = self.writer();
+        let links = Self::query_links(&conn, "", &[], old)?;
+        for link in &links {
+            let encrypted_label = match &link.label {
+                Some(label) => Some(new.encrypt(label.as_bytes())?),
+                None => None,
+            };
+            conn.execute(
+                "UPDATE item_links SET label = ?2, created_at = ?3 WHERE id = ?1",
+                params![link.id, encrypted_label, new.encrypt(link.created_at.to_rfc3339().as_bytes())?],
+            )?;
         }
+        Ok(links.len())
+    }
 
-        // Delete all database records
-        tx.execute("DELETE FROM vault_items WHERE deleted_at IS NOT NULL", [])?;
-        tx.commit()?;
+    // item id -> revision last synced through the sync folder; revision ids are random and
+    // carry nothing about the item, like item ids they stay unencrypted
+    fn create_sync_state_table(conn: &Connection) -> Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sync_state (
+                item_id TEXT PRIMARY KEY,
+                revision TEXT NOT NULL
+            )",
+            [],
+        )?;
         Ok(())
     }
 
-    pub fn restore_item_to_root(&self, id: &str) -> Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let changes = conn.execute(
-            "UPDATE vault_items SET deleted_at = NULL, parent_id = NULL WHERE id = ?1",
-            params![id],
-        )?;
-        Ok(changes > 0)
+    pub fn get_sync_state(&self) -> Result<HashMap<String, String>> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare("SELECT item_id, revision FROM sync_state")?;
+        let state = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<RusqliteResult<HashMap<String, String>>>()?;
+        Ok(state)
     }
 
-    pub fn restore_item_and_descendants(&self, id: &str, _crypto: &Crypto) -> Result<()> {
-        let mut conn = self.conn.lock().unwrap();
+    pub fn save_sync_state(&self, state: &HashMap<String, String>) -> Result<()> {
+        let mut conn = self.writer();
         let tx = conn.transaction()?;
+        tx.execute("DELETE FROM sync_state", [])?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO sync_state (item_id, revision) VALUES (?1, ?2)")?;
+            for (item_id, revision) in state {
+                stmt.execute(params![item_id, revision])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
 
-        let mut ids_to_restore = Vec::new();
-        let mut queue = vec![id.to_string()];
+    pub fn clear_sync_state(&self) -> Result<()> {
+        self.writer().execute("DELETE FROM sync_state", [])?;
+        Ok(())
+    }
 
-        {
-            let mut get_children_stmt = tx.prepare("SELECT id FROM vault_items WHERE parent_id = ?1")?;
-            while let Some(current_id) = queue.pop() {
-                let children_ids: Vec<String> = get_children_stmt
-                    .query_map(params![&current_id], |row| row.get(0))?
-                    .collect::<RusqliteResult<_>>()?;
+    /// Resolves every `{ref:...}` custom field of `item` to the value it points at.
+    pub fn resolve_references(&self, item: &VaultItem, crypto: &Crypto) -> Result<Vec<ResolvedReference>> {
+        let mut resolved = Vec::new();
+        for field in &item.custom_fields {
+            let Some(reference) = FieldReference::parse(&field.value) else {
+                continue;
+            };
+            let (status, value) = self.resolve_reference(&reference, crypto, 0)?;
+            resolved.push(ResolvedReference { label: field.label.clone(), reference, status, value });
+        }
+        Ok(resolved)
+    }
 
-                queue.extend(children_ids);
-                ids_to_restore.push(current_id);
-            }
+    fn resolve_reference(&self, reference: &FieldReference, crypto: &Crypto, depth: usize) -> Result<(ReferenceStatus, Option<String>)> {
+        if depth >= MAX_REFERENCE_DEPTH {
+            return Ok((ReferenceStatus::TooDeep, None));
+        }
+        let Some(target) = self.get_item(&reference.item_id, crypto)? else {
+            return Ok((ReferenceStatus::MissingItem, None));
+        };
+        if target.deleted_at.is_some() {
+            return Ok((ReferenceStatus::TargetDeleted, None));
         }
 
-        if ids_to_restore.is_empty() {
-            tx.commit()?;
-            return Ok(());
+        match self.read_referenced_field(&target, &reference.field, crypto)? {
+            None => Ok((ReferenceStatus::MissingField, None)),
+            Some(value) => match FieldReference::parse(&value) {
+                Some(next) => self.resolve_reference(&next, crypto, depth + 1),
+                None => Ok((ReferenceStatus::Resolved, Some(value))),
+            },
         }
+    }
 
-        {
-            let placeholders = ids_to_restore.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-            let sql = format!("UPDATE vault_items SET deleted_at = NULL WHERE id IN ({})", placeholders);
-            let params_from_ids = rusqlite::params_from_iter(ids_to_restore.iter());
-            tx.execute(&sql, params_from_ids)?;
+    fn read_referenced_field(&self, item: &VaultItem, field: &str, crypto: &Crypto) -> Result<Option<String>> {
+        if let Some(label) = field.strip_prefix("field:") {
+            return Ok(item.custom_fields.iter().find(|f| f.label.eq_ignore_ascii_case(label.trim())).map(|f| f.value.clone()));
+        }
+        match field {
+            "name" => return Ok(Some(item.name.clone())),
+            "totp" => return Ok(item.totp_secret.clone()),
+            _ => {}
         }
 
-        tx.commit()?;
-        Ok(())
+        if item.item_type == LOGIN_ITEM_TYPE {
+            let login = self.read_login(item, crypto)?;
+            return Ok(match field {
+                "username" => Some(login.username.clone()),
+                "password" => Some(login.password.clone()),
+                "url" => login.urls.first().cloned(),
+                "notes" => Some(login.notes.clone()),
+                _ => None,
+            });
+        }
+        if is_record_type(&item.item_type) {
+            let record = serde_json::to_value(self.read_record(item, crypto)?)?;
+            return Ok(match record["data"].get(field) {
+                Some(serde_json::Value::String(value)) => Some(value.clone()),
+                Some(serde_json::Value::Number(value)) => Some(value.to_string()),
+                _ => None,
+            });
+        }
+        if field == "content" && is_searchable_content_type(&item.item_type) && !item.data_path.is_empty() {
+            let content = self.read_encrypted_file(&item.data_path, crypto)?;
+            return Ok(String::from_utf8(content).ok());
+        }
+        Ok(None)
+    }
+
+    pub fn set_item_expiry(&self, id: &str, expires_at: Option<DateTime<Utc>>, crypto: &Crypto) -> Result<()> {
+        let mut item = self.get_item(id, crypto)?.ok_or_else(|| Error::ItemNotFound(id.to_string()))?;
+        if item.item_type == "folder" || item.item_type == SMART_FOLDER_TYPE {
+            return Err(Error::InvalidInput("Folders cannot have an expiry date".into()));
+        }
+        item.expires_at = expires_at;
+        item.updated_at = Utc::now();
+        self.update_item_fields(&item, crypto)
+    }
+
+    pub fn get_expiring_items(&self, days: u32, crypto: &Crypto) -> Result<Vec<ExpiringItem>> {
+        let items = self.get_all_items_recursive(crypto)?;
+        Ok(expiring_items(&items, days, Utc::now()))
     }
 
     pub fn is_initialized(&self) -> bool {
@@ -687,14 +2117,14 @@ impl Storage {
     }
 
     fn get_meta_value(&self, key: &str) -> Result<Option<String>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader()?;
         let mut stmt = conn.prepare("SELECT value FROM vault_meta WHERE key = ?1")?;
         let value: RusqliteResult<String> = stmt.query_row(params![key], |row| row.get(0));
         Ok(value.ok())
     }
 
     fn set_meta_value(&self, key: &str, value: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.writer();
         conn.execute(
             "INSERT OR REPLACE INTO vault_meta (key, value) VALUES (?1, ?2)",
             params![key, value],
@@ -702,6 +2132,34 @@ impl Storage {
         Ok(())
     }
 
+    pub fn get_encrypted_meta_value(&self, key: &str, crypto: &Crypto) -> Result<Option<Vec<u8>>> {
+        let Some(value) = self.get_meta_value(key)? else {
+            return Ok(None);
+        };
+        let encrypted = STANDARD.decode(value).map_err(|e| Error::Storage(format!("Failed to decode {}: {}", key, e)))?;
+        Ok(Some(crypto.decrypt(&encrypted)?))
+    }
+
+    pub fn set_encrypted_meta_value(&self, key: &str, value: &[u8], crypto: &Crypto) -> Result<()> {
+        self.set_meta_value(key, &STANDARD.encode(crypto.encrypt(value)?))
+    }
+
+    pub fn remove_meta_value(&self, key: &str) -> Result<()> {
+        self.writer().execute("DELETE FROM vault_meta WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    pub fn rekey_encrypted_meta(&self, old: &Crypto, new: &Crypto) -> Result<()> {
+        for key in ENCRYPTED_META_KEYS {
+            if let Some(mut value) = self.get_encrypted_meta_value(key, old)? {
+                let stored = self.set_encrypted_meta_value(key, &value, new);
+                value.zeroize();
+                stored?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn initialize(&self, salt: &[u8], strength: KeyDerivationStrength) -> Result<()> {
         fs::write(self.vault_path.join("salt"), salt)?;
         self.set_key_derivation_strength(strength)?;
@@ -746,6 +2204,26 @@ impl Storage {
         Ok(())
     }
 
+    pub fn get_reminder_settings(&self) -> Result<ReminderSettings> {
+        let settings_json = self.get_meta_value("expiry_reminder_settings")?;
+        if let Some(json) = settings_json {
+            serde_json::from_str(&json).map_err(|e| Error::Storage(format!("Failed to parse reminder settings: {}", e)))
+        } else {
+            Ok(ReminderSettings::default())
+        }
+    }
+
+    /// Parses a query with `is:expiring` covering the configured reminder window.
+    pub fn parse_query(&self, input: &str) -> Result<Query> {
+        Query::parse_at(input, Utc::now(), self.get_reminder_settings()?.days_before)
+    }
+
+    pub fn set_reminder_settings(&self, settings: ReminderSettings) -> Result<()> {
+        let settings_json = serde_json::to_string(&settings)?;
+        self.set_meta_value("expiry_reminder_settings", &settings_json)?;
+        Ok(())
+    }
+
     pub fn get_failed_login_attempts(&self) -> Result<u32> {
         let attempts_str = self.get_meta_value("failed_login_attempts")?;
         attempts_str.unwrap_or_else(|| "0".to_string()).parse().map_err(|e| Error::Storage(format!("Failed to parse failed login attempts: {}", e)))
@@ -837,16 +2315,133 @@ impl Storage {
         }
     }
 
+    /// Writes a consistent copy of the database to `destination` without blocking writers.
+    pub fn snapshot_database(&self, destination: &Path) -> Result<()> {
+        let conn = self.reader()?;
+        conn.execute("VACUUM INTO ?1", params![destination.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Replaces the database with the copy at `source`, e.g. a snapshot pulled from a remote.
+    /// Failed unlock attempts and folder sync state belong to this device and survive it.
+    pub fn restore_database(&self, source: &Path) -> Result<()> {
+        let sync_state = self.get_sync_state()?;
+        let device_meta: Vec<(&str, Option<String>)> =
+            DEVICE_META_KEYS.iter().map(|key| Ok((*key, self.get_meta_value(key)?))).collect::<Result<_>>()?;
+        {
+            let mut conn = self.writer();
+            conn.restore(DatabaseName::Main, source, None::<fn(Progress)>)?;
+            Self::create_schema(&conn)?;
+        }
+        self.clear_metadata_cache();
+
+        self.save_sync_state(&sync_state)?;
+        for (key, value) in device_meta {
+            match value {
+                Some(value) => self.set_meta_value(key, &value)?,
+                None => self.remove_meta_value(key)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries what belongs to this device rather than to the vault, failed unlock attempts and
+    /// folder sync state, over to `target`, a copy of the vault about to take this one's place.
+    pub fn copy_device_state(&self, target: &Storage) -> Result<()> {
+        target.save_sync_state(&self.get_sync_state()?)?;
+        for key in DEVICE_META_KEYS {
+            match self.get_meta_value(key)? {
+                Some(value) => target.set_meta_value(key, &value)?,
+                None => target.remove_meta_value(key)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a change made of many writes, e.g. a merge, and undoes it if it fails part way: the
+    /// database goes back to a snapshot taken first and the data files `apply` noted down in the
+    /// list it is given are removed. Writes from other threads wait until it is done, so the
+    /// rollback can't take theirs with it. Files that already existed aren't brought back, so
+    /// `apply` must only write new ones.
+    pub fn with_rollback<T>(&self, apply: impl FnOnce(&mut Vec<String>) -> Result<T>) -> Result<T> {
+        let _hold = self.hold_writer();
+        let snapshot = std::env::temp_dir().join(format!("fetch-rollback-{}.db", uuid::Uuid::new_v4()));
+        self.snapshot_database(&snapshot)?;
+
+        let mut written = Vec::new();
+        let result = apply(&mut written);
+        if let Err(e) = &result {
+            warn!("Rolling back a change that failed part way: {}", e);
+            if let Err(restore_error) = self.restore_database(&snapshot) {
+                // leave the snapshot where it is, it's the only copy of the vault as it was
+                error!("Failed to roll back, the vault as it was is kept at {}: {}", snapshot.display(), restore_error);
+                return result;
+            }
+            self.shred_data_files(&written);
+        }
+        if let Err(e) = fs::remove_file(&snapshot) {
+            warn!("Failed to remove rollback snapshot {}: {}", snapshot.display(), e);
+        }
+        result
+    }
+
+    /// Hash of what a snapshot of the database carries: items, attachments, links and settings,
+    /// leaving out device state. Unlike a hash of the file it doesn't change when the same rows
+    /// end up in different pages.
+    pub fn content_fingerprint(&self) -> Result<String> {
+        let conn = self.reader()?;
+        let placeholders = DEVICE_META_KEYS.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        let meta_sql = format!("SELECT key, value FROM vault_meta WHERE key NOT IN ({}) ORDER BY key", placeholders);
+        let queries: [(&str, Vec<&str>); 4] = [
+            ("SELECT * FROM vault_items ORDER BY id", vec![]),
+            ("SELECT * FROM attachments ORDER BY id", vec![]),
+            ("SELECT * FROM item_links ORDER BY id", vec![]),
+            (meta_sql.as_str(), DEVICE_META_KEYS.to_vec()),
+        ];
+
+        let mut hasher = Sha256::new();
+        for (sql, query_params) in queries {
+            let mut stmt = conn.prepare(sql)?;
+            let columns = stmt.column_count();
+            let mut rows = stmt.query(rusqlite::params_from_iter(query_params))?;
+            while let Some(row) = rows.next()? {
+                for i in 0..columns {
+                    match row.get_ref(i)? {
+                        ValueRef::Null => hasher.update([0]),
+                        ValueRef::Integer(value) => {
+                            hasher.update([1]);
+                            hasher.update(value.to_le_bytes());
+                        }
+                        ValueRef::Real(value) => {
+                            hasher.update([2]);
+                            hasher.update(value.to_le_bytes());
+                        }
+                        ValueRef::Text(bytes) | ValueRef::Blob(bytes) => {
+                            hasher.update([3]);
+                            hasher.update((bytes.len() as u64).to_le_bytes());
+                            hasher.update(bytes);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(to_hex(&hasher.finalize()))
+    }
+
     pub fn get_vault_path(&self) -> &PathBuf {
         &self.vault_path
     }
 
     pub fn reset(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.writer();
         
         // clear all tables (eat shit data)
         conn.execute("DELETE FROM vault_items", [])?;
         conn.execute("DELETE FROM vault_meta", [])?;
+        conn.execute("DELETE FROM attachments", [])?;
+        conn.execute("DELETE FROM item_links", [])?;
+        conn.execute("DELETE FROM sync_state", [])?;
+        self.clear_metadata_cache();
         
         // reset the database to initial state (fresh start!)
         conn.execute(
@@ -861,7 +2456,9 @@ impl Storage {
                 created_at BLOB NOT NULL,
                 updated_at BLOB NOT NULL,
                 deleted_at BLOB,
-                totp_secret BLOB
+                totp_secret BLOB,
+                custom_fields BLOB,
+                expires_at BLOB
             )",
             [],
         )?;
@@ -879,6 +2476,14 @@ impl Storage {
             info!("Migrating database (reset): Adding totp_secret column to vault_items");
             conn.execute("ALTER TABLE vault_items ADD COLUMN totp_secret BLOB", [])?;
         }
+        if !columns.contains(&"custom_fields".to_string()) {
+            info!("Migrating database (reset): Adding custom_fields column to vault_items");
+            conn.execute("ALTER TABLE vault_items ADD COLUMN custom_fields BLOB", [])?;
+        }
+        if !columns.contains(&"expires_at".to_string()) {
+            info!("Migrating database (reset): Adding expires_at column to vault_items");
+            conn.execute("ALTER TABLE vault_items ADD COLUMN expires_at BLOB", [])?;
+        }
 
 
         conn.execute(
@@ -888,6 +2493,9 @@ impl Storage {
             )",
             [],
         )?;
+        Self::create_attachments_table(&conn)?;
+        Self::create_links_table(&conn)?;
+        Self::create_sync_state_table(&conn)?;
 
         // clear the data directory (nuke those files!)
         let data_dir = self.vault_path.join("data");
@@ -912,12 +2520,13 @@ impl Storage {
 
     pub fn rename_tag_in_all_items(&self, old_tag: &str, new_tag: &str, crypto: &Crypto) -> Result<()> {
         info!("Attempting to rename tag: '{}' to '{}'", old_tag, new_tag);
-        let mut items = self.get_all_items_recursive(crypto)?;
-        info!("Found {} items. Processing tags...", items.len());
-        let mut conn = self.conn.lock().unwrap();
+        // read and rewrite under the writer lock so no other write can slip in between
+        let mut conn = self.writer();
         let tx = conn.transaction()?;
+        let mut items = self.fetch_items(&tx, "", &[], crypto)?;
+        info!("Found {} items. Processing tags...", items.len());
+        let mut changed_ids = Vec::new();
 
-        let mut changes_made = 0;
         for item in &mut items {
             let mut updated = false;
             let mut new_tags: Vec<String> = Vec::new();
@@ -935,35 +2544,37 @@ impl Storage {
                 item.tags = new_tags;
                 item.updated_at = Utc::now();
                 self.update_item_fields_in_transaction(item, crypto, &tx)?;
-                changes_made += 1;
+                changed_ids.push(item.id.clone());
                 info!("Updated tags for item ID: {}", item.id);
             }
         }
         tx.commit()?;
-        info!("Transaction committed for rename_tag. Total items with tags renamed: {}", changes_made);
+        self.invalidate_cached_items(&changed_ids);
+        info!("Transaction committed for rename_tag. Total items with tags renamed: {}", changed_ids.len());
         Ok(())
     }
 
     pub fn remove_tag_from_all_items(&self, tag_to_remove: &str, crypto: &Crypto) -> Result<()> {
         info!("Attempting to delete tag: '{}'", tag_to_remove);
-        let mut items = self.get_all_items_recursive(crypto)?;
-        info!("Found {} items. Processing tags...", items.len());
-        let mut conn = self.conn.lock().unwrap();
+        let mut conn = self.writer();
         let tx = conn.transaction()?;
+        let mut items = self.fetch_items(&tx, "", &[], crypto)?;
+        info!("Found {} items. Processing tags...", items.len());
+        let mut changed_ids = Vec::new();
 
-        let mut changes_made = 0;
         for item in &mut items {
             let original_tag_count = item.tags.len();
             item.tags.retain(|tag| tag != tag_to_remove);
             if item.tags.len() != original_tag_count {
                 item.updated_at = Utc::now();
                 self.update_item_fields_in_transaction(item, crypto, &tx)?;
-                changes_made += 1;
+                changed_ids.push(item.id.clone());
                 info!("Removed tag from item ID: {}", item.id);
             }
         }
         tx.commit()?;
-        info!("Transaction committed for delete_tag. Total items with tag removed: {}", changes_made);
+        self.invalidate_cached_items(&changed_ids);
+        info!("Transaction committed for delete_tag. Total items with tag removed: {}", changed_ids.len());
         Ok(())
     }
 
@@ -988,9 +2599,14 @@ impl Storage {
             Some(secret) => Some(crypto.encrypt(secret.as_bytes())?),
             None => None,
         };
+        let encrypted_custom_fields = Self::encrypt_custom_fields(&item.custom_fields, crypto)?;
+        let encrypted_expires_at = match &item.expires_at {
+            Some(dt) => Some(crypto.encrypt(dt.to_rfc3339().as_bytes())?),
+            None => None,
+        };
         
         tx.execute(
-            "UPDATE vault_items SET parent_id = ?2, name = ?3, item_type = ?4, data_path = ?5, folder_type = ?6, tags = ?7, created_at = ?8, updated_at = ?9, deleted_at = ?10, totp_secret = ?11 WHERE id = ?1",
+            "UPDATE vault_items SET parent_id = ?2, name = ?3, item_type = ?4, data_path = ?5, folder_type = ?6, tags = ?7, created_at = ?8, updated_at = ?9, deleted_at = ?10, totp_secret = ?11, custom_fields = ?12, expires_at = ?13 WHERE id = ?1",
             params![
                 item.id,
                 item.parent_id,
@@ -1003,6 +2619,8 @@ impl Storage {
                 encrypted_updated_at,
                 encrypted_deleted_at,
                 encrypted_totp_secret,
+                encrypted_custom_fields,
+                encrypted_expires_at,
             ],
         )?;
 
@@ -0,0 +1,1255 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::{sha256_hex, Crypto};
+use crate::error::Error;
+use crate::links::ItemLink;
+use crate::storage::{Attachment, Storage, VaultItem};
+use crate::Result;
+
+const SYNC_FORMAT_VERSION: u32 = 2;
+const MANIFEST_FILE: &str = "fetch-sync.json";
+const CHECKPOINT_FILE: &str = "checkpoint";
+const LOG_DIR: &str = "devices";
+const BLOB_DIR: &str = "blobs";
+const LOG_EXTENSION: &str = "log";
+const ACK_EXTENSION: &str = "ack";
+// log records the checkpoint doesn't cover yet before a device writes a new one
+const CHECKPOINT_AFTER_RECORDS: usize = 1000;
+// a blob nothing refers to may be one a device wrote and hasn't logged yet, it stays this long
+const UNREFERENCED_BLOB_HOURS: u64 = 24;
+// fields that never make two revisions conflict; the merge result takes them from the winner
+const MERGE_IGNORED_FIELDS: [&str; 3] = ["id", "updated_at", "data_path"];
+
+/// Where this device syncs a vault to. Kept in the vault registry rather than in the vault, so a
+/// vault folder copied to another machine doesn't take this device's identity with it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SyncSettings {
+    pub folder: PathBuf,
+    pub device_id: String,
+    pub last_synced_at: Option<DateTime<Utc>>,
+}
+
+impl SyncSettings {
+    pub fn new(folder: PathBuf) -> Self {
+        Self {
+            folder,
+            device_id: uuid::Uuid::new_v4().to_string(),
+            last_synced_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SyncReport {
+    // local changes written to this device's log
+    pub pushed: usize,
+    // changes from other devices applied to this vault
+    pub pulled: usize,
+    // concurrent edits that were merged
+    pub merged: usize,
+    // merges where both sides changed the same field; the losing side is kept as a copy
+    pub conflicts: usize,
+    // items deleted on another device, moved to the recycle bin here
+    pub deleted: usize,
+    // other devices writing to the sync folder
+    pub devices: usize,
+}
+
+/// Where the content of a revision is kept: an encrypted file in the blob directory, shared by
+/// revisions whose content is the same so an edit to the name doesn't copy the content again.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ContentRef {
+    // hash of the plaintext, only ever written inside an encrypted log line
+    sha256: String,
+    blob: String,
+}
+
+/// An attachment of an item at one revision. Attachments don't change once added, so one that
+/// was synced before keeps its blob.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AttachmentSnapshot {
+    id: String,
+    name: String,
+    mime_type: String,
+    created_at: DateTime<Utc>,
+    content: ContentRef,
+}
+
+/// A link from an item to another one. Links belong to the item they start from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct LinkSnapshot {
+    id: String,
+    target_id: String,
+    label: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<&ItemLink> for LinkSnapshot {
+    fn from(link: &ItemLink) -> Self {
+        Self {
+            id: link.id.clone(),
+            target_id: link.target_id.clone(),
+            label: link.label.clone(),
+            created_at: link.created_at,
+        }
+    }
+}
+
+/// An item as it was at one revision: its metadata, where its content is, its attachments and
+/// the links it starts.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ItemSnapshot {
+    item: VaultItem,
+    #[serde(default)]
+    content: Option<ContentRef>,
+    // `None` in revisions written before attachments and links were synced, applying one of
+    // those leaves them as they are; both lists are sorted by id
+    #[serde(default)]
+    attachments: Option<Vec<AttachmentSnapshot>>,
+    #[serde(default)]
+    links: Option<Vec<LinkSnapshot>>,
+}
+
+/// One entry of a device's change log. Every revision names the revisions it was based on, so
+/// the logs of all devices together form a history per item that concurrent edits fork and
+/// merges join again.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ChangeRecord {
+    revision: String,
+    parents: Vec<String>,
+    item_id: String,
+    device_id: String,
+    recorded_at: DateTime<Utc>,
+    // `None` records a permanent delete
+    snapshot: Option<ItemSnapshot>,
+}
+
+/// The resolved revision of every item at some point. Log records it covers can be dropped once
+/// every device has synced with it, so the logs only hold what happened since.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    id: String,
+    created_at: DateTime<Utc>,
+    records: Vec<ChangeRecord>,
+    // the previous checkpoint's revisions, a device that hasn't synced since still merges from them
+    #[serde(default)]
+    retained: Vec<ChangeRecord>,
+    // revisions the records are based on that may still be in a log; once one device drops them
+    // from its log the others' can't be linked to the records anymore
+    #[serde(default)]
+    covered: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SyncManifest {
+    version: u32,
+    // a random token encrypted with the vault key, tells apart folders of other vaults
+    check: String,
+}
+
+/// Everything in the sync folder: the checkpoint's records first, then every device's log.
+struct History {
+    records: Vec<ChangeRecord>,
+    checkpoint_id: Option<String>,
+    checkpoint_heads: Vec<String>,
+    // revisions the checkpoint supersedes, never heads
+    settled: HashSet<String>,
+    devices: HashSet<String>,
+    // a log line couldn't be read, so whatever it refers to must be kept
+    unreadable: bool,
+}
+
+/// Revision ids for merges and conflict copies are derived from what they merge, so devices that
+/// resolve the same fork independently write the same revision instead of forking again.
+fn derived_id(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    let digest = hasher.finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Uuid::from_bytes(bytes).to_string()
+}
+
+/// Checks that `folder` is a sync folder of this vault, setting it up if it is empty.
+pub fn prepare_folder(folder: &Path, crypto: &Crypto) -> Result<()> {
+    fs::create_dir_all(folder.join(LOG_DIR))?;
+    fs::create_dir_all(folder.join(BLOB_DIR))?;
+    let manifest_path = folder.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        let manifest = SyncManifest {
+            version: SYNC_FORMAT_VERSION,
+            check: STANDARD.encode(crypto.encrypt(&Crypto::generate_verification_token())?),
+        };
+        fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+        info!("Set up sync folder at {}", folder.display());
+        return Ok(());
+    }
+
+    let manifest: SyncManifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+    if manifest.version > SYNC_FORMAT_VERSION {
+        return Err(Error::InvalidInput("This sync folder was written by a newer version of the app".into()));
+    }
+    let check = STANDARD.decode(&manifest.check).map_err(|e| Error::Serialization(e.to_string()))?;
+    if crypto.decrypt(&check).is_err() {
+        return Err(Error::InvalidInput("This sync folder belongs to another vault or a different master key".into()));
+    }
+    if manifest.version < SYNC_FORMAT_VERSION {
+        // an older version would write revisions without the attachments and links of the item
+        let manifest = SyncManifest { version: SYNC_FORMAT_VERSION, ..manifest };
+        replace_file(&manifest_path, &serde_json::to_vec_pretty(&manifest)?)?;
+        info!("Upgraded sync folder at {} to format {}", folder.display(), SYNC_FORMAT_VERSION);
+    }
+    Ok(())
+}
+
+fn read_history(folder: &Path, crypto: &Crypto) -> Result<History> {
+    let mut history = History {
+        records: Vec::new(),
+        checkpoint_id: None,
+        checkpoint_heads: Vec::new(),
+        settled: HashSet::new(),
+        devices: HashSet::new(),
+        unreadable: false,
+    };
+    let mut seen = HashSet::new();
+    match fs::read_to_string(folder.join(CHECKPOINT_FILE)) {
+        Ok(line) => {
+            // unlike a log line a checkpoint can't be skipped, the logs no longer hold what it covers
+            let checkpoint: Checkpoint = decode(&line, crypto)
+                .map_err(|e| Error::InvalidInput(format!("The sync folder's checkpoint can't be read, it may still be copying: {}", e)))?;
+            history.checkpoint_heads = checkpoint.records.iter().map(|record| record.revision.clone()).collect();
+            history.settled = checkpoint.retained.iter().map(|record| record.revision.clone()).chain(checkpoint.covered).collect();
+            history.checkpoint_id = Some(checkpoint.id);
+            history.records = checkpoint.records.into_iter().chain(checkpoint.retained).collect();
+            seen.extend(history.records.iter().map(|record| record.revision.clone()));
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    for entry in fs::read_dir(folder.join(LOG_DIR))? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(LOG_EXTENSION) {
+            continue;
+        }
+        if let Some(device) = path.file_stem().and_then(|stem| stem.to_str()) {
+            history.devices.insert(device.to_string());
+        }
+        for (number, line) in fs::read_to_string(&path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            // a file sync tool may hand us a log whose last line is still being copied
+            match decode::<ChangeRecord>(line, crypto) {
+                Ok(record) => {
+                    if seen.insert(record.revision.clone()) {
+                        history.records.push(record);
+                    }
+                }
+                Err(e) => {
+                    warn!("Skipping unreadable line {} of {}: {}", number + 1, path.display(), e);
+                    history.unreadable = true;
+                }
+            }
+        }
+    }
+    Ok(history)
+}
+
+fn decode<T: DeserializeOwned>(line: &str, crypto: &Crypto) -> Result<T> {
+    let encrypted = STANDARD.decode(line.trim()).map_err(|e| Error::Serialization(e.to_string()))?;
+    let mut json = crypto.decrypt(&encrypted)?;
+    let value = serde_json::from_slice(&json);
+    json.zeroize();
+    Ok(value?)
+}
+
+fn encode<T: Serialize>(value: &T, crypto: &Crypto) -> Result<String> {
+    let mut json = serde_json::to_vec(value)?;
+    let encrypted = crypto.encrypt(&json);
+    json.zeroize();
+    Ok(STANDARD.encode(encrypted?))
+}
+
+fn log_path(folder: &Path, device_id: &str) -> PathBuf {
+    folder.join(LOG_DIR).join(format!("{}.{}", device_id, LOG_EXTENSION))
+}
+
+fn ack_path(folder: &Path, device_id: &str) -> PathBuf {
+    folder.join(LOG_DIR).join(format!("{}.{}", device_id, ACK_EXTENSION))
+}
+
+fn append_records(folder: &Path, device_id: &str, records: &[ChangeRecord], crypto: &Crypto) -> Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for record in records {
+        lines.push_str(&encode(record, crypto)?);
+        lines.push('\n');
+    }
+    let mut log = OpenOptions::new().create(true).append(true).open(log_path(folder, device_id))?;
+    log.write_all(lines.as_bytes())?;
+    log.sync_all()?;
+    Ok(())
+}
+
+// written next to the final name and renamed, so readers never see half a file
+fn replace_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn write_blob(folder: &Path, content: &[u8], crypto: &Crypto) -> Result<String> {
+    let blob = uuid::Uuid::new_v4().to_string();
+    replace_file(&folder.join(BLOB_DIR).join(&blob), &crypto.encrypt(content)?)?;
+    Ok(blob)
+}
+
+fn read_blob(folder: &Path, blob: &str, crypto: &Crypto) -> Result<Zeroizing<Vec<u8>>> {
+    // blob names come from our own records, still never let one point outside the folder
+    if uuid::Uuid::parse_str(blob).is_err() {
+        return Err(Error::InvalidInput(format!("Invalid blob name '{}' in the sync folder", blob)));
+    }
+    Ok(Zeroizing::new(crypto.decrypt(&fs::read(folder.join(BLOB_DIR).join(blob))?)?))
+}
+
+fn read_content(storage: &Storage, item: &VaultItem, crypto: &Crypto) -> Result<Option<Zeroizing<Vec<u8>>>> {
+    if item.data_path.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Zeroizing::new(storage.read_encrypted_file(&item.data_path, crypto)?)))
+}
+
+/// Revision graph of a single item across every device's log.
+struct ItemHistory<'a> {
+    revisions: HashMap<&'a str, &'a ChangeRecord>,
+    settled: &'a HashSet<String>,
+}
+
+impl<'a> ItemHistory<'a> {
+    // revisions nothing else is based on yet, sorted so every device folds them in the same order
+    fn heads(&self) -> Vec<&'a ChangeRecord> {
+        let superseded: HashSet<&str> = self.revisions.values().flat_map(|record| record.parents.iter().map(String::as_str)).collect();
+        let mut heads: Vec<&ChangeRecord> = self
+            .revisions
+            .values()
+            .filter(|record| !superseded.contains(record.revision.as_str()) && !self.settled.contains(&record.revision))
+            .copied()
+            .collect();
+        heads.sort_by(|a, b| a.revision.cmp(&b.revision));
+        heads
+    }
+
+    // `revision` and everything it is based on, nearest first
+    fn ancestors(&self, revision: &str) -> Vec<&'a str> {
+        let mut seen = HashSet::new();
+        let mut ordered = Vec::new();
+        let mut queue = VecDeque::from([revision]);
+        while let Some(current) = queue.pop_front() {
+            let Some(record) = self.revisions.get(current) else { continue };
+            if !seen.insert(record.revision.as_str()) {
+                continue;
+            }
+            ordered.push(record.revision.as_str());
+            let mut parents: Vec<&str> = record.parents.iter().map(String::as_str).collect();
+            parents.sort_unstable();
+            queue.extend(parents);
+        }
+        ordered
+    }
+
+    fn common_ancestor(&self, a: &str, b: &str) -> Option<&'a ChangeRecord> {
+        let of_a: HashSet<&str> = self.ancestors(a).into_iter().collect();
+        self.ancestors(b).into_iter().find(|revision| of_a.contains(revision)).and_then(|revision| self.revisions.get(revision).copied())
+    }
+
+    fn is_ancestor(&self, ancestor: &str, revision: &str) -> bool {
+        self.ancestors(revision).contains(&ancestor)
+    }
+}
+
+/// The outcome of folding concurrent heads into one revision.
+struct Resolved {
+    snapshot: Option<ItemSnapshot>,
+    conflict_copies: Vec<ItemSnapshot>,
+}
+
+fn fields(snapshot: &ItemSnapshot) -> Result<BTreeMap<String, serde_json::Value>> {
+    let mut fields = match serde_json::to_value(&snapshot.item)? {
+        serde_json::Value::Object(map) => map.into_iter().collect::<BTreeMap<_, _>>(),
+        _ => BTreeMap::new(),
+    };
+    fields.insert("content".into(), serde_json::to_value(snapshot.content.as_ref().map(|content| &content.sha256))?);
+    // blob names differ between devices that added the same attachment, the content doesn't
+    let attachments = snapshot.attachments.as_ref().map(|attachments| {
+        attachments
+            .iter()
+            .map(|attachment| (&attachment.id, &attachment.name, &attachment.mime_type, attachment.created_at, &attachment.content.sha256))
+            .collect::<Vec<_>>()
+    });
+    fields.insert("attachments".into(), serde_json::to_value(attachments)?);
+    fields.insert("links".into(), serde_json::to_value(&snapshot.links)?);
+    Ok(fields)
+}
+
+/// Three-way merge of two concurrent revisions against their common ancestor. A field changed on
+/// one side only takes that change; a field changed differently on both sides is a conflict, the
+/// later edit wins and the other one is kept as a copy. Edits win over deletes.
+fn merge_pair(base: Option<&ItemSnapshot>, a: (&str, Option<&ItemSnapshot>), b: (&str, Option<&ItemSnapshot>)) -> Result<Resolved> {
+    let (a_snapshot, b_snapshot) = match (a.1, b.1) {
+        (None, None) => return Ok(Resolved { snapshot: None, conflict_copies: vec![] }),
+        (Some(edited), None) | (None, Some(edited)) => {
+            let unchanged = match base {
+                Some(base) => fields(base)? == fields(edited)?,
+                None => false,
+            };
+            let snapshot = if unchanged { None } else { Some(edited.clone()) };
+            return Ok(Resolved { snapshot, conflict_copies: vec![] });
+        }
+        (Some(a_snapshot), Some(b_snapshot)) => (a_snapshot, b_snapshot),
+    };
+
+    // the later edit wins, ties go to the higher revision id so every device picks the same side
+    let a_wins = (a_snapshot.item.updated_at, a.0) >= (b_snapshot.item.updated_at, b.0);
+    let (winner, loser, loser_revision) = if a_wins { (a_snapshot, b_snapshot, b.0) } else { (b_snapshot, a_snapshot, a.0) };
+
+    let winner_fields = fields(winner)?;
+    let loser_fields = fields(loser)?;
+    let base_fields = base.map(fields).transpose()?;
+    let mut merged = winner_fields.clone();
+    let mut conflict = false;
+    let keys: HashSet<&String> = winner_fields.keys().chain(loser_fields.keys()).collect();
+    for key in keys {
+        if MERGE_IGNORED_FIELDS.contains(&key.as_str()) {
+            continue;
+        }
+        let (ours, theirs) = (winner_fields.get(key), loser_fields.get(key));
+        if ours == theirs {
+            continue;
+        }
+        let original = base_fields.as_ref().and_then(|base| base.get(key));
+        if base_fields.is_some() && original == ours {
+            match theirs {
+                Some(value) => merged.insert(key.clone(), value.clone()),
+                None => merged.remove(key),
+            };
+        } else if base_fields.is_none() || original != theirs {
+            conflict = true;
+        }
+    }
+
+    let content = merged.remove("content").unwrap_or(serde_json::Value::Null);
+    let attachments = merged.remove("attachments").unwrap_or(serde_json::Value::Null);
+    let links = merged.remove("links").unwrap_or(serde_json::Value::Null);
+    let mut item: VaultItem = serde_json::from_value(serde_json::Value::Object(merged.into_iter().collect()))?;
+    item.updated_at = winner.item.updated_at.max(loser.item.updated_at);
+    // the merged content, attachments and links are each one side's, so their blobs are too
+    let sides = [(winner, &winner_fields), (loser, &loser_fields)];
+    let side_with = |key: &str, value: &serde_json::Value| sides.iter().find(|(_, fields)| fields.get(key) == Some(value)).map(|(side, _)| *side);
+    let snapshot = ItemSnapshot {
+        item,
+        content: side_with("content", &content).and_then(|side| side.content.clone()),
+        attachments: side_with("attachments", &attachments).and_then(|side| side.attachments.clone()),
+        links: side_with("links", &links).and_then(|side| side.links.clone()),
+    };
+
+    let mut conflict_copies = Vec::new();
+    if conflict {
+        let mut copy = loser.clone();
+        copy.item.id = derived_id(&["conflict", &winner.item.id, loser_revision]);
+        copy.item.name = format!("{} (conflict {})", loser.item.name, loser.item.updated_at.format("%Y-%m-%d %H:%M"));
+        if !copy.item.data_path.is_empty() {
+            copy.item.data_path = copy.item.id.clone();
+        }
+        // the copy's attachments are its own; links stay with the item that won
+        for attachment in copy.attachments.iter_mut().flatten() {
+            attachment.id = derived_id(&["conflict", &copy.item.id, &attachment.id]);
+        }
+        if let Some(attachments) = copy.attachments.as_mut() {
+            attachments.sort_by(|a, b| a.id.cmp(&b.id));
+        }
+        copy.links = Some(Vec::new());
+        conflict_copies.push(copy);
+    }
+    Ok(Resolved { snapshot: Some(snapshot), conflict_copies })
+}
+
+fn content_sha256(storage: &Storage, item: &VaultItem, crypto: &Crypto) -> Result<Option<String>> {
+    Ok(read_content(storage, item, crypto)?.map(|content| sha256_hex(&content)))
+}
+
+fn same_metadata(a: &VaultItem, b: &VaultItem) -> bool {
+    matches!((serde_json::to_value(a), serde_json::to_value(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+// a revision without attachments or links has the same ones as an item without any
+fn same_attachments(synced: Option<&Vec<AttachmentSnapshot>>, local: &[Attachment]) -> bool {
+    let synced = synced.map(Vec::as_slice).unwrap_or_default();
+    synced.len() == local.len()
+        && local.iter().all(|attachment| {
+            synced
+                .iter()
+                .any(|other| other.id == attachment.id && other.name == attachment.name && other.mime_type == attachment.mime_type)
+        })
+}
+
+fn link_snapshots(links: &[ItemLink]) -> Vec<LinkSnapshot> {
+    let mut snapshots: Vec<LinkSnapshot> = links.iter().map(LinkSnapshot::from).collect();
+    snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+    snapshots
+}
+
+fn same_links(synced: Option<&Vec<LinkSnapshot>>, local: &[ItemLink]) -> bool {
+    synced.map(Vec::as_slice).unwrap_or_default() == link_snapshots(local).as_slice()
+}
+
+// id and content of an attachment that isn't in a blob yet
+type UnwrittenAttachment = (String, Zeroizing<Vec<u8>>);
+
+/// The attachments of an item as they go into a new revision. One that is in the revision the
+/// item was synced at keeps its blob; the content of the others is handed back so it can be
+/// written once the revision is known to be new.
+fn attachment_snapshots(
+    storage: &Storage,
+    crypto: &Crypto,
+    attachments: &[Attachment],
+    synced: Option<&ItemSnapshot>,
+) -> Result<(Vec<AttachmentSnapshot>, Vec<UnwrittenAttachment>)> {
+    let previous = synced.and_then(|synced| synced.attachments.as_ref());
+    let mut snapshots = Vec::new();
+    let mut unwritten = Vec::new();
+    for attachment in attachments {
+        if let Some(previous) = previous.and_then(|previous| previous.iter().find(|other| other.id == attachment.id)) {
+            snapshots.push(previous.clone());
+            continue;
+        }
+        let content = Zeroizing::new(storage.read_attachment(attachment, crypto)?);
+        snapshots.push(AttachmentSnapshot {
+            id: attachment.id.clone(),
+            name: attachment.name.clone(),
+            mime_type: attachment.mime_type.clone(),
+            created_at: attachment.created_at,
+            content: ContentRef { sha256: sha256_hex(&content), blob: String::new() },
+        });
+        unwritten.push((attachment.id.clone(), content));
+    }
+    snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok((snapshots, unwritten))
+}
+
+/// Content of the revision's attachments that aren't on the local item yet.
+fn read_new_attachments(folder: &Path, crypto: &Crypto, snapshot: &ItemSnapshot, local: &[Attachment]) -> Result<Vec<(AttachmentSnapshot, Zeroizing<Vec<u8>>)>> {
+    let mut added = Vec::new();
+    for attachment in snapshot.attachments.iter().flatten() {
+        if !local.iter().any(|other| other.id == attachment.id) {
+            added.push((attachment.clone(), read_blob(folder, &attachment.content.blob, crypto)?));
+        }
+    }
+    Ok(added)
+}
+
+fn apply_snapshot(
+    storage: &Storage,
+    crypto: &Crypto,
+    snapshot: &ItemSnapshot,
+    content: Option<&[u8]>,
+    added: &[(AttachmentSnapshot, Zeroizing<Vec<u8>>)],
+    exists: bool,
+) -> Result<()> {
+    if let Some(content) = content {
+        storage.write_encrypted_file(&crypto.encrypt(content)?, &snapshot.item.data_path)?;
+    }
+    if exists {
+        storage.update_item_fields(&snapshot.item, crypto)?;
+    } else {
+        storage.add_item(&snapshot.item, crypto)?;
+    }
+
+    let item_id = &snapshot.item.id;
+    if let Some(attachments) = &snapshot.attachments {
+        for removed in storage.list_attachments(item_id, crypto)?.iter().filter(|local| !attachments.iter().any(|other| other.id == local.id)) {
+            storage.remove_attachment(&removed.id, crypto)?;
+        }
+    }
+    for (attachment, content) in added {
+        let attachment = Attachment {
+            id: attachment.id.clone(),
+            item_id: item_id.clone(),
+            name: attachment.name.clone(),
+            mime_type: attachment.mime_type.clone(),
+            size: 0,
+            data_path: String::new(),
+            created_at: attachment.created_at,
+        };
+        storage.insert_attachment(&attachment, content, crypto)?;
+    }
+    if let Some(links) = &snapshot.links {
+        let local = link_snapshots(&storage.list_links_from(item_id, crypto)?);
+        for removed in local.iter().filter(|link| !links.contains(link)) {
+            storage.remove_link(&removed.id)?;
+        }
+        for link in links.iter().filter(|link| !local.contains(link)) {
+            let link = ItemLink {
+                id: link.id.clone(),
+                source_id: item_id.clone(),
+                target_id: link.target_id.clone(),
+                label: link.label.clone(),
+                created_at: link.created_at,
+            };
+            storage.insert_link(&link, crypto)?;
+        }
+    }
+    Ok(())
+}
+
+/// An item another device deleted for good goes to the recycle bin here rather than away. What
+/// is inside it and wasn't deleted along with it, i.e. added or changed since the other device
+/// last synced, is moved to the top level first so it isn't lost with the folder. Returns the
+/// items that went to the bin.
+fn move_to_recycle_bin(storage: &Storage, crypto: &Crypto, item_id: &str, children: &HashMap<&str, Vec<&VaultItem>>, deleted: &HashSet<&str>) -> Result<Vec<String>> {
+    let mut binned = vec![item_id.to_string()];
+    let mut pending = vec![item_id];
+    while let Some(id) = pending.pop() {
+        for child in children.get(id).into_iter().flatten().filter(|child| child.deleted_at.is_none()) {
+            if deleted.contains(child.id.as_str()) {
+                binned.push(child.id.clone());
+                pending.push(&child.id);
+                continue;
+            }
+            info!("Keeping item {} at the top level, the folder it was in was deleted on another device", child.id);
+            let mut kept = (*child).clone();
+            kept.parent_id = None;
+            kept.updated_at = Utc::now();
+            storage.update_item_fields(&kept, crypto)?;
+        }
+    }
+    storage.delete_item_and_descendants(item_id, crypto)?;
+    Ok(binned)
+}
+
+/// Syncs the vault with `folder`: local changes since the last sync go into this device's log,
+/// then every item whose history moved on elsewhere is brought up to date, merging concurrent
+/// edits. Attachments and the links an item starts travel with the item.
+pub fn sync_folder(storage: &Storage, crypto: &Crypto, folder: &Path, device_id: &str) -> Result<SyncReport> {
+    prepare_folder(folder, crypto)?;
+    let mut history = read_history(folder, crypto)?;
+    let mut records = std::mem::take(&mut history.records);
+    let mut report = SyncReport {
+        devices: history.devices.iter().filter(|device| device.as_str() != device_id).count(),
+        ..Default::default()
+    };
+    let mut state = storage.get_sync_state()?;
+    let local_items: HashMap<String, VaultItem> = storage.get_all_items_recursive(crypto)?.into_iter().map(|item| (item.id.clone(), item)).collect();
+    let mut local_attachments: HashMap<String, Vec<Attachment>> = HashMap::new();
+    for attachment in storage.list_all_attachments(crypto)? {
+        local_attachments.entry(attachment.item_id.clone()).or_default().push(attachment);
+    }
+    let mut local_links: HashMap<String, Vec<ItemLink>> = HashMap::new();
+    for link in storage.list_all_links(crypto)? {
+        local_links.entry(link.source_id.clone()).or_default().push(link);
+    }
+    // whether every item ended up at its resolved revision, only then is the folder compacted
+    let mut complete = !history.unreadable;
+
+    // push: anything that differs from the revision we last synced becomes a new revision
+    let mut pushed = Vec::new();
+    {
+        let by_revision: HashMap<&str, &ChangeRecord> = records.iter().map(|record| (record.revision.as_str(), record)).collect();
+        let mut heads_by_item: HashMap<&str, Vec<&ChangeRecord>> = HashMap::new();
+        for record in &records {
+            heads_by_item.entry(record.item_id.as_str()).or_default().push(record);
+        }
+
+        for item in local_items.values() {
+            let synced = state.get(&item.id).and_then(|revision| by_revision.get(revision.as_str()));
+            let attachments = local_attachments.get(&item.id).map(Vec::as_slice).unwrap_or_default();
+            let links = local_links.get(&item.id).map(Vec::as_slice).unwrap_or_default();
+            match synced.map(|record| record.snapshot.as_ref()) {
+                Some(Some(snapshot))
+                    if same_metadata(&snapshot.item, item)
+                        && same_attachments(snapshot.attachments.as_ref(), attachments)
+                        && same_links(snapshot.links.as_ref(), links) =>
+                {
+                    continue
+                }
+                // deleted on another device and kept in the recycle bin here
+                Some(None) if item.deleted_at.is_some() => continue,
+                _ => {}
+            }
+            let content = read_content(storage, item, crypto)?;
+            let (attachments, unwritten) = attachment_snapshots(storage, crypto, attachments, synced.and_then(|record| record.snapshot.as_ref()))?;
+            let mut snapshot = ItemSnapshot {
+                item: item.clone(),
+                content: content.as_ref().map(|content| ContentRef { sha256: sha256_hex(content), blob: String::new() }),
+                attachments: Some(attachments),
+                links: Some(link_snapshots(links)),
+            };
+            if !state.contains_key(&item.id) {
+                // first sync of a vault copied from another device: adopt an identical revision
+                let local_fields = fields(&snapshot)?;
+                let identical = heads_by_item.get(item.id.as_str()).and_then(|candidates| {
+                    candidates.iter().find(|record| record.snapshot.as_ref().is_some_and(|theirs| fields(theirs).is_ok_and(|theirs| theirs == local_fields)))
+                });
+                if let Some(record) = identical {
+                    state.insert(item.id.clone(), record.revision.clone());
+                    continue;
+                }
+            }
+            if let (Some(reference), Some(content)) = (snapshot.content.as_mut(), content.as_ref()) {
+                // unchanged content keeps the blob of the revision it came from
+                let previous = synced.and_then(|record| record.snapshot.as_ref()).and_then(|synced| synced.content.as_ref()).filter(|previous| previous.sha256 == reference.sha256);
+                reference.blob = match previous {
+                    Some(previous) => previous.blob.clone(),
+                    None => write_blob(folder, content, crypto)?,
+                };
+            }
+            for (id, content) in unwritten {
+                if let Some(attachment) = snapshot.attachments.iter_mut().flatten().find(|attachment| attachment.id == id) {
+                    attachment.content.blob = write_blob(folder, &content, crypto)?;
+                }
+            }
+            let record = ChangeRecord {
+                revision: uuid::Uuid::new_v4().to_string(),
+                parents: state.get(&item.id).cloned().into_iter().collect(),
+                item_id: item.id.clone(),
+                device_id: device_id.to_string(),
+                recorded_at: Utc::now(),
+                snapshot: Some(snapshot),
+            };
+            state.insert(item.id.clone(), record.revision.clone());
+            pushed.push(record);
+        }
+
+        let removed: Vec<String> = state.keys().filter(|id| !local_items.contains_key(*id)).cloned().collect();
+        for id in removed {
+            let revision = state[&id].clone();
+            if by_revision.get(revision.as_str()).is_some_and(|record| record.snapshot.is_none()) {
+                continue;
+            }
+            let record = ChangeRecord {
+                revision: uuid::Uuid::new_v4().to_string(),
+                parents: vec![revision],
+                item_id: id.clone(),
+                device_id: device_id.to_string(),
+                recorded_at: Utc::now(),
+                snapshot: None,
+            };
+            state.insert(id, record.revision.clone());
+            pushed.push(record);
+        }
+    }
+    report.pushed = pushed.len();
+    append_records(folder, device_id, &pushed, crypto)?;
+    records.extend(pushed);
+
+    // pull: fold each item's heads into one revision, then bring the local items up to them
+    let mut histories: BTreeMap<&str, ItemHistory> = BTreeMap::new();
+    for record in &records {
+        histories
+            .entry(record.item_id.as_str())
+            .or_insert_with(|| ItemHistory { revisions: HashMap::new(), settled: &history.settled })
+            .revisions
+            .insert(record.revision.as_str(), record);
+    }
+
+    let mut merges = Vec::new();
+    let mut pending_copies: Vec<ItemSnapshot> = Vec::new();
+    let mut resolved: Vec<(&str, String, Option<ItemSnapshot>)> = Vec::new();
+    for (item_id, item_history) in &histories {
+        let heads = item_history.heads();
+        let Some(first) = heads.first() else { continue };
+        let local_revision = state.get(*item_id).map(String::as_str);
+        if heads.len() == 1 && local_revision == Some(first.revision.as_str()) {
+            continue;
+        }
+
+        let (revision, snapshot) = if heads.len() == 1 {
+            (first.revision.clone(), first.snapshot.clone())
+        } else {
+            let mut snapshot = first.snapshot.clone();
+            for head in &heads[1..] {
+                let base = item_history.common_ancestor(&first.revision, &head.revision).and_then(|record| record.snapshot.as_ref());
+                let resolved = merge_pair(base, (&first.revision, snapshot.as_ref()), (&head.revision, head.snapshot.as_ref()))?;
+                if !resolved.conflict_copies.is_empty() {
+                    report.conflicts += 1;
+                }
+                pending_copies.extend(resolved.conflict_copies);
+                snapshot = resolved.snapshot;
+            }
+            let parents: Vec<String> = heads.iter().map(|head| head.revision.clone()).collect();
+            let mut parts = vec!["merge", *item_id];
+            parts.extend(parents.iter().map(String::as_str));
+            let revision = derived_id(&parts);
+            merges.push(ChangeRecord {
+                revision: revision.clone(),
+                parents,
+                item_id: item_id.to_string(),
+                device_id: device_id.to_string(),
+                recorded_at: Utc::now(),
+                snapshot: snapshot.clone(),
+            });
+            report.merged += 1;
+            (revision, snapshot)
+        };
+
+        // our last synced revision is always part of the history we just resolved, unless the
+        // folder lost it, in which case the resolved revision is still the best we know of
+        if let Some(local_revision) = local_revision {
+            if local_revision != revision && !item_history.is_ancestor(local_revision, &revision) {
+                warn!("Revision {} of item {} is missing from the sync folder", local_revision, item_id);
+            }
+        }
+        resolved.push((item_id, revision, snapshot));
+    }
+
+    // an edit made while we were syncing is picked up by the next sync rather than overwritten
+    let untouched = |scanned: Option<&VaultItem>, current: Option<&VaultItem>| match (scanned, current) {
+        (Some(scanned), Some(current)) => same_metadata(scanned, current),
+        (None, None) => true,
+        _ => false,
+    };
+    let deleted: HashSet<&str> = resolved.iter().filter(|(_, _, snapshot)| snapshot.is_none()).map(|(item_id, ..)| *item_id).collect();
+    let mut tombstones = Vec::new();
+    for (item_id, revision, snapshot) in resolved {
+        let Some(snapshot) = snapshot else {
+            tombstones.push((item_id, revision));
+            continue;
+        };
+        let current = storage.get_item(item_id, crypto)?;
+        if !untouched(local_items.get(item_id), current.as_ref()) {
+            complete = false;
+            continue;
+        }
+        let current_sha256 = match &current {
+            Some(current) => content_sha256(storage, current, crypto)?,
+            None => None,
+        };
+        let content_changed = snapshot.content.as_ref().map(|content| &content.sha256) != current_sha256.as_ref()
+            || current.as_ref().is_some_and(|current| current.data_path != snapshot.item.data_path);
+        let attachments = storage.list_attachments(item_id, crypto)?;
+        let extras_changed = (snapshot.attachments.is_some() && !same_attachments(snapshot.attachments.as_ref(), &attachments))
+            || (snapshot.links.is_some() && !same_links(snapshot.links.as_ref(), &storage.list_links_from(item_id, crypto)?));
+        if current.as_ref().is_some_and(|current| same_metadata(&snapshot.item, current)) && !content_changed && !extras_changed {
+            state.insert(item_id.to_string(), revision);
+            continue;
+        }
+        // the blobs may still be on their way, the next sync picks the revision up
+        let content = match snapshot.content.as_ref().filter(|_| content_changed) {
+            Some(content) => match read_blob(folder, &content.blob, crypto) {
+                Ok(content) => Some(content),
+                Err(e) => {
+                    warn!("Skipping item {}, its content can't be read from the sync folder: {}", item_id, e);
+                    complete = false;
+                    continue;
+                }
+            },
+            None => None,
+        };
+        let added = match read_new_attachments(folder, crypto, &snapshot, &attachments) {
+            Ok(added) => added,
+            Err(e) => {
+                warn!("Skipping item {}, an attachment can't be read from the sync folder: {}", item_id, e);
+                complete = false;
+                continue;
+            }
+        };
+        apply_snapshot(storage, crypto, &snapshot, content.as_deref().map(Vec::as_slice), &added, current.is_some())?;
+        report.pulled += 1;
+        state.insert(item_id.to_string(), revision);
+    }
+
+    // deletes go last so items moved out of a deleted folder are already out of it
+    if !tombstones.is_empty() {
+        let current_items = storage.get_all_items_recursive(crypto)?;
+        let by_id: HashMap<&str, &VaultItem> = current_items.iter().map(|item| (item.id.as_str(), item)).collect();
+        let mut children: HashMap<&str, Vec<&VaultItem>> = HashMap::new();
+        for item in &current_items {
+            if let Some(parent_id) = &item.parent_id {
+                children.entry(parent_id.as_str()).or_default().push(item);
+            }
+        }
+        let mut binned: HashSet<String> = HashSet::new();
+        for (item_id, revision) in tombstones {
+            let current = by_id.get(item_id).copied();
+            if !binned.contains(item_id) {
+                if !untouched(local_items.get(item_id), current) {
+                    complete = false;
+                    continue;
+                }
+                if current.is_some_and(|current| current.deleted_at.is_none()) {
+                    binned.extend(move_to_recycle_bin(storage, crypto, item_id, &children, &deleted)?);
+                    report.deleted += 1;
+                }
+            }
+            state.insert(item_id.to_string(), revision);
+        }
+    }
+
+    // conflict copies are new items; their ids are derived, so a device resolving the same
+    // conflict writes the same revision
+    for copy in pending_copies {
+        let revision = derived_id(&["copy", &copy.item.id]);
+        if state.contains_key(&copy.item.id) || histories.contains_key(copy.item.id.as_str()) {
+            continue;
+        }
+        let content = match &copy.content {
+            Some(content) => match read_blob(folder, &content.blob, crypto) {
+                Ok(content) => Some(content),
+                Err(e) => {
+                    warn!("Skipping a conflict copy of item {}, its content can't be read from the sync folder: {}", copy.item.id, e);
+                    complete = false;
+                    continue;
+                }
+            },
+            None => None,
+        };
+        let added = match read_new_attachments(folder, crypto, &copy, &storage.list_attachments(&copy.item.id, crypto)?) {
+            Ok(added) => added,
+            Err(e) => {
+                warn!("Skipping a conflict copy of item {}, an attachment can't be read from the sync folder: {}", copy.item.id, e);
+                complete = false;
+                continue;
+            }
+        };
+        apply_snapshot(storage, crypto, &copy, content.as_deref().map(Vec::as_slice), &added, storage.get_item(&copy.item.id, crypto)?.is_some())?;
+        state.insert(copy.item.id.clone(), revision.clone());
+        merges.push(ChangeRecord {
+            revision,
+            parents: vec![],
+            item_id: copy.item.id.clone(),
+            device_id: device_id.to_string(),
+            recorded_at: Utc::now(),
+            snapshot: Some(copy),
+        });
+    }
+    append_records(folder, device_id, &merges, crypto)?;
+    storage.save_sync_state(&state)?;
+    drop(histories);
+    records.extend(merges);
+
+    if complete {
+        compact(folder, crypto, device_id, &history, &records, &state)?;
+    }
+
+    info!(
+        "Synced with {}: {} pushed, {} pulled, {} merged, {} conflicts, {} deleted",
+        folder.display(),
+        report.pushed,
+        report.pulled,
+        report.merged,
+        report.conflicts,
+        report.deleted
+    );
+    Ok(report)
+}
+
+/// Acknowledges the checkpoint, drops what it covers from our own log once every device has
+/// acknowledged it, starts a new checkpoint when the logs have grown, and removes blobs nothing
+/// refers to anymore. Only runs after a sync that brought every item to its resolved revision.
+fn compact(folder: &Path, crypto: &Crypto, device_id: &str, history: &History, records: &[ChangeRecord], state: &HashMap<String, String>) -> Result<()> {
+    let mut acknowledged = true;
+    if let Some(checkpoint_id) = &history.checkpoint_id {
+        replace_file(&ack_path(folder, device_id), checkpoint_id.as_bytes())?;
+        acknowledged = history
+            .devices
+            .iter()
+            .all(|device| device == device_id || fs::read_to_string(ack_path(folder, device)).is_ok_and(|ack| ack.trim() == checkpoint_id));
+    }
+
+    // a device that hasn't acknowledged the checkpoint may still be based on what it covers
+    if acknowledged {
+        let mut covered = covered_revisions(records, &history.checkpoint_heads);
+        covered.extend(history.settled.iter().cloned());
+        truncate_log(folder, device_id, crypto, &covered)?;
+        let uncovered = records.iter().filter(|record| !covered.contains(&record.revision)).count();
+        if uncovered >= CHECKPOINT_AFTER_RECORDS {
+            let by_revision: HashMap<&str, &ChangeRecord> = records.iter().map(|record| (record.revision.as_str(), record)).collect();
+            let heads: Vec<String> = state.values().filter(|revision| by_revision.contains_key(revision.as_str())).cloned().collect();
+            let kept: HashSet<&String> = heads.iter().collect();
+            let retained: HashSet<&String> = history.checkpoint_heads.iter().filter(|revision| !kept.contains(revision)).collect();
+            covered.extend(covered_revisions(records, &heads));
+            let checkpoint = Checkpoint {
+                id: uuid::Uuid::new_v4().to_string(),
+                created_at: Utc::now(),
+                records: heads.iter().filter_map(|revision| by_revision.get(revision.as_str()).map(|record| (*record).clone())).collect(),
+                retained: retained.iter().filter_map(|revision| by_revision.get(revision.as_str()).map(|record| (*record).clone())).collect(),
+                // what's no longer in the folder doesn't need to be remembered
+                covered: records
+                    .iter()
+                    .map(|record| &record.revision)
+                    .filter(|revision| covered.contains(*revision) && !kept.contains(revision) && !retained.contains(revision))
+                    .cloned()
+                    .collect(),
+            };
+            replace_file(&folder.join(CHECKPOINT_FILE), encode(&checkpoint, crypto)?.as_bytes())?;
+            replace_file(&ack_path(folder, device_id), checkpoint.id.as_bytes())?;
+            info!("Wrote sync checkpoint {} covering {} items", checkpoint.id, checkpoint.records.len());
+        }
+    }
+
+    remove_unreferenced_blobs(folder, records)
+}
+
+// the checkpoint's revisions and everything they are based on
+fn covered_revisions(records: &[ChangeRecord], heads: &[String]) -> HashSet<String> {
+    let by_revision: HashMap<&str, &ChangeRecord> = records.iter().map(|record| (record.revision.as_str(), record)).collect();
+    let mut covered = HashSet::new();
+    let mut pending: Vec<&str> = heads.iter().map(String::as_str).collect();
+    while let Some(revision) = pending.pop() {
+        if !covered.insert(revision.to_string()) {
+            continue;
+        }
+        if let Some(record) = by_revision.get(revision) {
+            pending.extend(record.parents.iter().map(String::as_str));
+        }
+    }
+    covered
+}
+
+// only our own log is rewritten, other devices may be appending to theirs
+fn truncate_log(folder: &Path, device_id: &str, crypto: &Crypto, covered: &HashSet<String>) -> Result<()> {
+    let path = log_path(folder, device_id);
+    let log = match fs::read_to_string(&path) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut kept = String::new();
+    let mut dropped = 0;
+    for line in log.lines().filter(|line| !line.trim().is_empty()) {
+        match decode::<ChangeRecord>(line, crypto) {
+            Ok(record) if covered.contains(&record.revision) => dropped += 1,
+            _ => {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+    }
+    if dropped > 0 {
+        replace_file(&path, kept.as_bytes())?;
+        info!("Dropped {} checkpointed records from the sync log", dropped);
+    }
+    Ok(())
+}
+
+fn remove_unreferenced_blobs(folder: &Path, records: &[ChangeRecord]) -> Result<()> {
+    let referenced: HashSet<&str> = records
+        .iter()
+        .filter_map(|record| record.snapshot.as_ref())
+        .flat_map(|snapshot| snapshot.content.iter().chain(snapshot.attachments.iter().flatten().map(|attachment| &attachment.content)))
+        .map(|content| content.blob.as_str())
+        .collect();
+    let cutoff = SystemTime::now() - Duration::from_secs(UNREFERENCED_BLOB_HOURS * 60 * 60);
+    for entry in fs::read_dir(folder.join(BLOB_DIR))? {
+        let entry = entry?;
+        if referenced.contains(entry.file_name().to_string_lossy().as_ref()) {
+            continue;
+        }
+        if entry.metadata()?.modified()? < cutoff {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn snapshot(name: &str, minute: i32, content: &str) -> ItemSnapshot {
+        let created_at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        ItemSnapshot {
+            item: VaultItem {
+                id: "item".into(),
+                parent_id: None,
+                name: name.into(),
+                data_path: "item.bin".into(),
+                item_type: "text".into(),
+                folder_type: None,
+                tags: vec![],
+                created_at,
+                updated_at: created_at + chrono::Duration::minutes(minute.into()),
+                deleted_at: None,
+                totp_secret: None,
+                custom_fields: vec![],
+                expires_at: None,
+            },
+            content: Some(ContentRef { sha256: content.into(), blob: format!("blob-{}", content) }),
+            attachments: Some(vec![]),
+            links: Some(vec![]),
+        }
+    }
+
+    fn attachment(id: &str) -> AttachmentSnapshot {
+        AttachmentSnapshot {
+            id: id.into(),
+            name: format!("{}.txt", id),
+            mime_type: "text/plain".into(),
+            created_at: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            content: ContentRef { sha256: id.into(), blob: format!("blob-{}", id) },
+        }
+    }
+
+    fn record(revision: &str, parents: &[&str]) -> ChangeRecord {
+        ChangeRecord {
+            revision: revision.into(),
+            parents: parents.iter().map(|parent| parent.to_string()).collect(),
+            item_id: "item".into(),
+            device_id: "a".into(),
+            recorded_at: Utc::now(),
+            snapshot: Some(snapshot("item", 0, "x")),
+        }
+    }
+
+    fn test_crypto() -> Crypto {
+        let mut crypto = Crypto::new();
+        crypto.unlock(&[7u8; 32]).unwrap();
+        crypto
+    }
+
+    #[test]
+    fn derived_ids_are_deterministic() {
+        assert_eq!(derived_id(&["merge", "item", "r1", "r2"]), derived_id(&["merge", "item", "r1", "r2"]));
+        assert_ne!(derived_id(&["merge", "item", "r1", "r2"]), derived_id(&["merge", "item", "r2", "r1"]));
+        // parts are separated, so they can't run into each other
+        assert_ne!(derived_id(&["ab", "c"]), derived_id(&["a", "bc"]));
+        assert!(uuid::Uuid::parse_str(&derived_id(&["copy", "item"])).is_ok());
+    }
+
+    #[test]
+    fn edits_to_different_fields_merge() {
+        let base = snapshot("item", 0, "x");
+        let renamed = snapshot("renamed", 1, "x");
+        let mut edited = snapshot("item", 2, "y");
+        edited.item.tags = vec!["work".into()];
+
+        for resolved in [
+            merge_pair(Some(&base), ("r1", Some(&renamed)), ("r2", Some(&edited))).unwrap(),
+            merge_pair(Some(&base), ("r2", Some(&edited)), ("r1", Some(&renamed))).unwrap(),
+        ] {
+            let merged = resolved.snapshot.unwrap();
+            assert_eq!(merged.item.name, "renamed");
+            assert_eq!(merged.item.tags, ["work"]);
+            assert_eq!(merged.item.updated_at, edited.item.updated_at);
+            assert_eq!(merged.content.unwrap().blob, "blob-y");
+            assert!(resolved.conflict_copies.is_empty());
+        }
+    }
+
+    #[test]
+    fn concurrent_edits_of_a_field_keep_a_copy() {
+        let base = snapshot("item", 0, "x");
+        let earlier = snapshot("item", 1, "a");
+        let later = snapshot("item", 2, "b");
+
+        // whichever device resolves the fork, it writes the same copy
+        let one = merge_pair(Some(&base), ("r1", Some(&earlier)), ("r2", Some(&later))).unwrap();
+        let other = merge_pair(Some(&base), ("r2", Some(&later)), ("r1", Some(&earlier))).unwrap();
+        for resolved in [&one, &other] {
+            assert_eq!(resolved.snapshot.as_ref().unwrap().content.as_ref().unwrap().sha256, "b");
+            assert_eq!(resolved.conflict_copies.len(), 1);
+            let copy = &resolved.conflict_copies[0];
+            assert_eq!(copy.item.id, derived_id(&["conflict", "item", "r1"]));
+            assert_eq!(copy.item.data_path, copy.item.id);
+            assert_eq!(copy.content.as_ref().unwrap().sha256, "a");
+            assert!(copy.item.name.starts_with("item (conflict "));
+        }
+    }
+
+    #[test]
+    fn edits_win_over_deletes() {
+        let base = snapshot("item", 0, "x");
+        let edited = snapshot("renamed", 1, "x");
+        let resolved = merge_pair(Some(&base), ("r1", Some(&edited)), ("r2", None)).unwrap();
+        assert_eq!(resolved.snapshot.unwrap().item.name, "renamed");
+        let resolved = merge_pair(Some(&base), ("r1", None), ("r2", Some(&edited))).unwrap();
+        assert_eq!(resolved.snapshot.unwrap().item.name, "renamed");
+
+        // nothing changed on the other side, so the delete stands
+        let resolved = merge_pair(Some(&base), ("r1", Some(&base)), ("r2", None)).unwrap();
+        assert!(resolved.snapshot.is_none());
+        assert!(merge_pair(Some(&base), ("r1", None), ("r2", None)).unwrap().snapshot.is_none());
+    }
+
+    #[test]
+    fn attachments_and_links_merge_with_the_item() {
+        let base = snapshot("item", 0, "x");
+        let mut attached = snapshot("item", 1, "x");
+        attached.attachments = Some(vec![attachment("one")]);
+        let mut linked = snapshot("renamed", 2, "x");
+        linked.links = Some(vec![LinkSnapshot { id: "link".into(), target_id: "other".into(), label: None, created_at: base.item.created_at }]);
+
+        let merged = merge_pair(Some(&base), ("r1", Some(&attached)), ("r2", Some(&linked))).unwrap();
+        assert!(merged.conflict_copies.is_empty());
+        let merged = merged.snapshot.unwrap();
+        assert_eq!(merged.item.name, "renamed");
+        assert_eq!(merged.attachments.unwrap()[0].content.blob, "blob-one");
+        assert_eq!(merged.links.unwrap()[0].target_id, "other");
+
+        // both added an attachment: the later list wins, the other is kept on the copy
+        let mut other = snapshot("item", 3, "x");
+        other.attachments = Some(vec![attachment("two")]);
+        other.links = linked.links.clone();
+        let resolved = merge_pair(Some(&base), ("r1", Some(&attached)), ("r3", Some(&other))).unwrap();
+        assert_eq!(resolved.snapshot.unwrap().attachments.unwrap()[0].id, "two");
+        let copy = &resolved.conflict_copies[0];
+        let copied = &copy.attachments.as_ref().unwrap()[0];
+        assert_eq!(copied.id, derived_id(&["conflict", &copy.item.id, "one"]));
+        assert_eq!(copied.content.blob, "blob-one");
+        assert_eq!(copy.links.as_deref(), Some(&[][..]));
+    }
+
+    #[test]
+    fn covered_revisions_follow_parents() {
+        let records = [record("r1", &[]), record("r2", &["r1"]), record("r3", &["r2"]), record("other", &[])];
+        let covered = covered_revisions(&records, &["r2".to_string()]);
+        assert_eq!(covered, HashSet::from(["r1".to_string(), "r2".to_string()]));
+    }
+
+    #[test]
+    fn logs_are_only_compacted_once_every_device_acknowledged() {
+        let folder = std::env::temp_dir().join(format!("fetch-sync-{}", uuid::Uuid::new_v4()));
+        let crypto = test_crypto();
+        prepare_folder(&folder, &crypto).unwrap();
+        let records = vec![record("r1", &[]), record("r2", &["r1"]), record("r3", &["r2"])];
+        append_records(&folder, "a", &records, &crypto).unwrap();
+        let history = History {
+            records: Vec::new(),
+            checkpoint_id: Some("checkpoint".into()),
+            checkpoint_heads: vec!["r2".into()],
+            settled: HashSet::new(),
+            devices: HashSet::from(["a".to_string(), "b".to_string()]),
+            unreadable: false,
+        };
+        let state = HashMap::from([("item".to_string(), "r3".to_string())]);
+        let logged = || fs::read_to_string(log_path(&folder, "a")).unwrap().lines().map(|line| decode::<ChangeRecord>(line, &crypto).unwrap().revision).collect::<Vec<_>>();
+
+        // b may still be based on r1 or r2
+        compact(&folder, &crypto, "a", &history, &records, &state).unwrap();
+        assert_eq!(fs::read_to_string(ack_path(&folder, "a")).unwrap(), "checkpoint");
+        assert_eq!(logged(), ["r1", "r2", "r3"]);
+
+        replace_file(&ack_path(&folder, "b"), b"checkpoint").unwrap();
+        compact(&folder, &crypto, "a", &history, &records, &state).unwrap();
+        assert_eq!(logged(), ["r3"]);
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,531 @@
+use log::{info, warn};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use reqwest::header::{HeaderValue, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH};
+use reqwest::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroize;
+
+use crate::backup::StagedRestore;
+use crate::crypto::sha256_hex;
+use crate::error::Error;
+use crate::storage::Storage;
+use crate::Result;
+
+pub const WEBDAV_CONFIG_KEY: &str = "webdav_config";
+// per device, outside of what gets uploaded: what each file looked like at the last push or pull
+const STATE_FILE: &str = "webdav-state.json";
+const DATABASE_FILE: &str = "vault.db";
+const DATA_DIR: &str = "data";
+// the vault files that make up a snapshot besides the database and data/
+const KEY_FILES: [&str; 2] = ["salt", "verify"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebDavConfig {
+    // collection the vault is stored in, e.g. https://dav.example.com/files/me/fetch/
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl Drop for WebDavConfig {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+impl WebDavConfig {
+    pub fn validate(&self) -> Result<()> {
+        let url = Url::parse(self.url.trim()).map_err(|e| Error::InvalidInput(format!("Invalid WebDAV URL: {}", e)))?;
+        match url.scheme() {
+            "https" => {}
+            // Security: credentials only go over plain http to a server on this machine
+            "http" if is_loopback(&url) => {}
+            "http" => return Err(Error::InvalidInput("WebDAV servers must use https".into())),
+            _ => return Err(Error::InvalidInput("WebDAV URL must start with https://".into())),
+        }
+        if self.username.trim().is_empty() {
+            return Err(Error::InvalidInput("WebDAV username cannot be empty".into()));
+        }
+        Ok(())
+    }
+
+    /// The config as shown to the frontend; the password never leaves the backend.
+    pub fn redacted(&self) -> Self {
+        Self {
+            url: self.url.clone(),
+            username: self.username.clone(),
+            password: String::new(),
+        }
+    }
+}
+
+pub(crate) fn is_loopback(url: &Url) -> bool {
+    matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TransferReport {
+    pub uploaded: usize,
+    pub downloaded: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+    // the pulled snapshot was encrypted with another master key; the vault has to be unlocked again
+    pub key_changed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct TrackedFile {
+    sha256: String,
+    etag: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct TransferState {
+    // remote url the state belongs to, a new url starts from scratch
+    url: String,
+    files: BTreeMap<String, TrackedFile>,
+}
+
+impl TransferState {
+    fn load(vault_path: &Path, url: &str) -> Self {
+        fs::read(vault_path.join(STATE_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_slice::<TransferState>(&json).ok())
+            .filter(|state| state.url == url)
+            .unwrap_or_else(|| TransferState { url: url.to_string(), files: BTreeMap::new() })
+    }
+
+    fn save(&self, vault_path: &Path) -> Result<()> {
+        let temp = vault_path.join(format!("{}.tmp", STATE_FILE));
+        fs::write(&temp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&temp, vault_path.join(STATE_FILE))?;
+        Ok(())
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%' && i + 2 < bytes.len())
+            .then(|| std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .flatten();
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+struct DavEntry {
+    href: String,
+    etag: Option<String>,
+    collection: bool,
+}
+
+// pulls href, getetag and resourcetype out of a PROPFIND multistatus, whatever the namespace prefix
+fn parse_multistatus(xml: &str) -> Result<Vec<DavEntry>> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    let mut entries = Vec::new();
+    let mut current: Option<DavEntry> = None;
+    let mut element = Vec::new();
+    loop {
+        match reader.read_event().map_err(|e| Error::Network(format!("Invalid WebDAV response: {}", e)))? {
+            Event::Start(tag) => {
+                let name = tag.local_name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"response" => current = Some(DavEntry { href: String::new(), etag: None, collection: false }),
+                    b"collection" => {
+                        if let Some(entry) = current.as_mut() {
+                            entry.collection = true;
+                        }
+                    }
+                    _ => {}
+                }
+                element = name;
+            }
+            Event::Empty(tag) if tag.local_name().as_ref() == b"collection" => {
+                if let Some(entry) = current.as_mut() {
+                    entry.collection = true;
+                }
+            }
+            Event::Text(text) => {
+                let value = text.unescape().map_err(|e| Error::Network(format!("Invalid WebDAV response: {}", e)))?.into_owned();
+                if let Some(entry) = current.as_mut() {
+                    match element.as_slice() {
+                        b"href" => entry.href = value,
+                        b"getetag" => entry.etag = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            Event::End(tag) => {
+                if tag.local_name().as_ref() == b"response" {
+                    entries.extend(current.take());
+                }
+                element.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// Minimal WebDAV client: PROPFIND for listings and ETags, then GET, PUT, DELETE and MKCOL.
+pub struct WebDavClient {
+    http: reqwest::Client,
+    base: Url,
+    username: String,
+    password: String,
+}
+
+impl Drop for WebDavClient {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+impl WebDavClient {
+    pub fn new(config: &WebDavConfig) -> Result<Self> {
+        config.validate()?;
+        let mut base = Url::parse(config.url.trim()).map_err(|e| Error::InvalidInput(format!("Invalid WebDAV URL: {}", e)))?;
+        // relative paths resolve inside the collection only if it ends with a slash
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(std::time::Duration::from_secs(60)).build()?,
+            base,
+            username: config.username.clone(),
+            password: config.password.clone(),
+        })
+    }
+
+    fn url(&self, path: &str) -> Result<Url> {
+        self.base.join(path).map_err(|e| Error::InvalidInput(format!("Invalid remote path {}: {}", path, e)))
+    }
+
+    fn request(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
+        self.http.request(method, url).basic_auth(&self.username, Some(&self.password))
+    }
+
+    fn check(response: &reqwest::Response, what: &str) -> Result<()> {
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::Network("The WebDAV server rejected the credentials".into())),
+            StatusCode::PRECONDITION_FAILED => Err(Error::Network(format!("{} changed on the server since the last sync, pull first", what))),
+            status => Err(Error::Network(format!("WebDAV request for {} failed with {}", what, status))),
+        }
+    }
+
+    /// Files directly inside `dir` (relative to the base, "" for the base itself) with their
+    /// ETags. A missing collection lists as empty.
+    async fn list_dir(&self, dir: &str) -> Result<BTreeMap<String, Option<String>>> {
+        let url = self.url(dir)?;
+        let body = r#"<?xml version="1.0" encoding="utf-8"?><d:propfind xmlns:d="DAV:"><d:prop><d:getetag/><d:resourcetype/></d:prop></d:propfind>"#;
+        let response = self
+            .request(Method::from_bytes(b"PROPFIND").expect("valid method"), url.clone())
+            .header("Depth", "1")
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/xml"))
+            .body(body)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(BTreeMap::new());
+        }
+        Self::check(&response, dir)?;
+        let xml = response.text().await?;
+
+        let mut files = BTreeMap::new();
+        for entry in parse_multistatus(&xml)? {
+            if entry.collection {
+                continue;
+            }
+            let Ok(entry_url) = url.join(&entry.href) else { continue };
+            let Some(relative) = entry_url.path().strip_prefix(self.base.path()) else { continue };
+            files.insert(percent_decode(relative), entry.etag);
+        }
+        Ok(files)
+    }
+
+    /// Every file of the remote snapshot with its ETag.
+    async fn list(&self) -> Result<BTreeMap<String, Option<String>>> {
+        let mut files = self.list_dir("").await?;
+        files.extend(self.list_dir(&format!("{}/", DATA_DIR)).await?);
+        Ok(files)
+    }
+
+    pub async fn check_connection(&self) -> Result<()> {
+        self.list_dir("").await.map(|_| ())
+    }
+
+    async fn ensure_collections(&self) -> Result<()> {
+        for dir in ["".to_string(), format!("{}/", DATA_DIR)] {
+            let response = self.request(Method::from_bytes(b"MKCOL").expect("valid method"), self.url(&dir)?).send().await?;
+            // 405 means it already exists
+            if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                Self::check(&response, if dir.is_empty() { "the vault folder" } else { &dir })?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get(&self, path: &str) -> Result<Vec<u8>> {
+        let response = self.request(Method::GET, self.url(path)?).send().await?;
+        Self::check(&response, path)?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    // `expected` guards against overwriting a version we haven't seen: the server refuses the
+    // upload if the file's ETag no longer matches, or if it appeared since we last looked
+    async fn put(&self, path: &str, data: Vec<u8>, expected: Option<&TrackedFile>, force: bool) -> Result<Option<String>> {
+        let mut request = self.request(Method::PUT, self.url(path)?).body(data);
+        if !force {
+            request = match expected.and_then(|tracked| tracked.etag.as_deref()) {
+                Some(etag) => request.header(IF_MATCH, etag),
+                None if expected.is_none() => request.header(IF_NONE_MATCH, "*"),
+                None => request,
+            };
+        }
+        let response = request.send().await?;
+        Self::check(&response, path)?;
+        Ok(response.headers().get(ETAG).and_then(|etag| etag.to_str().ok()).map(str::to_string))
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let response = self.request(Method::DELETE, self.url(path)?).send().await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            Self::check(&response, path)?;
+        }
+        Ok(())
+    }
+}
+
+// the vault files a snapshot is made of besides the database, keyed by their remote path
+fn local_files(vault_path: &Path) -> Result<BTreeMap<String, PathBuf>> {
+    let mut files = BTreeMap::new();
+    for name in KEY_FILES {
+        files.insert(name.to_string(), vault_path.join(name));
+    }
+    for entry in fs::read_dir(vault_path.join(DATA_DIR))? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            if let Some(name) = entry.file_name().to_str() {
+                files.insert(format!("{}/{}", DATA_DIR, name), entry.path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+// hashes of the local vault as tracked in the transfer state; the database is tracked by its
+// content rather than its bytes
+fn local_hashes(storage: &Storage, files: &BTreeMap<String, PathBuf>) -> Result<BTreeMap<String, String>> {
+    let mut hashes = BTreeMap::new();
+    for (path, file) in files {
+        hashes.insert(path.clone(), sha256_hex(&fs::read(file)?));
+    }
+    hashes.insert(DATABASE_FILE.to_string(), storage.content_fingerprint()?);
+    Ok(hashes)
+}
+
+fn temporary_database_path() -> PathBuf {
+    std::env::temp_dir().join(format!("fetch-webdav-{}.db", uuid::Uuid::new_v4()))
+}
+
+fn remove_temporary_database(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove temporary database copy {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Uploads the vault to the WebDAV collection. Only files that changed since the last transfer
+/// are sent. Unless `force` is set this refuses to overwrite what another device pushed in the
+/// meantime.
+pub async fn push(storage: &Storage, config: &WebDavConfig, force: bool) -> Result<TransferReport> {
+    let client = WebDavClient::new(config)?;
+    let vault_path = storage.get_vault_path().clone();
+    let mut state = TransferState::load(&vault_path, &config.url);
+
+    let remote = client.list().await?;
+    if !force {
+        let changed_remotely = remote
+            .iter()
+            .any(|(path, etag)| !state.files.get(path).is_some_and(|tracked| tracked.etag.is_none() || tracked.etag == *etag));
+        if changed_remotely {
+            return Err(Error::Network("The WebDAV copy changed since the last sync, pull first".into()));
+        }
+    }
+    client.ensure_collections().await?;
+
+    let database_copy = temporary_database_path();
+    let result = upload_changed(&client, storage, &database_copy, &remote, &mut state, force).await;
+    remove_temporary_database(&database_copy);
+    let mut report = result?;
+
+    // not every server returns the ETag of an upload, take them from a fresh listing
+    let remote = client.list().await?;
+    state.files.retain(|path, _| remote.contains_key(path));
+    for (path, etag) in remote {
+        if let Some(tracked) = state.files.get_mut(&path) {
+            tracked.etag = etag;
+        }
+    }
+    state.save(&vault_path)?;
+    report.unchanged = state.files.len().saturating_sub(report.uploaded);
+    info!("Pushed vault to WebDAV: {} uploaded, {} deleted, {} unchanged", report.uploaded, report.deleted, report.unchanged);
+    Ok(report)
+}
+
+async fn upload_changed(
+    client: &WebDavClient,
+    storage: &Storage,
+    database_copy: &Path,
+    remote: &BTreeMap<String, Option<String>>,
+    state: &mut TransferState,
+    force: bool,
+) -> Result<TransferReport> {
+    let mut files = local_files(storage.get_vault_path())?;
+    let hashes = local_hashes(storage, &files)?;
+    // a consistent copy taken through SQLite rather than the live file
+    storage.snapshot_database(database_copy)?;
+    files.insert(DATABASE_FILE.to_string(), database_copy.to_path_buf());
+
+    let mut report = TransferReport::default();
+    for (path, file) in &files {
+        let sha256 = hashes[path].clone();
+        let tracked = state.files.get(path).filter(|_| remote.contains_key(path));
+        if tracked.is_some_and(|tracked| tracked.sha256 == sha256) {
+            continue;
+        }
+        let expected = match (tracked, remote.get(path)) {
+            (Some(tracked), _) => Some(tracked.clone()),
+            // on the server but never transferred by us, e.g. a forced first push
+            (None, Some(etag)) => Some(TrackedFile { sha256: String::new(), etag: etag.clone() }),
+            (None, None) => None,
+        };
+        let etag = client.put(path, fs::read(file)?, expected.as_ref(), force).await?;
+        state.files.insert(path.clone(), TrackedFile { sha256, etag });
+        report.uploaded += 1;
+    }
+    for path in remote.keys().filter(|path| !files.contains_key(*path)) {
+        client.delete(path).await?;
+        state.files.remove(path);
+        report.deleted += 1;
+    }
+    Ok(report)
+}
+
+/// Fetches the snapshot on the WebDAV server, downloading only files that changed there. Unless
+/// `force` is set this refuses to discard local changes that were never pushed. The pulled
+/// vault is put together next to the local one and returned, to take its place once it's
+/// closed; `None` if the local vault is up to date already.
+pub async fn pull(storage: &Storage, config: &WebDavConfig, force: bool) -> Result<(TransferReport, Option<StagedRestore>)> {
+    let client = WebDavClient::new(config)?;
+    let vault_path = storage.get_vault_path().clone();
+    let mut state = TransferState::load(&vault_path, &config.url);
+
+    let remote = client.list().await?;
+    if !remote.contains_key(DATABASE_FILE) || KEY_FILES.iter().any(|name| !remote.contains_key(*name)) {
+        return Err(Error::InvalidInput("There is no vault at this WebDAV location".into()));
+    }
+
+    let (mut report, staged) = download_changed(&client, storage, &remote, &mut state, force).await?;
+    let Some(mut staged) = staged else {
+        state.save(&vault_path)?;
+        info!("Pulled vault from WebDAV: already up to date");
+        return Ok((report, None));
+    };
+    {
+        let pulled = staged.open()?;
+        storage.copy_device_state(&pulled)?;
+        if let Some(tracked) = state.files.get_mut(DATABASE_FILE) {
+            tracked.sha256 = pulled.content_fingerprint()?;
+        }
+    }
+    report.key_changed = !staged.keeps_key();
+    // the transfer state describes the pulled files, so it goes in with them
+    state.save(staged.path())?;
+    info!("Pulled vault from WebDAV: {} downloaded, {} deleted, {} unchanged", report.downloaded, report.deleted, report.unchanged);
+    Ok((report, Some(staged)))
+}
+
+// Files are downloaded into a staging folder, never over the vault: salt and verify written
+// before vault.db would leave a vault no key opens if the pull stopped in between.
+async fn download_changed(
+    client: &WebDavClient,
+    storage: &Storage,
+    remote: &BTreeMap<String, Option<String>>,
+    state: &mut TransferState,
+    force: bool,
+) -> Result<(TransferReport, Option<StagedRestore>)> {
+    let vault_path = storage.get_vault_path().clone();
+    let files = local_files(&vault_path)?;
+    let hashes = local_hashes(storage, &files)?;
+    if !force && !state.files.is_empty() {
+        let changed_locally = hashes.iter().any(|(path, sha256)| !state.files.get(path).is_some_and(|tracked| tracked.sha256 == *sha256))
+            || state.files.keys().any(|path| !hashes.contains_key(path));
+        if changed_locally {
+            return Err(Error::InvalidInput("This vault has changes that were never pushed, push them or pull with force".into()));
+        }
+    }
+
+    let up_to_date = |path: &String, etag: &Option<String>| {
+        etag.is_some() && state.files.get(path).is_some_and(|tracked| tracked.etag == *etag && hashes.get(path) == Some(&tracked.sha256))
+    };
+    let removed: Vec<&String> = files.keys().filter(|path| path.starts_with(DATA_DIR) && !remote.contains_key(*path)).collect();
+    let mut report = TransferReport::default();
+    if removed.is_empty() && remote.iter().all(|(path, etag)| up_to_date(path, etag)) {
+        report.unchanged = remote.len();
+        return Ok((report, None));
+    }
+    let to_download: HashSet<&String> = remote.iter().filter(|(path, etag)| !up_to_date(path, etag)).map(|(path, _)| path).collect();
+
+    let staged = StagedRestore::begin(&vault_path)?;
+    fs::create_dir(staged.path().join(DATA_DIR))?;
+    for (path, etag) in remote {
+        // Security: the server picks the names, never write outside the staging folder
+        if path.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+            return Err(Error::Network(format!("Refusing to download {}", path)));
+        }
+        let target = staged.path().join(path);
+        if !to_download.contains(path) {
+            // unchanged files are taken from the local vault, the database through SQLite
+            if path == DATABASE_FILE {
+                storage.snapshot_database(&target)?;
+            } else {
+                fs::copy(&files[path], &target)?;
+            }
+            report.unchanged += 1;
+            continue;
+        }
+        let data = client.get(path).await?;
+        report.downloaded += 1;
+        // the database is tracked by its content, filled in once the pulled vault is opened
+        let sha256 = if path == DATABASE_FILE { String::new() } else { sha256_hex(&data) };
+        fs::write(&target, data)?;
+        state.files.insert(path.clone(), TrackedFile { sha256, etag: etag.clone() });
+    }
+
+    for path in removed {
+        state.files.remove(path);
+        report.deleted += 1;
+    }
+    Ok((report, Some(staged)))
+}
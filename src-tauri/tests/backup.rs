@@ -0,0 +1,89 @@
+//! Portable backups: writing one, verifying it against its manifest and restoring from it.
+
+mod common;
+
+use common::{add_note, new_vault, temp_dir};
+use fetch::backup::{open_backup, verify_backup, write_backup};
+use fetch::crypto::KeyDerivationStrength;
+use fetch::merge::{merge_vault, MergeStrategy};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const PASSPHRASE: &str = "backup passphrase";
+
+// copies the backup at `from` to `to`, letting `change` edit or drop (by returning false) each file
+fn rewrite(from: &Path, to: &Path, mut change: impl FnMut(&str, &mut Vec<u8>) -> bool, extra: Option<(&str, &[u8])>) {
+    let mut archive = ZipArchive::new(File::open(from).unwrap()).unwrap();
+    let mut zip = ZipWriter::new(File::create(to).unwrap());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).unwrap();
+        let name = entry.name().to_string();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        if change(&name, &mut data) {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(&data).unwrap();
+        }
+    }
+    if let Some((name, data)) = extra {
+        zip.start_file(name, FileOptions::default()).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn error(result: fetch::Result<impl Sized>) -> String {
+    result.err().unwrap().to_string()
+}
+
+#[test]
+fn backups_are_checked_against_their_manifest() {
+    let dir = temp_dir("backup");
+    let (storage, crypto) = new_vault(&dir.join("vault"));
+    add_note(&storage, &crypto, "one", b"one");
+    add_note(&storage, &crypto, "two", b"two");
+    storage.add_attachment("one", "a.txt", "text/plain", b"attached", &crypto).unwrap();
+    storage.add_link("one", "two", None, &crypto).unwrap();
+    let backup = dir.join("vault.fbak");
+    assert!(write_backup(&storage, &crypto, &backup, "short", KeyDerivationStrength::Fast).is_err());
+    let written = write_backup(&storage, &crypto, &backup, PASSPHRASE, KeyDerivationStrength::Fast).unwrap();
+    assert_eq!((written.item_count, written.attachment_count, written.link_count), (2, 1, 1));
+
+    // without the passphrase only the checksums can be checked
+    let summary = verify_backup(&backup, None).unwrap();
+    assert_eq!((summary.files_checked, summary.decrypted), (written.files_checked, false));
+    assert!(verify_backup(&backup, Some(PASSPHRASE)).unwrap().decrypted);
+    assert!(error(verify_backup(&backup, Some("wrong passphrase"))).contains("Wrong backup passphrase"));
+
+    let damaged = dir.join("damaged.fbak");
+    rewrite(&backup, &damaged, |name, data| {
+        if name.starts_with("blobs/") {
+            data[0] ^= 1;
+        }
+        true
+    }, None);
+    assert!(error(verify_backup(&damaged, None)).contains("does not match its checksum"));
+    rewrite(&backup, &damaged, |name, _| !name.starts_with("blobs/"), None);
+    assert!(error(verify_backup(&damaged, None)).contains("is missing"));
+    rewrite(&backup, &damaged, |_, _| true, Some(("blobs/extra", b"extra")));
+    assert!(error(verify_backup(&damaged, None)).contains("unexpected file"));
+    rewrite(&backup, &damaged, |name, _| name != "manifest.json", None);
+    assert!(error(verify_backup(&damaged, None)).contains("manifest is missing"));
+    assert!(open_backup(&damaged, PASSPHRASE).is_err());
+
+    // restoring merges the backup into a vault
+    let (restored, restored_crypto) = new_vault(&dir.join("restored"));
+    let source = open_backup(&backup, PASSPHRASE).unwrap();
+    let report = merge_vault(&restored, &restored_crypto, &source, MergeStrategy::KeepBoth, false).unwrap();
+    assert_eq!((report.added, report.attachments, report.links), (2, 1, 1));
+    let one = restored.get_item("one", &restored_crypto).unwrap().unwrap();
+    assert_eq!(restored.read_encrypted_file(&one.data_path, &restored_crypto).unwrap(), b"one");
+    let attachment = restored.list_attachments("one", &restored_crypto).unwrap().remove(0);
+    assert_eq!(restored.read_attachment(&attachment, &restored_crypto).unwrap(), b"attached");
+    drop(source);
+    drop((storage, restored));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,136 @@
+//! Bitwarden JSON imports, and exports that import back into a new vault unchanged.
+
+mod common;
+
+use common::{new_vault, temp_dir, temp_path};
+use fetch::bitwarden::import_bitwarden;
+use fetch::crypto::Crypto;
+use fetch::error::Error;
+use fetch::export::{export_decrypted, select_items, ExportControl, ExportFormat, ExportProgress, ExportSelection};
+use fetch::records::ItemRecord;
+use fetch::storage::{Storage, VaultItem};
+use std::sync::atomic::AtomicBool;
+
+// one of each item type, a nested folder, every custom field type, the three ways a TOTP secret
+// is written, a deleted item and a type this vault has nothing for
+const EXPORT: &str = r#"{
+  "encrypted": false,
+  "folders": [{"id": "f1", "name": "Work"}, {"id": "f2", "name": "Work/Servers"}],
+  "items": [
+    {"id": "i1", "folderId": "f2", "type": 1, "name": "ssh box", "notes": "jump host", "favorite": true,
+     "fields": [{"name": "Tags", "value": "ops, prod", "type": 0}, {"name": "pin", "value": "1234", "type": 1},
+                {"name": "on", "value": "true", "type": 2}, {"name": "linked", "value": null, "type": 3, "linkedId": 100}],
+     "login": {"uris": [{"match": null, "uri": "https://a.example"}, {"match": null, "uri": "https://b.example"}],
+               "username": "root", "password": "pw", "totp": "JBSWY3DPEHPK3PXP"},
+     "revisionDate": "2024-01-02T03:04:05.000Z", "creationDate": "2023-01-02T03:04:05.000Z", "deletedDate": null},
+    {"id": "i2", "type": 1, "name": "uri totp",
+     "login": {"uris": null, "username": "u", "password": null, "totp": "otpauth://totp/x?secret=JBSWY3DPEHPK3PXP&issuer=x"}},
+    {"id": "i3", "type": 1, "name": "steam", "login": {"totp": "steam://ABC"}},
+    {"id": "i4", "folderId": "f1", "type": 2, "name": "note", "notes": "secret note", "secureNote": {"type": 0}},
+    {"id": "i5", "type": 3, "name": "visa",
+     "card": {"cardholderName": "A B", "brand": "Visa", "number": "4111111111111111", "expMonth": "7", "expYear": "29", "code": "123"}},
+    {"id": "i6", "type": 4, "name": "me",
+     "identity": {"firstName": "Al", "lastName": "B", "address1": "1 St", "address2": "Apt 2", "address3": "Bldg 3",
+                  "ssn": "123-45", "username": "al", "email": "a@b.c"}},
+    {"id": "i7", "type": 1, "name": "gone", "deletedDate": "2024-01-01T00:00:00Z", "login": {}},
+    {"id": "i8", "type": 5, "name": "key", "sshKey": {}}
+  ]
+}"#;
+
+fn named(items: &[VaultItem], name: &str) -> VaultItem {
+    items.iter().find(|item| item.name == name).unwrap_or_else(|| panic!("{} is missing", name)).clone()
+}
+
+// everything an item carries that should survive a round trip, apart from ids
+fn contents(storage: &Storage, crypto: &Crypto) -> Vec<String> {
+    let items = storage.get_all_items_recursive(crypto).unwrap();
+    let parent_name = |item: &VaultItem| item.parent_id.as_ref().map(|id| items.iter().find(|parent| &parent.id == id).unwrap().name.clone());
+    let mut contents: Vec<String> = items
+        .iter()
+        .map(|item| {
+            let mut tags = item.tags.clone();
+            tags.sort();
+            let fields: Vec<(&str, &str, &str)> = item.custom_fields.iter().map(|field| (field.label.as_str(), field.kind.as_str(), field.value.as_str())).collect();
+            let body = match item.item_type.as_str() {
+                "folder" => String::new(),
+                _ => String::from_utf8(storage.read_encrypted_file(&item.data_path, crypto).unwrap().to_vec()).unwrap(),
+            };
+            format!("{} in {:?}: {} {:?} {:?} {:?} {}", item.name, parent_name(item), item.item_type, tags, item.totp_secret, fields, body)
+        })
+        .collect();
+    contents.sort();
+    contents
+}
+
+#[test]
+fn imports_map_onto_vault_items() {
+    let path = temp_path("bitwarden");
+    let (storage, crypto) = new_vault(&path);
+    let report = import_bitwarden(&storage, &crypto, EXPORT, None).unwrap();
+    assert_eq!((report.folders, report.items, report.skipped.len()), (2, 6, 2), "{:?}", report);
+
+    let items = storage.get_all_items_recursive(&crypto).unwrap();
+    let servers = named(&items, "Servers");
+    assert_eq!(servers.parent_id, Some(named(&items, "Work").id));
+    let ssh = named(&items, "ssh box");
+    assert_eq!(ssh.parent_id, Some(servers.id));
+    assert_eq!(ssh.tags, ["favorite", "ops", "prod"]);
+    assert_eq!(ssh.totp_secret.as_deref(), Some("SGVsbG8h3q2+7w=="));
+    // linked fields point at other fields of a Bitwarden item and have no value of their own
+    assert_eq!(ssh.custom_fields.len(), 2);
+    assert_eq!(ssh.created_at.to_rfc3339(), "2023-01-02T03:04:05+00:00");
+    assert_eq!(storage.read_login(&ssh, &crypto).unwrap().urls, ["https://a.example", "https://b.example"]);
+    assert_eq!(named(&items, "uri totp").totp_secret, ssh.totp_secret);
+    // Steam codes aren't plain TOTP, so the secret is kept as a field
+    assert_eq!(named(&items, "steam").custom_fields[0].value, "steam://ABC");
+    assert_eq!(storage.read_encrypted_file(&named(&items, "note").data_path, &crypto).unwrap(), b"secret note");
+    match storage.read_record(&named(&items, "visa"), &crypto).unwrap() {
+        ItemRecord::Card(card) => assert_eq!(card.expiry_year, Some(2029)),
+        other => panic!("{:?}", other),
+    }
+    match storage.read_record(&named(&items, "me"), &crypto).unwrap() {
+        ItemRecord::Identity(identity) => assert_eq!((identity.address_line2.as_str(), identity.national_id.as_str()), ("Apt 2, Bldg 3", "123-45")),
+        other => panic!("{:?}", other),
+    }
+
+    // importing again reuses the folders
+    assert_eq!(import_bitwarden(&storage, &crypto, EXPORT, None).unwrap().folders, 0);
+    assert!(import_bitwarden(&storage, &crypto, r#"{"encrypted": true, "items": []}"#, None).is_err());
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn exports_import_back_unchanged() {
+    let base = temp_path("bitwarden");
+    let (storage, crypto) = new_vault(&base.join("source"));
+    import_bitwarden(&storage, &crypto, EXPORT, None).unwrap();
+    let out = temp_dir("bitwarden-out");
+    let destination = out.join("vault.json");
+    let not_cancelled = AtomicBool::new(false);
+    let mut on_progress = |_: ExportProgress| {};
+    let mut control = ExportControl::new(&not_cancelled, &mut on_progress);
+    let items = select_items(&storage, &crypto, &ExportSelection::default()).unwrap();
+    export_decrypted(&storage, &crypto, &items, ExportFormat::Bitwarden, &destination, &mut control).unwrap();
+
+    let json = std::fs::read_to_string(&destination).unwrap();
+    let exported: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert!(exported["folders"].as_array().unwrap().iter().any(|folder| folder["name"] == "Work/Servers"));
+    let ssh = exported["items"].as_array().unwrap().iter().find(|item| item["name"] == "ssh box").unwrap();
+    assert_eq!((&ssh["login"]["totp"], &ssh["favorite"]), (&"JBSWY3DPEHPK3PXP".into(), &true.into()));
+
+    let (imported, imported_crypto) = new_vault(&base.join("imported"));
+    let report = import_bitwarden(&imported, &imported_crypto, &json, None).unwrap();
+    assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+    assert_eq!(contents(&imported, &imported_crypto), contents(&storage, &crypto));
+
+    // a cancelled export leaves nothing behind
+    let cancelled = AtomicBool::new(true);
+    let mut control = ExportControl::new(&cancelled, &mut on_progress);
+    let destination = out.join("cancelled.json");
+    assert!(matches!(export_decrypted(&storage, &crypto, &items, ExportFormat::Bitwarden, &destination, &mut control), Err(Error::Cancelled)));
+    assert!(!destination.exists());
+    drop((storage, imported));
+    std::fs::remove_dir_all(base).unwrap();
+    std::fs::remove_dir_all(out).unwrap();
+}
@@ -0,0 +1,69 @@
+//! Bulk actions over a list of items, all applied or none.
+
+mod common;
+
+use common::{add_note, new_vault, note, temp_path};
+use fetch::storage::{BulkAction, VaultItem};
+
+fn folder(id: &str) -> VaultItem {
+    VaultItem { data_path: String::new(), item_type: "folder".into(), ..note(id) }
+}
+
+fn ids(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| id.to_string()).collect()
+}
+
+#[test]
+fn a_failing_item_rolls_the_batch_back() {
+    let path = temp_path("bulk");
+    let (storage, crypto) = new_vault(&path);
+    add_note(&storage, &crypto, "a", b"a");
+    add_note(&storage, &crypto, "b", b"b");
+
+    let result = storage.bulk_update(&ids(&["a", "missing", "b"]), &BulkAction::Delete, &crypto).unwrap();
+    assert!(!result.committed);
+    let failed: Vec<(&str, bool)> = result.results.iter().map(|r| (r.id.as_str(), r.success)).collect();
+    assert_eq!(failed, [("a", false), ("missing", false), ("b", false)]);
+    assert!(result.results[0].error.as_deref().unwrap().contains("Rolled back"));
+    for id in ["a", "b"] {
+        assert!(storage.get_item(id, &crypto).unwrap().unwrap().deleted_at.is_none());
+    }
+
+    // a permanent delete that is rolled back keeps the content too
+    let result = storage.bulk_update(&ids(&["a", "missing"]), &BulkAction::PermanentDelete, &crypto).unwrap();
+    assert!(!result.committed);
+    assert_eq!(storage.read_encrypted_file("a.bin", &crypto).unwrap(), b"a");
+
+    let result = storage.bulk_update(&ids(&["a", "b"]), &BulkAction::AddTags { tags: vec!["work".into()] }, &crypto).unwrap();
+    assert!(result.committed && result.results.iter().all(|r| r.success));
+    assert_eq!(storage.get_item("b", &crypto).unwrap().unwrap().tags, ["work"]);
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn folders_take_their_contents_along() {
+    let path = temp_path("bulk");
+    let (storage, crypto) = new_vault(&path);
+    storage.add_item(&folder("f"), &crypto).unwrap();
+    storage.add_item(&VaultItem { parent_id: Some("f".into()), ..note("inside") }, &crypto).unwrap();
+    storage.write_encrypted_file(&crypto.encrypt(b"inside").unwrap(), "inside.bin").unwrap();
+
+    // a folder can't be moved into itself
+    let result = storage.bulk_update(&ids(&["f"]), &BulkAction::Move { parent_id: Some("f".into()) }, &crypto).unwrap();
+    assert!(!result.committed);
+
+    let result = storage.bulk_update(&ids(&["f", "inside"]), &BulkAction::Delete, &crypto).unwrap();
+    assert!(result.committed);
+    assert!(storage.get_item("inside", &crypto).unwrap().unwrap().deleted_at.is_some());
+    let result = storage.bulk_update(&ids(&["f"]), &BulkAction::Restore, &crypto).unwrap();
+    assert!(result.committed);
+    assert!(storage.get_item("inside", &crypto).unwrap().unwrap().deleted_at.is_none());
+
+    let result = storage.bulk_update(&ids(&["f"]), &BulkAction::PermanentDelete, &crypto).unwrap();
+    assert!(result.committed);
+    assert!(storage.get_all_items_recursive(&crypto).unwrap().is_empty());
+    assert!(!path.join("data").join("inside.bin").exists());
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
@@ -0,0 +1,58 @@
+//! Fixtures shared by the integration tests.
+// each test binary uses its own subset of these
+#![allow(dead_code)]
+
+use fetch::crypto::{Crypto, KeyDerivationStrength};
+use fetch::storage::{Storage, VaultItem};
+use std::path::{Path, PathBuf};
+
+pub const PASSWORD: &str = "correct horse";
+
+/// A fresh path under the temp dir; nothing is created there.
+pub fn temp_path(prefix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("fetch-{}-{}", prefix, uuid::Uuid::new_v4()))
+}
+
+pub fn temp_dir(prefix: &str) -> PathBuf {
+    let dir = temp_path(prefix);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A new vault at `path`, unlocked with `PASSWORD`.
+pub fn new_vault(path: &Path) -> (Storage, Crypto) {
+    let storage = Storage::new(path.to_path_buf()).unwrap();
+    let salt = Crypto::generate_salt();
+    storage.initialize(&salt, KeyDerivationStrength::Fast).unwrap();
+    let mut crypto = Crypto::new();
+    let key = crypto.derive_key(PASSWORD, &salt, KeyDerivationStrength::Fast).unwrap();
+    crypto.unlock(&key).unwrap();
+    storage.store_verification_token(&crypto.encrypt(&Crypto::generate_verification_token()).unwrap()).unwrap();
+    (storage, crypto)
+}
+
+/// A top-level text item whose content lives in `<id>.bin`.
+pub fn note(id: &str) -> VaultItem {
+    VaultItem {
+        id: id.into(),
+        parent_id: None,
+        name: id.into(),
+        data_path: format!("{}.bin", id),
+        item_type: "text".into(),
+        folder_type: None,
+        tags: vec![],
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        totp_secret: None,
+        custom_fields: vec![],
+        expires_at: None,
+    }
+}
+
+pub fn add_note(storage: &Storage, crypto: &Crypto, id: &str, body: &[u8]) -> VaultItem {
+    let item = note(id);
+    storage.write_encrypted_file(&crypto.encrypt(body).unwrap(), &item.data_path).unwrap();
+    storage.add_item(&item, crypto).unwrap();
+    item
+}
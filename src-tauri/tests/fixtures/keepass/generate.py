@@ -0,0 +1,137 @@
+"""Writes the KDBX 4 fixtures for tests/keepass.rs. They come from this script rather than
+KeePass so their contents are known; the crypto is the `cryptography` package's, not ours.
+
+    pip install cryptography && python3 generate.py
+"""
+import base64, gzip, hashlib, hmac, os, struct
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+from cryptography.hazmat.primitives.kdf.argon2 import Argon2d, Argon2id
+from cryptography.hazmat.primitives import padding
+
+AES = bytes.fromhex('31c1f2e6bf714350be5805216afc5aff')
+CHACHA = bytes.fromhex('d6038a2b8b6f4cb5a524339a31dbb59a')
+KDF_AES = bytes.fromhex('c9d9f39a628a4460bf740d08c18a4fea')
+KDF_A2D = bytes.fromhex('ef636ddf8c29444b91f7a9a403e30a0c')
+KDF_A2ID = bytes.fromhex('9e298b1956db4773b23dfc3ec6f0a1e6')
+
+def vd(entries):
+    out = struct.pack('<H', 0x0100)
+    for t, n, v in entries:
+        n = n.encode(); out += bytes([t]) + struct.pack('<i', len(n)) + n + struct.pack('<i', len(v)) + v
+    return out + b'\x00'
+
+def chacha_enc(key, nonce):
+    return Cipher(algorithms.ChaCha20(key, b'\x00' * 4 + nonce), mode=None).encryptor()
+
+def t(unix):
+    return base64.b64encode(struct.pack('<q', unix + 62135596800)).decode()
+
+def uuid():
+    return base64.b64encode(os.urandom(16)).decode()
+
+def build_xml(stream):
+    def prot(v):
+        return base64.b64encode(stream.update(v.encode())).decode()
+    bin_uuid = uuid()
+    return f'''<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<KeePassFile><Meta><Generator>gen</Generator><RecycleBinEnabled>True</RecycleBinEnabled><RecycleBinUUID>{bin_uuid}</RecycleBinUUID>
+<CustomData><Item><Key>x</Key><Value>y</Value></Item></CustomData></Meta>
+<Root><Group><UUID>{uuid()}</UUID><Name>Passwords</Name><Times><CreationTime>{t(1600000000)}</CreationTime></Times>
+<Entry><UUID>{uuid()}</UUID><Tags>alpha;beta</Tags><Times><CreationTime>{t(1600000000)}</CreationTime><LastModificationTime>{t(1650000000)}</LastModificationTime><ExpiryTime>{t(1900000000)}</ExpiryTime><Expires>True</Expires></Times>
+<String><Key>Title</Key><Value>Mail &amp; co</Value></String>
+<String><Key>UserName</Key><Value>me@example.com</Value></String>
+<String><Key>Password</Key><Value Protected="True">{prot(" p4ss word ")}</Value></String>
+<String><Key>URL</Key><Value>https://mail.example.com</Value></String>
+<String><Key>KP2A_URL_1</Key><Value>https://webmail.example.com</Value></String>
+<String><Key>otp</Key><Value Protected="True">{prot("otpauth://totp/x?secret=JBSWY3DPEHPK3PXP&amp;issuer=x".replace("&amp;","&"))}</Value></String>
+<String><Key>PIN</Key><Value Protected="True">{prot("1234")}</Value></String>
+<String><Key>Recovery</Key><Value>codes</Value></String>
+<String><Key>Notes</Key><Value>line1
+line2</Value></String>
+<String><Key>Empty</Key><Value Protected="True"/></String>
+<Binary><Key>scan.pdf</Key><Value Ref="0"/></Binary>
+<Binary><Key>note.txt</Key><Value Ref="1"/></Binary>
+<AutoType><Enabled>True</Enabled><Association><Window>x</Window><KeystrokeSequence>y</KeystrokeSequence></Association></AutoType>
+<History><Entry><UUID>{uuid()}</UUID><String><Key>Title</Key><Value>Mail old</Value></String><String><Key>Password</Key><Value Protected="True">{prot("old-password")}</Value></String></Entry></History>
+</Entry>
+<Group><UUID>{uuid()}</UUID><Name>Work</Name>
+<Entry><UUID>{uuid()}</UUID><String><Key>Title</Key><Value>VPN</Value></String><String><Key>UserName</Key><Value>bob</Value></String><String><Key>Password</Key><Value Protected="True">{prot("vpn-secret-ü")}</Value></String></Entry>
+<Entry><UUID>{uuid()}</UUID><String><Key>Title</Key><Value>Just a note</Value></String><String><Key>Notes</Key><Value><![CDATA[note <body>]]></Value></String></Entry>
+<Group><UUID>{uuid()}</UUID><Name>Servers</Name><Entry><UUID>{uuid()}</UUID><String><Key>Title</Key><Value>db</Value></String><String><Key>Password</Key><Value Protected="True">{prot("dbpw")}</Value></String></Entry></Group>
+</Group>
+<Group><UUID>{bin_uuid}</UUID><Name>Recycle Bin</Name><Entry><UUID>{uuid()}</UUID><String><Key>Title</Key><Value>trashed</Value></String><String><Key>Password</Key><Value Protected="True">{prot("gone")}</Value></String></Entry></Group>
+</Group><DeletedObjects/></Root></KeePassFile>'''
+
+def write(path, password, key_file_key, cipher, kdf, compress):
+    seed = os.urandom(32)
+    iv = os.urandom(12 if cipher == CHACHA else 16)
+    salt = os.urandom(32)
+    if kdf.startswith('argon2'):
+        params = vd([(0x42, '$UUID', KDF_A2ID if kdf == 'argon2id' else KDF_A2D), (0x42, 'S', salt), (0x04, 'P', struct.pack('<I', 2)), (0x05, 'M', struct.pack('<Q', 8 * 1024 * 1024)), (0x05, 'I', struct.pack('<Q', 2)), (0x04, 'V', struct.pack('<I', 0x13))])
+    else:
+        params = vd([(0x42, '$UUID', KDF_AES), (0x42, 'S', salt), (0x05, 'R', struct.pack('<Q', 1000))])
+    header = struct.pack('<III', 0x9AA2D903, 0xB54BFB67, 0x00040000)
+    for fid, data in [(2, cipher), (3, struct.pack('<I', 1 if compress else 0)), (4, seed), (7, iv), (11, params), (0, b'\r\n\r\n')]:
+        header += bytes([fid]) + struct.pack('<I', len(data)) + data
+    comp = hashlib.sha256()
+    if password is not None:
+        comp.update(hashlib.sha256(password.encode()).digest())
+    if key_file_key is not None:
+        comp.update(key_file_key)
+    composite = comp.digest()
+    if kdf.startswith('argon2'):
+        argon2 = Argon2id if kdf == 'argon2id' else Argon2d
+        transformed = argon2(salt=salt, length=32, iterations=2, lanes=2, memory_cost=8 * 1024).derive(composite)
+    else:
+        k = composite
+        enc = Cipher(algorithms.AES(salt), modes.ECB()).encryptor()
+        for _ in range(1000):
+            k = enc.update(k)
+        transformed = hashlib.sha256(k).digest()
+    cipher_key = hashlib.sha256(seed + transformed).digest()
+    hmac_base = hashlib.sha512(seed + transformed + b'\x01').digest()
+    bk = lambda i: hashlib.sha512(struct.pack('<Q', i) + hmac_base).digest()
+
+    stream_key = os.urandom(64)
+    h = hashlib.sha512(stream_key).digest()
+    stream = chacha_enc(h[:32], h[32:44])
+    xml = build_xml(stream).encode()
+    inner = b''
+    for fid, data in [(1, struct.pack('<I', 3)), (2, stream_key), (3, b'\x01' + b'%PDF-1.4 fake pdf'), (3, b'\x00' + b'hello attachment'), (0, b'')]:
+        inner += bytes([fid]) + struct.pack('<I', len(data)) + data
+    payload = inner + xml
+    if compress:
+        payload = gzip.compress(payload)
+    if cipher == CHACHA:
+        encrypted = chacha_enc(cipher_key, iv).update(payload)
+    else:
+        p = padding.PKCS7(128).padder(); padded = p.update(payload) + p.finalize()
+        e = Cipher(algorithms.AES(cipher_key), modes.CBC(iv)).encryptor(); encrypted = e.update(padded) + e.finalize()
+    out = header + hashlib.sha256(header).digest() + hmac.new(bk(2**64 - 1), header, hashlib.sha256).digest()
+    blocks = [encrypted[i:i + 1000] for i in range(0, len(encrypted), 1000)] + [b'']
+    for i, block in enumerate(blocks):
+        out += hmac.new(bk(i), struct.pack('<Q', i) + struct.pack('<i', len(block)) + block, hashlib.sha256).digest() + struct.pack('<i', len(block)) + block
+    open(path, 'wb').write(out)
+
+d = os.path.dirname(os.path.abspath(__file__)) + '/'
+password = 'correct horse'
+write(d + 'argon2d-chacha20.kdbx', password, None, CHACHA, 'argon2d', False)
+write(d + 'argon2id-aes.kdbx', password, None, AES, 'argon2id', True)
+key = os.urandom(32)
+hexkey = key.hex().upper()
+groups = ' '.join(hexkey[i:i + 8] for i in range(0, 64, 8))
+open(d + 'key.keyx', 'w').write(f'''<?xml version="1.0" encoding="utf-8"?>
+<KeyFile>
+	<Meta>
+		<Version>2.0</Version>
+	</Meta>
+	<Key>
+		<Data Hash="{hashlib.sha256(key).hexdigest()[:8].upper()}">
+			{groups[:35]}
+			{groups[36:]}
+		</Data>
+	</Key>
+</KeyFile>
+''')
+write(d + 'aeskdf-aes-keyfile.kdbx', password, key, AES, 'aes', True)
+write(d + 'aeskdf-chacha20-keyfile-only.kdbx', None, key, CHACHA, 'aes', True)
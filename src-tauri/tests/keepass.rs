@@ -0,0 +1,134 @@
+//! KeePass imports from the KDBX 4 files in fixtures/keepass, written by its generate.py. Each
+//! holds the same groups and entries: protected values, an entry with history, two attachments
+//! and a recycle bin with an entry in it.
+
+mod common;
+
+use common::{new_vault, temp_path, PASSWORD};
+use fetch::error::Error;
+use fetch::keepass::{import_keepass, KeePassDatabase};
+use fetch::storage::VaultItem;
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "keepass", name].iter().collect();
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+// imports the database into a new vault and checks everything came over as it should
+fn check_import(database: &KeePassDatabase) {
+    let (storage, crypto) = new_vault(&temp_path("keepass"));
+    let report = import_keepass(&storage, &crypto, database, None).unwrap();
+    assert_eq!((report.folders, report.items, report.attachments), (3, 4, 2), "{:?}", report);
+    assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+
+    let items = storage.get_all_items_recursive(&crypto).unwrap();
+    let named = |name: &str| -> VaultItem { items.iter().find(|item| item.name == name).unwrap_or_else(|| panic!("{} is missing", name)).clone() };
+    // the recycle bin and what's in it stay behind, as do history versions
+    assert!(items.iter().all(|item| !["Recycle Bin", "trashed", "Mail old"].contains(&item.name.as_str())));
+
+    let root = named("Passwords");
+    let work = named("Work");
+    assert_eq!(work.parent_id.as_deref(), Some(root.id.as_str()));
+    assert_eq!(named("Servers").parent_id.as_deref(), Some(work.id.as_str()));
+
+    let mail = named("Mail & co");
+    assert_eq!(mail.parent_id.as_deref(), Some(root.id.as_str()));
+    assert_eq!(mail.tags, ["alpha", "beta"]);
+    assert_eq!(mail.totp_secret.as_deref(), Some("SGVsbG8h3q2+7w=="));
+    assert_eq!((mail.created_at.timestamp(), mail.updated_at.timestamp()), (1_600_000_000, 1_650_000_000));
+    assert_eq!(mail.expires_at.map(|time| time.timestamp()), Some(1_900_000_000));
+    let login = storage.read_login(&mail, &crypto).unwrap();
+    assert_eq!(login.password, " p4ss word ");
+    assert_eq!(login.urls, ["https://mail.example.com", "https://webmail.example.com"]);
+    assert_eq!(login.notes, "line1\nline2");
+    let fields: Vec<(&str, &str, &str)> = mail.custom_fields.iter().map(|field| (field.label.as_str(), field.kind.as_str(), field.value.as_str())).collect();
+    assert_eq!(fields, [("PIN", "hidden", "1234"), ("Recovery", "text", "codes"), ("Empty", "hidden", "")]);
+
+    // protected values after the history's are still decrypted in step
+    assert_eq!(storage.read_login(&named("VPN"), &crypto).unwrap().password, "vpn-secret-ü");
+    assert_eq!(storage.read_login(&named("db"), &crypto).unwrap().password, "dbpw");
+    let note = named("Just a note");
+    assert_eq!(note.item_type, "text/plain");
+    assert_eq!(storage.read_encrypted_file(&note.data_path, &crypto).unwrap(), b"note <body>");
+
+    let pdf = named("scan.pdf");
+    assert_eq!((pdf.item_type.as_str(), pdf.parent_id.as_deref()), ("application/pdf", mail.parent_id.as_deref()));
+    assert_eq!(storage.read_encrypted_file(&pdf.data_path, &crypto).unwrap(), b"%PDF-1.4 fake pdf");
+    assert_eq!(storage.read_encrypted_file(&named("note.txt").data_path, &crypto).unwrap(), b"hello attachment");
+    let links = storage.list_all_links(&crypto).unwrap();
+    assert_eq!(links.iter().filter(|link| link.source_id == mail.id).count(), 2);
+
+    // importing again reuses the folders
+    assert_eq!(import_keepass(&storage, &crypto, database, None).unwrap().folders, 0);
+    std::fs::remove_dir_all(storage.get_vault_path()).unwrap();
+}
+
+#[test]
+fn argon2d_kdf_with_chacha20() {
+    let data = fixture("argon2d-chacha20.kdbx");
+    check_import(&KeePassDatabase::open(&data, PASSWORD, None).unwrap());
+}
+
+#[test]
+fn argon2id_kdf_with_aes() {
+    let data = fixture("argon2id-aes.kdbx");
+    check_import(&KeePassDatabase::open(&data, PASSWORD, None).unwrap());
+}
+
+#[test]
+fn aes_kdf_with_key_file() {
+    let (data, key_file) = (fixture("aeskdf-aes-keyfile.kdbx"), fixture("key.keyx"));
+    check_import(&KeePassDatabase::open(&data, PASSWORD, Some(&key_file)).unwrap());
+    assert!(matches!(KeePassDatabase::open(&data, PASSWORD, None), Err(Error::InvalidKey)));
+
+    let data = fixture("aeskdf-chacha20-keyfile-only.kdbx");
+    check_import(&KeePassDatabase::open(&data, "", Some(&key_file)).unwrap());
+    assert!(matches!(KeePassDatabase::open(&data, "", Some(b"another key file")), Err(Error::InvalidKey)));
+}
+
+#[test]
+fn wrong_password() {
+    for name in ["argon2d-chacha20.kdbx", "argon2id-aes.kdbx"] {
+        let result = KeePassDatabase::open(&fixture(name), "wrong horse", None);
+        assert!(matches!(result, Err(Error::InvalidKey)), "{}", name);
+    }
+    let result = KeePassDatabase::open(&fixture("aeskdf-aes-keyfile.kdbx"), "wrong horse", Some(&fixture("key.keyx")));
+    assert!(matches!(result, Err(Error::InvalidKey)));
+}
+
+#[test]
+fn damaged_files() {
+    let data = fixture("argon2d-chacha20.kdbx");
+    let mut damaged = data.clone();
+    let position = damaged.len() - 100;
+    damaged[position] ^= 1;
+    assert!(matches!(KeePassDatabase::open(&damaged, PASSWORD, None), Err(Error::InvalidInput(_))));
+    assert!(KeePassDatabase::open(&data[..200], PASSWORD, None).is_err());
+    assert!(KeePassDatabase::open(b"not a database", PASSWORD, None).is_err());
+}
+
+// sets a UInt64 key derivation parameter in the header and fixes up the header's checksum,
+// so the file is only refused for the value
+fn with_kdf_parameter(data: &[u8], name: u8, value: u64) -> Vec<u8> {
+    let mut data = data.to_vec();
+    let entry = [0x05, 1, 0, 0, 0, name, 8, 0, 0, 0];
+    let at = data.windows(entry.len()).position(|window| window == entry).unwrap() + entry.len();
+    data[at..at + 8].copy_from_slice(&value.to_le_bytes());
+    let end_of_header = [0, 4, 0, 0, 0, b'\r', b'\n', b'\r', b'\n'];
+    let header_len = data.windows(end_of_header.len()).position(|window| window == end_of_header).unwrap() + end_of_header.len();
+    let checksum = <sha2::Sha256 as sha2::Digest>::digest(&data[..header_len]);
+    data[header_len..header_len + 32].copy_from_slice(&checksum);
+    data
+}
+
+#[test]
+fn excessive_key_derivation_is_refused() {
+    let data = with_kdf_parameter(&fixture("argon2id-aes.kdbx"), b'I', 1_000_000);
+    let error = KeePassDatabase::open(&data, PASSWORD, None).err().unwrap();
+    assert!(matches!(&error, Error::InvalidInput(message) if message.contains("1000000 Argon2 iterations")), "{:?}", error);
+
+    let data = with_kdf_parameter(&fixture("aeskdf-aes-keyfile.kdbx"), b'R', u64::MAX);
+    let error = KeePassDatabase::open(&data, PASSWORD, Some(&fixture("key.keyx"))).err().unwrap();
+    assert!(matches!(&error, Error::InvalidInput(message) if message.contains("key derivation rounds")), "{:?}", error);
+}
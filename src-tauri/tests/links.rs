@@ -0,0 +1,100 @@
+//! Links between items and custom fields that reference another item's field.
+
+mod common;
+
+use common::{new_vault, note, temp_path};
+use fetch::crypto::Crypto;
+use fetch::fields::{CustomField, CustomFieldKind};
+use fetch::links::{FieldReference, ReferenceStatus};
+use fetch::login::LoginData;
+use fetch::records::{CardData, ItemRecord};
+use fetch::storage::{Storage, VaultItem};
+
+fn reference(label: &str, value: &str) -> CustomField {
+    CustomField { label: label.into(), kind: CustomFieldKind::Text, value: value.into() }
+}
+
+fn add_login(storage: &Storage, crypto: &Crypto, id: &str, password: &str) {
+    let item = VaultItem { item_type: "login".into(), ..note(id) };
+    let login = LoginData { username: "root".into(), password: password.into(), urls: vec!["https://aws.amazon.com".into()], notes: String::new() };
+    storage.write_login(&item.data_path, &login, crypto).unwrap();
+    storage.add_item(&item, crypto).unwrap();
+}
+
+fn statuses(storage: &Storage, crypto: &Crypto, id: &str) -> Vec<(ReferenceStatus, Option<String>)> {
+    let item = storage.get_item(id, crypto).unwrap().unwrap();
+    storage.resolve_references(&item, crypto).unwrap().into_iter().map(|resolved| (resolved.status, resolved.value)).collect()
+}
+
+#[test]
+fn references_resolve_through_other_items() {
+    let path = temp_path("links");
+    let (storage, crypto) = new_vault(&path);
+    add_login(&storage, &crypto, "aws", "hunter2");
+    let mut card = CardData::default();
+    card.number = "4111111111111111".into();
+    let card_item = VaultItem { item_type: "card".into(), ..note("card") };
+    storage.write_record(&card_item.data_path, &ItemRecord::Card(card), &crypto).unwrap();
+    storage.add_item(&card_item, &crypto).unwrap();
+    let fields = vec![
+        reference("password", "{ref:aws:password}"),
+        reference("card", "{ref:card:number}"),
+        // a reference to a reference is followed
+        reference("chained", "{ref:key:field:password}"),
+        reference("cycle", "{ref:key:field:Cycle}"),
+        reference("missing item", "{ref:nothing:name}"),
+        reference("missing field", "{ref:aws:field:otp}"),
+    ];
+    storage.add_item(&VaultItem { item_type: "key".into(), data_path: String::new(), custom_fields: fields, ..note("key") }, &crypto).unwrap();
+
+    let hunter2 = Some("hunter2".to_string());
+    assert_eq!(
+        statuses(&storage, &crypto, "key"),
+        [
+            (ReferenceStatus::Resolved, hunter2.clone()),
+            (ReferenceStatus::Resolved, Some("4111111111111111".into())),
+            (ReferenceStatus::Resolved, hunter2.clone()),
+            (ReferenceStatus::TooDeep, None),
+            (ReferenceStatus::MissingItem, None),
+            (ReferenceStatus::MissingField, None),
+        ]
+    );
+    let referenced_by = storage.get_links("aws", &crypto).unwrap().referenced_by;
+    assert_eq!(referenced_by.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), ["key"]);
+
+    // a reference to an item in the recycle bin works again once it is restored
+    storage.delete_item_and_descendants("aws", &crypto).unwrap();
+    assert_eq!(statuses(&storage, &crypto, "key")[0], (ReferenceStatus::TargetDeleted, None));
+    storage.restore_item("aws").unwrap();
+    assert_eq!(statuses(&storage, &crypto, "key")[0], (ReferenceStatus::Resolved, hunter2));
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn links_outlive_soft_deletes_only() {
+    let path = temp_path("links");
+    let (storage, crypto) = new_vault(&path);
+    add_login(&storage, &crypto, "aws", "hunter2");
+    storage.add_item(&VaultItem { item_type: "key".into(), ..note("key") }, &crypto).unwrap();
+
+    let link = storage.add_link("key", "aws", Some(" root account "), &crypto).unwrap();
+    assert_eq!(link.label.as_deref(), Some("root account"));
+    assert!(storage.add_link("key", "aws", None, &crypto).is_err());
+    assert!(storage.add_link("key", "key", None, &crypto).is_err());
+    assert!(storage.add_link("key", "nothing", None, &crypto).is_err());
+    let backlinks = storage.get_links("aws", &crypto).unwrap().backlinks;
+    assert_eq!(backlinks.len(), 1);
+    assert_eq!(backlinks[0].item.as_ref().unwrap().id, "key");
+
+    storage.delete_item_and_descendants("aws", &crypto).unwrap();
+    let outgoing = storage.get_links("key", &crypto).unwrap().outgoing;
+    assert!(outgoing[0].item.as_ref().is_some_and(|item| item.deleted));
+    storage.permanently_delete_item_and_descendants("aws", &crypto).unwrap();
+    assert!(storage.get_links("key", &crypto).unwrap().outgoing.is_empty());
+
+    assert_eq!(FieldReference::parse(" {ref: aws : password} "), Some(FieldReference { item_id: "aws".into(), field: "password".into() }));
+    assert_eq!(FieldReference::parse("{ref:aws}"), None);
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
@@ -0,0 +1,112 @@
+//! Merging one vault into another, and rolling a merge back when it fails part way.
+
+mod common;
+
+use common::{add_note, new_vault, temp_path, PASSWORD};
+use fetch::crypto::Crypto;
+use fetch::error::Error;
+use fetch::fields::{CustomField, CustomFieldKind};
+use fetch::merge::{merge_vault, MergeSource, MergeStrategy};
+use fetch::storage::{Storage, VaultItem};
+use std::time::Duration;
+
+fn item_ids(storage: &Storage, crypto: &Crypto) -> Vec<String> {
+    let mut ids: Vec<String> = storage.get_all_items_recursive(crypto).unwrap().into_iter().map(|item| item.id).collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn merged_items_bring_their_content_attachments_and_links() {
+    let base = temp_path("merge");
+    let (target, target_crypto) = new_vault(&base.join("target"));
+    add_note(&target, &target_crypto, "notes", b"target notes");
+    let source_path = base.join("source");
+    let (source, source_crypto) = new_vault(&source_path);
+    add_note(&source, &source_crypto, "notes", b"source notes");
+    let mut key = add_note(&source, &source_crypto, "key", b"key");
+    key.custom_fields = vec![CustomField { label: "notes".into(), kind: CustomFieldKind::Text, value: "{ref:notes:content}".into() }];
+    source.update_item_fields(&key, &source_crypto).unwrap();
+    source.add_attachment("key", "a.txt", "text/plain", b"hello", &source_crypto).unwrap();
+    source.add_link("key", "notes", Some("docs"), &source_crypto).unwrap();
+    drop(source);
+
+    let merge_source = MergeSource::open(&source_path, PASSWORD).unwrap();
+    let dry_run = merge_vault(&target, &target_crypto, &merge_source, MergeStrategy::KeepBoth, true).unwrap();
+    assert_eq!((dry_run.added, dry_run.conflicts.len()), (2, 1));
+    assert_eq!(item_ids(&target, &target_crypto), ["notes"]);
+
+    let report = merge_vault(&target, &target_crypto, &merge_source, MergeStrategy::KeepBoth, false).unwrap();
+    assert_eq!((report.added, report.attachments, report.links), (2, 1, 1));
+    let items = target.get_all_items_recursive(&target_crypto).unwrap();
+    let copy: &VaultItem = items.iter().find(|item| item.name.starts_with("notes (merged ")).unwrap();
+    assert_eq!(target.read_encrypted_file(&copy.data_path, &target_crypto).unwrap(), b"source notes");
+    // what pointed at the source's notes points at the copy now
+    let key = target.get_item("key", &target_crypto).unwrap().unwrap();
+    assert_eq!(key.custom_fields[0].value, format!("{{ref:{}:content}}", copy.id));
+    assert_eq!(target.get_links("key", &target_crypto).unwrap().outgoing[0].link.target_id, copy.id);
+    let attachment = target.list_attachments("key", &target_crypto).unwrap().remove(0);
+    assert_eq!(target.read_attachment(&attachment, &target_crypto).unwrap(), b"hello");
+
+    drop(merge_source);
+    drop(target);
+    std::fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn failed_merge_is_rolled_back() {
+    let base = temp_path("merge");
+    let (target, target_crypto) = new_vault(&base.join("target"));
+    add_note(&target, &target_crypto, "mine", b"mine");
+    let source_path = base.join("source");
+    let (source, source_crypto) = new_vault(&source_path);
+    for id in ["a", "b", "c"] {
+        add_note(&source, &source_crypto, id, id.as_bytes());
+    }
+    drop(source);
+
+    // the last item's content is gone, so the merge fails after writing the first two
+    std::fs::remove_file(source_path.join("data").join("c.bin")).unwrap();
+    let data_files = || std::fs::read_dir(base.join("target").join("data")).unwrap().count();
+    let files_before = data_files();
+    let merge_source = MergeSource::open(&source_path, PASSWORD).unwrap();
+    assert!(merge_vault(&target, &target_crypto, &merge_source, MergeStrategy::KeepBoth, false).is_err());
+    assert_eq!(item_ids(&target, &target_crypto), ["mine"]);
+    assert_eq!(data_files(), files_before);
+    drop(merge_source);
+
+    std::fs::write(source_path.join("data").join("c.bin"), source_crypto.encrypt(b"c").unwrap()).unwrap();
+    let merge_source = MergeSource::open(&source_path, PASSWORD).unwrap();
+    let report = merge_vault(&target, &target_crypto, &merge_source, MergeStrategy::KeepBoth, false).unwrap();
+    assert_eq!(report.added, 3);
+    assert_eq!(item_ids(&target, &target_crypto), ["a", "b", "c", "mine"]);
+    drop(merge_source);
+    drop(target);
+    std::fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+fn writes_during_a_rollback_survive_it() {
+    let path = temp_path("rollback");
+    let (storage, crypto) = new_vault(&path);
+    add_note(&storage, &crypto, "kept", b"kept");
+
+    std::thread::scope(|scope| {
+        let result = storage.with_rollback(|written| {
+            add_note(&storage, &crypto, "merged", b"merged");
+            written.push("merged.bin".to_string());
+            // another command writing meanwhile waits for the rollback rather than being part of it
+            let concurrent = scope.spawn(|| add_note(&storage, &crypto, "concurrent", b"concurrent"));
+            std::thread::sleep(Duration::from_millis(300));
+            assert!(!concurrent.is_finished());
+            Err::<(), _>(Error::Internal("failed part way".into()))
+        });
+        assert!(result.is_err());
+    });
+
+    assert_eq!(item_ids(&storage, &crypto), ["concurrent", "kept"]);
+    assert_eq!(storage.read_encrypted_file("concurrent.bin", &crypto).unwrap(), b"concurrent");
+    assert!(!path.join("data").join("merged.bin").exists());
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
@@ -0,0 +1,81 @@
+//! Listing folders a page at a time with cursors.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{new_vault, note, temp_path};
+use fetch::crypto::Crypto;
+use fetch::storage::{SortOrder, Storage, VaultItem};
+
+fn all_pages(storage: &Storage, crypto: &Crypto, order: SortOrder, page_size: usize) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = storage.get_items_page(None, false, None, Some(order), cursor.as_deref(), Some(page_size), crypto).unwrap();
+        assert!(page.items.len() <= page_size);
+        ids.extend(page.items.into_iter().map(|item| item.id));
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return ids,
+        }
+    }
+}
+
+fn item(index: usize) -> VaultItem {
+    // shuffled names and times, so each order walks the items differently
+    let at = Utc::now() - Duration::minutes(((index * 7) % 25) as i64);
+    let item_type = if index % 10 == 0 { "folder" } else { "key" };
+    VaultItem { name: format!("item {:02}", (index * 11) % 25), item_type: item_type.into(), data_path: String::new(), created_at: at, updated_at: at, ..note(&format!("{:02}", index)) }
+}
+
+#[test]
+fn pages_add_up_to_the_full_listing() {
+    let path = temp_path("pages");
+    let (storage, crypto) = new_vault(&path);
+    for index in 0..25 {
+        storage.add_item(&item(index), &crypto).unwrap();
+    }
+
+    for order in [SortOrder::NameAsc, SortOrder::NameDesc, SortOrder::CreatedAtDesc, SortOrder::UpdatedAtAsc] {
+        let full: Vec<String> = storage.get_items(None, None, Some(order), &crypto).unwrap().into_iter().map(|item| item.id).collect();
+        assert_eq!(all_pages(&storage, &crypto, order, 7), full, "{:?}", order);
+    }
+
+    let page = storage.get_items_page(None, true, Some("key".into()), None, None, Some(100), &crypto).unwrap();
+    assert_eq!((page.total, page.items.len()), (22, 22));
+    assert!(page.next_cursor.is_none());
+    assert!(storage.get_items_page(None, false, None, None, Some("not a cursor"), None, &crypto).is_err());
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn cursors_survive_changes_between_pages() {
+    let path = temp_path("pages");
+    let (storage, crypto) = new_vault(&path);
+    for index in 0..30 {
+        storage.add_item(&VaultItem { name: format!("n{:03}", index), ..note(&format!("i{:03}", index)) }, &crypto).unwrap();
+    }
+
+    let first = storage.get_items_page(None, false, None, Some(SortOrder::NameAsc), None, Some(10), &crypto).unwrap();
+    assert_eq!(first.total, 30);
+    // an item before the cursor and one after it are added, the one removed was already listed
+    storage.add_item(&VaultItem { name: "n000a".into(), ..note("early") }, &crypto).unwrap();
+    storage.add_item(&VaultItem { name: "n999".into(), ..note("late") }, &crypto).unwrap();
+    storage.permanently_delete_item_and_descendants("i000", &crypto).unwrap();
+
+    let mut names: Vec<String> = first.items.into_iter().map(|item| item.name).collect();
+    let mut cursor = first.next_cursor;
+    while let Some(current) = cursor {
+        let page = storage.get_items_page(None, false, None, Some(SortOrder::NameAsc), Some(&current), Some(10), &crypto).unwrap();
+        assert_eq!(page.total, 31);
+        names.extend(page.items.into_iter().map(|item| item.name));
+        cursor = page.next_cursor;
+    }
+    // nothing is listed twice or skipped past the cursor
+    let mut expected: Vec<String> = (0..30).map(|index| format!("n{:03}", index)).collect();
+    expected.push("n999".into());
+    assert_eq!(names, expected);
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
@@ -0,0 +1,403 @@
+//! Backups to an in-process S3 server. It keeps objects in memory, checks SigV4 signatures and
+//! checksums, assembles multipart uploads, pages listings and enforces object lock retention,
+//! which is the part of S3 the backups rely on.
+
+mod common;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use common::{add_note, new_vault, temp_path};
+use fetch::backup::{create_snapshot, StagedRestore};
+use fetch::crypto::Crypto;
+use fetch::s3::{download_snapshot, list_snapshots, upload_snapshot, ObjectLock, ObjectLockMode, S3Client, S3Config};
+use fetch::storage::Storage;
+use hmac::{Hmac, Mac};
+use hyper::http::request::Parts;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+const ACCESS_KEY: &str = "fetch";
+const SECRET_KEY: &str = "fetch-secret";
+const REGION: &str = "us-east-1";
+const BUCKET: &str = "vault";
+const LOCKED_BUCKET: &str = "vault-locked";
+// listings come back in pages this long, so listing goes through continuation tokens
+const PAGE_SIZE: usize = 2;
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+const RETAIN_UNTIL: &str = "x-amz-object-lock-retain-until-date";
+
+struct Object {
+    data: Vec<u8>,
+    retain_until: Option<String>,
+}
+
+struct Upload {
+    key: String,
+    parts: BTreeMap<u32, Vec<u8>>,
+    retain_until: Option<String>,
+}
+
+// objects are keyed by bucket and key, "vault/some/key"
+#[derive(Default)]
+struct Store {
+    objects: BTreeMap<String, Object>,
+    uploads: HashMap<String, Upload>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (byte, tail.get(..2).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).unwrap()
+}
+
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'/' if !encode_slash => "/".to_string(),
+            _ if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn query_pairs(parts: &Parts) -> BTreeMap<String, String> {
+    let query = parts.uri.query().unwrap_or("");
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+// the signature the request should carry, worked out from what arrived rather than trusting the
+// client's canonical forms
+fn is_signed(parts: &Parts, body: &[u8]) -> bool {
+    let header = |name: &str| parts.headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or("").trim().to_string();
+    let authorization = header("authorization");
+    let Some(fields) = authorization.strip_prefix("AWS4-HMAC-SHA256 ") else { return false };
+    let fields: HashMap<&str, &str> = fields.split(',').filter_map(|field| field.trim().split_once('=')).collect();
+    let (Some(credential), Some(signed_headers), Some(signature)) = (fields.get("Credential"), fields.get("SignedHeaders"), fields.get("Signature")) else {
+        return false;
+    };
+    let date = header("x-amz-date");
+    let payload_hash = header("x-amz-content-sha256");
+    let scope = format!("{}/{}/s3/aws4_request", date.get(..8).unwrap_or(""), REGION);
+    if *credential != format!("{}/{}", ACCESS_KEY, scope) || payload_hash != hex(&Sha256::digest(body)) {
+        return false;
+    }
+
+    let query: Vec<String> = query_pairs(parts).iter().map(|(name, value)| format!("{}={}", uri_encode(name, true), uri_encode(value, true))).collect();
+    let canonical_headers: String = signed_headers.split(';').map(|name| format!("{}:{}\n", name, header(name))).collect();
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        parts.method,
+        uri_encode(&percent_decode(parts.uri.path()), false),
+        query.join("&"),
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", date, scope, hex(&Sha256::digest(canonical_request.as_bytes())));
+    let mut key = hmac_sha256(format!("AWS4{}", SECRET_KEY).as_bytes(), &date[..8]);
+    for part in [REGION, "s3", "aws4_request"] {
+        key = hmac_sha256(&key, part);
+    }
+    hex(&hmac_sha256(&key, &string_to_sign)) == *signature
+}
+
+fn reply(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    Response::builder().status(status).body(body.into()).unwrap()
+}
+
+fn error(status: StatusCode, code: &str) -> Response<Body> {
+    reply(status, format!("<Error><Code>{}</Code><Message>{}</Message></Error>", code, code))
+}
+
+// the text of every `name` element in `xml`
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    xml.split(open.as_str()).skip(1).filter_map(|rest| rest.split_once(close.as_str()).map(|(text, _)| text)).collect()
+}
+
+fn has_checksum(parts: &Parts, body: &[u8]) -> bool {
+    parts.headers.get("x-amz-checksum-sha256").is_some_and(|checksum| checksum == STANDARD.encode(Sha256::digest(body)).as_str())
+}
+
+fn handle(store: &mut Store, parts: &Parts, body: Vec<u8>) -> Response<Body> {
+    if !is_signed(parts, &body) {
+        return error(StatusCode::FORBIDDEN, "SignatureDoesNotMatch");
+    }
+    let query = query_pairs(parts);
+    let path = percent_decode(parts.uri.path());
+    let (bucket, key) = path.trim_start_matches('/').split_once('/').unwrap_or((path.trim_start_matches('/'), ""));
+    if bucket != BUCKET && bucket != LOCKED_BUCKET {
+        return error(StatusCode::NOT_FOUND, "NoSuchBucket");
+    }
+    let locked_bucket = bucket == LOCKED_BUCKET;
+    let retain_until = parts.headers.get(RETAIN_UNTIL).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let object_key = format!("{}/{}", bucket, key);
+
+    if key.is_empty() {
+        if query.contains_key("object-lock") {
+            return match locked_bucket {
+                true => reply(
+                    StatusCode::OK,
+                    "<ObjectLockConfiguration><ObjectLockEnabled>Enabled</ObjectLockEnabled></ObjectLockConfiguration>",
+                ),
+                false => error(StatusCode::NOT_FOUND, "ObjectLockConfigurationNotFoundError"),
+            };
+        }
+        let prefix = format!("{}/{}", bucket, query.get("prefix").map_or("", String::as_str));
+        let keys: Vec<&String> = store.objects.keys().filter(|key| key.starts_with(&prefix)).collect();
+        let start: usize = query.get("continuation-token").map_or(0, |token| token.parse().unwrap());
+        let page_size = query.get("max-keys").map_or(PAGE_SIZE, |max| max.parse::<usize>().unwrap().min(PAGE_SIZE));
+        let end = keys.len().min(start + page_size);
+        let contents: String = keys[start..end]
+            .iter()
+            .map(|key| {
+                let size = store.objects[*key].data.len();
+                format!("<Contents><Key>{}</Key><Size>{}</Size><LastModified>2024-01-01T00:00:00.000Z</LastModified></Contents>", &key[bucket.len() + 1..], size)
+            })
+            .collect();
+        let more = match end < keys.len() {
+            true => format!("<IsTruncated>true</IsTruncated><NextContinuationToken>{}</NextContinuationToken>", end),
+            false => "<IsTruncated>false</IsTruncated>".to_string(),
+        };
+        return reply(StatusCode::OK, format!("<ListBucketResult>{}{}</ListBucketResult>", contents, more));
+    }
+
+    if parts.method == Method::POST && query.contains_key("uploads") {
+        let id = uuid::Uuid::new_v4().to_string();
+        store.uploads.insert(id.clone(), Upload { key: object_key, parts: BTreeMap::new(), retain_until });
+        return reply(StatusCode::OK, format!("<InitiateMultipartUploadResult><UploadId>{}</UploadId></InitiateMultipartUploadResult>", id));
+    }
+    if let Some(id) = query.get("uploadId") {
+        let Some(upload) = store.uploads.get_mut(id) else {
+            return error(StatusCode::NOT_FOUND, "NoSuchUpload");
+        };
+        return match parts.method {
+            Method::PUT if !has_checksum(parts, &body) => error(StatusCode::BAD_REQUEST, "BadDigest"),
+            Method::PUT => {
+                let etag = format!("\"{}\"", hex(&Sha256::digest(&body)));
+                upload.parts.insert(query["partNumber"].parse().unwrap(), body);
+                Response::builder().header("ETag", etag).body(Body::empty()).unwrap()
+            }
+            Method::DELETE => {
+                store.uploads.remove(id);
+                reply(StatusCode::NO_CONTENT, Body::empty())
+            }
+            _ => {
+                let completion = String::from_utf8(body).unwrap();
+                let numbers: Vec<u32> = elements(&completion, "PartNumber").iter().map(|number| number.parse().unwrap()).collect();
+                // like S3, a failed assembly still answers 200
+                if !numbers.iter().eq(upload.parts.keys()) || elements(&completion, "ChecksumSHA256").len() != numbers.len() {
+                    return reply(StatusCode::OK, "<Error><Code>InvalidPart</Code><Message>parts don't match</Message></Error>");
+                }
+                if numbers[..numbers.len() - 1].iter().any(|number| upload.parts[number].len() < MIN_PART_SIZE) {
+                    return error(StatusCode::BAD_REQUEST, "EntityTooSmall");
+                }
+                let upload = store.uploads.remove(id).unwrap();
+                let data = upload.parts.into_values().flatten().collect();
+                store.objects.insert(upload.key, Object { data, retain_until: upload.retain_until });
+                reply(StatusCode::OK, "<CompleteMultipartUploadResult><ETag>assembled</ETag></CompleteMultipartUploadResult>")
+            }
+        };
+    }
+
+    match parts.method {
+        Method::PUT if !has_checksum(parts, &body) => error(StatusCode::BAD_REQUEST, "BadDigest"),
+        Method::PUT => {
+            store.objects.insert(object_key, Object { data: body, retain_until });
+            reply(StatusCode::OK, Body::empty())
+        }
+        _ => {
+            let Some(object) = store.objects.get(&object_key) else {
+                return error(StatusCode::NOT_FOUND, "NoSuchKey");
+            };
+            if parts.method == Method::DELETE {
+                let locked = object.retain_until.as_ref().is_some_and(|until| chrono::DateTime::parse_from_rfc3339(until).unwrap() > chrono::Utc::now());
+                if locked {
+                    return error(StatusCode::FORBIDDEN, "AccessDenied");
+                }
+                store.objects.remove(&object_key);
+                return reply(StatusCode::NO_CONTENT, Body::empty());
+            }
+            let mut response = Response::builder().header("Content-Length", object.data.len());
+            if let Some(until) = &object.retain_until {
+                response = response.header(RETAIN_UNTIL, until.as_str());
+            }
+            let body = match parts.method {
+                Method::HEAD => Body::empty(),
+                _ => Body::from(object.data.clone()),
+            };
+            response.body(body).unwrap()
+        }
+    }
+}
+
+struct Server {
+    endpoint: String,
+    task: JoinHandle<()>,
+}
+
+impl Server {
+    fn start() -> Self {
+        let store = Arc::new(Mutex::new(Store::default()));
+        let service = make_service_fn(move |_| {
+            let store = store.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let store = store.clone();
+                    async move {
+                        let (parts, body) = request.into_parts();
+                        let body = hyper::body::to_bytes(body).await.unwrap().to_vec();
+                        let response = handle(&mut store.lock().unwrap(), &parts, body);
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let endpoint = format!("http://{}", server.local_addr());
+        let task = tokio::spawn(async move { server.await.unwrap() });
+        Self { endpoint, task }
+    }
+
+    fn config(&self, bucket: &str) -> S3Config {
+        S3Config {
+            endpoint: self.endpoint.clone(),
+            region: REGION.into(),
+            bucket: bucket.into(),
+            prefix: "fetch-test".into(),
+            access_key_id: ACCESS_KEY.into(),
+            secret_access_key: SECRET_KEY.into(),
+            path_style: true,
+            retention: 0,
+            interval_hours: None,
+            object_lock: None,
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn upload(storage: &Storage, crypto: &Crypto, config: &S3Config) -> fetch::s3::S3BackupReport {
+    let path = temp_path("snapshot");
+    create_snapshot(storage, crypto, &path).unwrap();
+    let report = upload_snapshot(config, &path).await;
+    std::fs::remove_file(path).unwrap();
+    // snapshot names have a resolution of one second
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    report.unwrap()
+}
+
+#[tokio::test]
+async fn put_list_get() {
+    let server = Server::start();
+    let mut config = server.config(BUCKET);
+    let path = temp_path("vault");
+    let (storage, crypto) = new_vault(&path);
+    add_note(&storage, &crypto, "small", b"first");
+    // big enough that the snapshot goes up in parts
+    let large: Vec<u8> = (0..20_000_000u32).map(|n| n.to_le_bytes()[n as usize % 4] ^ (n >> 11) as u8).collect();
+    add_note(&storage, &crypto, "large", &large);
+
+    let first = upload(&storage, &crypto, &config).await;
+    assert!(first.snapshot.key.starts_with(&config.prefix));
+    assert!(first.snapshot.size > 20_000_000, "{:?}", first.snapshot);
+    storage.permanently_delete_item_and_descendants("small", &crypto).unwrap();
+    let second = upload(&storage, &crypto, &config).await;
+    let listed: Vec<String> = list_snapshots(&config).await.unwrap().into_iter().map(|snapshot| snapshot.key).collect();
+    assert_eq!(listed, [second.snapshot.key.clone(), first.snapshot.key.clone()]);
+
+    // get: the first snapshot brings the deleted note back
+    let download = temp_path("download");
+    download_snapshot(&config, &first.snapshot.key, &download).await.unwrap();
+    assert_eq!(std::fs::metadata(&download).unwrap().len(), first.snapshot.size);
+    let staged = StagedRestore::from_snapshot(&download, &storage, &crypto, None).unwrap();
+    std::fs::remove_file(&download).unwrap();
+    assert!(staged.keeps_key());
+    drop(storage);
+    std::fs::remove_dir_all(staged.commit().unwrap().unwrap()).unwrap();
+    let storage = Storage::new(path.clone()).unwrap();
+    assert_eq!(storage.read_encrypted_file("small.bin", &crypto).unwrap(), b"first");
+    assert_eq!(storage.read_encrypted_file("large.bin", &crypto).unwrap(), large);
+
+    // only snapshots this vault made can be downloaded
+    let other_key = format!("{}/notes.txt", config.prefix);
+    assert!(download_snapshot(&config, &other_key, &temp_path("download")).await.is_err());
+
+    // retention prunes the oldest
+    storage.permanently_delete_item_and_descendants("large", &crypto).unwrap();
+    config.retention = 1;
+    let third = upload(&storage, &crypto, &config).await;
+    assert_eq!((third.pruned, third.kept_locked), (2, 0));
+    assert_eq!(list_snapshots(&config).await.unwrap().len(), 1);
+
+    // wrong credentials
+    let mut wrong = config.clone();
+    wrong.secret_access_key = "not the secret".into();
+    let error = list_snapshots(&wrong).await.unwrap_err();
+    assert!(error.to_string().contains("credentials"), "{}", error);
+
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[tokio::test]
+async fn object_lock() {
+    let server = Server::start();
+    let mut config = server.config(LOCKED_BUCKET);
+    let path = temp_path("vault");
+    let (storage, crypto) = new_vault(&path);
+    add_note(&storage, &crypto, "note", b"locked away");
+    assert!(S3Client::new(&config).unwrap().bucket_status().await.unwrap().object_lock_enabled);
+
+    config.object_lock = Some(ObjectLock { mode: ObjectLockMode::Governance, days: 1 });
+    config.retention = 1;
+    let first = upload(&storage, &crypto, &config).await;
+    let until = first.snapshot.locked_until.expect("the snapshot should be locked");
+    assert!(until > chrono::Utc::now() + chrono::Duration::hours(23), "{}", until);
+    // past the retention count but still locked, so it stays
+    let second = upload(&storage, &crypto, &config).await;
+    assert_eq!((second.pruned, second.kept_locked), (0, 1));
+    assert_eq!(list_snapshots(&config).await.unwrap().len(), 2);
+
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
@@ -0,0 +1,47 @@
+//! Full-text search over names, tags and, when asked for, item content.
+
+mod common;
+
+use common::{add_note, new_vault, note, temp_path};
+use fetch::login::{LoginData, LOGIN_ITEM_TYPE};
+use fetch::search::{search_items, SearchOptions};
+
+fn options(query: &str, include_content: bool) -> SearchOptions {
+    SearchOptions { query: query.into(), fuzzy: false, include_content, include_deleted: false, limit: None }
+}
+
+#[test]
+fn content_is_only_searched_when_asked_for() {
+    let path = temp_path("search");
+    let (storage, crypto) = new_vault(&path);
+    add_note(&storage, &crypto, "diary", b"met alice at the station");
+    let mut login = note("mail");
+    login.item_type = LOGIN_ITEM_TYPE.into();
+    login.tags = vec!["work".into()];
+    let data = LoginData { username: "alice".into(), password: "hunter2".into(), urls: vec!["https://mail.example.com".into()], notes: "old account".into() };
+    storage.write_login(&login.data_path, &data, &crypto).unwrap();
+    storage.add_item(&login, &crypto).unwrap();
+
+    let ids = |query: &str, include_content: bool| -> Vec<String> {
+        let mut ids: Vec<String> = search_items(&storage, &crypto, &options(query, include_content)).unwrap().into_iter().map(|hit| hit.item.id).collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(ids("alice", true), ["diary", "mail"]);
+    assert_eq!(ids("example", true), ["mail"]);
+    assert_eq!(ids("account", true), ["mail"]);
+    // the password never is
+    assert!(ids("hunter2", true).is_empty());
+
+    // without content only names and tags count, the same for every kind of item
+    for query in ["alice", "example", "account", "station"] {
+        assert!(ids(query, false).is_empty(), "{}", query);
+    }
+    assert_eq!(ids("work", false), ["mail"]);
+    // and no data file is needed for that
+    std::fs::remove_dir_all(path.join("data")).unwrap();
+    assert_eq!(ids("diary", false), ["diary"]);
+
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
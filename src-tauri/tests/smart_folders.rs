@@ -0,0 +1,61 @@
+//! Smart folders: saved queries whose contents are worked out whenever they are listed.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{add_note, new_vault, note, temp_path};
+use fetch::crypto::Crypto;
+use fetch::storage::{SortOrder, Storage, VaultItem, SMART_FOLDER_TYPE};
+
+fn smart_folder(storage: &Storage, crypto: &Crypto, id: &str, query: &str) {
+    let item = VaultItem { item_type: SMART_FOLDER_TYPE.into(), data_path: format!("{}.query", id), ..note(id) };
+    storage.write_encrypted_file(&crypto.encrypt(query.as_bytes()).unwrap(), &item.data_path).unwrap();
+    storage.add_item(&item, crypto).unwrap();
+}
+
+fn contents(storage: &Storage, crypto: &Crypto, id: &str) -> Vec<String> {
+    let mut ids: Vec<String> = storage.get_items(Some(id.into()), None, None, crypto).unwrap().into_iter().map(|item| item.id).collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn contents_follow_the_saved_query() {
+    let path = temp_path("smart");
+    let (storage, crypto) = new_vault(&path);
+    storage.add_item(&VaultItem { item_type: "folder".into(), data_path: String::new(), ..note("prod") }, &crypto).unwrap();
+    storage.add_item(&VaultItem { parent_id: Some("prod".into()), item_type: "key".into(), tags: vec!["aws".into()], ..note("aws-prod") }, &crypto).unwrap();
+    storage.add_item(&VaultItem { item_type: "key".into(), tags: vec!["aws".into()], deleted_at: Some(Utc::now()), ..note("aws-old") }, &crypto).unwrap();
+    storage.add_item(&VaultItem { totp_secret: Some("JBSWY3DPEHPK3PXP".into()), ..note("github") }, &crypto).unwrap();
+    let last_month = Utc::now() - Duration::days(30);
+    storage.add_item(&VaultItem { created_at: last_month, updated_at: last_month, ..note("stale") }, &crypto).unwrap();
+
+    smart_folder(&storage, &crypto, "aws-keys", "tag:aws type:key");
+    smart_folder(&storage, &crypto, "with-totp", "has:totp");
+    smart_folder(&storage, &crypto, "recent", "updated:>7d");
+    smart_folder(&storage, &crypto, "in-prod", "folder:prod");
+
+    // deleted items stay out unless the query asks for them, smart folders never list each other
+    assert_eq!(contents(&storage, &crypto, "aws-keys"), ["aws-prod"]);
+    assert_eq!(contents(&storage, &crypto, "with-totp"), ["github"]);
+    assert_eq!(contents(&storage, &crypto, "recent"), ["aws-prod", "github", "prod"]);
+    assert_eq!(contents(&storage, &crypto, "in-prod"), ["aws-prod"]);
+
+    // an edit shows up the next time the folder is listed
+    let mut stale = storage.get_item("stale", &crypto).unwrap().unwrap();
+    stale.updated_at = Utc::now();
+    storage.update_item_fields(&stale, &crypto).unwrap();
+    add_note(&storage, &crypto, "fresh", b"fresh");
+    assert_eq!(contents(&storage, &crypto, "recent"), ["aws-prod", "fresh", "github", "prod", "stale"]);
+
+    // filters and paging apply to smart folders as to real ones
+    let keys = storage.get_items(Some("recent".into()), Some("key".into()), None, &crypto).unwrap();
+    assert_eq!(keys.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), ["aws-prod"]);
+    let page = storage.get_items_page(Some("recent".into()), false, None, Some(SortOrder::NameAsc), None, Some(2), &crypto).unwrap();
+    assert_eq!(page.total, 5);
+    // folders first, as in any listing
+    assert_eq!(page.items.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), ["prod", "aws-prod"]);
+    assert!(page.next_cursor.is_some());
+    drop(storage);
+    std::fs::remove_dir_all(path).unwrap();
+}
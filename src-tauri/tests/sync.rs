@@ -0,0 +1,74 @@
+//! Syncing two devices' copies of a vault through a shared folder.
+
+mod common;
+
+use common::{add_note, new_vault, temp_path};
+use fetch::crypto::{Crypto, KeyDerivationStrength};
+use fetch::storage::Storage;
+use fetch::sync::sync_folder;
+
+fn attachments(storage: &Storage, crypto: &Crypto, item_id: &str) -> Vec<(String, Vec<u8>)> {
+    let mut attachments: Vec<(String, Vec<u8>)> = storage
+        .list_attachments(item_id, crypto)
+        .unwrap()
+        .iter()
+        .map(|attachment| (attachment.name.clone(), storage.read_attachment(attachment, crypto).unwrap()))
+        .collect();
+    attachments.sort();
+    attachments
+}
+
+fn link_targets(storage: &Storage, crypto: &Crypto, item_id: &str) -> Vec<String> {
+    storage.get_links(item_id, crypto).unwrap().outgoing.into_iter().map(|linked| linked.link.target_id).collect()
+}
+
+#[test]
+fn attachments_and_links_travel_with_their_item() {
+    let base = temp_path("sync");
+    let folder = base.join("folder");
+    let (a, crypto) = new_vault(&base.join("a"));
+    // the second device's copy of the vault, unlocked with the same key
+    let b = Storage::new(base.join("b")).unwrap();
+    b.initialize(&a.get_salt().unwrap(), KeyDerivationStrength::Fast).unwrap();
+
+    add_note(&a, &crypto, "note", b"note");
+    add_note(&a, &crypto, "other", b"other");
+    a.add_attachment("note", "scan.pdf", "application/pdf", b"scan", &crypto).unwrap();
+    a.add_link("note", "other", Some("see also"), &crypto).unwrap();
+    sync_folder(&a, &crypto, &folder, "a").unwrap();
+    sync_folder(&b, &crypto, &folder, "b").unwrap();
+    assert_eq!(attachments(&b, &crypto, "note"), [("scan.pdf".to_string(), b"scan".to_vec())]);
+    assert_eq!(link_targets(&b, &crypto, "note"), ["other"]);
+
+    // removing and adding on one device does the same on the other
+    let scan = b.list_attachments("note", &crypto).unwrap().remove(0);
+    b.remove_attachment(&scan.id, &crypto).unwrap();
+    b.add_attachment("note", "receipt.png", "image/png", b"receipt", &crypto).unwrap();
+    let link = b.get_links("note", &crypto).unwrap().outgoing.remove(0).link;
+    b.remove_link(&link.id).unwrap();
+    sync_folder(&b, &crypto, &folder, "b").unwrap();
+    sync_folder(&a, &crypto, &folder, "a").unwrap();
+    assert_eq!(attachments(&a, &crypto, "note"), [("receipt.png".to_string(), b"receipt".to_vec())]);
+    assert!(link_targets(&a, &crypto, "note").is_empty());
+
+    // deleted for good on one device, kept in the recycle bin on the other and restored there
+    a.add_link("note", "other", None, &crypto).unwrap();
+    sync_folder(&a, &crypto, &folder, "a").unwrap();
+    sync_folder(&b, &crypto, &folder, "b").unwrap();
+    a.permanently_delete_item_and_descendants("note", &crypto).unwrap();
+    sync_folder(&a, &crypto, &folder, "a").unwrap();
+    assert_eq!(sync_folder(&b, &crypto, &folder, "b").unwrap().deleted, 1);
+    b.restore_item("note").unwrap();
+    sync_folder(&b, &crypto, &folder, "b").unwrap();
+    sync_folder(&a, &crypto, &folder, "a").unwrap();
+    assert!(a.get_item("note", &crypto).unwrap().is_some_and(|note| note.deleted_at.is_none()));
+    assert_eq!(attachments(&a, &crypto, "note"), [("receipt.png".to_string(), b"receipt".to_vec())]);
+    assert_eq!(link_targets(&a, &crypto, "note"), ["other"]);
+
+    let report = sync_folder(&a, &crypto, &folder, "a").unwrap();
+    assert_eq!((report.pushed, report.pulled), (0, 0));
+    let report = sync_folder(&b, &crypto, &folder, "b").unwrap();
+    assert_eq!((report.pushed, report.pulled), (0, 0));
+    drop((a, b));
+    std::fs::remove_dir_all(base).unwrap();
+}
@@ -0,0 +1,234 @@
+//! Push and pull against an in-process WebDAV server. It keeps its files in memory and checks
+//! credentials and the ETag preconditions the way real servers do.
+
+mod common;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use common::{new_vault, note, temp_dir};
+use fetch::crypto::Crypto;
+use fetch::storage::Storage;
+use fetch::webdav::{pull, push, WebDavConfig};
+use hyper::header::{AUTHORIZATION, ETAG, IF_MATCH, IF_NONE_MATCH};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+const USERNAME: &str = "fetch";
+const PASSWORD: &str = "dav secret";
+
+// paths are the request paths without a trailing slash, the root collection is ""
+#[derive(Default)]
+struct Files {
+    collections: BTreeSet<String>,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+fn etag(data: &[u8]) -> String {
+    format!("\"{:x}\"", Sha256::digest(data))
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn reply(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle(files: Arc<Mutex<Files>>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let credentials = format!("Basic {}", STANDARD.encode(format!("{}:{}", USERNAME, PASSWORD)));
+    if request.headers().get(AUTHORIZATION).map_or(true, |value| value != credentials.as_str()) {
+        return Ok(reply(StatusCode::UNAUTHORIZED));
+    }
+    let path = request.uri().path().trim_end_matches('/').to_string();
+    let method = request.method().clone();
+    let header = |name| request.headers().get(name).and_then(|value: &hyper::header::HeaderValue| value.to_str().ok()).map(str::to_string);
+    let (if_match, if_none_match) = (header(IF_MATCH), header(IF_NONE_MATCH));
+    let body = hyper::body::to_bytes(request.into_body()).await.unwrap().to_vec();
+    let mut files = files.lock().unwrap();
+
+    let response = match method.as_str() {
+        "PROPFIND" => {
+            let is_collection = path.is_empty() || files.collections.contains(&path);
+            if !is_collection && !files.files.contains_key(&path) {
+                return Ok(reply(StatusCode::NOT_FOUND));
+            }
+            let mut entries = vec![(path.clone(), files.files.get(&path).map(|data| etag(data)))];
+            if is_collection {
+                entries.extend(files.collections.iter().filter(|child| parent(child) == path).map(|child| (child.clone(), None)));
+                entries.extend(files.files.iter().filter(|(child, _)| parent(child) == path).map(|(child, data)| (child.clone(), Some(etag(data)))));
+            }
+            let responses: String = entries
+                .into_iter()
+                .map(|(href, etag)| {
+                    let (href, prop) = match etag {
+                        Some(etag) => (href, format!("<D:resourcetype/><D:getetag>{}</D:getetag>", etag.replace('"', "&quot;"))),
+                        None => (format!("{}/", href), "<D:resourcetype><D:collection/></D:resourcetype>".to_string()),
+                    };
+                    format!("<D:response><D:href>{}</D:href><D:propstat><D:prop>{}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>", href, prop)
+                })
+                .collect();
+            let xml = format!(r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">{}</D:multistatus>"#, responses);
+            let mut response = Response::new(Body::from(xml));
+            *response.status_mut() = StatusCode::MULTI_STATUS;
+            response
+        }
+        "MKCOL" if path.is_empty() || files.collections.contains(&path) => reply(StatusCode::METHOD_NOT_ALLOWED),
+        "MKCOL" if !parent(&path).is_empty() && !files.collections.contains(parent(&path)) => reply(StatusCode::CONFLICT),
+        "MKCOL" => {
+            files.collections.insert(path);
+            reply(StatusCode::CREATED)
+        }
+        "GET" => match files.files.get(&path) {
+            Some(data) => Response::builder().header(ETAG, etag(data)).body(Body::from(data.clone())).unwrap(),
+            None => reply(StatusCode::NOT_FOUND),
+        },
+        "PUT" if !parent(&path).is_empty() && !files.collections.contains(parent(&path)) => reply(StatusCode::CONFLICT),
+        "PUT" => {
+            let current = files.files.get(&path).map(|data| etag(data));
+            let matches = if_match.map_or(true, |expected| current.as_ref() == Some(&expected));
+            let absent = if_none_match.as_deref() != Some("*") || current.is_none();
+            if !matches || !absent {
+                return Ok(reply(StatusCode::PRECONDITION_FAILED));
+            }
+            let tag = etag(&body);
+            files.files.insert(path, body);
+            Response::builder().status(StatusCode::CREATED).header(ETAG, tag).body(Body::empty()).unwrap()
+        }
+        "DELETE" => match files.files.remove(&path) {
+            Some(_) => reply(StatusCode::NO_CONTENT),
+            None => reply(StatusCode::NOT_FOUND),
+        },
+        _ => reply(StatusCode::METHOD_NOT_ALLOWED),
+    };
+    Ok(response)
+}
+
+struct Server {
+    files: Arc<Mutex<Files>>,
+    url: String,
+    task: JoinHandle<()>,
+}
+
+impl Server {
+    fn start() -> Self {
+        let files = Arc::new(Mutex::new(Files::default()));
+        let shared = files.clone();
+        let service = make_service_fn(move |_| {
+            let files = shared.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |request| handle(files.clone(), request))) }
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let url = format!("http://{}/vault/", server.local_addr());
+        let task = tokio::spawn(async move { server.await.unwrap() });
+        Self { files, url, task }
+    }
+
+    fn config(&self) -> WebDavConfig {
+        WebDavConfig { url: self.url.clone(), username: USERNAME.into(), password: PASSWORD.into() }
+    }
+
+    fn has_file(&self, path: &str) -> bool {
+        self.files.lock().unwrap().files.contains_key(path)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn write_note(storage: &Storage, crypto: &Crypto, id: &str, body: &str) {
+    let item = note(id);
+    storage.write_encrypted_file(&crypto.encrypt(body.as_bytes()).unwrap(), &item.data_path).unwrap();
+    if storage.get_item(id, crypto).unwrap().is_some() {
+        storage.update_item_fields(&item, crypto).unwrap();
+    } else {
+        storage.add_item(&item, crypto).unwrap();
+    }
+}
+
+fn read_note(storage: &Storage, crypto: &Crypto, id: &str) -> String {
+    String::from_utf8(storage.read_encrypted_file(&format!("{}.bin", id), crypto).unwrap()).unwrap()
+}
+
+// pulls into the vault at `path`, closing and reopening it around the swap like the app does
+async fn pull_into(path: &Path, storage: Storage, config: &WebDavConfig, force: bool) -> fetch::Result<(fetch::webdav::TransferReport, Storage)> {
+    let (report, staged) = pull(&storage, config, force).await?;
+    drop(storage);
+    if let Some(staged) = staged {
+        if let Some(previous) = staged.commit()? {
+            std::fs::remove_dir_all(previous).unwrap();
+        }
+    }
+    Ok((report, Storage::new(path.to_path_buf())?))
+}
+
+#[tokio::test]
+async fn push_and_pull() {
+    let server = Server::start();
+    let config = server.config();
+    let (path_a, path_b) = (temp_dir("vault-a"), temp_dir("vault-b"));
+    let (a, crypto) = new_vault(&path_a);
+    write_note(&a, &crypto, "one", "first");
+    write_note(&a, &crypto, "two", "second");
+
+    // upload: everything the first time, nothing when nothing changed
+    let report = push(&a, &config, false).await.unwrap();
+    assert_eq!(report.uploaded, 5, "{:?}", report);
+    assert!(server.has_file("/vault/data/one.bin"));
+    assert_eq!(push(&a, &config, false).await.unwrap().uploaded, 0);
+
+    // a vault with another key takes the pulled one's wholesale
+    let (b, _) = new_vault(&path_b);
+    let (report, b) = pull_into(&path_b, b, &config, true).await.unwrap();
+    assert_eq!((report.downloaded, report.key_changed), (5, true), "{:?}", report);
+    assert_eq!(read_note(&b, &crypto, "two"), "second");
+
+    // download-changed: only the database and the edited file come down
+    write_note(&a, &crypto, "one", "edited");
+    let report = push(&a, &config, false).await.unwrap();
+    assert_eq!(report.uploaded, 2, "{:?}", report);
+    let (report, b) = pull_into(&path_b, b, &config, false).await.unwrap();
+    assert_eq!((report.downloaded, report.unchanged, report.key_changed), (2, 3, false), "{:?}", report);
+    assert_eq!(read_note(&b, &crypto, "one"), "edited");
+    let (report, b) = pull_into(&path_b, b, &config, false).await.unwrap();
+    assert_eq!(report.downloaded, 0);
+
+    // a file removed on the remote side goes away locally
+    a.permanently_delete_item_and_descendants("two", &crypto).unwrap();
+    push(&a, &config, false).await.unwrap();
+    let (report, b) = pull_into(&path_b, b, &config, false).await.unwrap();
+    assert_eq!(report.deleted, 1, "{:?}", report);
+    assert!(b.get_item("two", &crypto).unwrap().is_none());
+    assert!(!path_b.join("data/two.bin").exists());
+
+    // conflict: both sides changed since the last transfer
+    write_note(&a, &crypto, "one", "from a");
+    write_note(&b, &crypto, "one", "from b");
+    push(&a, &config, false).await.unwrap();
+    assert!(push(&b, &config, false).await.is_err());
+    assert!(pull(&b, &config, false).await.is_err());
+    assert_eq!(read_note(&b, &crypto, "one"), "from b");
+    let (_, b) = pull_into(&path_b, b, &config, true).await.unwrap();
+    assert_eq!(read_note(&b, &crypto, "one"), "from a");
+
+    let mut wrong = config.clone();
+    wrong.password = "not the password".into();
+    let error = push(&b, &wrong, false).await.unwrap_err();
+    assert!(error.to_string().contains("credentials"), "{}", error);
+
+    drop((a, b));
+    for path in [path_a, path_b] {
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -156,7 +156,7 @@ export function SettingsPage() {
             return <BruteForceSettings />;
         case 'management':
             return <VaultManagementSettings 
-                        onExportEncrypted={vaultManagement.handleExportEncrypted} 
+                        onExportEncrypted={() => vaultManagement.handleExportEncrypted()} 
                         onExportDecrypted={(format) => {
                             setExportFormat(format || 'json');
                             setIsExportModalOpen(true);
@@ -4,6 +4,7 @@ import { invoke } from '@tauri-apps/api/core';
 import { Menu, Transition } from '@headlessui/react';
 import { ChevronDownIcon } from '@heroicons/react/24/outline';
 import { Fragment } from 'react';
+import { BulkOperationResult } from '../../../types';
 
 interface VaultManagementSettingsProps {
     onExportEncrypted: () => void;
@@ -28,7 +29,8 @@ export const VaultManagementSettings = ({ onExportEncrypted, onExportDecrypted,
         { name: 'JSON (Readable)', value: 'json', description: 'Export as a formatted JSON file' },
         { name: 'CSV', value: 'csv', description: 'For spreadsheets and data analysis' },
         { name: 'Plain Text', value: 'txt', description: 'Export as a simple text file' },
-        { name: 'Markdown', value: 'md', description: 'Export as formatted markdown' }
+        { name: 'Markdown', value: 'md', description: 'Export as formatted markdown' },
+        { name: 'Bitwarden', value: 'bitwarden', description: 'Unencrypted JSON that Bitwarden can import' }
     ];
 
     const handleExportFormat = (format: string) => {
@@ -288,11 +290,17 @@ export const VaultManagementSettings = ({ onExportEncrypted, onExportDecrypted,
             setCleanProgress({ current: 0, total: toDelete.length });
             let deletedCount = 0;
             
-            for (let i = 0; i < toDelete.length; i++) {
-                await invoke('delete_item', { id: toDelete[i].id });
-                deletedCount++;
-                deletedItems.push(toDelete[i]);
-                setCleanProgress({ current: i + 1, total: toDelete.length });
+            if (toDelete.length > 0) {
+                const result = await invoke<BulkOperationResult>('bulk_update_items', {
+                    args: { ids: toDelete.map(item => item.id), action: { action: 'delete' } }
+                });
+                if (!result.committed) {
+                    const failure = result.results.find(r => r.error);
+                    throw new Error(failure?.error ?? 'Bulk delete failed');
+                }
+                deletedCount = toDelete.length;
+                deletedItems.push(...toDelete);
+                setCleanProgress({ current: toDelete.length, total: toDelete.length });
             }
             
             const report = {
@@ -1,10 +1,13 @@
 import { useState } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { save } from '@tauri-apps/plugin-dialog';
+import { ExportSelection, ExportSummary } from '../types';
 
 export function useVaultManagement() {
     const [isLoading, setIsLoading] = useState(false);
     const [error, setError] = useState<string | null>(null);
     const [isSuccess, setIsSuccess] = useState(false);
+    const [exportId, setExportId] = useState<string | null>(null);
 
     const handleUpdateMasterKey = async (currentKey: string, newKey: string, strength: string) => {
         setIsLoading(true);
@@ -22,56 +25,58 @@ export function useVaultManagement() {
         }
     };
     
-    const handleExportDecrypted = async (masterKey: string, format: string = 'json') => {
+    // exports are written straight to the chosen file; progress comes as 'export-progress' events
+    const handleExportDecrypted = async (masterKey: string, format: string = 'json', selection?: ExportSelection) => {
+        const extension = format === 'bitwarden' ? 'json' : format;
+        const path = await save({ defaultPath: `fetch-vault-export-${Date.now()}.${extension}` });
+        if (!path) return false;
+        const id = crypto.randomUUID();
         setIsLoading(true);
         setError(null);
+        setExportId(id);
         try {
-            const content = await invoke<string>('export_decrypted_vault', { 
+            await invoke<ExportSummary>('export_decrypted_vault', {
                 args: {
                     master_key: masterKey,
-                    format
+                    format,
+                    path,
+                    exportId: id,
+                    selection
                 }
             });
-            
-            // Set the correct MIME type based on the format
-            const mimeTypes: { [key: string]: string } = {
-                json: 'application/json',
-                csv: 'text/csv',
-                txt: 'text/plain',
-                md: 'text/markdown'
-            };
-            
-            const blob = new Blob([content], { type: mimeTypes[format] || 'text/plain' });
-            const url = URL.createObjectURL(blob);
-            const a = document.createElement('a');
-            a.href = url;
-            a.download = `fetch-vault-export-${Date.now()}.${format}`;
-            a.click();
-            URL.revokeObjectURL(url);
-            setIsLoading(false);
             return true;
         } catch (err: any) {
             console.error("Decrypted export failed:", err);
             const message = typeof err === 'string' ? err : err.message || "An unknown error occurred.";
+            if (message.includes('Cancelled')) return false;
             setError(message);
-            setIsLoading(false);
             throw err;
+        } finally {
+            setExportId(null);
+            setIsLoading(false);
         }
     };
 
-    const handleExportEncrypted = async () => {
+    const handleExportEncrypted = async (selection?: ExportSelection) => {
+        const path = await save({ defaultPath: `fetch-vault-encrypted-backup-${Date.now()}.zip` });
+        if (!path) return;
+        const id = crypto.randomUUID();
+        setExportId(id);
         try {
-            const data = await invoke<number[]>('export_encrypted_vault');
-            const blob = new Blob([new Uint8Array(data)], { type: 'application/zip' });
-            const url = URL.createObjectURL(blob);
-            const a = document.createElement('a');
-            a.href = url;
-            a.download = `fetch-vault-encrypted-backup-${Date.now()}.zip`;
-            a.click();
-            URL.revokeObjectURL(url);
-        } catch (err) {
+            await invoke<ExportSummary>('export_encrypted_vault', { args: { path, exportId: id, selection } });
+        } catch (err: any) {
+            const message = typeof err === 'string' ? err : err.message || '';
+            if (message.includes('Cancelled')) return;
             console.error("Encrypted export failed:", err);
             alert("Error: Could not create backup file.");
+        } finally {
+            setExportId(null);
+        }
+    };
+
+    const cancelExport = async () => {
+        if (exportId) {
+            await invoke<boolean>('cancel_export', { exportId });
         }
     };
 
@@ -99,6 +104,8 @@ export function useVaultManagement() {
         handleUpdateMasterKey,
         handleExportDecrypted,
         handleExportEncrypted,
+        cancelExport,
+        isExporting: exportId !== null,
         handleDeleteVault,
         clearError
     };
@@ -1,3 +1,11 @@
+export type CustomFieldType = 'text' | 'hidden' | 'url' | 'email' | 'date' | 'totp';
+
+export interface CustomField {
+    label: string;
+    type: CustomFieldType;
+    value: string;
+}
+
 export interface VaultItem {
     id: string;
     parent_id: string | null;
@@ -10,10 +18,91 @@ export interface VaultItem {
     created_at: number;
     updated_at: number;
     deleted_at?: number | null;
-    type: 'text' | 'key' | 'image' | 'video' | 'audio' | 'folder';
+    type: 'text' | 'key' | 'image' | 'video' | 'audio' | 'folder' | 'smart_folder' | 'login' | 'card' | 'identity' | 'bank_account' | 'license';
     totp_secret?: string;
+    custom_fields?: CustomField[];
+    expires_at?: string;
+}
+
+export interface LoginData {
+    username: string;
+    password: string;
+    urls: string[];
+    notes: string;
+}
+
+export interface LoginItem {
+    item: RawBackendItem;
+    login: LoginData;
+}
+
+export interface CardData {
+    cardholder_name: string;
+    number: string;
+    expiry_month: number | null;
+    expiry_year: number | null;
+    security_code: string;
+    pin: string;
+    notes: string;
+}
+
+export interface IdentityData {
+    title: string;
+    first_name: string;
+    middle_name: string;
+    last_name: string;
+    date_of_birth: string;
+    email: string;
+    phone: string;
+    company: string;
+    address_line1: string;
+    address_line2: string;
+    city: string;
+    state: string;
+    postal_code: string;
+    country: string;
+    passport_number: string;
+    license_number: string;
+    national_id: string;
+    notes: string;
+}
+
+export interface BankAccountData {
+    bank_name: string;
+    account_holder: string;
+    account_type: string;
+    account_number: string;
+    routing_number: string;
+    iban: string;
+    bic: string;
+    pin: string;
+    notes: string;
+}
+
+export interface LicenseData {
+    product: string;
+    version: string;
+    license_key: string;
+    licensed_to: string;
+    email: string;
+    company: string;
+    order_number: string;
+    purchase_date: string;
+    expiry_date: string;
+    notes: string;
 }
 
+export type ItemRecord =
+    | { type: 'card'; data: CardData }
+    | { type: 'identity'; data: IdentityData }
+    | { type: 'bank_account'; data: BankAccountData }
+    | { type: 'license'; data: LicenseData };
+
+export type TypedItem = ItemRecord & {
+    item: RawBackendItem;
+    expired: boolean;
+};
+
 export interface RawBackendItem {
     id: string;
     parent_id: string | null;
@@ -26,6 +115,8 @@ export interface RawBackendItem {
     updated_at: string;
     deleted_at?: string | null;
     totp_secret?: string;
+    custom_fields?: CustomField[];
+    expires_at?: string;
 }
 
 export interface Breadcrumb {
@@ -45,4 +136,319 @@ export interface LockoutStatus {
     failed_attempts: number;
     max_attempts: number;
     lockout_duration_minutes: number;
-}
\ No newline at end of file
+}
+export interface BulkItemResult {
+    id: string;
+    success: boolean;
+    error?: string;
+}
+
+export interface BulkOperationResult {
+    committed: boolean;
+    results: BulkItemResult[];
+}
+
+export interface SnippetSegment {
+    text: string;
+    highlighted: boolean;
+}
+
+export interface SearchMatch {
+    field: 'name' | 'tag' | 'url' | 'content';
+    segments: SnippetSegment[];
+}
+
+export interface SearchHit {
+    item: RawBackendItem;
+    score: number;
+    matches: SearchMatch[];
+}
+
+export interface Attachment {
+    id: string;
+    item_id: string;
+    name: string;
+    mime_type: string;
+    size: number;
+    data_path: string;
+    created_at: string;
+}
+
+export interface ItemLink {
+    id: string;
+    source_id: string;
+    target_id: string;
+    label: string | null;
+    created_at: string;
+}
+
+export interface LinkedItem {
+    link: ItemLink;
+    // null once the other item has been permanently deleted
+    item: ItemSummary | null;
+}
+
+export interface ItemLinks {
+    outgoing: LinkedItem[];
+    backlinks: LinkedItem[];
+    referenced_by: ItemSummary[];
+}
+
+export interface FieldReference {
+    item_id: string;
+    field: string;
+}
+
+export type ReferenceStatus = 'resolved' | 'target_deleted' | 'missing_item' | 'missing_field' | 'too_deep';
+
+export interface ResolvedReference {
+    label: string;
+    reference: FieldReference;
+    status: ReferenceStatus;
+    value: string | null;
+}
+
+export interface ExpiringItem {
+    item: ItemSummary;
+    expires_at: string;
+    // negative once expired
+    days_left: number;
+    expired: boolean;
+}
+
+export interface ReminderSettings {
+    enabled: boolean;
+    days_before: number;
+}
+
+export interface VaultInfo {
+    id: string;
+    name: string;
+    path: string;
+    created_at: string;
+    last_opened_at: string | null;
+    // only present once the vault is connected to a sync folder on this device
+    sync?: SyncSettings;
+    // only present once automatic backups are turned on
+    backup?: BackupSchedule;
+    active: boolean;
+    open: boolean;
+    unlocked: boolean;
+    initialized: boolean;
+    // false when the vault folder can't be reached, e.g. an unplugged USB stick
+    available: boolean;
+}
+
+export interface SyncSettings {
+    folder: string;
+    device_id: string;
+    last_synced_at: string | null;
+}
+
+export interface SyncReport {
+    pushed: number;
+    pulled: number;
+    merged: number;
+    conflicts: number;
+    deleted: number;
+    devices: number;
+}
+
+// emitted after a background sync
+export interface VaultSyncedEvent {
+    vault_id: string;
+    report: SyncReport;
+}
+
+export interface WebDavConfig {
+    url: string;
+    username: string;
+    // always empty when read back; leave empty on save to keep the stored password
+    password: string;
+}
+
+export interface TransferReport {
+    uploaded: number;
+    downloaded: number;
+    deleted: number;
+    unchanged: number;
+    // true after a pull that brought in a different master key; the vault is locked
+    key_changed: boolean;
+}
+
+export type ObjectLockMode = 'governance' | 'compliance';
+
+export interface ObjectLock {
+    mode: ObjectLockMode;
+    days: number;
+}
+
+export interface S3Config {
+    endpoint: string;
+    region: string;
+    bucket: string;
+    prefix: string;
+    access_key_id: string;
+    // always empty when read back; leave empty on save to keep the stored secret
+    secret_access_key: string;
+    path_style: boolean;
+    // snapshots to keep, 0 keeps all
+    retention: number;
+    interval_hours: number | null;
+    object_lock: ObjectLock | null;
+}
+
+export interface S3BucketStatus {
+    object_lock_enabled: boolean;
+    default_retention: ObjectLock | null;
+}
+
+export interface S3Snapshot {
+    key: string;
+    size: number;
+    created_at: string;
+    locked_until: string | null;
+}
+
+export interface S3BackupReport {
+    snapshot: S3Snapshot;
+    pruned: number;
+    // past the retention count but still under object lock
+    kept_locked: number;
+}
+
+export interface S3RestoreReport {
+    key: string;
+    // the vault is locked and needs the snapshot's master key
+    key_changed: boolean;
+}
+
+// emitted after a scheduled S3 backup
+export interface VaultBackedUpEvent {
+    vault_id: string;
+    report: S3BackupReport;
+}
+
+// what an export covers: items matching any selector plus the folders they are in; empty exports everything
+export interface ExportSelection {
+    folderIds?: string[];
+    tags?: string[];
+    // query_items syntax
+    query?: string;
+    // the search bar's fuzzy search
+    search?: string;
+    itemIds?: string[];
+    includeDeleted?: boolean;
+}
+
+// result of import_bitwarden; skipped lists "name: reason" for items left out
+export interface BitwardenImportReport {
+    folders: number;
+    items: number;
+    skipped: string[];
+}
+
+export interface KeePassImportReport {
+    folders: number;
+    items: number;
+    attachments: number;
+    skipped: string[];
+}
+
+export interface ExportSummary {
+    path: string;
+    // items for a decrypted export, files for an encrypted one
+    entries: number;
+    bytes: number;
+}
+
+// emitted while an export runs
+export interface ExportProgressEvent {
+    export_id: string;
+    current: number;
+    total: number;
+    message: string;
+}
+
+export interface BackupSchedule {
+    directory: string;
+    daily: boolean;
+    // a backup after this many writes, null to not count writes
+    every_writes: number | null;
+    // back up before deleting the vault, emptying the bin, changing the master key or importing
+    before_destructive: boolean;
+    keep_daily: number;
+    keep_weekly: number;
+    keep_monthly: number;
+    last_backup_at: string | null;
+    // why the last attempt failed, cleared by the next successful backup
+    last_error: string | null;
+}
+
+export interface LocalBackup {
+    path: string;
+    size: number;
+    created_at: string;
+}
+
+// emitted after a scheduled local backup
+export interface LocalBackupEvent {
+    vault_id: string;
+    backup: LocalBackup;
+}
+
+export interface BackupSummary {
+    version: number;
+    created_at: string;
+    item_count: number;
+    attachment_count: number;
+    link_count: number;
+    files_checked: number;
+    // false when verified without the passphrase, i.e. checksums only
+    decrypted: boolean;
+}
+
+export type MergeStrategy = 'keep_both' | 'prefer_newer' | 'skip';
+
+export interface MergeConflict {
+    source_id: string;
+    target_id: string;
+    name: string;
+    type: string;
+    kind: 'same_id' | 'same_name_and_type';
+    resolution: 'added' | 'replaced' | 'skipped';
+}
+
+export interface MergeReport {
+    added: number;
+    replaced: number;
+    skipped: number;
+    folders_merged: number;
+    attachments: number;
+    links: number;
+    conflicts: MergeConflict[];
+    dry_run: boolean;
+}
+
+export interface ItemSummary {
+    id: string;
+    parent_id: string | null;
+    name: string;
+    type: string;
+    folder_type: string | null;
+    deleted: boolean;
+}
+
+export interface ItemPage {
+    items: RawBackendItem[];
+    next_cursor: string | null;
+    total: number;
+}
+
+export interface ItemStreamEvent {
+    request_id: string;
+    items: RawBackendItem[];
+    total: number;
+    done: boolean;
+    error?: string;
+}